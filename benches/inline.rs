@@ -0,0 +1,78 @@
+//! Time call-heavy programs with and without inlining
+//!
+//! Run with `cargo bench --bench inline`. Each program's `run` is compiled
+//! twice at `OptLevel::Speed`, calling every function and inlining the
+//! small ones, and timed over a few runs.
+
+use std::time::{Duration, Instant};
+
+use Kennedy::compiler::inline::DEFAULT_INLINE_SIZE;
+use Kennedy::compiler::{Compiler, CompilerOptions, OptLevel};
+
+const RUNS: u32 = 5;
+
+const PROGRAMS: &[(&str, &str, i64)] = &[
+    ("squares", "
+func sq(x: int): int { return x * x; }
+func run(n: int): int {
+    let total = 0;
+    for (let i = 0; i < n; i++) { total += sq(i) - sq(i - 1); }
+    return total;
+}", 20_000_000),
+    ("helpers", "
+func clamp(x: int, low: int, high: int): int {
+    if (x < low) { return low; }
+    if (x > high) { return high; }
+    return x;
+}
+func lerp(a: int, b: int, t: int): int { return a + (b - a) * t / 256; }
+func step(x: int): int { return clamp(lerp(x, x * 3, 128), -1000, 1000000); }
+func run(n: int): int {
+    let x = 1;
+    for (let i = 0; i < n; i++) { x = step(x) - i / 2; }
+    return x;
+}", 20_000_000),
+    ("recursion", "
+func add(a: int, b: int): int { return a + b; }
+func fib(n: int): int {
+    if (n < 2) { return n; }
+    return add(fib(n - 1), fib(n - 2));
+}
+func run(n: int): int { return fib(n); }", 30),
+];
+
+fn main() {
+    println!("{:<12}{:>14}{:>14}{:>10}", "program", "calls", "inlined", "speedup");
+
+    for (name, source, n) in PROGRAMS {
+        let (called, expected) = time(source, 0, *n);
+        let (inlined, result) = time(source, DEFAULT_INLINE_SIZE, *n);
+        assert_eq!(result, expected, "{} computes something else when inlined", name);
+
+        println!(
+            "{:<12}{:>14?}{:>14?}{:>9.2}x",
+            name,
+            called,
+            inlined,
+            called.as_secs_f64() / inlined.as_secs_f64(),
+        );
+    }
+}
+
+/// Fastest of `RUNS` runs of `run(n)`, and what it returned
+fn time(source: &str, inline_size: usize, n: i64) -> (Duration, i64) {
+    let options = CompilerOptions::new().opt_level(OptLevel::Speed).inline_size(inline_size);
+    let mut compiler = Compiler::new(options).unwrap();
+    compiler.compile(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+
+    let run: extern "C" fn(i64) -> i64 = unsafe { std::mem::transmute(compiler.get_function("run").unwrap()) };
+
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let result = std::hint::black_box(run(std::hint::black_box(n)));
+            (start.elapsed(), result)
+        })
+        .min()
+        .unwrap()
+}
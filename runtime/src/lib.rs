@@ -0,0 +1,662 @@
+//! Runtime support called from compiled Kennedy code
+//!
+//! Every function here is `extern "C"` and exported unmangled, so the JIT
+//! can register it by name and object files can be linked against it. The
+//! crate is also built as `libkennedy_runtime.a` for that. Those that can
+//! trap are `extern "C-unwind"`, as a trap caught by [`catch_traps`] unwinds
+//! out of them.
+//!
+//! The string, array and struct functions are `unsafe` because they trust
+//! compiled code to only ever pass them live objects; that is the whole of
+//! their safety contract.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::alloc::{self, Layout};
+use std::cell::Cell;
+use std::fmt;
+
+/// Why compiled code stopped
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapKind {
+    /// Checked arithmetic over/underflowed its integer type
+    IntegerOverflow = 0,
+    /// Integer division with a zero divisor
+    DivisionByZero = 1,
+    /// Index or slice outside the bounds of a string or array
+    IndexOutOfBounds = 2,
+}
+
+impl TrapKind {
+    pub fn from_u32(kind: u32) -> Option<Self> {
+        match kind {
+            0 => Some(TrapKind::IntegerOverflow),
+            1 => Some(TrapKind::DivisionByZero),
+            2 => Some(TrapKind::IndexOutOfBounds),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TrapKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrapKind::IntegerOverflow => write!(f, "integer overflow"),
+            TrapKind::DivisionByZero => write!(f, "division by zero"),
+            TrapKind::IndexOutOfBounds => write!(f, "index out of bounds"),
+        }
+    }
+}
+
+/// A runtime error at a 1-based source location, caught by [`catch_traps`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Runtime error at {}:{}: {}", self.line, self.column, self.kind)
+    }
+}
+
+thread_local! {
+    /// Whether traps on this thread unwind to `catch_traps`, rather than
+    /// ending the process
+    static CATCHING_TRAPS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, which calls compiled code, returning the trap that stopped it
+/// if there's one
+/// Unwinding skips the releases the compiled code had left to do, so what
+/// it held is leaked. Traps are only caught where the JIT has told the
+/// unwinder about compiled code (see `compiler::unwind`); elsewhere they
+/// abort as ever
+pub fn catch_traps<T>(f: impl FnOnce() -> T) -> Result<T, Trap> {
+    let previous = CATCHING_TRAPS.with(|catching| catching.replace(true));
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    CATCHING_TRAPS.with(|catching| catching.set(previous));
+
+    result.map_err(|payload| match payload.downcast::<Trap>() {
+        Ok(trap) => *trap,
+        Err(payload) => std::panic::resume_unwind(payload),
+    })
+}
+
+/// Report a runtime error at a 1-based source location, then abort, unless
+/// it's caught by `catch_traps`
+#[no_mangle]
+pub extern "C-unwind" fn kennedy_trap(kind: u32, line: u32, column: u32) -> ! {
+    let Some(kind) = TrapKind::from_u32(kind) else {
+        eprintln!("Runtime error at {}:{}: unknown trap {}", line, column, kind);
+        std::process::abort();
+    };
+
+    let trap = Trap { kind, line, column };
+    if CATCHING_TRAPS.with(Cell::get) {
+        // not a panic, so the panic hook doesn't report it
+        std::panic::resume_unwind(Box::new(trap));
+    }
+
+    eprintln!("{}", trap);
+    std::process::abort();
+}
+
+/// Header of a Kennedy string. The UTF-8 bytes follow it directly.
+///
+/// A string value in compiled code is a pointer to this header. Strings are
+/// immutable and reference counted: every variable and every temporary owns
+/// one reference. Literals live in read-only data objects and are immortal.
+#[repr(C)]
+pub struct KennedyString {
+    pub refcount: usize,
+    pub len: usize,
+}
+
+/// Refcount of objects that are never freed (string literals, unit variants)
+pub const IMMORTAL: usize = usize::MAX;
+
+/// Size of the string header; the bytes start at this offset
+pub const STRING_HEADER_SIZE: usize = std::mem::size_of::<KennedyString>();
+
+/// Bytes of a string literal as stored in a data object
+pub fn string_literal_data(value: &str) -> Box<[u8]> {
+    let mut data = Vec::with_capacity(STRING_HEADER_SIZE + value.len());
+    data.extend_from_slice(&IMMORTAL.to_ne_bytes());
+    data.extend_from_slice(&value.len().to_ne_bytes());
+    data.extend_from_slice(value.as_bytes());
+    data.into_boxed_slice()
+}
+
+thread_local! {
+    /// Strings allocated on this thread and not yet freed
+    static LIVE_STRINGS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of heap strings allocated on this thread that are still alive
+/// Compiled code runs on the thread that calls it, so this can be used to
+/// check that a call didn't leak
+pub fn live_strings() -> usize {
+    LIVE_STRINGS.with(|live| live.get())
+}
+
+fn string_layout(len: usize) -> Layout {
+    Layout::from_size_align(STRING_HEADER_SIZE + len, std::mem::align_of::<KennedyString>())
+        .expect("string too large")
+}
+
+/// Allocate a string holding `bytes`, with a refcount of one
+pub fn alloc_string(bytes: &[u8]) -> *const KennedyString {
+    unsafe {
+        let ptr = alloc::alloc(string_layout(bytes.len())) as *mut KennedyString;
+        if ptr.is_null() {
+            alloc::handle_alloc_error(string_layout(bytes.len()));
+        }
+
+        ptr.write(KennedyString { refcount: 1, len: bytes.len() });
+        LIVE_STRINGS.with(|live| live.set(live.get() + 1));
+        std::ptr::copy_nonoverlapping(
+            bytes.as_ptr(),
+            (ptr as *mut u8).add(STRING_HEADER_SIZE),
+            bytes.len(),
+        );
+
+        ptr
+    }
+}
+
+/// The bytes of a string
+///
+/// # Safety
+/// `s` must point to a live Kennedy string
+pub unsafe fn string_bytes<'a>(s: *const KennedyString) -> &'a [u8] {
+    std::slice::from_raw_parts((s as *const u8).add(STRING_HEADER_SIZE), (*s).len)
+}
+
+/// Copy a Kennedy string into a Rust `String`
+///
+/// # Safety
+/// `s` must point to a live Kennedy string
+pub unsafe fn string_to_rust(s: *const KennedyString) -> String {
+    String::from_utf8_lossy(string_bytes(s)).into_owned()
+}
+
+/// Add a reference to a string
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_string_retain(s: *mut KennedyString) {
+    if (*s).refcount != IMMORTAL {
+        (*s).refcount += 1;
+    }
+}
+
+/// Drop a reference to a string, freeing it once there are none left
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_string_release(s: *mut KennedyString) {
+    match (*s).refcount {
+        IMMORTAL => {}
+        1 => {
+            alloc::dealloc(s as *mut u8, string_layout((*s).len));
+            LIVE_STRINGS.with(|live| live.set(live.get() - 1));
+        }
+        _ => (*s).refcount -= 1,
+    }
+}
+
+/// `a + b`
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_string_concat(a: *const KennedyString, b: *const KennedyString) -> *const KennedyString {
+    alloc_string(&[string_bytes(a), string_bytes(b)].concat())
+}
+
+/// `len(s)`, in bytes
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_string_len(s: *const KennedyString) -> i64 {
+    (*s).len as i64
+}
+
+/// `a == b`
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_string_eq(a: *const KennedyString, b: *const KennedyString) -> i8 {
+    (string_bytes(a) == string_bytes(b)) as i8
+}
+
+/// `s[index]`, the byte at `index`
+#[no_mangle]
+pub unsafe extern "C-unwind" fn kennedy_string_index(s: *const KennedyString, index: i64, line: u32, column: u32) -> u8 {
+    let bytes = string_bytes(s);
+
+    match usize::try_from(index).ok().and_then(|index| bytes.get(index)) {
+        Some(byte) => *byte,
+        None => kennedy_trap(TrapKind::IndexOutOfBounds as u32, line, column),
+    }
+}
+
+/// `s[start:end]`, the bytes from `start` up to (not including) `end`
+#[no_mangle]
+pub unsafe extern "C-unwind" fn kennedy_string_slice(
+    s: *const KennedyString,
+    start: i64,
+    end: i64,
+    line: u32,
+    column: u32,
+) -> *const KennedyString {
+    let bytes = string_bytes(s);
+
+    let range = usize::try_from(start).ok().zip(usize::try_from(end).ok())
+        .filter(|(start, end)| start <= end && *end <= bytes.len());
+
+    match range {
+        Some((start, end)) => alloc_string(&bytes[start..end]),
+        None => kennedy_trap(TrapKind::IndexOutOfBounds as u32, line, column),
+    }
+}
+
+/// `x as string` for signed integers
+#[no_mangle]
+pub extern "C" fn kennedy_string_from_int(value: i64) -> *const KennedyString {
+    alloc_string(value.to_string().as_bytes())
+}
+
+/// `x as string` for unsigned integers
+#[no_mangle]
+pub extern "C" fn kennedy_string_from_uint(value: u64) -> *const KennedyString {
+    alloc_string(value.to_string().as_bytes())
+}
+
+/// `x as string` for floats
+#[no_mangle]
+pub extern "C" fn kennedy_string_from_float(value: f32) -> *const KennedyString {
+    alloc_string(value.to_string().as_bytes())
+}
+
+/// `x as string` for `f64`s
+#[no_mangle]
+pub extern "C" fn kennedy_string_from_f64(value: f64) -> *const KennedyString {
+    alloc_string(value.to_string().as_bytes())
+}
+
+/// `x as string` for bools
+#[no_mangle]
+pub extern "C" fn kennedy_string_from_bool(value: i8) -> *const KennedyString {
+    alloc_string(if value != 0 { b"true" } else { b"false" })
+}
+
+/// Header of a Kennedy array
+///
+/// An array value in compiled code is a pointer to this header, which stays
+/// put while the elements live in a separate buffer that `push` may move.
+/// Arrays are mutable and reference counted like strings, so every variable
+/// holding the same array sees the same elements. Compiled code loads `len`
+/// and `data` directly to do bounds checked element accesses.
+#[repr(C)]
+pub struct KennedyArray {
+    pub refcount: usize,
+    pub len: usize,
+    pub capacity: usize,
+    pub data: *mut u8,
+    /// Size in bytes of one element, which is also its alignment
+    pub element_size: usize,
+    /// What the elements are, so they can be released with the array
+    pub element_kind: usize,
+}
+
+/// Array elements or struct fields that need no cleanup
+pub const ELEMENT_PLAIN: usize = 0;
+/// Array elements or struct fields that are strings
+pub const ELEMENT_STRING: usize = 1;
+/// Array elements or struct fields that are arrays
+pub const ELEMENT_ARRAY: usize = 2;
+/// Array elements or struct fields that are structs
+pub const ELEMENT_STRUCT: usize = 3;
+
+/// Offset of `KennedyArray::len`
+pub const ARRAY_LEN_OFFSET: i32 = std::mem::offset_of!(KennedyArray, len) as i32;
+/// Offset of `KennedyArray::data`
+pub const ARRAY_DATA_OFFSET: i32 = std::mem::offset_of!(KennedyArray, data) as i32;
+
+thread_local! {
+    /// Arrays allocated on this thread and not yet freed
+    static LIVE_ARRAYS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of arrays allocated on this thread that are still alive
+pub fn live_arrays() -> usize {
+    LIVE_ARRAYS.with(|live| live.get())
+}
+
+fn array_data_layout(capacity: usize, element_size: usize) -> Layout {
+    Layout::from_size_align(capacity * element_size, element_size.max(1))
+        .expect("array too large")
+}
+
+/// `[a, b, c]`: a new array of `len` zeroed elements, with a refcount of one
+/// Compiled code fills in the elements straight away
+#[no_mangle]
+pub extern "C" fn kennedy_array_new(len: usize, element_size: usize, element_kind: usize) -> *mut KennedyArray {
+    let data = if len == 0 {
+        std::ptr::null_mut()
+    } else {
+        let layout = array_data_layout(len, element_size);
+        let data = unsafe { alloc::alloc_zeroed(layout) };
+        if data.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        data
+    };
+
+    LIVE_ARRAYS.with(|live| live.set(live.get() + 1));
+    Box::into_raw(Box::new(KennedyArray {
+        refcount: 1,
+        len,
+        capacity: len,
+        data,
+        element_size,
+        element_kind,
+    }))
+}
+
+/// Add a reference to an array
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_array_retain(a: *mut KennedyArray) {
+    (*a).refcount += 1;
+}
+
+/// Drop a reference to an array, freeing it and releasing its elements once
+/// there are none left
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_array_release(a: *mut KennedyArray) {
+    if (*a).refcount > 1 {
+        (*a).refcount -= 1;
+        return;
+    }
+
+    let array = Box::from_raw(a);
+    release_elements(&array);
+
+    if array.capacity > 0 {
+        alloc::dealloc(array.data, array_data_layout(array.capacity, array.element_size));
+    }
+    LIVE_ARRAYS.with(|live| live.set(live.get() - 1));
+}
+
+/// Elements of an array that hold references to other objects
+unsafe fn references(array: &KennedyArray) -> &[*mut u8] {
+    if array.element_kind == ELEMENT_PLAIN || array.len == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(array.data as *const *mut u8, array.len)
+    }
+}
+
+unsafe fn retain_elements(array: &KennedyArray) {
+    for element in references(array) {
+        retain_reference(array.element_kind, *element);
+    }
+}
+
+unsafe fn release_elements(array: &KennedyArray) {
+    for element in references(array) {
+        release_reference(array.element_kind, *element);
+    }
+}
+
+/// Add a reference to a string, array or struct held as an element or field
+unsafe fn retain_reference(kind: usize, object: *mut u8) {
+    match kind {
+        ELEMENT_STRING => kennedy_string_retain(object as *mut KennedyString),
+        ELEMENT_ARRAY => kennedy_array_retain(object as *mut KennedyArray),
+        ELEMENT_STRUCT => kennedy_struct_retain(object as *mut KennedyStruct),
+        _ => {}
+    }
+}
+
+/// Drop a reference to a string, array or struct held as an element or field
+unsafe fn release_reference(kind: usize, object: *mut u8) {
+    match kind {
+        ELEMENT_STRING => kennedy_string_release(object as *mut KennedyString),
+        ELEMENT_ARRAY => kennedy_array_release(object as *mut KennedyArray),
+        ELEMENT_STRUCT => kennedy_struct_release(object as *mut KennedyStruct),
+        _ => {}
+    }
+}
+
+/// `len(a)`, in elements
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_array_len(a: *const KennedyArray) -> i64 {
+    (*a).len as i64
+}
+
+/// `push(a, x)`: grow the array by one element and return where to store it
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_array_push_slot(a: *mut KennedyArray) -> *mut u8 {
+    let array = &mut *a;
+
+    if array.len == array.capacity {
+        let capacity = (array.capacity * 2).max(4);
+        let layout = array_data_layout(capacity, array.element_size);
+
+        let data = if array.capacity == 0 {
+            alloc::alloc(layout)
+        } else {
+            let old = array_data_layout(array.capacity, array.element_size);
+            alloc::realloc(array.data, old, layout.size())
+        };
+        if data.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+
+        array.data = data;
+        array.capacity = capacity;
+    }
+
+    array.len += 1;
+    array.data.add((array.len - 1) * array.element_size)
+}
+
+/// `a[start:end]`, a new array holding the elements from `start` up to (not
+/// including) `end`
+#[no_mangle]
+pub unsafe extern "C-unwind" fn kennedy_array_slice(
+    a: *const KennedyArray,
+    start: i64,
+    end: i64,
+    line: u32,
+    column: u32,
+) -> *mut KennedyArray {
+    let array = &*a;
+
+    let range = usize::try_from(start).ok().zip(usize::try_from(end).ok())
+        .filter(|(start, end)| start <= end && *end <= array.len);
+
+    let (start, end) = match range {
+        Some(range) => range,
+        None => kennedy_trap(TrapKind::IndexOutOfBounds as u32, line, column),
+    };
+
+    let slice = kennedy_array_new(end - start, array.element_size, array.element_kind);
+    if end > start {
+        std::ptr::copy_nonoverlapping(
+            array.data.add(start * array.element_size),
+            (*slice).data,
+            (end - start) * array.element_size,
+        );
+    }
+
+    // the copies are new references to the same elements
+    retain_elements(&*slice);
+
+    slice
+}
+
+/// Header of a Kennedy struct. The fields follow it directly, in C layout
+/// (see `compiler::layout`).
+///
+/// Structs are mutable and reference counted like arrays. Enum values are
+/// structs too: a `u32` tag followed by the variant's values. Variants that
+/// carry nothing live in data objects, are immortal and have no descriptor.
+#[repr(C)]
+pub struct KennedyStruct {
+    pub refcount: usize,
+    pub descriptor: *const StructDescriptor,
+}
+
+/// Offset of the first field from the start of a struct
+pub const STRUCT_FIELDS_OFFSET: i32 = std::mem::size_of::<KennedyStruct>() as i32;
+
+/// What the runtime needs to know about a struct type, stored in a data
+/// object per struct. Followed by `references` pairs of (field offset,
+/// `ELEMENT_*` kind) for the fields to release along with the struct
+#[repr(C)]
+pub struct StructDescriptor {
+    pub size: usize,
+    pub align: usize,
+    pub references: usize,
+}
+
+/// Bytes of a struct descriptor as stored in a data object
+pub fn struct_descriptor_data(size: usize, align: usize, references: &[(usize, usize)]) -> Box<[u8]> {
+    let words = [size, align, references.len()].into_iter()
+        .chain(references.iter().flat_map(|(offset, kind)| [*offset, *kind]));
+
+    words.flat_map(usize::to_ne_bytes).collect()
+}
+
+/// Bytes of an immortal struct as stored in a data object
+pub fn immortal_struct_data(fields: &[u8]) -> Box<[u8]> {
+    let mut data = Vec::with_capacity(STRUCT_FIELDS_OFFSET as usize + fields.len());
+    data.extend_from_slice(&IMMORTAL.to_ne_bytes());
+    data.extend_from_slice(&0usize.to_ne_bytes());
+    data.extend_from_slice(fields);
+    data.into_boxed_slice()
+}
+
+thread_local! {
+    /// Structs allocated on this thread and not yet freed
+    static LIVE_STRUCTS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Number of structs allocated on this thread that are still alive
+pub fn live_structs() -> usize {
+    LIVE_STRUCTS.with(|live| live.get())
+}
+
+unsafe fn struct_layout(descriptor: *const StructDescriptor) -> Layout {
+    let align = (*descriptor).align.max(std::mem::align_of::<KennedyStruct>());
+    Layout::from_size_align(STRUCT_FIELDS_OFFSET as usize + (*descriptor).size, align)
+        .expect("struct too large")
+}
+
+/// (offset, kind) of each field of a struct that needs releasing
+unsafe fn struct_references<'a>(descriptor: *const StructDescriptor) -> &'a [[usize; 2]] {
+    std::slice::from_raw_parts(descriptor.add(1) as *const [usize; 2], (*descriptor).references)
+}
+
+/// `Point { ... }`: a new struct with zeroed fields, with a refcount of one
+/// Compiled code fills in the fields straight away
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_struct_new(descriptor: *const StructDescriptor) -> *mut KennedyStruct {
+    let layout = struct_layout(descriptor);
+    let ptr = alloc::alloc_zeroed(layout) as *mut KennedyStruct;
+    if ptr.is_null() {
+        alloc::handle_alloc_error(layout);
+    }
+
+    ptr.write(KennedyStruct { refcount: 1, descriptor });
+    LIVE_STRUCTS.with(|live| live.set(live.get() + 1));
+    ptr
+}
+
+/// Add a reference to a struct
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_struct_retain(s: *mut KennedyStruct) {
+    if (*s).refcount != IMMORTAL {
+        (*s).refcount += 1;
+    }
+}
+
+/// Drop a reference to a struct, freeing it and releasing its fields once
+/// there are none left
+#[no_mangle]
+pub unsafe extern "C" fn kennedy_struct_release(s: *mut KennedyStruct) {
+    match (*s).refcount {
+        IMMORTAL => return,
+        1 => {}
+        _ => {
+            (*s).refcount -= 1;
+            return;
+        }
+    }
+
+    let descriptor = (*s).descriptor;
+    let fields = (s as *mut u8).add(STRUCT_FIELDS_OFFSET as usize);
+
+    for [offset, kind] in struct_references(descriptor) {
+        release_reference(*kind, *(fields.add(*offset) as *const *mut u8));
+    }
+
+    alloc::dealloc(s as *mut u8, struct_layout(descriptor));
+    LIVE_STRUCTS.with(|live| live.set(live.get() - 1));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_refcounting() {
+        unsafe {
+            let s = alloc_string(b"hello") as *mut KennedyString;
+            kennedy_string_retain(s);
+            assert_eq!((*s).refcount, 2);
+
+            kennedy_string_release(s);
+            assert_eq!((*s).refcount, 1);
+            assert_eq!(string_to_rust(s), "hello");
+            kennedy_string_release(s);
+        }
+
+        // literals are never freed
+        let data = string_literal_data("hi");
+        let mut aligned = vec![0usize; data.len().div_ceil(std::mem::size_of::<usize>())];
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), aligned.as_mut_ptr() as *mut u8, data.len()) };
+        let literal = aligned.as_mut_ptr() as *mut KennedyString;
+
+        unsafe {
+            kennedy_string_release(literal);
+            kennedy_string_retain(literal);
+            assert_eq!((*literal).refcount, IMMORTAL);
+            assert_eq!(string_to_rust(literal), "hi");
+        }
+    }
+
+    #[test]
+    fn test_array_push_and_slice() {
+        unsafe {
+            let a = kennedy_array_new(0, 4, ELEMENT_PLAIN);
+            for i in 0..10i32 {
+                (kennedy_array_push_slot(a) as *mut i32).write(i * i);
+            }
+            assert_eq!(kennedy_array_len(a), 10);
+
+            let slice = kennedy_array_slice(a, 2, 5, 0, 0);
+            let elements = std::slice::from_raw_parts((*slice).data as *const i32, (*slice).len);
+            assert_eq!(elements, &[4, 9, 16]);
+
+            kennedy_array_release(slice);
+            kennedy_array_release(a);
+        }
+
+        // arrays release the strings they hold
+        unsafe {
+            let strings = live_strings();
+            let a = kennedy_array_new(1, std::mem::size_of::<usize>(), ELEMENT_STRING);
+            (*((*a).data as *mut *const KennedyString)) = alloc_string(b"element");
+
+            kennedy_array_release(a);
+            assert_eq!(live_strings(), strings);
+        }
+    }
+}
@@ -0,0 +1,650 @@
+//! What the compiler knows about a program, for tools to query
+//!
+//! An [`Analysis`] type checks a program and resolves every name in it,
+//! then answers questions about it: the functions and their signatures,
+//! the type of an expression, where a name is declared, which functions
+//! call which, and the variables each function reads and writes.
+//!
+//! The language server answers its queries from an analysis of each open
+//! document, which may not type check: [`Analysis::checked`] takes what the
+//! checker worked out before it stopped.
+//!
+//! Spans are those of the program, so for one loaded from source with
+//! imports, spans past the end of the source are in the modules it imports.
+//! Expressions in generic functions only have types in their instances,
+//! which aren't written anywhere, so they have none here.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use crate::ast::visit::{walk_expression, walk_statement};
+use crate::ast::{self, Block, Expression, MatchBody, Pattern, Program, Statement, Type, Visitor};
+use crate::compiler::symbol_table::SymbolTable;
+use crate::error::{CompileResult, Span};
+use crate::modules::{self, SourceMap};
+use crate::type_checking::{FunctionSignature, TypeChecker};
+
+/// What a name is declared as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Function,
+    Extern,
+    Global,
+    Constant,
+    Struct,
+    Enum,
+    /// A field of a struct, only used through a value
+    Field,
+    Parameter,
+    Variable,
+    /// A value bound by a match arm's pattern
+    Binding,
+}
+
+/// A name declared in the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub ident: String,
+    pub kind: DefinitionKind,
+    /// The whole declaration, i.e. the `let` statement or the function
+    pub span: Span,
+    /// Function it's local to, `None` for top level declarations
+    pub owner: Option<String>,
+    /// Its type, for a value whose type is known
+    pub ty: Option<Type>,
+    /// Where it can be used by name, `None` for top level declarations,
+    /// which can be used anywhere, and fields
+    pub scope: Option<Span>,
+}
+
+impl Definition {
+    /// Whether it's a variable, which functions read and write
+    pub fn is_variable(&self) -> bool {
+        !matches!(
+            self.kind,
+            DefinitionKind::Function
+                | DefinitionKind::Extern
+                | DefinitionKind::Struct
+                | DefinitionKind::Enum
+                | DefinitionKind::Field
+        )
+    }
+}
+
+/// A function of the program
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionInfo {
+    pub ident: String,
+    pub signature: FunctionSignature,
+    /// Declared `extern`, so defined in C
+    pub external: bool,
+    pub span: Span,
+}
+
+/// A call from one function of the program to another, by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub caller: String,
+    pub callee: String,
+    /// The call expression
+    pub span: Span,
+}
+
+/// A type checked program with every name in it resolved
+pub struct Analysis {
+    functions: Vec<FunctionInfo>,
+    /// Type of each expression, by where it is
+    types: HashMap<Span, Type>,
+    definitions: Vec<Definition>,
+    /// Each use of a name, and the definition it refers to
+    references: Vec<(Span, usize)>,
+    calls: Vec<Call>,
+    /// Definitions of the variables each function reads, then those it
+    /// writes, in the order they're first used
+    accesses: HashMap<String, (Vec<usize>, Vec<usize>)>,
+}
+
+impl Analysis {
+    /// Analyze a program, which must type check
+    pub fn new(program: &Program) -> CompileResult<Analysis> {
+        let mut checker = TypeChecker::new();
+        checker.check_program(program)?;
+
+        Ok(Self::checked(program, &checker))
+    }
+
+    /// Analyze a program `checker` has checked, from what it worked out
+    /// whether or not the program type checks
+    pub fn checked(program: &Program, checker: &TypeChecker) -> Analysis {
+        let instances: HashSet<&str> = checker.instances().iter().map(|instance| instance.ident.as_str()).collect();
+        let types: HashMap<Span, Type> = checker.expression_types().iter()
+            .filter(|((owner, _), _)| !instances.contains(owner.as_str()))
+            .map(|((_, span), ty)| (span.clone(), ty.clone()))
+            .collect();
+
+        let functions = program.functions.iter()
+            .map(|function| FunctionInfo {
+                ident: function.ident.clone(),
+                signature: FunctionSignature::of(function),
+                external: false,
+                span: function.span.clone(),
+            })
+            .chain(program.externs.iter().map(|function| FunctionInfo {
+                ident: function.ident.clone(),
+                signature: FunctionSignature::of_extern(function),
+                external: true,
+                span: function.span.clone(),
+            }))
+            .collect();
+
+        let mut resolver = Resolver {
+            program,
+            checker,
+            types: &types,
+            owner: None,
+            scopes: SymbolTable::new(),
+            scope_ends: Vec::new(),
+            definitions: Vec::new(),
+            references: Vec::new(),
+            calls: Vec::new(),
+            accesses: HashMap::new(),
+        };
+        resolver.visit_program(program);
+        let Resolver { definitions, references, calls, accesses, .. } = resolver;
+
+        Analysis { functions, types, definitions, references, calls, accesses }
+    }
+
+    /// Load a program, and the modules it imports from the working
+    /// directory, and analyze it
+    pub fn from_source(source: &str) -> CompileResult<Analysis> {
+        Self::load(None, source)
+    }
+
+    /// Load the program in the file at `path`, and the modules it imports,
+    /// and analyze it
+    pub fn from_file(path: impl AsRef<Path>) -> CompileResult<Analysis> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            crate::CompileError::CompileError(format!("Cannot read `{}`: {}", path.display(), e))
+        })?;
+
+        Self::load(Some(path), &source)
+    }
+
+    fn load(path: Option<&Path>, source: &str) -> CompileResult<Analysis> {
+        let mut sources = SourceMap::default();
+
+        modules::load(path, source, &mut sources)
+            .and_then(|program| Self::new(&program))
+            .map_err(|error| sources.attribute(error))
+    }
+
+    /// Every function and extern function, in declaration order
+    pub fn functions(&self) -> &[FunctionInfo] {
+        &self.functions
+    }
+
+    /// Function or extern function called `ident`
+    pub fn function(&self, ident: &str) -> Option<&FunctionInfo> {
+        self.functions.iter().find(|function| function.ident == ident)
+    }
+
+    /// Type of the expression at exactly `span`
+    pub fn expression_type(&self, span: &Span) -> Option<&Type> {
+        self.types.get(span)
+    }
+
+    /// The innermost expression with a type at `offset`, and its type
+    pub fn expression_at(&self, offset: usize) -> Option<(&Span, &Type)> {
+        self.types.iter()
+            .filter(|(span, _)| span.start <= offset && offset < span.end)
+            .min_by_key(|(span, _)| span.end - span.start)
+    }
+
+    /// Where the name used at `span` is declared, i.e. for the span of an
+    /// identifier expression
+    pub fn definition(&self, span: &Span) -> Option<&Definition> {
+        self.references.iter()
+            .find(|(reference, _)| reference == span)
+            .map(|(_, definition)| &self.definitions[*definition])
+    }
+
+    /// Every name declared in the program, functions and variables alike
+    pub fn definitions(&self) -> &[Definition] {
+        &self.definitions
+    }
+
+    /// Which of the [`definitions`](Self::definitions) the name used at
+    /// `offset`, or just after it, refers to
+    pub fn resolve(&self, offset: usize) -> Option<usize> {
+        self.references.iter()
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(_, definition)| *definition)
+    }
+
+    /// Every use of one of the [`definitions`](Self::definitions), in the
+    /// order they're written
+    pub fn references(&self, definition: usize) -> Vec<&Span> {
+        let mut spans: Vec<&Span> = self.references.iter()
+            .filter(|(_, to)| *to == definition)
+            .map(|(span, _)| span)
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// Every call from a function of the program to another, in the order
+    /// they're written
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// Names of the functions `ident` calls, each once
+    pub fn callees(&self, ident: &str) -> Vec<&str> {
+        let mut callees: Vec<&str> = Vec::new();
+        for call in self.calls.iter().filter(|call| call.caller == ident) {
+            if !callees.contains(&call.callee.as_str()) {
+                callees.push(&call.callee);
+            }
+        }
+        callees
+    }
+
+    /// Names of the functions calling `ident`, each once
+    pub fn callers(&self, ident: &str) -> Vec<&str> {
+        let mut callers: Vec<&str> = Vec::new();
+        for call in self.calls.iter().filter(|call| call.callee == ident) {
+            if !callers.contains(&call.caller.as_str()) {
+                callers.push(&call.caller);
+            }
+        }
+        callers
+    }
+
+    /// The variables a function reads: its own, and globals
+    pub fn reads(&self, function: &str) -> Vec<&Definition> {
+        self.accesses.get(function)
+            .map_or_else(Vec::new, |(reads, _)| reads.iter().map(|&i| &self.definitions[i]).collect())
+    }
+
+    /// The variables a function assigns to once they're declared
+    pub fn writes(&self, function: &str) -> Vec<&Definition> {
+        self.accesses.get(function)
+            .map_or_else(Vec::new, |(_, writes)| writes.iter().map(|&i| &self.definitions[i]).collect())
+    }
+}
+
+/// Resolves every name in a program to its definition, recording what
+/// each function does with them
+struct Resolver<'a> {
+    program: &'a Program,
+    checker: &'a TypeChecker,
+    types: &'a HashMap<Span, Type>,
+    /// Function being resolved, `None` for global initializers
+    owner: Option<String>,
+    /// Definition of each name in scope
+    scopes: SymbolTable<String, usize>,
+    /// Where each local scope ends, innermost last
+    scope_ends: Vec<usize>,
+    definitions: Vec<Definition>,
+    references: Vec<(Span, usize)>,
+    calls: Vec<Call>,
+    accesses: HashMap<String, (Vec<usize>, Vec<usize>)>,
+}
+
+impl Resolver<'_> {
+    /// Declare a name, which if it's local is in scope from `from` to the
+    /// end of the current scope
+    fn define(&mut self, ident: &str, kind: DefinitionKind, span: &Span, ty: Option<Type>, from: usize) {
+        let scope = self.scope_ends.last().map(|&end| Span { start: from, end });
+
+        self.definitions.push(Definition {
+            ident: ident.to_string(),
+            kind,
+            span: span.clone(),
+            owner: self.owner.clone(),
+            ty,
+            scope,
+        });
+        self.scopes.insert(ident.to_string(), self.definitions.len() - 1);
+    }
+
+    fn push_scope(&mut self, end: usize) {
+        self.scopes.push_scope();
+        self.scope_ends.push(end);
+    }
+
+    fn pop_scope(&mut self) {
+        self.scopes.pop_scope();
+        self.scope_ends.pop();
+    }
+
+    /// Resolve a use of a name, which is read, written or both
+    fn refer(&mut self, ident: &str, span: &Span, read: bool, write: bool) -> Option<usize> {
+        let definition = *self.scopes.get(&ident.to_string())?;
+        self.references.push((span.clone(), definition));
+
+        if let (Some(owner), true) = (&self.owner, self.definitions[definition].is_variable()) {
+            let (reads, writes) = self.accesses.entry(owner.clone()).or_default();
+            if read && !reads.contains(&definition) {
+                reads.push(definition);
+            }
+            if write && !writes.contains(&definition) {
+                writes.push(definition);
+            }
+        }
+
+        Some(definition)
+    }
+
+    /// Resolve the target of an assignment, `++` or `--`, which is written
+    /// as well as read if it's a variable
+    fn assign(&mut self, target: &Expression, read: bool) {
+        match target {
+            Expression::Identifier { ident, span } => {
+                self.refer(ident, span, read, true);
+            }
+            // `a[i] = x` and `p.x = x` change what `a` and `p` refer to, not
+            // the variables
+            target => self.visit_expression(target),
+        }
+    }
+
+    fn declare_parameters(&mut self, params: &ast::Parameters, body: &Block) {
+        for param in &params.params {
+            let ty = Some(param.param_type.clone());
+            self.define(&param.ident, DefinitionKind::Parameter, &param.span, ty, body.span.start);
+        }
+    }
+
+    /// Declare what a match arm's pattern binds, as values of the variant
+    /// of the scrutinee's enum
+    fn declare_bindings(&mut self, pattern: &Pattern, scrutinee: Option<&Type>) {
+        let Pattern::Variant { variant, bindings, span, .. } = pattern else {
+            return;
+        };
+
+        let fields = match scrutinee {
+            Some(Type::Named(ident)) => self.program.enums.iter()
+                .find(|declaration| declaration.ident == *ident)
+                .and_then(|declaration| declaration.variant(variant))
+                .map(|(_, variant)| variant.fields.clone()),
+            _ => None,
+        };
+
+        for (i, binding) in bindings.iter().enumerate().filter(|(_, binding)| *binding != "_") {
+            let ty = fields.as_ref().and_then(|fields| fields.get(i)).cloned();
+            self.define(binding, DefinitionKind::Binding, span, ty, span.end);
+        }
+    }
+
+    /// The definition of `field` on the struct `target` is
+    fn field(&self, target: &Expression, field: &str) -> Option<usize> {
+        let Some(Type::Named(ident)) = self.types.get(target.span()) else {
+            return None;
+        };
+        let declaration = self.program.structs.iter().find(|declaration| declaration.ident == *ident)?;
+        let span = &declaration.field(field)?.span;
+
+        self.definitions.iter().position(|definition| {
+            definition.kind == DefinitionKind::Field && definition.span == *span
+        })
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    fn visit_program(&mut self, program: &Program) {
+        for global in &program.globals {
+            let kind = if global.constant { DefinitionKind::Constant } else { DefinitionKind::Global };
+            let ty = self.checker.global_type(&global.ident).or(global.global_type.as_ref()).cloned();
+            self.define(&global.ident, kind, &global.span, ty, 0);
+        }
+        for function in &program.externs {
+            self.define(&function.ident, DefinitionKind::Extern, &function.span, None, 0);
+        }
+        for function in &program.functions {
+            self.define(&function.ident, DefinitionKind::Function, &function.span, None, 0);
+        }
+        for declaration in &program.structs {
+            self.define(&declaration.ident, DefinitionKind::Struct, &declaration.span, None, 0);
+
+            // fields are only reached through a value, not by name
+            for field in &declaration.fields {
+                self.definitions.push(Definition {
+                    ident: field.ident.clone(),
+                    kind: DefinitionKind::Field,
+                    span: field.span.clone(),
+                    owner: None,
+                    ty: Some(field.field_type.clone()),
+                    scope: None,
+                });
+            }
+        }
+        for declaration in &program.enums {
+            self.define(&declaration.ident, DefinitionKind::Enum, &declaration.span, None, 0);
+        }
+
+        for global in &program.globals {
+            self.visit_expression(&global.value);
+        }
+
+        for function in &program.functions {
+            self.visit_function(function);
+        }
+    }
+
+    fn visit_function(&mut self, function: &ast::Function) {
+        self.owner = Some(function.ident.clone());
+
+        self.push_scope(function.body.span.end);
+        self.declare_parameters(&function.params, &function.body);
+        self.visit_block(&function.body);
+        self.pop_scope();
+
+        self.owner = None;
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        self.push_scope(block.span.end);
+        block.statements.iter().for_each(|statement| self.visit_statement(statement));
+        self.pop_scope();
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { ident, var_type, value, span } => {
+                self.visit_expression(value);
+
+                let ty = var_type.as_ref().or_else(|| self.types.get(value.span())).cloned();
+                self.define(ident, DefinitionKind::Variable, span, ty, span.end);
+            }
+            Statement::Assign { ident, value, span } => {
+                let name = Span { start: span.start, end: span.start + ident.chars().count() };
+                self.refer(ident, &name, false, true);
+                self.visit_expression(value);
+            }
+            // the loop's variable is only in scope within it
+            Statement::For { init, condition, increment, body, span } => {
+                self.push_scope(span.end);
+                self.visit_statement(init);
+                self.visit_expression(condition);
+                self.visit_block(body);
+                self.visit_statement(increment);
+                self.pop_scope();
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier { ident, span } => {
+                self.refer(ident, span, true, false);
+            }
+            Expression::Call { callee, arguments, span } => {
+                if let Expression::Identifier { ident, span: name } = callee.as_ref() {
+                    let definition = self.refer(ident, name, true, false);
+                    let function = definition.map(|definition| &self.definitions[definition])
+                        .filter(|definition| matches!(definition.kind, DefinitionKind::Function | DefinitionKind::Extern));
+
+                    if let (Some(caller), Some(function)) = (&self.owner, function) {
+                        self.calls.push(Call { caller: caller.clone(), callee: function.ident.clone(), span: span.clone() });
+                    }
+                } else {
+                    self.visit_expression(callee);
+                }
+
+                arguments.iter().for_each(|argument| self.visit_expression(argument));
+            }
+            Expression::Assign { left, operator, right, .. } => {
+                self.assign(left, *operator != ast::AssignOperator::Equal);
+                self.visit_expression(right);
+            }
+            Expression::Postfix { left: target, .. } | Expression::Prefix { right: target, .. } => {
+                self.assign(target, true);
+            }
+            Expression::Function { params, body, .. } => {
+                self.push_scope(body.span.end);
+                self.declare_parameters(params, body);
+                self.visit_block(body);
+                self.pop_scope();
+            }
+            Expression::StructLiteral { ident, span, .. } => {
+                let name = Span { start: span.start, end: span.start + ident.chars().count() };
+                self.refer(ident, &name, false, false);
+                walk_expression(self, expression);
+            }
+            Expression::Field { target, field, span } => {
+                self.visit_expression(target);
+                if let Some(definition) = self.field(target, field) {
+                    let name = Span { start: span.end - field.chars().count(), end: span.end };
+                    self.references.push((name, definition));
+                }
+            }
+            Expression::Match { scrutinee, arms, .. } => {
+                self.visit_expression(scrutinee);
+                let scrutinee = self.types.get(scrutinee.span()).cloned();
+
+                for arm in arms {
+                    let end = match &arm.body {
+                        MatchBody::Expression(expression) => expression.span().end,
+                        MatchBody::Block(block) => block.span.end,
+                    };
+
+                    self.push_scope(end);
+                    self.declare_bindings(&arm.pattern, scrutinee.as_ref());
+                    match &arm.body {
+                        MatchBody::Expression(expression) => self.visit_expression(expression),
+                        MatchBody::Block(block) => self.visit_block(block),
+                    }
+                    self.pop_scope();
+                }
+            }
+            _ => walk_expression(self, expression),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+const LIMIT: int = 10;
+let total: int = 0;
+struct Point { x: int, y: int }
+func add(p: Point): int {
+    total += p.x + p.y;
+    return total;
+}
+func run(n: int): int {
+    let count = 0;
+    for (let i = 0; i < n; i++) {
+        count = count + add(Point { x: i, y: LIMIT });
+    }
+    let twice = func(x: int): int { return add(Point { x: x, y: x }); };
+    return twice(count);
+}";
+
+    /// Span of the `n`th (from zero) occurrence of `needle` in `SOURCE`
+    fn find(needle: &str, n: usize) -> Span {
+        let byte = SOURCE.match_indices(needle).nth(n).unwrap().0;
+        let start = SOURCE[..byte].chars().count();
+        Span { start, end: start + needle.chars().count() }
+    }
+
+    #[test]
+    fn test_analysis() {
+        let analysis = Analysis::from_source(SOURCE).unwrap();
+
+        let functions: Vec<(&str, String)> = analysis.functions().iter()
+            .map(|function| (function.ident.as_str(), format!("{:?}", function.signature.params)))
+            .collect();
+        assert_eq!(functions, [("add", "[Named(\"Point\")]".to_string()), ("run", "[Int]".to_string())]);
+        assert_eq!(analysis.function("run").unwrap().signature.return_type, Type::Int);
+
+        assert_eq!(analysis.expression_type(&find("p.x + p.y", 0)), Some(&Type::Int));
+        assert_eq!(analysis.expression_type(&find("Point { x: i, y: LIMIT }", 0)), Some(&Type::Named("Point".to_string())));
+
+        let definition = analysis.definition(&find("count", 2)).unwrap();
+        assert_eq!((definition.kind, definition.span.start), (DefinitionKind::Variable, find("let count", 0).start));
+        assert_eq!(definition.owner.as_deref(), Some("run"));
+        assert_eq!(analysis.definition(&find("LIMIT", 1)).unwrap().kind, DefinitionKind::Constant);
+        assert_eq!(analysis.definition(&find("x", 6)).unwrap().kind, DefinitionKind::Parameter);
+        assert_eq!(analysis.definition(&find("Point", 3)).unwrap().kind, DefinitionKind::Struct);
+
+        // fields, through the value's type
+        let x = analysis.resolve(find("p.x", 0).start + 2).unwrap();
+        assert_eq!(analysis.definitions()[x].kind, DefinitionKind::Field);
+        assert_eq!(analysis.references(x), [&find("x", 1)]);
+
+        // locals have the type of their value, in scope after they're declared
+        let count = analysis.resolve(find("count", 2).start).unwrap();
+        assert_eq!(analysis.definitions()[count].ty, Some(Type::Int));
+        assert_eq!(analysis.definitions()[count].scope.as_ref().map(|scope| scope.start), Some(find("let count = 0;", 0).end));
+        assert_eq!(analysis.references(count).len(), 3);
+        assert_eq!(analysis.expression_at(find("p.y", 0).start + 2), Some((&find("p.y", 0), &Type::Int)));
+    }
+
+    #[test]
+    fn test_calls_and_accesses() {
+        let analysis = Analysis::from_source(SOURCE).unwrap();
+
+        // calls from the lambda are the function's, and calling it isn't a
+        // call to a function
+        let calls: Vec<(&str, &str)> = analysis.calls().iter()
+            .map(|call| (call.caller.as_str(), call.callee.as_str()))
+            .collect();
+        assert_eq!(calls, [("run", "add"), ("run", "add")]);
+        assert_eq!(analysis.callees("run"), ["add"]);
+        assert_eq!(analysis.callers("add"), ["run"]);
+        assert!(analysis.callers("run").is_empty());
+
+        assert_eq!(idents(&analysis.reads("add")), ["total", "p"]);
+        assert_eq!(idents(&analysis.writes("add")), ["total"]);
+        assert_eq!(idents(&analysis.reads("run")), ["i", "n", "count", "LIMIT", "x", "twice"]);
+        // the loop's increment is written after its body
+        assert_eq!(idents(&analysis.writes("run")), ["count", "i"]);
+    }
+
+    #[test]
+    fn test_match_bindings() {
+        let source = "\
+enum Shape { Circle(float), Square(float) }
+func area(s: Shape): float {
+    return match (s) { Circle(r) => r * r * 3.0, Square(_) => 0.0 };
+}";
+        let analysis = Analysis::from_source(source).unwrap();
+
+        let start = source.find("r * r").unwrap();
+        let definition = analysis.definition(&Span { start, end: start + 1 }).unwrap();
+        assert_eq!(definition.kind, DefinitionKind::Binding);
+        assert_eq!(definition.ty, Some(Type::Float));
+        assert_eq!(idents(&analysis.reads("area")), ["s", "r"]);
+    }
+
+    fn idents<'a>(definitions: &[&'a Definition]) -> Vec<&'a str> {
+        definitions.iter().map(|definition| definition.ident.as_str()).collect()
+    }
+}
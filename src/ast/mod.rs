@@ -0,0 +1,763 @@
+pub mod visit;
+
+pub use crate::error::Span;
+pub use visit::{Visitor, VisitorMut};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub imports: Vec<Import>,
+    /// In declaration order, which initializers may rely on
+    pub globals: Vec<Global>,
+    pub externs: Vec<ExternFunction>,
+    pub functions: Vec<Function>,
+    pub structs: Vec<Struct>,
+    pub enums: Vec<Enum>,
+}
+
+/// import math; or import "lib/math.ken";
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// Name its items are qualified with, i.e. `math` in `math::add`
+    pub module: String,
+    /// Relative to the importing file
+    pub path: String,
+    pub span: Span,
+}
+
+/// let counter: int = 0; or const LIMIT = 10;
+/// The value is worked out at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub ident: String,
+    pub ident_span: Span,
+    /// Declared `pub`, so other modules can use it
+    pub public: bool,
+    /// Declared `const`, so it can't be assigned to
+    pub constant: bool,
+    pub global_type: Option<Type>,
+    pub value: Expression,
+    pub span: Span,
+}
+
+/// extern func sqrtf(x: float): float;
+/// A C function, found by name in the host process when compiling into
+/// memory, or by the linker for object files
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternFunction {
+    pub ident: String,
+    /// Declared `pub`, so other modules can use it
+    pub public: bool,
+    pub params: Parameters,
+    pub return_type: Type,
+    pub span: Span,
+}
+
+/// struct Point { x: float, y: float }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Struct {
+    pub ident: String,
+    pub ident_span: Span,
+    /// Declared `pub`, so other modules can use it
+    pub public: bool,
+    /// In declaration order, which is also their order in memory
+    pub fields: Vec<Field>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub ident: String,
+    pub field_type: Type,
+    pub span: Span,
+}
+
+impl Struct {
+    /// The field called `ident`
+    pub fn field(&self, ident: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.ident == ident)
+    }
+}
+
+/// enum Shape { Circle(float), Rect(float, float), Empty }
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enum {
+    pub ident: String,
+    pub ident_span: Span,
+    /// Declared `pub`, so other modules can use it
+    pub public: bool,
+    /// Each variant's discriminant is its position in this list
+    pub variants: Vec<Variant>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub ident: String,
+    /// Types of the values it carries, if any
+    pub fields: Vec<Type>,
+    pub span: Span,
+}
+
+impl Enum {
+    /// Discriminant and declaration of the variant called `ident`
+    pub fn variant(&self, ident: &str) -> Option<(usize, &Variant)> {
+        self.variants.iter().enumerate().find(|(_, variant)| variant.ident == ident)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    // @inline, written before it
+    pub attributes: Vec<Attribute>,
+    // ident
+    pub ident: String,
+    pub ident_span: Span,
+    // pub, so other modules can call it
+    pub public: bool,
+    // <T, U: numeric>, empty unless the function is generic
+    pub type_params: Vec<TypeParam>,
+    // params
+    pub params: Parameters,
+    // type
+    pub return_type: Type,
+    // body
+    pub body: Block,
+    pub span: Span,
+}
+
+impl Function {
+    /// Whether the function has type parameters
+    pub fn is_generic(&self) -> bool {
+        !self.type_params.is_empty()
+    }
+
+    /// The attribute called `ident`, if the function has it
+    pub fn attribute(&self, ident: &str) -> Option<&Attribute> {
+        self.attributes.iter().find(|attribute| attribute.ident == ident)
+    }
+}
+
+/// `@inline` or `@name(a, b)`
+/// Tells the compiler something about the function it's written before
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub ident: String,
+    pub arguments: Vec<String>,
+    pub span: Span,
+}
+
+/// `T` or `T: numeric`
+/// Within the function, the parameter is written as a `Type::Named`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub ident: String,
+    pub bound: Option<Bound>,
+    pub span: Span,
+}
+
+/// What a type argument must support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// Arithmetic and ordering: the integer types and `float`
+    Numeric,
+    /// The integer types
+    Integer,
+    /// `==` and `!=`: numbers, `bool` and `string`
+    Equatable,
+}
+
+impl Bound {
+    pub fn from_ident(ident: &str) -> Option<Bound> {
+        match ident {
+            "numeric" => Some(Bound::Numeric),
+            "integer" => Some(Bound::Integer),
+            "equatable" => Some(Bound::Equatable),
+            _ => None,
+        }
+    }
+
+    /// Whether `ty` meets the bound
+    pub fn allows(&self, ty: &Type) -> bool {
+        match self {
+            Bound::Numeric => ty.is_numeric(),
+            Bound::Integer => ty.is_integer(),
+            Bound::Equatable => ty.is_numeric() || *ty == Type::Bool || *ty == Type::String,
+        }
+    }
+}
+
+impl std::fmt::Display for Bound {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Bound::Numeric => write!(f, "numeric"),
+            Bound::Integer => write!(f, "integer"),
+            Bound::Equatable => write!(f, "equatable"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    pub params: Vec<Parameter>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub ident: String,
+    pub param_type: Type,
+    /// From the name to the end of the type
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    /// From `{` to `}`
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    // variable decl
+    VariableDeclaration {
+        ident: String,
+        var_type: Option<Type>,
+        value: Expression,
+        span: Span,
+    },
+    // variable assign
+    Assign {
+        ident: String,
+        value: Expression,
+        span: Span,
+    },
+    // return 1;
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+    /// { ... }
+    Block {
+        block: Box<Block>,
+        span: Span,
+    },
+    // if (x) { 1; } else { 2; }
+    If {
+        condition: Expression,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+        span: Span,
+    },
+    // while (x) { 1; }
+    While {
+        condition: Expression,
+        body: Block,
+        span: Span,
+    },
+    // 1;
+    Expression {
+        expression: Expression,
+        span: Span,
+    },
+    DoUntil {
+        condition: Expression,
+        body: Box<Statement>,
+        span: Span,
+    },
+    For {
+        init: Box<Statement>,
+        condition: Expression,
+        increment: Box<Statement>,
+        body: Block,
+        span: Span,
+    },
+}
+
+impl Statement {
+    /// Source span covered by the statement, including its `;`
+    pub fn span(&self) -> &Span {
+        match self {
+            Statement::VariableDeclaration { span, .. }
+            | Statement::Assign { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::Block { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Expression { span, .. }
+            | Statement::DoUntil { span, .. }
+            | Statement::For { span, .. } => span,
+        }
+    }
+
+    /// Whether nothing after the statement runs, as it always returns or
+    /// loops forever (there's no `break`)
+    pub fn diverges(&self) -> bool {
+        match self {
+            Statement::Return { .. } => true,
+            Statement::Block { block, .. } => block.statements.iter().any(Statement::diverges),
+            Statement::If { then_branch, else_branch: Some(else_branch), .. } => {
+                then_branch.diverges() && else_branch.diverges()
+            }
+            // the body runs at least once
+            Statement::DoUntil { body, condition, .. } => {
+                body.diverges() || matches!(condition, Expression::BooleanLiteral { value: false, .. })
+            }
+            Statement::While { condition, .. } | Statement::For { condition, .. } => {
+                matches!(condition, Expression::BooleanLiteral { value: true, .. })
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    // 1
+    IntegerLiteral {
+        /// Up to `u64::MAX`, checked against the type it's given
+        value: i128,
+        span: Span,
+    },
+    // 1.0
+    FloatLiteral {
+        value: f64,
+        span: Span,
+    },
+    // "hello"
+    StringLiteral {
+        value: String,
+        span: Span,
+    },
+    // true
+    BooleanLiteral {
+        value: bool,
+        span: Span,
+    },
+    // null
+    NullLiteral {
+        span: Span,
+    },
+    // x
+    Identifier {
+        ident: String,
+        span: Span,
+    },
+    // x + 1
+    Binary {
+        left: Box<Expression>,
+        operator: BinaryOperator,
+        right: Box<Expression>,
+        span: Span,
+    },
+    // -1
+    Unary {
+        operator: UnaryOperator,
+        right: Box<Expression>,
+        span: Span,
+    },
+    // (1 + 2)
+    Grouping {
+        expression: Box<Expression>,
+        span: Span,
+    },
+    // function (x) { return x; }
+    Function {
+        params: Parameters,
+        return_type: Type,
+        body: Block,
+        span: Span,
+    },
+    // call()
+    Call {
+        callee: Box<Expression>,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    // x++
+    Postfix {
+        left: Box<Expression>,
+        operator: PostfixOperator,
+        span: Span,
+    },
+    // x--
+    Prefix {
+        operator: PrefixOperator,
+        right: Box<Expression>,
+        span: Span,
+    },
+    // x += 1
+    Assign {
+        left: Box<Expression>,
+        operator: AssignOperator,
+        right: Box<Expression>,
+        span: Span,
+    },
+    // x as u8
+    Cast {
+        expression: Box<Expression>,
+        target_type: Type,
+        span: Span,
+    },
+    // s[0]
+    Index {
+        target: Box<Expression>,
+        index: Box<Expression>,
+        span: Span,
+    },
+    // s[1:3], s[1:], s[:3]
+    Slice {
+        target: Box<Expression>,
+        start: Option<Box<Expression>>,
+        end: Option<Box<Expression>>,
+        span: Span,
+    },
+    // [1, 2, 3]
+    ArrayLiteral {
+        elements: Vec<Expression>,
+        span: Span,
+    },
+    // Point { x: 1.0, y: 2.0 }
+    StructLiteral {
+        ident: String,
+        fields: Vec<(String, Expression)>,
+        span: Span,
+    },
+    // p.x
+    Field {
+        target: Box<Expression>,
+        field: String,
+        span: Span,
+    },
+    // Shape::Circle(1.0), Shape::Empty
+    Variant {
+        enum_ident: String,
+        variant: String,
+        arguments: Vec<Expression>,
+        span: Span,
+    },
+    // match (s) { Circle(r) => r * r, _ => 0.0 }
+    Match {
+        scrutinee: Box<Expression>,
+        arms: Vec<MatchArm>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: MatchBody,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchBody {
+    // Circle(r) => r * r
+    Expression(Expression),
+    // Circle(r) => { ... }, which has no value
+    Block(Block),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    // Circle(r), Shape::Rect(w, _), Empty
+    Variant {
+        enum_ident: Option<String>,
+        variant: String,
+        /// `_` ignores a value
+        bindings: Vec<String>,
+        span: Span,
+    },
+    // 1, -1
+    Integer {
+        value: i128,
+        span: Span,
+    },
+    // true
+    Boolean {
+        value: bool,
+        span: Span,
+    },
+    // _
+    Wildcard {
+        span: Span,
+    },
+}
+
+impl MatchBody {
+    /// Whether the arm's value is an integer literal, which takes its type
+    /// from the other arms
+    pub fn is_integer_literal(&self) -> bool {
+        matches!(self, MatchBody::Expression(expression) if expression.is_integer_literal())
+    }
+}
+
+impl MatchArm {
+    /// Span to report errors about the arm's value at
+    pub fn span(&self) -> &Span {
+        match &self.body {
+            MatchBody::Expression(expression) => expression.span(),
+            MatchBody::Block(_) => self.pattern.span(),
+        }
+    }
+}
+
+impl Pattern {
+    /// Source span covered by the pattern
+    pub fn span(&self) -> &Span {
+        match self {
+            Pattern::Variant { span, .. }
+            | Pattern::Integer { span, .. }
+            | Pattern::Boolean { span, .. }
+            | Pattern::Wildcard { span } => span,
+        }
+    }
+}
+
+impl Expression {
+    /// Source span covered by the expression
+    pub fn span(&self) -> &Span {
+        match self {
+            Expression::IntegerLiteral { span, .. }
+            | Expression::FloatLiteral { span, .. }
+            | Expression::StringLiteral { span, .. }
+            | Expression::BooleanLiteral { span, .. }
+            | Expression::NullLiteral { span }
+            | Expression::Identifier { span, .. }
+            | Expression::Binary { span, .. }
+            | Expression::Unary { span, .. }
+            | Expression::Grouping { span, .. }
+            | Expression::Function { span, .. }
+            | Expression::Call { span, .. }
+            | Expression::Postfix { span, .. }
+            | Expression::Prefix { span, .. }
+            | Expression::Assign { span, .. }
+            | Expression::Cast { span, .. }
+            | Expression::Index { span, .. }
+            | Expression::Slice { span, .. }
+            | Expression::ArrayLiteral { span, .. }
+            | Expression::StructLiteral { span, .. }
+            | Expression::Field { span, .. }
+            | Expression::Variant { span, .. }
+            | Expression::Match { span, .. } => span,
+        }
+    }
+
+    /// Value of an integer literal (optionally negated or parenthesised).
+    /// These have no fixed width of their own and take the integer type
+    /// expected by their context, defaulting to `int`.
+    pub fn integer_literal_value(&self) -> Option<i128> {
+        match self {
+            Expression::IntegerLiteral { value, .. } => Some(*value),
+            Expression::Unary { operator: UnaryOperator::Minus, right, .. } => {
+                right.integer_literal_value().map(|value| -value)
+            }
+            Expression::Grouping { expression, .. } => expression.integer_literal_value(),
+            _ => None,
+        }
+    }
+
+    /// Whether the expression is an integer literal
+    pub fn is_integer_literal(&self) -> bool {
+        self.integer_literal_value().is_some()
+    }
+
+    /// Whether the expression is a float literal (optionally negated or
+    /// parenthesised), which is an `f64` where one is expected and a
+    /// `float` otherwise
+    pub fn is_float_literal(&self) -> bool {
+        match self {
+            Expression::FloatLiteral { .. } => true,
+            Expression::Unary { operator: UnaryOperator::Minus, right, .. } => right.is_float_literal(),
+            Expression::Grouping { expression, .. } => expression.is_float_literal(),
+            _ => false,
+        }
+    }
+
+    /// Whether the expression is a literal whose type depends on where
+    /// it's used
+    pub fn is_number_literal(&self) -> bool {
+        self.is_integer_literal() || self.is_float_literal()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// Alias of `i64`
+    Int,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    /// 32 bit, C's `float`
+    Float,
+    /// 64 bit, C's `double`
+    F64,
+    Bool,
+    String,
+    Null,
+    /// `int[]`, a growable array
+    Array(Box<Type>),
+    /// `[int; 4]`, an array whose length is part of its type
+    FixedArray(Box<Type>, usize),
+    /// A struct or enum, by name
+    Named(String),
+    /// `func(int, int): int`, a function or closure
+    Function(Vec<Type>, Box<Type>),
+}
+
+impl Type {
+    /// Whether this is one of the integer types
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::Int | Type::I8 | Type::I16 | Type::I32 | Type::I64
+                | Type::U8 | Type::U16 | Type::U32 | Type::U64
+        )
+    }
+
+    /// Whether this is a signed integer type
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Type::Int | Type::I8 | Type::I16 | Type::I32 | Type::I64)
+    }
+
+    /// Whether this is `float` or `f64`
+    pub fn is_float(&self) -> bool {
+        matches!(self, Type::Float | Type::F64)
+    }
+
+    /// Whether this is an integer or float type
+    pub fn is_numeric(&self) -> bool {
+        self.is_integer() || self.is_float()
+    }
+
+    /// Width in bits of an integer type
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Type::I8 | Type::U8 => Some(8),
+            Type::I16 | Type::U16 => Some(16),
+            Type::I32 | Type::U32 => Some(32),
+            Type::Int | Type::I64 | Type::U64 => Some(64),
+            _ => None,
+        }
+    }
+
+    /// Range of values representable by an integer type
+    pub fn integer_range(&self) -> Option<(i128, i128)> {
+        let bits = self.bits()?;
+
+        if self.is_signed() {
+            Some((-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1))
+        } else {
+            Some((0, (1i128 << bits) - 1))
+        }
+    }
+
+    /// Element type of an array type
+    pub fn element_type(&self) -> Option<&Type> {
+        match self {
+            Type::Array(element) | Type::FixedArray(element, _) => Some(element),
+            _ => None,
+        }
+    }
+
+    /// Whether values of this type are reference counted heap objects
+    pub fn is_refcounted(&self) -> bool {
+        matches!(self, Type::String | Type::Array(_) | Type::FixedArray(..) | Type::Named(_) | Type::Function(..))
+    }
+
+    /// `int` is just another name for `i64`; everything else is its own type.
+    pub fn canonical(&self) -> Type {
+        match self {
+            Type::Int => Type::I64,
+            Type::Array(element) => Type::Array(Box::new(element.canonical())),
+            Type::FixedArray(element, len) => Type::FixedArray(Box::new(element.canonical()), *len),
+            Type::Function(params, return_type) => Type::Function(
+                params.iter().map(Type::canonical).collect(),
+                Box::new(return_type.canonical()),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// Whether two types are the same once aliases are resolved
+    pub fn same_as(&self, other: &Type) -> bool {
+        self.canonical() == other.canonical()
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::I8 => write!(f, "i8"),
+            Type::I16 => write!(f, "i16"),
+            Type::I32 => write!(f, "i32"),
+            Type::I64 => write!(f, "i64"),
+            Type::U8 => write!(f, "u8"),
+            Type::U16 => write!(f, "u16"),
+            Type::U32 => write!(f, "u32"),
+            Type::U64 => write!(f, "u64"),
+            Type::Float => write!(f, "float"),
+            Type::F64 => write!(f, "f64"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Null => write!(f, "null"),
+            Type::Array(element) => write!(f, "{}[]", element),
+            Type::FixedArray(element, len) => write!(f, "[{}; {}]", element, len),
+            Type::Named(ident) => write!(f, "{}", ident),
+            Type::Function(params, return_type) => {
+                let params: Vec<String> = params.iter().map(Type::to_string).collect();
+                write!(f, "func({}): {}", params.join(", "), return_type)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryOperator {
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    StarStar,
+    SlashSlash,
+    EqualEqual,
+    BangEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Or,
+    And,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnaryOperator {
+    Minus,
+    Bang,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostfixOperator {
+    PlusPlus,
+    MinusMinus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefixOperator {
+    PlusPlus,
+    MinusMinus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssignOperator {
+    Equal,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+}
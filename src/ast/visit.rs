@@ -0,0 +1,508 @@
+//! Walking the syntax tree
+//!
+//! A pass implements [`Visitor`] to look at a tree, or [`VisitorMut`] to
+//! change it in place, overriding the `visit_*` methods of the nodes it cares
+//! about. Each defaults to the matching `walk_*` function, which visits the
+//! node's children; an override calls it to carry on into them, or doesn't to
+//! skip them.
+//!
+//! The walk functions match every variant, so a new one only needs handling
+//! here for every pass to see inside it.
+
+use super::{
+    Block, Enum, Expression, ExternFunction, Function, Global, Import, MatchArm, MatchBody, Parameters, Pattern,
+    Program, Statement, Struct, Type,
+};
+
+/// Looks at a syntax tree
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_import(&mut self, _import: &Import) {}
+
+    fn visit_global(&mut self, global: &Global) {
+        walk_global(self, global);
+    }
+
+    fn visit_extern_function(&mut self, function: &ExternFunction) {
+        walk_extern_function(self, function);
+    }
+
+    fn visit_struct(&mut self, declaration: &Struct) {
+        walk_struct(self, declaration);
+    }
+
+    fn visit_enum(&mut self, declaration: &Enum) {
+        walk_enum(self, declaration);
+    }
+
+    fn visit_function(&mut self, function: &Function) {
+        walk_function(self, function);
+    }
+
+    fn visit_parameters(&mut self, params: &Parameters) {
+        walk_parameters(self, params);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        walk_match_arm(self, arm);
+    }
+
+    fn visit_pattern(&mut self, _pattern: &Pattern) {}
+
+    /// Every type written in the tree: of a declaration, parameter, field,
+    /// cast or return
+    fn visit_type(&mut self, _ty: &Type) {}
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    program.imports.iter().for_each(|import| visitor.visit_import(import));
+    program.structs.iter().for_each(|declaration| visitor.visit_struct(declaration));
+    program.enums.iter().for_each(|declaration| visitor.visit_enum(declaration));
+    program.globals.iter().for_each(|global| visitor.visit_global(global));
+    program.externs.iter().for_each(|function| visitor.visit_extern_function(function));
+    program.functions.iter().for_each(|function| visitor.visit_function(function));
+}
+
+pub fn walk_global<V: Visitor + ?Sized>(visitor: &mut V, global: &Global) {
+    if let Some(global_type) = &global.global_type {
+        visitor.visit_type(global_type);
+    }
+    visitor.visit_expression(&global.value);
+}
+
+pub fn walk_extern_function<V: Visitor + ?Sized>(visitor: &mut V, function: &ExternFunction) {
+    visitor.visit_parameters(&function.params);
+    visitor.visit_type(&function.return_type);
+}
+
+pub fn walk_struct<V: Visitor + ?Sized>(visitor: &mut V, declaration: &Struct) {
+    declaration.fields.iter().for_each(|field| visitor.visit_type(&field.field_type));
+}
+
+pub fn walk_enum<V: Visitor + ?Sized>(visitor: &mut V, declaration: &Enum) {
+    for variant in &declaration.variants {
+        variant.fields.iter().for_each(|field| visitor.visit_type(field));
+    }
+}
+
+pub fn walk_function<V: Visitor + ?Sized>(visitor: &mut V, function: &Function) {
+    visitor.visit_parameters(&function.params);
+    visitor.visit_type(&function.return_type);
+    visitor.visit_block(&function.body);
+}
+
+pub fn walk_parameters<V: Visitor + ?Sized>(visitor: &mut V, params: &Parameters) {
+    params.params.iter().for_each(|param| visitor.visit_type(&param.param_type));
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    block.statements.iter().for_each(|statement| visitor.visit_statement(statement));
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::VariableDeclaration { var_type, value, .. } => {
+            if let Some(var_type) = var_type {
+                visitor.visit_type(var_type);
+            }
+            visitor.visit_expression(value);
+        }
+        Statement::Assign { value, .. } => visitor.visit_expression(value),
+        Statement::Return { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression(value);
+            }
+        }
+        Statement::Block { block, .. } => visitor.visit_block(block),
+        Statement::If { condition, then_branch, else_branch, .. } => {
+            visitor.visit_expression(condition);
+            visitor.visit_statement(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_statement(else_branch);
+            }
+        }
+        Statement::While { condition, body, .. } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+        }
+        Statement::Expression { expression, .. } => visitor.visit_expression(expression),
+        Statement::DoUntil { condition, body, .. } => {
+            visitor.visit_statement(body);
+            visitor.visit_expression(condition);
+        }
+        Statement::For { init, condition, increment, body, .. } => {
+            visitor.visit_statement(init);
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+            visitor.visit_statement(increment);
+        }
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::IntegerLiteral { .. }
+        | Expression::FloatLiteral { .. }
+        | Expression::StringLiteral { .. }
+        | Expression::BooleanLiteral { .. }
+        | Expression::NullLiteral { .. }
+        | Expression::Identifier { .. } => {}
+
+        Expression::Binary { left, right, .. } | Expression::Assign { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Unary { right, .. } | Expression::Prefix { right, .. } => visitor.visit_expression(right),
+        Expression::Postfix { left, .. } => visitor.visit_expression(left),
+        Expression::Grouping { expression, .. } => visitor.visit_expression(expression),
+        Expression::Function { params, return_type, body, .. } => {
+            visitor.visit_parameters(params);
+            visitor.visit_type(return_type);
+            visitor.visit_block(body);
+        }
+        Expression::Call { callee, arguments, .. } => {
+            visitor.visit_expression(callee);
+            arguments.iter().for_each(|argument| visitor.visit_expression(argument));
+        }
+        Expression::Cast { expression, target_type, .. } => {
+            visitor.visit_expression(expression);
+            visitor.visit_type(target_type);
+        }
+        Expression::Index { target, index, .. } => {
+            visitor.visit_expression(target);
+            visitor.visit_expression(index);
+        }
+        Expression::Slice { target, start, end, .. } => {
+            visitor.visit_expression(target);
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_expression(bound);
+            }
+        }
+        Expression::ArrayLiteral { elements, .. } => {
+            elements.iter().for_each(|element| visitor.visit_expression(element));
+        }
+        Expression::StructLiteral { fields, .. } => {
+            fields.iter().for_each(|(_, value)| visitor.visit_expression(value));
+        }
+        Expression::Field { target, .. } => visitor.visit_expression(target),
+        Expression::Variant { arguments, .. } => {
+            arguments.iter().for_each(|argument| visitor.visit_expression(argument));
+        }
+        Expression::Match { scrutinee, arms, .. } => {
+            visitor.visit_expression(scrutinee);
+            arms.iter().for_each(|arm| visitor.visit_match_arm(arm));
+        }
+    }
+}
+
+pub fn walk_match_arm<V: Visitor + ?Sized>(visitor: &mut V, arm: &MatchArm) {
+    visitor.visit_pattern(&arm.pattern);
+    match &arm.body {
+        MatchBody::Expression(expression) => visitor.visit_expression(expression),
+        MatchBody::Block(block) => visitor.visit_block(block),
+    }
+}
+
+/// Changes a syntax tree in place
+/// An override can replace a node outright, i.e. `*expression = ...`
+pub trait VisitorMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program);
+    }
+
+    fn visit_import_mut(&mut self, _import: &mut Import) {}
+
+    fn visit_global_mut(&mut self, global: &mut Global) {
+        walk_global_mut(self, global);
+    }
+
+    fn visit_extern_function_mut(&mut self, function: &mut ExternFunction) {
+        walk_extern_function_mut(self, function);
+    }
+
+    fn visit_struct_mut(&mut self, declaration: &mut Struct) {
+        walk_struct_mut(self, declaration);
+    }
+
+    fn visit_enum_mut(&mut self, declaration: &mut Enum) {
+        walk_enum_mut(self, declaration);
+    }
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        walk_function_mut(self, function);
+    }
+
+    fn visit_parameters_mut(&mut self, params: &mut Parameters) {
+        walk_parameters_mut(self, params);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression);
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm);
+    }
+
+    fn visit_pattern_mut(&mut self, _pattern: &mut Pattern) {}
+
+    /// Every type written in the tree: of a declaration, parameter, field,
+    /// cast or return
+    fn visit_type_mut(&mut self, _ty: &mut Type) {}
+}
+
+pub fn walk_program_mut<V: VisitorMut + ?Sized>(visitor: &mut V, program: &mut Program) {
+    program.imports.iter_mut().for_each(|import| visitor.visit_import_mut(import));
+    program.structs.iter_mut().for_each(|declaration| visitor.visit_struct_mut(declaration));
+    program.enums.iter_mut().for_each(|declaration| visitor.visit_enum_mut(declaration));
+    program.globals.iter_mut().for_each(|global| visitor.visit_global_mut(global));
+    program.externs.iter_mut().for_each(|function| visitor.visit_extern_function_mut(function));
+    program.functions.iter_mut().for_each(|function| visitor.visit_function_mut(function));
+}
+
+pub fn walk_global_mut<V: VisitorMut + ?Sized>(visitor: &mut V, global: &mut Global) {
+    if let Some(global_type) = &mut global.global_type {
+        visitor.visit_type_mut(global_type);
+    }
+    visitor.visit_expression_mut(&mut global.value);
+}
+
+pub fn walk_extern_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut ExternFunction) {
+    visitor.visit_parameters_mut(&mut function.params);
+    visitor.visit_type_mut(&mut function.return_type);
+}
+
+pub fn walk_struct_mut<V: VisitorMut + ?Sized>(visitor: &mut V, declaration: &mut Struct) {
+    declaration.fields.iter_mut().for_each(|field| visitor.visit_type_mut(&mut field.field_type));
+}
+
+pub fn walk_enum_mut<V: VisitorMut + ?Sized>(visitor: &mut V, declaration: &mut Enum) {
+    for variant in &mut declaration.variants {
+        variant.fields.iter_mut().for_each(|field| visitor.visit_type_mut(field));
+    }
+}
+
+pub fn walk_function_mut<V: VisitorMut + ?Sized>(visitor: &mut V, function: &mut Function) {
+    visitor.visit_parameters_mut(&mut function.params);
+    visitor.visit_type_mut(&mut function.return_type);
+    visitor.visit_block_mut(&mut function.body);
+}
+
+pub fn walk_parameters_mut<V: VisitorMut + ?Sized>(visitor: &mut V, params: &mut Parameters) {
+    params.params.iter_mut().for_each(|param| visitor.visit_type_mut(&mut param.param_type));
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut Block) {
+    block.statements.iter_mut().for_each(|statement| visitor.visit_statement_mut(statement));
+}
+
+pub fn walk_statement_mut<V: VisitorMut + ?Sized>(visitor: &mut V, statement: &mut Statement) {
+    match statement {
+        Statement::VariableDeclaration { var_type, value, .. } => {
+            if let Some(var_type) = var_type {
+                visitor.visit_type_mut(var_type);
+            }
+            visitor.visit_expression_mut(value);
+        }
+        Statement::Assign { value, .. } => visitor.visit_expression_mut(value),
+        Statement::Return { value, .. } => {
+            if let Some(value) = value {
+                visitor.visit_expression_mut(value);
+            }
+        }
+        Statement::Block { block, .. } => visitor.visit_block_mut(block),
+        Statement::If { condition, then_branch, else_branch, .. } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_statement_mut(then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_statement_mut(else_branch);
+            }
+        }
+        Statement::While { condition, body, .. } => {
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(body);
+        }
+        Statement::Expression { expression, .. } => visitor.visit_expression_mut(expression),
+        Statement::DoUntil { condition, body, .. } => {
+            visitor.visit_statement_mut(body);
+            visitor.visit_expression_mut(condition);
+        }
+        Statement::For { init, condition, increment, body, .. } => {
+            visitor.visit_statement_mut(init);
+            visitor.visit_expression_mut(condition);
+            visitor.visit_block_mut(body);
+            visitor.visit_statement_mut(increment);
+        }
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expression: &mut Expression) {
+    match expression {
+        Expression::IntegerLiteral { .. }
+        | Expression::FloatLiteral { .. }
+        | Expression::StringLiteral { .. }
+        | Expression::BooleanLiteral { .. }
+        | Expression::NullLiteral { .. }
+        | Expression::Identifier { .. } => {}
+
+        Expression::Binary { left, right, .. } | Expression::Assign { left, right, .. } => {
+            visitor.visit_expression_mut(left);
+            visitor.visit_expression_mut(right);
+        }
+        Expression::Unary { right, .. } | Expression::Prefix { right, .. } => visitor.visit_expression_mut(right),
+        Expression::Postfix { left, .. } => visitor.visit_expression_mut(left),
+        Expression::Grouping { expression, .. } => visitor.visit_expression_mut(expression),
+        Expression::Function { params, return_type, body, .. } => {
+            visitor.visit_parameters_mut(params);
+            visitor.visit_type_mut(return_type);
+            visitor.visit_block_mut(body);
+        }
+        Expression::Call { callee, arguments, .. } => {
+            visitor.visit_expression_mut(callee);
+            arguments.iter_mut().for_each(|argument| visitor.visit_expression_mut(argument));
+        }
+        Expression::Cast { expression, target_type, .. } => {
+            visitor.visit_expression_mut(expression);
+            visitor.visit_type_mut(target_type);
+        }
+        Expression::Index { target, index, .. } => {
+            visitor.visit_expression_mut(target);
+            visitor.visit_expression_mut(index);
+        }
+        Expression::Slice { target, start, end, .. } => {
+            visitor.visit_expression_mut(target);
+            for bound in [start, end].into_iter().flatten() {
+                visitor.visit_expression_mut(bound);
+            }
+        }
+        Expression::ArrayLiteral { elements, .. } => {
+            elements.iter_mut().for_each(|element| visitor.visit_expression_mut(element));
+        }
+        Expression::StructLiteral { fields, .. } => {
+            fields.iter_mut().for_each(|(_, value)| visitor.visit_expression_mut(value));
+        }
+        Expression::Field { target, .. } => visitor.visit_expression_mut(target),
+        Expression::Variant { arguments, .. } => {
+            arguments.iter_mut().for_each(|argument| visitor.visit_expression_mut(argument));
+        }
+        Expression::Match { scrutinee, arms, .. } => {
+            visitor.visit_expression_mut(scrutinee);
+            arms.iter_mut().for_each(|arm| visitor.visit_match_arm_mut(arm));
+        }
+    }
+}
+
+pub fn walk_match_arm_mut<V: VisitorMut + ?Sized>(visitor: &mut V, arm: &mut MatchArm) {
+    visitor.visit_pattern_mut(&mut arm.pattern);
+    match &mut arm.body {
+        MatchBody::Expression(expression) => visitor.visit_expression_mut(expression),
+        MatchBody::Block(block) => visitor.visit_block_mut(block),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        Parser::new(lexer::lex(source.to_string()).unwrap()).parse().unwrap()
+    }
+
+    /// Counts the calls in a program, and the identifiers inside lambdas
+    #[derive(Default)]
+    struct Counter {
+        calls: usize,
+        lambda_idents: Vec<String>,
+        in_lambda: bool,
+    }
+
+    impl Visitor for Counter {
+        fn visit_expression(&mut self, expression: &Expression) {
+            match expression {
+                Expression::Call { .. } => self.calls += 1,
+                Expression::Identifier { ident, .. } if self.in_lambda => self.lambda_idents.push(ident.clone()),
+                Expression::Function { .. } => {
+                    let outer = std::mem::replace(&mut self.in_lambda, true);
+                    walk_expression(self, expression);
+                    self.in_lambda = outer;
+                    return;
+                }
+                _ => {}
+            }
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let program = parse(r#"
+let g = f(1);
+func f(x: int): int {
+    for (let i = 0; i < g(x); i++) {
+        let h = func(y: int): int { return match (y) { 1 => x, _ => { return k(y); } }; };
+    }
+    return f(f(x));
+}
+"#);
+        let mut counter = Counter::default();
+        counter.visit_program(&program);
+
+        assert_eq!(counter.calls, 5);
+        assert_eq!(counter.lambda_idents, ["y", "x", "k", "y"]);
+    }
+
+    /// Renames a variable, and doubles integer literals
+    struct Rewriter;
+
+    impl VisitorMut for Rewriter {
+        fn visit_expression_mut(&mut self, expression: &mut Expression) {
+            match expression {
+                Expression::Identifier { ident, .. } if ident == "a" => *ident = "b".to_string(),
+                Expression::IntegerLiteral { value, .. } => *value *= 2,
+                _ => walk_expression_mut(self, expression),
+            }
+        }
+
+        fn visit_type_mut(&mut self, ty: &mut Type) {
+            if *ty == Type::Int {
+                *ty = Type::I32;
+            }
+        }
+    }
+
+    #[test]
+    fn test_visitor_mut() {
+        let mut program = parse("func f(a: int): int { let c: int = a + 1; return (c as int) * [a][0]; }");
+        Rewriter.visit_program_mut(&mut program);
+
+        assert_eq!(
+            program.functions[0].to_string(),
+            "func f(a: i32): i32 {\n    let c: i32 = b + 2;\n    return c as i32 * [b][0];\n}",
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! `kennedy-lsp`, the Kennedy language server, spoken over stdin and stdout
+
+use std::io;
+use std::process::ExitCode;
+
+use Kennedy::lsp;
+
+fn main() -> ExitCode {
+    match lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        // the protocol says to exit with 1 if the client didn't ask the
+        // server to shut down first
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("kennedy-lsp: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Functions built into the language
+//!
+//! Built-ins are called like ordinary functions but may accept several
+//! argument types, so the type checker and compiler handle each one directly.
+
+/// A built-in function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `len(s)`: length of a string in bytes, or of an array in elements
+    Len,
+    /// `push(a, x)`: append to a growable array
+    Push,
+}
+
+impl Builtin {
+    /// The built-in called `ident`, if there is one
+    pub fn from_ident(ident: &str) -> Option<Self> {
+        match ident {
+            "len" => Some(Builtin::Len),
+            "push" => Some(Builtin::Push),
+            _ => None,
+        }
+    }
+}
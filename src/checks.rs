@@ -0,0 +1,501 @@
+//! Checks for code that compiles but is probably a mistake
+//!
+//! The type checker rejects programs that use or assign to names that were
+//! never declared. What's left to find is what's declared but never used:
+//! variables (including those bound by match patterns), parameters and
+//! functions, and statements that can never run, because one before them
+//! always returns or loops forever.
+//!
+//! Each check has a [`Severity`], so it can be left out, reported as a
+//! `CompileError::Warning` or as an error that fails the compile, and an
+//! ID to set it by, like [lints](crate::lint). What a check reports ends
+//! with its ID, and a function can change its severity for its own body
+//! with the same attributes as lints:
+//!
+//! ```text
+//! @allow(unused_parameters)
+//! func handle(event: int): int { return 0; }
+//! ```
+//!
+//! Names starting with `_` are never reported as unused.
+//!
+//! A variable is used when its value is read. Assigning to it, including
+//! with `+=` or `++` as a statement of its own, doesn't count, so a counter
+//! nobody looks at is reported as assigned but never used.
+
+use std::collections::HashSet;
+
+use crate::ast::visit::{walk_block, walk_expression, walk_match_arm, walk_statement};
+use crate::ast::{Block, Expression, Function, MatchArm, Parameters, Pattern, Program, Statement, Visitor};
+use crate::error::{CompileError, Span};
+use crate::lint;
+
+/// What to do about something a check finds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Ignore it
+    Allow,
+    /// Report it as a warning
+    Warn,
+    /// Report it as an error, failing the compile
+    Deny,
+}
+
+impl Severity {
+    pub fn from_ident(ident: &str) -> Option<Severity> {
+        match ident {
+            "allow" => Some(Severity::Allow),
+            "warn" => Some(Severity::Warn),
+            "deny" => Some(Severity::Deny),
+            _ => None,
+        }
+    }
+
+    /// `message` from the lint or check `id` as this severity, ending with
+    /// the ID, or `None` if it's allowed
+    pub(crate) fn report(self, message: String, id: &str, span: Span) -> Option<CompileError> {
+        let message = format!("{} [{}]", message, id);
+        match self {
+            Severity::Allow => None,
+            Severity::Warn => Some(CompileError::Warning(message, span)),
+            Severity::Deny => Some(CompileError::SemanticError(message, span)),
+        }
+    }
+}
+
+/// Severity of each check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checks {
+    /// Variables declared with `let`, in a `for` or by a match pattern
+    pub unused_variables: Severity,
+    /// Parameters of functions and anonymous functions
+    pub unused_parameters: Severity,
+    /// Functions no other function refers to, other than `main` and `pub`
+    /// ones. Allowed by default, as the host can look up any function
+    pub unused_functions: Severity,
+    /// Statements after one that always returns or loops forever
+    pub unreachable_code: Severity,
+}
+
+impl Default for Checks {
+    fn default() -> Self {
+        Self {
+            unused_variables: Severity::Warn,
+            unused_parameters: Severity::Warn,
+            unused_functions: Severity::Allow,
+            unreachable_code: Severity::Warn,
+        }
+    }
+}
+
+impl Checks {
+    /// How each check is named on the command line and in attributes,
+    /// which are the names of their fields
+    pub const IDS: [&'static str; 4] = ["unused_variables", "unused_parameters", "unused_functions", "unreachable_code"];
+
+    /// Set the severity of the check called `id`, returning whether there
+    /// is one
+    pub fn set(&mut self, id: &str, severity: Severity) -> bool {
+        let check = match id {
+            "unused_variables" => &mut self.unused_variables,
+            "unused_parameters" => &mut self.unused_parameters,
+            "unused_functions" => &mut self.unused_functions,
+            "unreachable_code" => &mut self.unreachable_code,
+            _ => return false,
+        };
+        *check = severity;
+        true
+    }
+
+    /// Severities within a function, changed by its `@allow`, `@warn` and
+    /// `@deny` attributes
+    fn within(&self, function: &Function) -> Checks {
+        let mut checks = *self;
+        for (id, severity) in lint::attribute_levels(function) {
+            checks.set(id, severity);
+        }
+        checks
+    }
+
+    /// Every check at the same severity
+    pub fn all(severity: Severity) -> Self {
+        Self {
+            unused_variables: severity,
+            unused_parameters: severity,
+            unused_functions: severity,
+            unreachable_code: severity,
+        }
+    }
+}
+
+/// Everything the checks find in a program that type checks, in the order
+/// it appears in the source
+/// Warnings are `CompileError::Warning`s, and denied ones
+/// `CompileError::SemanticError`s, both ending with the check's ID
+pub fn check(program: &Program, checks: &Checks) -> Vec<CompileError> {
+    let functions: HashSet<&str> = program.functions.iter().map(|function| function.ident.as_str()).collect();
+
+    let mut walker = Walker {
+        checks: *checks,
+        functions: &functions,
+        owner: None,
+        scopes: Vec::new(),
+        referenced: HashSet::new(),
+        diagnostics: Vec::new(),
+    };
+    walker.visit_program(program);
+
+    let Walker { referenced, mut diagnostics, .. } = walker;
+
+    let unused = program.functions.iter().filter(|function| {
+        !function.public
+            && function.ident != "main"
+            && !function.ident.starts_with('_')
+            && !referenced.contains(function.ident.as_str())
+    });
+    for function in unused {
+        let message = format!("Function `{}` is never used", function.ident);
+        let severity = checks.within(function).unused_functions;
+        diagnostics.extend(severity.report(message, "unused_functions", function.ident_span.clone()));
+    }
+
+    diagnostics.sort_by_key(|diagnostic| span(diagnostic).start);
+    diagnostics
+}
+
+/// What a warning or denied check is about
+fn span(diagnostic: &CompileError) -> &Span {
+    match diagnostic {
+        CompileError::Warning(_, span) | CompileError::SemanticError(_, span) => span,
+        _ => unreachable!("checks only report warnings and semantic errors"),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Variable,
+    Parameter,
+}
+
+/// A variable or parameter in scope
+struct Variable {
+    ident: String,
+    kind: Kind,
+    /// Its declaration
+    span: Span,
+    read: bool,
+    assigned: bool,
+}
+
+struct Walker<'a> {
+    /// Severities within the function being walked
+    checks: Checks,
+    /// Every function of the program
+    functions: &'a HashSet<&'a str>,
+    /// Function being walked
+    owner: Option<&'a str>,
+    /// Innermost last, in the order they were declared
+    scopes: Vec<Vec<Variable>>,
+    /// Functions referred to by another function
+    referenced: HashSet<String>,
+    diagnostics: Vec<CompileError>,
+}
+
+impl<'a> Walker<'a> {
+    fn push_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    /// Leave a scope, reporting what was declared in it and never read
+    fn pop_scope(&mut self) {
+        let scope = self.scopes.pop().expect("scope to pop");
+        for variable in scope {
+            self.report_unused(variable);
+        }
+    }
+
+    fn declare(&mut self, ident: &str, kind: Kind, span: &Span) {
+        let scope = self.scopes.last_mut().expect("scope to declare in");
+
+        // the variable it replaces can't be used from here on
+        let replaced = scope.iter().position(|variable| variable.ident == ident).map(|i| scope.remove(i));
+
+        scope.push(Variable { ident: ident.to_string(), kind, span: span.clone(), read: false, assigned: false });
+
+        if let Some(replaced) = replaced {
+            self.report_unused(replaced);
+        }
+    }
+
+    fn report_unused(&mut self, variable: Variable) {
+        if variable.read || variable.ident.starts_with('_') {
+            return;
+        }
+
+        let (severity, id, message) = match variable.kind {
+            Kind::Parameter => (
+                self.checks.unused_parameters,
+                "unused_parameters",
+                format!("Parameter `{}` is never used", variable.ident),
+            ),
+            Kind::Variable if variable.assigned => (
+                self.checks.unused_variables,
+                "unused_variables",
+                format!("Variable `{}` is assigned to, but never used", variable.ident),
+            ),
+            Kind::Variable => (
+                self.checks.unused_variables,
+                "unused_variables",
+                format!("Variable `{}` is never used", variable.ident),
+            ),
+        };
+
+        self.diagnostics.extend(severity.report(message, id, variable.span));
+    }
+
+    /// The innermost variable called `ident`, if it's a local one
+    fn variable(&mut self, ident: &str) -> Option<&mut Variable> {
+        self.scopes.iter_mut().rev().flat_map(|scope| scope.iter_mut()).find(|variable| variable.ident == ident)
+    }
+
+    fn read(&mut self, ident: &str) {
+        if let Some(variable) = self.variable(ident) {
+            variable.read = true;
+        } else if self.functions.contains(ident) && self.owner != Some(ident) {
+            self.referenced.insert(ident.to_string());
+        }
+    }
+
+    fn assign(&mut self, ident: &str) {
+        if let Some(variable) = self.variable(ident) {
+            variable.assigned = true;
+        }
+    }
+
+    /// An expression whose value is thrown away: assigning to a variable
+    /// there doesn't read it, even with `+=` or `++`
+    fn visit_effect(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Assign { left, right, .. } => match left.as_ref() {
+                Expression::Identifier { ident, .. } => {
+                    self.visit_expression(right);
+                    self.assign(ident);
+                }
+                _ => self.visit_expression(expression),
+            },
+            Expression::Postfix { left: target, .. } | Expression::Prefix { right: target, .. } => match target.as_ref() {
+                Expression::Identifier { ident, .. } => self.assign(ident),
+                _ => self.visit_expression(expression),
+            },
+            _ => self.visit_expression(expression),
+        }
+    }
+
+    /// Declare the parameters of a function, in a scope of their own
+    fn declare_parameters(&mut self, params: &Parameters) {
+        self.push_scope();
+        for param in &params.params {
+            self.declare(&param.ident, Kind::Parameter, &param.span);
+        }
+    }
+}
+
+impl<'a> Visitor for Walker<'a> {
+    fn visit_function(&mut self, function: &Function) {
+        let within = self.checks.within(function);
+        let outer = std::mem::replace(&mut self.checks, within);
+        self.owner = self.functions.get(function.ident.as_str()).copied();
+
+        self.declare_parameters(&function.params);
+        self.visit_block(&function.body);
+        self.pop_scope();
+
+        self.owner = None;
+        self.checks = outer;
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        if let Some(last) = block.statements.iter().position(Statement::diverges) {
+            let unreachable = &block.statements[last + 1..];
+
+            if let (Some(first), Some(end)) = (unreachable.first(), unreachable.last()) {
+                let span = Span { start: first.span().start, end: end.span().end };
+                let diagnostic = self.checks.unreachable_code.report("Unreachable code".to_string(), "unreachable_code", span);
+                self.diagnostics.extend(diagnostic);
+            }
+        }
+
+        self.push_scope();
+        walk_block(self, block);
+        self.pop_scope();
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { ident, value, span, .. } => {
+                self.visit_expression(value);
+                self.declare(ident, Kind::Variable, span);
+            }
+            Statement::Assign { ident, value, .. } => {
+                self.visit_expression(value);
+                self.assign(ident);
+            }
+            Statement::Expression { expression, .. } => self.visit_effect(expression),
+            // the variable of the loop is only in scope within it
+            Statement::For { init, condition, increment, body, .. } => {
+                self.push_scope();
+                self.visit_statement(init);
+                self.visit_expression(condition);
+                self.visit_block(body);
+                self.visit_statement(increment);
+                self.pop_scope();
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identifier { ident, .. } => self.read(ident),
+            Expression::Function { params, body, .. } => {
+                self.declare_parameters(params);
+                self.visit_block(body);
+                self.pop_scope();
+            }
+            _ => walk_expression(self, expression),
+        }
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        self.push_scope();
+        if let Pattern::Variant { bindings, span, .. } = &arm.pattern {
+            for binding in bindings.iter().filter(|binding| *binding != "_") {
+                self.declare(binding, Kind::Variable, span);
+            }
+        }
+
+        walk_match_arm(self, arm);
+        self.pop_scope();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    /// What the checks find in `source`, as `line:column: message`, with
+    /// errors marked
+    fn check_source(source: &str, checks: Checks) -> Vec<String> {
+        let program = Parser::new(lex(source.to_string()).unwrap()).parse().unwrap();
+
+        check(&program, &checks).iter()
+            .map(|diagnostic| {
+                let (line, column) = span(diagnostic).location(source);
+                match diagnostic {
+                    CompileError::Warning(message, _) => format!("{}:{}: {}", line, column, message),
+                    CompileError::SemanticError(message, _) => format!("{}:{}: error: {}", line, column, message),
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_unused() {
+        let found = check_source("\
+enum Shape { Circle(float), Rect(float, float) }
+func area(s: Shape, scale: float): float {
+    let unused = 1;
+    let count = 0;
+    count += 1;
+    let x = 2;
+    { let x = 3; x++; }
+    for (let i = 0; i < x; i++) {}
+    return match (s) { Circle(r) => 3.0 * r * r, Rect(w, h) => w * w };
+}
+func apply(f: func(int): int): int { return f(1); }
+func main(): int {
+    let total = apply(func (n: int): int { return 1; });
+    let _ignored = 2;
+    total = total + 1;
+    return total;
+}", Checks::default());
+
+        assert_eq!(found, [
+            "2:21: Parameter `scale` is never used [unused_parameters]",
+            "3:5: Variable `unused` is never used [unused_variables]",
+            "4:5: Variable `count` is assigned to, but never used [unused_variables]",
+            "7:7: Variable `x` is assigned to, but never used [unused_variables]",
+            "9:50: Variable `h` is never used [unused_variables]",
+            "13:29: Parameter `n` is never used [unused_parameters]",
+        ]);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let found = check_source("\
+func f(x: int): int {
+    let y = x;
+    let y = 2;
+    let z = 1;
+    let z = z + 1;
+    return y + z;
+}", Checks::default());
+
+        // the first `y` is never read before the second replaces it
+        assert_eq!(found, ["2:5: Variable `y` is never used [unused_variables]"]);
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let found = check_source("\
+func f(x: int): int {
+    if (x > 0) { return 1; } else { return 2; }
+    x = 3;
+    return x;
+}
+func g(): int {
+    while (true) {}
+    return 1;
+}
+func h(x: int): int {
+    do { x++; } until (false)
+    return x;
+}
+func k(x: int): int {
+    if (x > 0) { return 1; }
+    return 2;
+}", Checks::default());
+
+        assert_eq!(found, ["3:5: Unreachable code [unreachable_code]", "8:5: Unreachable code [unreachable_code]", "12:5: Unreachable code [unreachable_code]"]);
+    }
+
+    #[test]
+    fn test_severity() {
+        let source = "\
+func helper(): int { return 1; }
+func unused(): int { return helper(); }
+func recursive(n: int): int { return recursive(n); }
+pub func exported(): int { return 0; }
+func main(): int { let a = 1; return 0; }
+@allow(unused_parameters) @deny(unused_variables)
+func callback(x: int): int { let b = 2; return 0; }";
+
+        assert_eq!(check_source(source, Checks::default()), [
+            "5:20: Variable `a` is never used [unused_variables]",
+            "7:30: error: Variable `b` is never used [unused_variables]",
+        ]);
+        assert_eq!(check_source(source, Checks::all(Severity::Allow)), ["7:30: error: Variable `b` is never used [unused_variables]"]);
+
+        // a function that only calls itself isn't used
+        assert_eq!(check_source(source, Checks::all(Severity::Deny)), [
+            "2:6: error: Function `unused` is never used [unused_functions]",
+            "3:6: error: Function `recursive` is never used [unused_functions]",
+            "5:20: error: Variable `a` is never used [unused_variables]",
+            "7:6: error: Function `callback` is never used [unused_functions]",
+            "7:30: error: Variable `b` is never used [unused_variables]",
+        ]);
+    }
+}
@@ -0,0 +1,55 @@
+//! What the compiler made of each function, for reviewing codegen
+//!
+//! With [`CompilerOptions::dump_code`](super::CompilerOptions::dump_code) on,
+//! the compiler keeps the Cranelift IR of every function it compiles, as the
+//! translator built it and as Cranelift optimized it, along with the machine
+//! code it became. They're looked up with
+//! [`Compiler::dumps`](super::Compiler::dumps) or
+//! [`Compiler::dump`](super::Compiler::dump), and printed in one go by
+//! displaying a `FunctionDump`.
+
+use std::fmt;
+
+/// The code compiled for one function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionDump {
+    /// Name of the function, qualified like `get_function` expects
+    /// Anonymous functions are named after the function they're written in
+    /// and where, i.e. `main::<lambda at 3:13>`
+    pub ident: String,
+
+    /// Kennedy IR the function was compiled from, if it was compiled
+    /// through the IR
+    pub ir: Option<String>,
+
+    /// Cranelift IR as the translator built it
+    pub clif: String,
+
+    /// Cranelift IR once optimized and legalized for the target
+    pub optimized_clif: String,
+
+    /// Machine instructions, after register allocation
+    pub disassembly: String,
+
+    /// Size of the machine code in bytes
+    pub code_size: u32,
+}
+
+impl fmt::Display for FunctionDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ir) = &self.ir {
+            writeln!(f, ";; {}: kennedy ir", self.ident)?;
+            writeln!(f, "{}", ir.trim_end())?;
+            writeln!(f)?;
+        }
+
+        writeln!(f, ";; {}: clif", self.ident)?;
+        writeln!(f, "{}", self.clif.trim_end())?;
+        writeln!(f)?;
+        writeln!(f, ";; {}: optimized clif", self.ident)?;
+        writeln!(f, "{}", self.optimized_clif.trim_end())?;
+        writeln!(f)?;
+        writeln!(f, ";; {}: machine code, {} bytes", self.ident, self.code_size)?;
+        write!(f, "{}", self.disassembly.trim_end())
+    }
+}
@@ -0,0 +1,85 @@
+//! Rust functions Kennedy code can call
+//!
+//! An application embedding Kennedy registers them with
+//! [`Compiler::register_host_fn`](super::Compiler::register_host_fn), as
+//! `extern "C"` function pointers. The Rust types of the function are checked
+//! against the Kennedy signature it is registered with, so a mismatch is a
+//! compile error rather than a crash.
+//!
+//! Arguments are handed over the way Kennedy functions get them: a string
+//! argument is owned by the host function, which must release it with
+//! [`kennedy_string_release`](super::runtime::kennedy_string_release). A
+//! returned string is owned by the caller.
+
+use crate::ast::Type;
+
+use super::runtime::KennedyString;
+
+/// A Rust type that is passed to and from Kennedy code as a Kennedy type
+pub trait HostType {
+    fn kennedy_type() -> Type;
+}
+
+macro_rules! host_type {
+    ($($rust:ty => $kennedy:expr),* $(,)?) => {
+        $(
+            impl HostType for $rust {
+                fn kennedy_type() -> Type {
+                    $kennedy
+                }
+            }
+        )*
+    };
+}
+
+host_type! {
+    i8 => Type::I8,
+    i16 => Type::I16,
+    i32 => Type::I32,
+    i64 => Type::I64,
+    u8 => Type::U8,
+    u16 => Type::U16,
+    u32 => Type::U32,
+    u64 => Type::U64,
+    f32 => Type::Float,
+    f64 => Type::F64,
+    bool => Type::Bool,
+    *const KennedyString => Type::String,
+    // only as a return type, for functions returning nothing
+    () => Type::Null,
+}
+
+/// An `extern "C"` function pointer Kennedy code can call
+/// A function item must be cast to one first, i.e.
+/// `log as extern "C" fn(i64)`
+pub trait HostFunction {
+    fn address(self) -> *const u8;
+    fn params() -> Vec<Type>;
+    fn return_type() -> Type;
+}
+
+macro_rules! host_function {
+    ($($param:ident),*) => {
+        impl<R: HostType, $($param: HostType),*> HostFunction for extern "C" fn($($param),*) -> R {
+            fn address(self) -> *const u8 {
+                self as *const u8
+            }
+
+            fn params() -> Vec<Type> {
+                vec![$($param::kennedy_type()),*]
+            }
+
+            fn return_type() -> Type {
+                R::kennedy_type()
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+host_function!(A, B, C, D, E);
+host_function!(A, B, C, D, E, F);
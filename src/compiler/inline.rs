@@ -0,0 +1,100 @@
+//! Which calls are compiled by copying the callee's body into the caller
+//!
+//! Cranelift compiles each function on its own, so a call to even the
+//! smallest helper costs a call. The translator can instead translate the
+//! body of the function called in place, with its parameters bound to the
+//! arguments and its `return`s jumping past the copy.
+//!
+//! `@inline` and `@noinline` decide for the functions they're written on;
+//! the rest are inlined if their body is small enough. A function is never
+//! inlined into itself, however indirectly it calls itself, nor more than
+//! `MAX_INLINE_DEPTH` calls deep.
+
+use std::collections::HashMap;
+
+use crate::ast::visit::{walk_expression, walk_statement};
+use crate::ast::{self, Expression, Statement, Visitor};
+
+/// Largest body inlined by `kennedy build` when optimizing, see
+/// `CompilerOptions::inline_size`
+pub const DEFAULT_INLINE_SIZE: usize = 20;
+
+/// How many inlined calls deep another is inlined
+pub const MAX_INLINE_DEPTH: usize = 4;
+
+/// The functions of a program calls to which are inlined, by name
+pub fn inlinable<'a>(
+    functions: impl IntoIterator<Item = &'a ast::Function>,
+    size_limit: usize,
+) -> HashMap<String, &'a ast::Function> {
+    functions.into_iter()
+        .filter(|function| should_inline(function, size_limit))
+        .map(|function| (function.ident.clone(), function))
+        .collect()
+}
+
+/// Whether calls to `function` are inlined
+fn should_inline(function: &ast::Function, size_limit: usize) -> bool {
+    if function.attribute("noinline").is_some() {
+        false
+    } else if function.attribute("inline").is_some() {
+        true
+    } else {
+        size(&function.body) <= size_limit
+    }
+}
+
+/// Number of statements and expressions in a block, a rough measure of how
+/// much code it compiles to
+pub fn size(block: &ast::Block) -> usize {
+    let mut counter = SizeCounter { size: 0 };
+    counter.visit_block(block);
+    counter.size
+}
+
+struct SizeCounter {
+    size: usize,
+}
+
+impl Visitor for SizeCounter {
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.size += 1;
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.size += 1;
+        walk_expression(self, expression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    fn functions(source: &str) -> Vec<ast::Function> {
+        Parser::new(lex(source.to_string()).unwrap()).parse().unwrap().functions
+    }
+
+    #[test]
+    fn test_inlinable() {
+        let functions = functions("\
+func sq(x: int): int { return x * x; }
+@noinline func cube(x: int): int { return x * x * x; }
+@inline func big(x: int): int { let a = x + 1; let b = a * a; let c = b * b - a; return a + b + c; }
+func bigger(x: int): int { let a = x + 1; let b = a * a; let c = b * b - a; let d = c; return a + b + d; }");
+
+        assert_eq!(size(&functions[0].body), 4);
+        assert_eq!(size(&functions[3].body), 22);
+
+        let mut inlined: Vec<String> = inlinable(&functions, DEFAULT_INLINE_SIZE).into_keys().collect();
+        inlined.sort();
+        assert_eq!(inlined, ["big", "sq"]);
+
+        // only functions marked `@inline`
+        let inlined: Vec<String> = inlinable(&functions, 0).into_keys().collect();
+        assert_eq!(inlined, ["big"]);
+    }
+}
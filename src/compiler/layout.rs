@@ -0,0 +1,139 @@
+//! Memory layout of structs
+//!
+//! Fields are laid out the way a C compiler would lay out the same struct:
+//! in declaration order, each at the next offset aligned to its size, with
+//! the total size padded to the largest alignment.
+//!
+//! Each variant of an enum is laid out as a struct whose first field is the
+//! `u32` tag, followed by the values the variant carries. Closures start
+//! with a pointer to their code, followed by the values they captured.
+
+use cranelift::prelude::Type;
+
+use crate::ast;
+use crate::error::CompileResult;
+
+use super::translator::cranelift_type;
+
+/// Name of the field holding an enum value's variant
+pub const TAG: &str = "tag";
+
+/// Where one field lives within its struct
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldLayout {
+    pub ident: String,
+    pub field_type: ast::Type,
+    /// Byte offset from the start of the fields
+    pub offset: u32,
+}
+
+/// Layout of the fields of a struct
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructLayout {
+    pub fields: Vec<FieldLayout>,
+    pub size: u32,
+    pub align: u32,
+}
+
+impl StructLayout {
+    pub fn of(declaration: &ast::Struct, pointer_type: Type) -> CompileResult<Self> {
+        let fields = declaration.fields.iter()
+            .map(|field| (field.ident.clone(), field.field_type.clone()));
+
+        Self::from_fields(fields, 0, pointer_type)
+    }
+
+    /// Layout of a variant of an enum: the tag, then the values it carries
+    /// as fields named `0`, `1`, ...
+    pub fn of_variant(variant: &ast::Variant, pointer_type: Type) -> CompileResult<Self> {
+        let fields = variant.fields.iter().enumerate()
+            .map(|(i, field_type)| (i.to_string(), field_type.clone()));
+
+        Self::from_fields(std::iter::once((TAG.to_string(), ast::Type::U32)).chain(fields), 0, pointer_type)
+    }
+
+    /// Layout of a closure: the values it captured, named after the
+    /// variables, after the pointer to its code at offset 0
+    pub fn of_closure(captures: &[(String, ast::Type)], pointer_type: Type) -> CompileResult<Self> {
+        Self::from_fields(captures.iter().cloned(), pointer_type.bytes(), pointer_type)
+    }
+
+    /// Lay out fields from `start` onwards, after something aligned to
+    /// `start` (if anything)
+    fn from_fields(
+        declared: impl Iterator<Item = (String, ast::Type)>,
+        start: u32,
+        pointer_type: Type,
+    ) -> CompileResult<Self> {
+        let mut fields = Vec::new();
+        let mut size: u32 = start;
+        let mut align = start.max(1);
+
+        for (ident, field_type) in declared {
+            // every field type is a scalar or a pointer, aligned to its size
+            let field_size = cranelift_type(&field_type, pointer_type)?.bytes();
+
+            let offset = size.next_multiple_of(field_size);
+            size = offset + field_size;
+            align = align.max(field_size);
+
+            fields.push(FieldLayout { ident, field_type, offset });
+        }
+
+        Ok(Self {
+            fields,
+            size: size.next_multiple_of(align),
+            align,
+        })
+    }
+
+    /// The field called `ident`
+    pub fn field(&self, ident: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.ident == ident)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift::prelude::types;
+
+    #[test]
+    fn test_c_layout() {
+        #[repr(C)]
+        struct Mixed {
+            a: u8,
+            b: f32,
+            c: i16,
+            d: *const u8,
+            e: bool,
+        }
+
+        let declaration = ast::Struct {
+            ident: "Mixed".to_string(),
+            ident_span: ast::Span::default(),
+            public: false,
+            fields: vec![
+                ast::Field { ident: "a".to_string(), field_type: ast::Type::U8, span: ast::Span::default() },
+                ast::Field { ident: "b".to_string(), field_type: ast::Type::Float, span: ast::Span::default() },
+                ast::Field { ident: "c".to_string(), field_type: ast::Type::I16, span: ast::Span::default() },
+                ast::Field { ident: "d".to_string(), field_type: ast::Type::String, span: ast::Span::default() },
+                ast::Field { ident: "e".to_string(), field_type: ast::Type::Bool, span: ast::Span::default() },
+            ],
+            span: ast::Span::default(),
+        };
+
+        let layout = StructLayout::of(&declaration, types::I64).unwrap();
+        let offsets: Vec<u32> = layout.fields.iter().map(|field| field.offset).collect();
+
+        assert_eq!(offsets, vec![
+            std::mem::offset_of!(Mixed, a) as u32,
+            std::mem::offset_of!(Mixed, b) as u32,
+            std::mem::offset_of!(Mixed, c) as u32,
+            std::mem::offset_of!(Mixed, d) as u32,
+            std::mem::offset_of!(Mixed, e) as u32,
+        ]);
+        assert_eq!(layout.size as usize, std::mem::size_of::<Mixed>());
+        assert_eq!(layout.align as usize, std::mem::align_of::<Mixed>());
+    }
+}
@@ -0,0 +1,128 @@
+//! Lowering of Kennedy IR into Cranelift IR
+//!
+//! Each IR block becomes a Cranelift block with the same parameters, and
+//! each instruction the code the translator generates for the expression
+//! it came from, sharing the translator's arithmetic so overflow and
+//! division by zero trap the same way.
+
+use cranelift::codegen::ir::TrapCode;
+use cranelift::prelude::*;
+use cranelift_module::Module;
+
+use crate::ast;
+use crate::error::{CompileError, CompileResult};
+use crate::ir::{self, InstructionKind, Terminator};
+
+use super::translator::{cranelift_type, FunctionTranslator};
+
+impl<M: Module> FunctionTranslator<'_, M> {
+    /// Translate the IR of a function, consuming the translator
+    pub fn translate_ir(mut self, function: &ir::Function) -> CompileResult<()> {
+        self.owner = function.ident.clone();
+        self.return_type = function.return_type.clone();
+
+        let blocks: Vec<Block> = function.blocks.iter().map(|_| self.builder.create_block()).collect();
+        let mut values: Vec<Option<Value>> = vec![None; function.value_count()];
+
+        self.builder.append_block_params_for_function_params(blocks[0]);
+        for (i, data) in function.blocks.iter().enumerate().skip(1) {
+            for param in &data.params {
+                let ty = cranelift_type(function.value_type(*param), self.pointer_type)?;
+                self.builder.append_block_param(blocks[i], ty);
+            }
+        }
+
+        for (i, data) in function.blocks.iter().enumerate() {
+            self.builder.switch_to_block(blocks[i]);
+
+            let params = self.builder.block_params(blocks[i]).to_vec();
+            for (param, value) in data.params.iter().zip(params) {
+                values[param.index()] = Some(value);
+            }
+
+            for instruction in &data.instructions {
+                let value = |value: &ir::Value| lowered(&values, *value);
+
+                let result = match &instruction.kind {
+                    InstructionKind::Integer(integer) => {
+                        self.integer_constant(function.value_type(instruction.result), *integer)?
+                    }
+                    InstructionKind::Float(float) => match function.value_type(instruction.result) {
+                        ast::Type::F64 => self.builder.ins().f64const(*float),
+                        _ => self.builder.ins().f32const(*float as f32),
+                    },
+                    InstructionKind::Bool(boolean) => self.builder.ins().iconst(types::I8, *boolean as i64),
+                    InstructionKind::Null => self.builder.ins().iconst(types::I8, 0),
+                    InstructionKind::Binary(operator, lhs, rhs) => {
+                        let ty = function.value_type(*lhs).clone();
+                        self.translate_binary(operator, value(lhs)?, value(rhs)?, &ty, &instruction.span)?.0
+                    }
+                    InstructionKind::Negate(operand) if function.value_type(*operand).is_float() => {
+                        self.builder.ins().fneg(value(operand)?)
+                    }
+                    InstructionKind::Negate(operand) => {
+                        let ty = function.value_type(*operand).clone();
+                        self.negate(value(operand)?, &ty, &instruction.span)?
+                    }
+                    InstructionKind::Not(operand) => self.builder.ins().icmp_imm(IntCC::Equal, value(operand)?, 0),
+                    InstructionKind::Cast(operand) => {
+                        let from = function.value_type(*operand).clone();
+                        let to = function.value_type(instruction.result).clone();
+                        self.translate_cast(value(operand)?, &from, &to)?
+                    }
+                    InstructionKind::Call(ident, args) => {
+                        let args = args.iter().map(value).collect::<CompileResult<Vec<Value>>>()?;
+                        let declared = self.functions.get(ident).cloned().ok_or_else(|| {
+                            CompileError::SemanticError(format!("Call to undefined function `{}`", ident), instruction.span.clone())
+                        })?;
+
+                        self.translate_direct_call(ident, &declared, &args)?.0
+                    }
+                };
+
+                values[instruction.result.index()] = Some(result);
+            }
+
+            let edge = |edge: &ir::Edge| -> CompileResult<(Block, Vec<Value>)> {
+                let args = edge.args.iter().map(|arg| lowered(&values, *arg)).collect::<CompileResult<_>>()?;
+                Ok((blocks[edge.block.index()], args))
+            };
+
+            match &data.terminator {
+                Terminator::Jump(target) => {
+                    let (block, args) = edge(target)?;
+                    self.builder.ins().jump(block, &args);
+                }
+                Terminator::Branch { condition, then, otherwise } => {
+                    let condition = lowered(&values, *condition)?;
+                    let (then_block, then_args) = edge(then)?;
+                    let (else_block, else_args) = edge(otherwise)?;
+                    self.builder.ins().brif(condition, then_block, &then_args, else_block, &else_args);
+                }
+                Terminator::Return(Some(value)) => {
+                    let value = lowered(&values, *value)?;
+                    self.builder.ins().return_(&[value]);
+                }
+                Terminator::Return(None) => {
+                    self.builder.ins().return_(&[]);
+                }
+                Terminator::Unreachable => {
+                    self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+                }
+            }
+        }
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+
+        Ok(())
+    }
+}
+
+/// The Cranelift value an IR value was lowered to, which must have been
+/// already, as blocks are lowered after those that dominate them
+fn lowered(values: &[Option<Value>], value: ir::Value) -> CompileResult<Value> {
+    values[value.index()].ok_or_else(|| {
+        CompileError::CompileError(format!("{} is used before it is defined", value))
+    })
+}
@@ -1,41 +1,137 @@
 pub mod symbol_table;
+pub mod runtime;
+pub mod layout;
+pub mod host;
+pub mod options;
+pub mod dump;
+pub mod inline;
+mod lower;
+mod translator;
+mod unwind;
+mod update;
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
 
 use cranelift::codegen::{
-    ir::{
-        types::{F32, I32, I64},
-        AbiParam, // function parameter
-        ArgumentPurpose, // function parameter
-        ExternalName, // function name
-        Function, // function
-        InstBuilder, // instruction builder
-        Signature, // function signature
-        SourceLoc, UserFuncName, // source location
-    },
-
-    isa::TargetIsa, // target ISA
-    settings, // settings
-    isa::CallConv, Context, // calling convention (for function signature)
+    ir::AbiParam, // function parameter
+    Context, // codegen context
 };
 
 use cranelift::prelude::*;
-use cranelift_module::{DataContext, Module, Linkage};
+use cranelift_module::{DataContext, DataId, FuncId, Module, Linkage};
 
 use crate::error::{
     CompileError,
     CompileResult,
+    Span,
 };
 
 use cranelift_jit::{JITBuilder, JITModule};
-
-use target_lexicon::Triple; // target triple (for target ISA)
+use cranelift_object::{ObjectBuilder, ObjectModule};
 
 use crate::ast;
-use crate::parser::Parser;
-use crate::lexer::lex;
+use crate::checks::{self, Checks};
+use crate::lint::{self, Lints};
+use crate::constant::{Constant, Evaluator};
+use crate::modules::{self, SourceMap};
+use crate::ir;
+use crate::optimizer::{self, Passes, Report};
+use crate::builtins::Builtin;
+use crate::type_checking::{Lambda, TypeChecker};
+
+use dump::FunctionDump;
+use host::HostFunction;
+use layout::StructLayout;
+use unwind::UnwindRegistry;
+use translator::{
+    element_kind, signature, string_literal, DeclaredEnum, DeclaredFunction, DeclaredGlobal, DeclaredLambda,
+    DeclaredStruct, DeclaredVariant, FunctionTranslator, FunctionValue,
+};
+
+pub use options::{CompilerOptions, OptLevel};
+pub use translator::OverflowMode;
+pub use crate::type_checking::FunctionSignature;
+
+pub type Compiled = CompileResult<*const u8>;
+
+/// Where compiled code goes: into memory to be run straight away
+/// (`JITModule`), or into an object file to be linked (`ObjectModule`)
+pub trait Backend: Module {
+    /// Make everything defined so far ready for use
+    fn finish_definitions(&mut self) -> CompileResult<()>;
+
+    /// Whether a symbol defined outside the module can be found
+    fn resolves(&self, symbol: &str) -> bool;
+
+    /// Tell the unwinder about the functions just finished
+    fn register_unwind_info(&self, registry: &mut UnwindRegistry) -> CompileResult<()>;
+
+    /// Let a function defined before be defined again in place, callers
+    /// switching to the new definition once it's finished
+    fn prepare_redefine(&mut self, id: FuncId) -> CompileResult<()>;
+}
+
+impl Backend for JITModule {
+    /// Resolve relocations, making the functions callable
+    fn finish_definitions(&mut self) -> CompileResult<()> {
+        self.finalize_definitions()
+            .map_err(|e| CompileError::CompileError(e.to_string()))
+    }
+
+    /// Looks in the host process, the C library it's linked with and the
+    /// C maths library, as the JIT would; it panics on symbols it can't find
+    #[cfg(unix)]
+    fn resolves(&self, symbol: &str) -> bool {
+        let Ok(symbol) = std::ffi::CString::new(symbol) else {
+            return false;
+        };
+
+        unsafe { !libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()).is_null() }
+    }
+
+    #[cfg(not(unix))]
+    fn resolves(&self, _symbol: &str) -> bool {
+        true
+    }
+
+    fn register_unwind_info(&self, registry: &mut UnwindRegistry) -> CompileResult<()> {
+        registry.register(self.isa(), |id| self.get_finalized_function(id))
+    }
+
+    /// The module must have been made with hotswap support
+    fn prepare_redefine(&mut self, id: FuncId) -> CompileResult<()> {
+        self.prepare_for_function_redefine(id)
+            .map_err(|e| CompileError::CompileError(e.to_string()))
+    }
+}
 
-pub type Compiled = Result<*const u8, String>;
+impl Backend for ObjectModule {
+    /// Relocations are left to the linker
+    fn finish_definitions(&mut self) -> CompileResult<()> {
+        Ok(())
+    }
+
+    /// Left to the linker too
+    fn resolves(&self, _symbol: &str) -> bool {
+        true
+    }
+
+    /// Nothing is run, so nothing is unwound
+    fn register_unwind_info(&self, registry: &mut UnwindRegistry) -> CompileResult<()> {
+        registry.clear();
+        Ok(())
+    }
+
+    fn prepare_redefine(&mut self, _id: FuncId) -> CompileResult<()> {
+        Err(CompileError::CompileError("Functions in object files cannot be redefined".to_string()))
+    }
+}
 
-pub struct Compiler {
+pub struct Compiler<M: Backend = JITModule> {
     /// Basic function builder context. This is the main context that we use to
     /// create Cranelift IR.
     builder_context: FunctionBuilderContext,
@@ -47,120 +143,2035 @@ pub struct Compiler {
     /// Manages the data objects (global variables) in the module.
     data_ctx: DataContext,
 
+    /// Data objects holding string literals, so each is only emitted once
+    string_literals: HashMap<String, DataId>,
+
     /// The module being compiled
-    /// Manages all the JIT'd functions and data objects
-    /// Interface for adding/removing functions, and looking up
-    /// functions at runtime
-    module: JITModule,
+    /// Manages all the compiled functions and data objects
+    module: M,
+
+    /// What integer arithmetic does when it over/underflows
+    overflow_mode: OverflowMode,
+
+    /// Every function declared in the module, by name
+    functions: HashMap<String, DeclaredFunction>,
+
+    /// Every struct declared in the module, by name
+    structs: HashMap<String, DeclaredStruct>,
+
+    /// Every enum declared in the module, by name
+    enums: HashMap<String, DeclaredEnum>,
+
+    /// Every global declared in the module, by name
+    globals: HashMap<String, DeclaredGlobal>,
+
+    /// Generic functions of the source being compiled, by name
+    /// Their instances are compiled as ordinary functions
+    generics: HashMap<String, FunctionSignature>,
+
+    /// Anonymous functions of the source being compiled, by the function
+    /// they're written in and where
+    lambdas: HashMap<(String, Span), DeclaredLambda>,
+
+    /// Named functions used as values, by name
+    function_values: HashMap<String, FunctionValue>,
+
+    /// Runtime functions compiled code can call, by name
+    runtime: HashMap<&'static str, FuncId>,
+
+    /// Signatures of the host functions registered so far, which programs
+    /// can call like their own functions
+    host_functions: HashMap<String, FunctionSignature>,
+
+    /// Addresses of the host functions, where the JIT looks for symbols
+    /// it doesn't know
+    host_symbols: Rc<RefCell<HashMap<String, *const u8>>>,
+
+    /// Whether to keep what each function compiled to
+    dump_code: bool,
+
+    /// What each function compiled to, in the order they were compiled
+    dumps: Vec<FunctionDump>,
+
+    /// Passes to simplify programs with before compiling them
+    passes: Passes,
+
+    /// What the passes did to every program compiled so far
+    report: Report,
+
+    /// Largest body of a function calls to which are inlined
+    inline_size: usize,
+
+    /// Whether to compile functions through the Kennedy IR
+    ir: bool,
+
+    /// Severity of each check programs are put through
+    checks: Checks,
+
+    /// Level of each lint programs are put through
+    lints: Lints,
+
+    /// What the checks and lints warned about in every program compiled
+    /// so far
+    warnings: Vec<CompileError>,
+
+    /// Source of every function and global compiled so far, as printed,
+    /// to tell whether a new version has changed
+    compiled_source: HashMap<String, String>,
+
+    /// Whether functions are defined again in place when they change,
+    /// rather than alongside the old version
+    hotswap: bool,
+
+    /// How to unwind every function defined so far, so traps can be caught
+    unwind: UnwindRegistry,
 }
 
 impl Default for Compiler {
-    /// Create a new compiler with the default settings
+    /// Create a new compiler with the default options, compiling into
+    /// memory
+    /// Panics if the host machine isn't supported; `Compiler::new` returns
+    /// an error instead
     fn default() -> Self {
-        // Flag builder will be used to create the settings
-        let mut flag_builder = settings::builder();
-        
-        // use_colocated_libcalls: use libcall functions that are colocated with the
-        // generated code. Meaning, the libcall functions are generated in the same
-        // object file as the generated code (default)
-        flag_builder.set("use_colocated_libcalls", "false").unwrap();
-        
-        // is_pic: generate position-independent code (default)
-        // Meaning, the generated code can be loaded at any address
-        flag_builder.set("is_pic", "false").unwrap();
-
-        // ISA builder will be used to create the target ISA
-        let isa_builder = cranelift_native::builder().unwrap_or_else(|msg| {
-            panic!("host machine is not supported: {}", msg);
-        });
+        Self::new(CompilerOptions::default()).unwrap_or_else(|e| panic!("{}", e))
+    }
+}
+
+impl Compiler<ObjectModule> {
+    /// Create a compiler writing a position-independent object file for
+    /// the host machine
+    /// Compiled code calls the runtime's `kennedy_*` functions, so the
+    /// object file must be linked with the runtime, which the
+    /// `kennedy-runtime` crate builds as `libkennedy_runtime.a` for that
+    pub fn object(name: &str) -> CompileResult<Self> {
+        Self::object_with_options(name, CompilerOptions::new().pic(true))
+    }
+
+    /// Create a compiler writing an object file for the target of `options`,
+    /// which may be another machine than this one
+    pub fn object_with_options(name: &str, options: CompilerOptions) -> CompileResult<Self> {
+        if options.hotswap {
+            return Err(CompileError::CompileError("Only code compiled into memory can be hot swapped".to_string()));
+        }
+
+        let builder = ObjectBuilder::new(options.isa()?, name, cranelift_module::default_libcall_names())
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
 
-        // Create the target ISA
-        let isa = isa_builder
-            .finish(settings::Flags::new(flag_builder))
-            .expect("failed to create target ISA");
+        Ok(Self::with_module(ObjectModule::new(builder), &options))
+    }
+
+    /// Finish the object file, returning its bytes
+    pub fn finish(self) -> CompileResult<Vec<u8>> {
+        self.module.finish().emit()
+            .map_err(|e| CompileError::CompileError(e.to_string()))
+    }
+}
+
+/// Make the C maths library visible to `dlsym`, so extern functions like
+/// `sqrt` resolve. Everything else in C's standard library is in libc,
+/// which the host is always linked with
+#[cfg(target_os = "linux")]
+fn load_libm() {
+    static LOAD: std::sync::Once = std::sync::Once::new();
+
+    // never closed, so compiled code can keep calling into it; with musl
+    // it's part of libc, and there's nothing to load
+    LOAD.call_once(|| unsafe {
+        libc::dlopen(c"libm.so.6".as_ptr(), libc::RTLD_NOW | libc::RTLD_GLOBAL);
+    });
+}
+
+/// Elsewhere the maths functions are in the C library itself
+#[cfg(not(target_os = "linux"))]
+fn load_libm() {}
+
+impl Compiler<JITModule> {
+    /// Create a compiler compiling into memory, with these options
+    pub fn new(options: CompilerOptions) -> CompileResult<Self> {
+        if !options.is_native() {
+            return Err(CompileError::CompileError(
+                "Only object files can be compiled for another machine".to_string(),
+            ));
+        }
+
+        // calls are swapped through the PLT, which Cranelift only makes for
+        // x86_64
+        if options.hotswap && !cfg!(target_arch = "x86_64") {
+            return Err(CompileError::CompileError("Hot swapping is only supported on x86_64".to_string()));
+        }
+
+        load_libm();
 
         // Create the JIT module
         // This is the main interface for adding/removing functions, and looking up
-        let builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
-        let module = JITModule::new(builder);
+        let mut builder = JITBuilder::with_isa(options.isa()?, cranelift_module::default_libcall_names());
+        builder.hotswap(options.hotswap);
+
+        // Make the runtime visible to compiled code
+        builder.symbols(runtime::functions().iter().map(|f| (f.name, f.address)));
+
+        // and the host functions, which are registered after the module is
+        // made
+        let host_symbols: Rc<RefCell<HashMap<String, *const u8>>> = Rc::default();
+        let symbols = host_symbols.clone();
+        builder.symbol_lookup_fn(Box::new(move |name| symbols.borrow().get(name).copied()));
+
+        Ok(Self { host_symbols, ..Self::with_module(JITModule::new(builder), &options) })
+    }
+
+    /// Let programs call a Rust function as `name`
+    /// The Rust function's types must be those of `signature`, which calls
+    /// are type checked against. See [`host`] for how values are passed
+    pub fn register_host_fn<F: HostFunction>(
+        &mut self,
+        name: &str,
+        function: F,
+        signature: FunctionSignature,
+    ) -> CompileResult<()> {
+        let error = |message: String| Err(CompileError::CompileError(message));
+
+        if Builtin::from_ident(name).is_some() {
+            return error(format!("`{}` is a built-in function and cannot be redefined", name));
+        }
+
+        if self.functions.contains_key(name) || self.host_functions.contains_key(name) {
+            return error(format!("Function `{}` is defined more than once", name));
+        }
+
+        if !signature.type_params.is_empty() {
+            return error(format!("Host function `{}` cannot be generic", name));
+        }
+
+        let params = F::params();
+        if params.len() != signature.params.len() {
+            return error(format!(
+                "Host function `{}` is declared with {} parameters, but the Rust function takes {}",
+                name, signature.params.len(), params.len(),
+            ));
+        }
+
+        for (i, (declared, actual)) in signature.params.iter().zip(&params).enumerate() {
+            if !declared.same_as(actual) || *actual == ast::Type::Null {
+                return error(format!(
+                    "Parameter {} of host function `{}` is declared {}, but the Rust function takes {}",
+                    i + 1, name, declared, actual,
+                ));
+            }
+        }
+
+        if !signature.return_type.same_as(&F::return_type()) {
+            return error(format!(
+                "Host function `{}` is declared to return {}, but the Rust function returns {}",
+                name, signature.return_type, F::return_type(),
+            ));
+        }
+
+        // position-independent code looks the address up as it's declared
+        self.host_symbols.borrow_mut().insert(name.to_string(), function.address());
+
+        let sig = self.c_signature(&signature)?;
+        let id = self.module.declare_function(name, Linkage::Import, &sig)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        self.functions.insert(name.to_string(), DeclaredFunction {
+            id,
+            signature: signature.clone(),
+            linkage: Linkage::Import,
+        });
+        self.host_functions.insert(name.to_string(), signature);
+
+        Ok(())
+    }
+
+    /// Address of a compiled function
+    /// Must be transmuted to the right `extern "C" fn` type to be called, or
+    /// `extern "C-unwind" fn` if it's called within `runtime::catch_traps`,
+    /// as a trap caught there unwinds out of it
+    pub fn get_function(&self, ident: &str) -> Compiled {
+        let function = self.functions.get(ident).ok_or_else(|| {
+            CompileError::CompileError(format!("No function named `{}`", ident))
+        })?;
+
+        if function.linkage == Linkage::Import {
+            return Err(CompileError::CompileError(format!("`{}` is an extern function", ident)));
+        }
+
+        Ok(self.module.get_finalized_function(function.id))
+    }
+
+    /// Address of the data object holding a global's value
+    pub fn get_global(&self, ident: &str) -> Compiled {
+        let global = self.globals.get(ident).ok_or_else(|| {
+            CompileError::CompileError(format!("No global named `{}`", ident))
+        })?;
+
+        Ok(self.module.get_finalized_data(global.id).0)
+    }
+}
+
+impl<M: Backend> Compiler<M> {
+    /// Create a compiler putting what it compiles into `module`
+    fn with_module(mut module: M, options: &CompilerOptions) -> Self {
+        // Import the runtime into the module
+        let runtime_functions = runtime::functions();
+        let pointer_type = module.target_config().pointer_type();
+        let abi_param = |abi_type: &runtime::AbiType| AbiParam::new(match abi_type {
+            runtime::AbiType::Value(ty) => *ty,
+            runtime::AbiType::Pointer => pointer_type,
+        });
+
+        let mut runtime = HashMap::new();
+        for function in &runtime_functions {
+            let mut sig = module.make_signature();
+            sig.params.extend(function.params.iter().map(abi_param));
+            sig.returns.extend(function.returns.iter().map(abi_param));
+
+            let id = module
+                .declare_function(function.name, Linkage::Import, &sig)
+                .expect("failed to declare runtime function");
+            runtime.insert(function.name, id);
+        }
 
         Self {
             builder_context: FunctionBuilderContext::new(),
             ctx: module.make_context(),
             data_ctx: DataContext::new(),
+            string_literals: HashMap::new(),
             module,
+            overflow_mode: options.overflow_mode,
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            globals: HashMap::new(),
+            generics: HashMap::new(),
+            lambdas: HashMap::new(),
+            function_values: HashMap::new(),
+            runtime,
+            host_functions: HashMap::new(),
+            host_symbols: Rc::default(),
+            dump_code: options.dump_code,
+            dumps: Vec::new(),
+            passes: options.passes,
+            report: Report::default(),
+            inline_size: options.inline_size,
+            ir: options.ir,
+            checks: options.checks,
+            lints: options.lints,
+            warnings: Vec::new(),
+            compiled_source: HashMap::new(),
+            hotswap: options.hotswap,
+            unwind: UnwindRegistry::default(),
         }
     }
-}
 
+    /// Choose what integer arithmetic does on over/underflow
+    /// Applies to functions compiled from now on
+    pub fn set_overflow_mode(&mut self, mode: OverflowMode) {
+        self.overflow_mode = mode;
+    }
 
-impl Compiler {
-    pub fn compile(&mut self, source: &str) -> Compiled {
-        let tokens = lex(source.to_string()).unwrap();
-        let mut parser = Parser::new(tokens);
-        let ast = parser.parse().unwrap();
+    /// Compile every function in `source`
+    /// Compiled functions can then be looked up with `get_function`
+    /// Modules it imports are looked for in the working directory
+    pub fn compile(&mut self, source: &str) -> CompileResult<()> {
+        self.compile_source(None, source, Self::compile_program)
+    }
 
-        for function in ast.functions {
-            self.compile_function(function)?;
-        };
+    /// Compile the program in the file at `path`, and every module it
+    /// imports
+    /// Functions of imported modules are qualified with the module, i.e.
+    /// `get_function("math::add")`
+    pub fn compile_file(&mut self, path: impl AsRef<Path>) -> CompileResult<()> {
+        let path = path.as_ref();
+        self.compile_source(Some(path), &read(path)?, Self::compile_program)
     }
 
-    /// Compile and return a function
-    fn compile_function(
+    /// Compile a new version of the program compiled so far, while it runs
+    /// Only the functions that have changed are compiled again, along with
+    /// those that depend on them. Globals keep their values unless their
+    /// type changes, and structs and enums can't change
+    /// With `hotswap` on, a changed function is replaced in place, so the
+    /// code calling it switches to the new version at once, without being
+    /// compiled again. Addresses `get_function` gave before still point at
+    /// the old version, and must be looked up again
+    pub fn reload(&mut self, source: &str) -> CompileResult<()> {
+        self.compile_source(None, source, Self::update)
+    }
+
+    /// Compile a new version of the program in the file at `path`, as
+    /// `reload` does
+    pub fn reload_file(&mut self, path: impl AsRef<Path>) -> CompileResult<()> {
+        let path = path.as_ref();
+        self.compile_source(Some(path), &read(path)?, Self::update)
+    }
+
+    /// Load a program and `compile` it, pointing errors at the file they're
+    /// in
+    fn compile_source(
         &mut self,
-        function: ast::Function,
-    ) -> Compiled {
-        // Create a new function builder
-        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        path: Option<&Path>,
+        source: &str,
+        compile: fn(&mut Self, &ast::Program, &SourceMap) -> CompileResult<()>,
+    ) -> CompileResult<()> {
+        let mut sources = SourceMap::default();
+
+        modules::load(path, source, &mut sources)
+            .and_then(|ast| compile(self, &ast, &sources))
+            .map_err(|error| sources.attribute(error))
+    }
 
-        // Create the function signature
-        let mut sig = Signature::new(CallConv::SystemV);
+    fn compile_program(&mut self, ast: &ast::Program, sources: &SourceMap) -> CompileResult<()> {
+        let mut checker = self.check(ast)?;
 
-        // Add parameters
-        for param in function.params.params {
-            let ty = match param.param_type {
-                ast::Type::Int => I64,
-                ast::Type::Float => F32,
-                _ => panic!("Unsupported type"),
-            };
+        // checked as written, before the passes change it
+        let diagnostics = checks::check(ast, &self.checks).into_iter().chain(lint::run(ast, &checker, &self.lints));
+        for diagnostic in diagnostics {
+            match diagnostic {
+                CompileError::Warning(..) => self.warnings.push(sources.attribute(diagnostic)),
+                error => return Err(error),
+            }
+        }
+
+        // the passes leave the program as valid as it was, but checking it
+        // again finds its generic instances and lambdas as they are now
+        let optimized;
+        let ast = if self.passes != Passes::default() {
+            let mut program = ast.clone();
+            let report = optimizer::run(&mut program, &checker, self.passes, self.overflow_mode);
 
-            sig.params.push(AbiParam::new(ty));
+            self.report.changes.extend(report.changes);
+            self.report.warnings.extend(report.warnings.into_iter().map(|warning| sources.attribute(warning)));
+
+            optimized = program;
+            checker = self.check(&optimized)?;
+            &optimized
+        } else {
+            ast
         };
 
-        // Add return type
-        match function.return_type {
-            ast::Type::Int => sig.returns.push(AbiParam::new(I64)),
-            ast::Type::Float => sig.returns.push(AbiParam::new(F32)),
-            ast::Type::Null => (),
-            _ => panic!("Unsupported type"),
+        for declaration in &ast.structs {
+            self.declare_struct(declaration)?;
+        }
+
+        for declaration in &ast.enums {
+            self.declare_enum(declaration)?;
+        }
+
+        self.define_globals(&ast.globals, &checker, &HashSet::new())?;
+
+        // generic functions are only compiled as the instances the type
+        // checker found calls to
+        self.generics.clear();
+        for function in ast.functions.iter().filter(|function| function.is_generic()) {
+            self.generics.insert(function.ident.clone(), FunctionSignature::of(function));
+        }
+
+        for function in &ast.externs {
+            self.declare_extern(function)?;
+        }
+
+        let functions: Vec<&ast::Function> = ast.functions.iter()
+            .filter(|function| !function.is_generic())
+            .chain(checker.instances())
+            .collect();
+
+        // Declare everything first so functions can call each other
+        // regardless of the order they're defined in
+        for function in &functions {
+            self.declare_function(function)?;
+        }
+
+        self.define_functions(&functions, &functions, &checker, sources)?;
+        self.record_source(&ast.globals, &functions);
+        Ok(())
+    }
+
+    /// Remember the source of the globals and functions just compiled, for
+    /// `update` to tell what changes
+    fn record_source(&mut self, globals: &[ast::Global], functions: &[&ast::Function]) {
+        for global in globals {
+            self.compiled_source.insert(global.ident.clone(), global.to_string());
+        }
+        for function in functions {
+            self.compiled_source.insert(function.ident.clone(), function.to_string());
+        }
+    }
+
+    /// Define `compiled`, some of the declared `functions` of a program,
+    /// and the lambdas written in them, then make them ready for use
+    fn define_functions(
+        &mut self,
+        functions: &[&ast::Function],
+        compiled: &[&ast::Function],
+        checker: &TypeChecker,
+        sources: &SourceMap,
+    ) -> CompileResult<()> {
+        let lambdas: Vec<&Lambda> = checker.lambdas().iter()
+            .filter(|lambda| compiled.iter().any(|function| function.ident == lambda.owner))
+            .collect();
+
+        // spans only identify lambdas within one program
+        // (and one function, as generic ones are instantiated many times)
+        self.lambdas.clear();
+        for lambda in &lambdas {
+            self.declare_lambda(lambda)?;
+        }
+
+        // only calls within the program are inlined, as only its bodies
+        // are at hand
+        let inlinable = inline::inlinable(functions.iter().copied(), self.inline_size);
+
+        // functions the IR can't represent yet are translated as they are
+        let signatures: Option<HashMap<String, FunctionSignature>> = self.ir.then(|| {
+            self.functions.iter().map(|(ident, declared)| (ident.clone(), declared.signature.clone())).collect()
+        });
+
+        for function in compiled {
+            let ir = signatures.as_ref().and_then(|signatures| ir::build(function, checker, signatures).ok());
+            self.compile_function(function, ir.as_ref(), checker, sources, &inlinable)?;
         };
 
-        // Create the function
-        let func_id = self.module.declare_function(
+        for lambda in lambdas {
+            self.compile_lambda(lambda, checker, sources, &inlinable)?;
+        }
+
+        // named functions used as values get their thunks last, once every
+        // function has had the chance to ask for one
+        let thunks: Vec<String> = self.function_values.iter()
+            .filter(|(_, value)| !value.defined)
+            .map(|(ident, _)| ident.clone())
+            .collect();
+
+        for ident in thunks {
+            self.define_thunk(&ident)?;
+        }
+
+        self.module.finish_definitions()?;
+        self.module.register_unwind_info(&mut self.unwind)
+    }
+
+    /// Type check a program, which may call the host functions
+    pub(crate) fn check(&self, ast: &ast::Program) -> CompileResult<TypeChecker> {
+        let mut checker = TypeChecker::new();
+        for (ident, signature) in &self.host_functions {
+            checker.declare_function(ident, signature.clone());
+        }
+
+        checker.check_program(ast)?;
+        Ok(checker)
+    }
+
+    /// What the passes of the options did to every program compiled so
+    /// far, with the warnings pointing at the files they're about
+    /// Empty unless the compiler was created with some passes
+    pub fn optimizer_report(&self) -> &Report {
+        &self.report
+    }
+
+    /// What the [checks](crate::checks) and [lints](crate::lint) warned
+    /// about in every program compiled so far, pointing at the files
+    /// they're about
+    pub fn warnings(&self) -> &[CompileError] {
+        &self.warnings
+    }
+
+    /// What every function compiled so far compiled to, in order
+    /// Empty unless the compiler was created with `dump_code` on
+    pub fn dumps(&self) -> &[FunctionDump] {
+        &self.dumps
+    }
+
+    /// What a function compiled to, if `dump_code` is on
+    pub fn dump(&self, ident: &str) -> Option<&FunctionDump> {
+        self.dumps.iter().rev().find(|dump| dump.ident == ident)
+    }
+
+    /// Layout of a struct's fields, as C would lay them out
+    pub fn struct_layout(&self, ident: &str) -> Option<&StructLayout> {
+        self.structs.get(ident).map(|declared| &declared.layout)
+    }
+
+    /// Name and layout of the variant of an enum with the tag `tag`
+    pub fn variant_layout(&self, ident: &str, tag: u32) -> Option<(&str, &StructLayout)> {
+        let variant = self.enums.get(ident)?.variants.get(tag as usize)?;
+        Some((&variant.ident, &variant.layout))
+    }
+
+    /// Work out the layout of a struct and define the descriptor the
+    /// runtime uses to free it
+    fn declare_struct(&mut self, declaration: &ast::Struct) -> CompileResult<()> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let layout = StructLayout::of(declaration, pointer_type)?;
+        let descriptor = self.define_descriptor(&layout)?;
+
+        self.structs.insert(declaration.ident.clone(), DeclaredStruct { layout, descriptor });
+        Ok(())
+    }
+
+    /// Work out the layout of each variant of an enum, and define the data
+    /// objects its values are made from
+    fn declare_enum(&mut self, declaration: &ast::Enum) -> CompileResult<()> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut variants = Vec::new();
+
+        for (tag, variant) in declaration.variants.iter().enumerate() {
+            let layout = StructLayout::of_variant(variant, pointer_type)?;
+
+            let data = if variant.fields.is_empty() {
+                // every value of the variant is the same immortal object
+                let mut fields = (tag as u32).to_ne_bytes().to_vec();
+                fields.resize(layout.size as usize, 0);
+
+                self.define_data(runtime::immortal_struct_data(&fields), std::mem::align_of::<runtime::KennedyStruct>())?
+            } else {
+                self.define_descriptor(&layout)?
+            };
+
+            variants.push(DeclaredVariant { ident: variant.ident.clone(), layout, data });
+        }
+
+        self.enums.insert(declaration.ident.clone(), DeclaredEnum { variants });
+        Ok(())
+    }
+
+    /// Work out the value of each global, in order, and define the data
+    /// object holding it, except for those `kept` as they are
+    /// Constants are read-only; initializers may only use the constants
+    /// before them
+    fn define_globals(&mut self, globals: &[ast::Global], checker: &TypeChecker, kept: &HashSet<String>) -> CompileResult<()> {
+        let mut constants = HashMap::new();
+
+        for global in globals {
+            let global_type = checker.global_type(&global.ident).unwrap().clone();
+            let evaluator = Evaluator { constants: &constants, overflow_mode: self.overflow_mode };
+            let (value, _) = evaluator.evaluate(&global.value, Some(&global_type))?;
+
+            if global.constant {
+                constants.insert(global.ident.clone(), (value.clone(), global_type.clone()));
+            }
+
+            if kept.contains(&global.ident) {
+                continue;
+            }
+
+            // a global defined again gets a new data object, as its name
+            // is taken by the old one
+            let id = if self.globals.contains_key(&global.ident) {
+                self.module.declare_anonymous_data(!global.constant, false)
+            } else {
+                self.module.declare_data(&global.ident, Linkage::Export, !global.constant, false)
+            }.map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+            match &value {
+                Constant::String(text) => {
+                    // a pointer to an immortal string, like a literal's
+                    let literal = string_literal(&mut self.module, &mut self.data_ctx, &mut self.string_literals, text)?;
+                    let pointer_bytes = self.module.target_config().pointer_bytes() as usize;
+
+                    self.data_ctx.define(vec![0; pointer_bytes].into_boxed_slice());
+                    self.data_ctx.set_align(pointer_bytes as u64);
+                    let literal = self.module.declare_data_in_data(literal, &mut self.data_ctx);
+                    self.data_ctx.write_data_addr(0, literal, 0);
+                }
+                value => {
+                    let bytes = constant_bytes(value, &global_type);
+                    self.data_ctx.set_align(bytes.len() as u64);
+                    self.data_ctx.define(bytes);
+                }
+            }
+
+            self.module.define_data(id, &self.data_ctx)
+                .map_err(|e| CompileError::CompileError(e.to_string()))?;
+            self.data_ctx.clear();
+
+            self.globals.insert(global.ident.clone(), DeclaredGlobal { id, global_type, constant: global.constant });
+        }
+
+        Ok(())
+    }
+
+    /// Define the descriptor the runtime uses to allocate and free structs
+    /// with this layout
+    fn define_descriptor(&mut self, layout: &StructLayout) -> CompileResult<DataId> {
+        let references: Vec<(usize, usize)> = layout.fields.iter()
+            .filter(|field| field.field_type.is_refcounted())
+            .map(|field| (field.offset as usize, element_kind(&field.field_type)))
+            .collect();
+
+        self.define_data(
+            runtime::struct_descriptor_data(layout.size as usize, layout.align as usize, &references),
+            std::mem::align_of::<runtime::StructDescriptor>(),
+        )
+    }
+
+    /// Define a read-only data object holding `bytes`
+    fn define_data(&mut self, bytes: Box<[u8]>, align: usize) -> CompileResult<DataId> {
+        let id = self.module.declare_anonymous_data(false, false)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        self.data_ctx.define(bytes);
+        self.data_ctx.set_align(align as u64);
+        self.module.define_data(id, &self.data_ctx)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+        self.data_ctx.clear();
+
+        Ok(id)
+    }
+
+    /// Cranelift signature of a function
+    fn signature(&self, function: &ast::Function) -> CompileResult<Signature> {
+        let signature_of = FunctionSignature::of(function);
+        signature(&self.module, false, &signature_of.params, &signature_of.return_type)
+    }
+
+    /// Declare a function in the module without defining it
+    fn declare_function(&mut self, function: &ast::Function) -> CompileResult<()> {
+        let sig = self.signature(function)?;
+
+        let id = self.module.declare_function(
             &function.ident,
             Linkage::Export,
             &sig,
-        ).map_err(|e| e.to_string())?;
-    
-        // Create the function context
-        let mut func_ctx = FunctionBuilderContext::new();
-        let mut func_builder = FunctionBuilder::new(&mut self.ctx.func, &mut func_ctx);
-
-        // Create the entry block
-        // This is the first block that will be executed when the function is called
-        let entry_block = func_builder.create_block();
-
-        // Since this is the entry block, add block parameters
-        // according to the function parameters
-        func_builder.append_block_params_for_function_params(entry_block);
-
-        // Set the insertion point to the entry block
-        // Tells the builder to insert instructions at the end of the entry block
-        func_builder.switch_to_block(entry_block);
-
-        // Now we can start adding instructions to the entry block
-        // First, we need to create a stack frame
-        // This is where the function's local variables will be stored
-        
-    }
-}
\ No newline at end of file
+        ).map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        self.functions.insert(function.ident.clone(), DeclaredFunction {
+            id,
+            signature: FunctionSignature::of(function),
+            linkage: Linkage::Export,
+        });
+
+        Ok(())
+    }
+
+    /// Cranelift signature of a function called with the C ABI
+    fn c_signature(&self, signature_of: &FunctionSignature) -> CompileResult<Signature> {
+        let mut sig = signature(&self.module, false, &signature_of.params, &signature_of.return_type)?;
+
+        for (param, ty) in sig.params.iter_mut().zip(&signature_of.params) {
+            *param = c_extension(*param, ty);
+        }
+        for result in &mut sig.returns {
+            *result = c_extension(*result, &signature_of.return_type);
+        }
+
+        Ok(sig)
+    }
+
+    /// Import a C function into the module
+    fn declare_extern(&mut self, function: &ast::ExternFunction) -> CompileResult<()> {
+        let host = self.host_symbols.borrow().contains_key(&function.ident);
+        if !host && !self.module.resolves(&function.ident) {
+            return Err(CompileError::SemanticError(
+                format!("Cannot find extern function `{}`", function.ident),
+                function.span.clone(),
+            ));
+        }
+
+        let signature_of = FunctionSignature::of_extern(function);
+        let sig = self.c_signature(&signature_of)?;
+
+        // declaring it again, from another module, gives the same function
+        let id = self.module.declare_function(&function.ident, Linkage::Import, &sig)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        self.functions.insert(function.ident.clone(), DeclaredFunction {
+            id,
+            signature: signature_of,
+            linkage: Linkage::Import,
+        });
+
+        Ok(())
+    }
+
+    /// Declare an anonymous function, and define the descriptor of the
+    /// closures created from it
+    fn declare_lambda(&mut self, lambda: &Lambda) -> CompileResult<()> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let params: Vec<ast::Type> = lambda.params.params.iter().map(|p| p.param_type.clone()).collect();
+
+        let sig = signature(&self.module, true, &params, &lambda.return_type)?;
+        let id = self.module.declare_anonymous_function(&sig)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        let captures = StructLayout::of_closure(&lambda.captures, pointer_type)?;
+        let descriptor = self.define_descriptor(&captures)?;
+
+        self.lambdas.insert((lambda.owner.clone(), lambda.span.clone()), DeclaredLambda {
+            id,
+            signature: FunctionSignature { type_params: Vec::new(), params, return_type: lambda.return_type.clone() },
+            captures,
+            descriptor,
+        });
+
+        Ok(())
+    }
+
+    /// Compile a declared function, from its IR if it has been built
+    fn compile_function(
+        &mut self,
+        function: &ast::Function,
+        ir: Option<&ir::Function>,
+        checker: &TypeChecker,
+        sources: &SourceMap,
+        inlinable: &HashMap<String, &ast::Function>,
+    ) -> CompileResult<()> {
+        let id = self.functions[&function.ident].id;
+        let sig = self.signature(function)?;
+
+        self.define_function(id, sig, &function.ident, checker, sources, inlinable, |translator| match ir {
+            Some(ir) => translator.translate_ir(ir),
+            None => translator.translate_function(function),
+        })?;
+
+        if let (Some(ir), Some(dump)) = (ir, self.dumps.last_mut()) {
+            if dump.ident == function.ident {
+                dump.ir = Some(ir.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compile a declared anonymous function
+    fn compile_lambda(
+        &mut self,
+        lambda: &Lambda,
+        checker: &TypeChecker,
+        sources: &SourceMap,
+        inlinable: &HashMap<String, &ast::Function>,
+    ) -> CompileResult<()> {
+        let declared = self.lambdas[&(lambda.owner.clone(), lambda.span.clone())].clone();
+        let sig = signature(&self.module, true, &declared.signature.params, &declared.signature.return_type)?;
+        let (line, column) = sources.location(&lambda.span);
+        let ident = format!("{}::<lambda at {}:{}>", lambda.owner, line, column);
+
+        self.define_function(declared.id, sig, &ident, checker, sources, inlinable, |translator| {
+            translator.translate_lambda(lambda, &declared.captures)
+        })
+    }
+
+    /// Define a declared function with the body `translate` builds
+    #[allow(clippy::too_many_arguments)]
+    fn define_function(
+        &mut self,
+        id: FuncId,
+        sig: Signature,
+        ident: &str,
+        checker: &TypeChecker,
+        sources: &SourceMap,
+        inlinable: &HashMap<String, &ast::Function>,
+        translate: impl FnOnce(FunctionTranslator<M>) -> CompileResult<()>,
+    ) -> CompileResult<()> {
+        self.ctx.func.signature = sig;
+
+        // Create the function builder
+        let builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+
+        let translator = FunctionTranslator::new(
+            builder,
+            &mut self.module,
+            &self.functions,
+            &self.structs,
+            &self.enums,
+            &self.globals,
+            &self.generics,
+            &self.lambdas,
+            &mut self.function_values,
+            &self.runtime,
+            &mut self.data_ctx,
+            &mut self.string_literals,
+            sources,
+            inlinable,
+            checker.expression_types(),
+            self.overflow_mode,
+        );
+        let translated = translate(translator);
+
+        // Cranelift optimizes the IR in place, so it's copied beforehand
+        let clif = self.dump_code.then(|| self.ctx.func.display().to_string());
+        self.ctx.set_disasm(self.dump_code);
+
+        // Hand the IR to Cranelift for compiling
+        let result = translated.and_then(|_| {
+            self.module.define_function(id, &mut self.ctx)
+                .map_err(|e| CompileError::CompileError(format!(
+                    "Failed to compile `{}`: {:?}", ident, e,
+                )))
+        });
+
+        if let (Ok(_), Some(clif), Some(compiled)) = (&result, clif, self.ctx.compiled_code()) {
+            self.dumps.push(FunctionDump {
+                ident: ident.to_string(),
+                ir: None,
+                clif,
+                optimized_clif: self.ctx.func.display().to_string(),
+                disassembly: compiled.vcode.clone().unwrap_or_default(),
+                code_size: compiled.code_info().total_size,
+            });
+        }
+
+        if result.is_ok() {
+            self.keep_unwind_info(id)?;
+        }
+
+        // Ready the context for the next function
+        self.module.clear_context(&mut self.ctx);
+
+        result.map(|_| ())
+    }
+
+    /// Keep how to unwind the function just compiled in the context, to
+    /// register once it's finished
+    fn keep_unwind_info(&mut self, id: FuncId) -> CompileResult<()> {
+        let compiled = self.ctx.compiled_code().expect("function to have been compiled");
+        let info = compiled.create_unwind_info(self.module.isa())
+            .map_err(|e| CompileError::CompileError(format!("Failed to describe unwinding: {:?}", e)))?;
+
+        if let Some(info) = info {
+            self.unwind.add(id, info);
+        }
+        Ok(())
+    }
+
+    /// Define the thunk letting a named function be called as a closure:
+    /// it drops the closure parameter and calls straight through
+    fn define_thunk(&mut self, ident: &str) -> CompileResult<()> {
+        let function = self.functions[ident].clone();
+        let thunk = self.function_values[ident].thunk;
+
+        self.ctx.func.signature = signature(&self.module, true, &function.signature.params, &function.signature.return_type)?;
+
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_context);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+
+        // arguments are handed over along with their references
+        let args = builder.block_params(block)[1..].to_vec();
+        let callee = self.module.declare_func_in_func(function.id, builder.func);
+        let call = builder.ins().call(callee, &args);
+        let results = builder.inst_results(call).to_vec();
+        builder.ins().return_(&results);
+        builder.finalize();
+
+        let result = self.module.define_function(thunk, &mut self.ctx)
+            .map_err(|e| CompileError::CompileError(format!(
+                "Failed to compile `{}` as a value: {:?}", ident, e,
+            )))
+            .and_then(|_| self.keep_unwind_info(thunk));
+        self.module.clear_context(&mut self.ctx);
+        result?;
+
+        self.function_values.get_mut(ident).unwrap().defined = true;
+        Ok(())
+    }
+}
+
+/// Text of a source file
+fn read(path: &Path) -> CompileResult<String> {
+    fs::read_to_string(path).map_err(|e| {
+        CompileError::CompileError(format!("Cannot read `{}`: {}", path.display(), e))
+    })
+}
+
+/// C passes integers narrower than 32 bits widened, and so must calls to C
+fn c_extension(param: AbiParam, ty: &ast::Type) -> AbiParam {
+    match ty.bits() {
+        Some(bits) if bits < 32 && ty.is_signed() => param.sext(),
+        Some(bits) if bits < 32 => param.uext(),
+        _ if *ty == ast::Type::Bool => param.uext(),
+        _ => param,
+    }
+}
+
+/// How a number or bool is laid out in memory
+fn constant_bytes(value: &Constant, ty: &ast::Type) -> Box<[u8]> {
+    let bytes = match value {
+        Constant::Integer(value) => match ty.bits().unwrap() {
+            8 => (*value as i8).to_ne_bytes().to_vec(),
+            16 => (*value as i16).to_ne_bytes().to_vec(),
+            32 => (*value as i32).to_ne_bytes().to_vec(),
+            _ => (*value as i64).to_ne_bytes().to_vec(),
+        },
+        Constant::Float(value) => value.to_ne_bytes().to_vec(),
+        Constant::F64(value) => value.to_ne_bytes().to_vec(),
+        Constant::Bool(value) => vec![*value as u8],
+        Constant::String(_) => unreachable!("strings are pointers to literals"),
+    };
+
+    bytes.into_boxed_slice()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str, mode: OverflowMode) -> Compiler {
+        let mut compiler = Compiler::default();
+        compiler.set_overflow_mode(mode);
+        compiler.compile(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+        compiler
+    }
+
+    #[test]
+    fn test_wrapping_arithmetic() {
+        let source = r#"
+func add_u8(a: u8, b: u8): u8 { return a + b; }
+func mul_i16(a: i16, b: i16): i16 { return a * b; }
+func div_i64(a: i64, b: i64): i64 { return a / b; }
+func sub_u32(a: u32, b: u32): u32 { return a - b; }
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        unsafe {
+            let add_u8: extern "C" fn(u8, u8) -> u8 = std::mem::transmute(compiler.get_function("add_u8").unwrap());
+            let mul_i16: extern "C" fn(i16, i16) -> i16 = std::mem::transmute(compiler.get_function("mul_i16").unwrap());
+            let div_i64: extern "C" fn(i64, i64) -> i64 = std::mem::transmute(compiler.get_function("div_i64").unwrap());
+            let sub_u32: extern "C" fn(u32, u32) -> u32 = std::mem::transmute(compiler.get_function("sub_u32").unwrap());
+
+            assert_eq!(add_u8(200, 100), 44);
+            assert_eq!(mul_i16(300, 300), 300i16.wrapping_mul(300));
+            assert_eq!(div_i64(i64::MIN, -1), i64::MIN);
+            assert_eq!(div_i64(-7, 2), -3);
+            assert_eq!(sub_u32(1, 2), u32::MAX);
+        }
+    }
+
+    #[test]
+    fn test_casts_and_comparisons() {
+        let source = r#"
+func widen(a: i8): i64 { return a as i64; }
+func to_unsigned(a: u8): u32 { return a as u32; }
+func truncate(a: int): u8 { return a as u8; }
+func unsigned_less(a: u32, b: u32): bool { return a < b; }
+func count(n: i32): i32 {
+    let total: i32 = 0;
+    for (let i: i32 = 0; i < n; i++) {
+        total += i;
+    }
+    return total;
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        unsafe {
+            let widen: extern "C" fn(i8) -> i64 = std::mem::transmute(compiler.get_function("widen").unwrap());
+            let to_unsigned: extern "C" fn(u8) -> u32 = std::mem::transmute(compiler.get_function("to_unsigned").unwrap());
+            let truncate: extern "C" fn(i64) -> u8 = std::mem::transmute(compiler.get_function("truncate").unwrap());
+            let unsigned_less: extern "C" fn(u32, u32) -> bool = std::mem::transmute(compiler.get_function("unsigned_less").unwrap());
+            let count: extern "C" fn(i32) -> i32 = std::mem::transmute(compiler.get_function("count").unwrap());
+
+            assert_eq!(widen(-5), -5);
+            assert_eq!(to_unsigned(255), 255);
+            assert_eq!(truncate(0x1ff), 0xff);
+            assert!(!unsigned_less(u32::MAX, 1));
+            assert_eq!(count(5), 10);
+        }
+    }
+
+    #[test]
+    fn test_checked_arithmetic_in_range() {
+        let source = r#"
+func add_i8(a: i8, b: i8): i8 { return a + b; }
+func mul_u64(a: u64, b: u64): u64 { return a * b; }
+func max_u64(): u64 { return 18446744073709551615; }
+"#;
+        let compiler = compile(source, OverflowMode::Checked);
+
+        unsafe {
+            let add_i8: extern "C" fn(i8, i8) -> i8 = std::mem::transmute(compiler.get_function("add_i8").unwrap());
+            let mul_u64: extern "C" fn(u64, u64) -> u64 = std::mem::transmute(compiler.get_function("mul_u64").unwrap());
+
+            assert_eq!(add_i8(100, 27), 127);
+            assert_eq!(add_i8(-100, -28), -128);
+            assert_eq!(mul_u64(1 << 32, (1 << 32) - 1), (1 << 32) * ((1 << 32) - 1));
+
+            let max_u64: extern "C" fn() -> u64 = std::mem::transmute(compiler.get_function("max_u64").unwrap());
+            assert_eq!(max_u64(), u64::MAX);
+        }
+    }
+
+    #[test]
+    fn test_strings() {
+        let source = r#"
+func greet(name: string): string {
+    let greeting = "Hello, " + name;
+    greeting += "!";
+    return greeting;
+}
+func describe(n: int, x: float): string { return (n as string) + " " + (x as string); }
+func first_byte(s: string): u8 { return s[0]; }
+func middle(s: string): string { return s[1:len(s) - 1]; }
+func same(a: string, b: string): bool { return a == b and !(a != b); }
+func count_a(s: string): int {
+    let count = 0;
+    for (let i = 0; i < len(s); i++) {
+        let c = s[i:i + 1];
+        if (c == "a") {
+            count++;
+        }
+    }
+    return count;
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        type Str = *const runtime::KennedyString;
+        let live = runtime::live_strings();
+
+        unsafe {
+            let greet: extern "C" fn(Str) -> Str = std::mem::transmute(compiler.get_function("greet").unwrap());
+            let describe: extern "C" fn(i64, f32) -> Str = std::mem::transmute(compiler.get_function("describe").unwrap());
+            let first_byte: extern "C" fn(Str) -> u8 = std::mem::transmute(compiler.get_function("first_byte").unwrap());
+            let middle: extern "C" fn(Str) -> Str = std::mem::transmute(compiler.get_function("middle").unwrap());
+            let same: extern "C" fn(Str, Str) -> bool = std::mem::transmute(compiler.get_function("same").unwrap());
+            let count_a: extern "C" fn(Str) -> i64 = std::mem::transmute(compiler.get_function("count_a").unwrap());
+
+            // arguments are passed owned, results are returned owned
+            let take = |s: Str| {
+                let string = runtime::string_to_rust(s);
+                runtime::kennedy_string_release(s as *mut _);
+                string
+            };
+            let arg = |s: &str| runtime::alloc_string(s.as_bytes());
+
+            assert_eq!(take(greet(arg("Kennedy"))), "Hello, Kennedy!");
+            assert_eq!(take(describe(-3, 1.5)), "-3 1.5");
+            assert_eq!(first_byte(arg("abc")), b'a');
+            assert_eq!(take(middle(arg("[abc]"))), "abc");
+            assert!(same(arg("abc"), arg("abc")));
+            assert!(!same(arg("abc"), arg("abd")));
+            assert_eq!(count_a(arg("banana")), 3);
+        }
+
+        // every string made along the way was freed
+        assert_eq!(runtime::live_strings(), live);
+    }
+
+    #[test]
+    fn test_string_index_out_of_bounds_traps() {
+        let stderr = run_trapping("compiler::tests::test_string_index_out_of_bounds_traps", || {
+            let source = "func at(s: string, i: int): u8 {\n    return s[i];\n}";
+            let compiler = compile(source, OverflowMode::Wrapping);
+
+            unsafe {
+                let at: extern "C" fn(*const runtime::KennedyString, i64) -> u8 = std::mem::transmute(compiler.get_function("at").unwrap());
+                at(runtime::alloc_string(b"abc"), 3);
+            }
+        });
+
+        assert!(stderr.contains("Runtime error at 2:12: index out of bounds"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_arrays() {
+        let source = r#"
+func sum(a: int[]): int {
+    let total = 0;
+    for (let i = 0; i < len(a); i++) {
+        total += a[i];
+    }
+    return total;
+}
+func squares(n: int): int {
+    let a: int[] = [];
+    for (let i = 0; i < n; i++) {
+        push(a, i * i);
+    }
+    return sum(a);
+}
+func fixed(): u8 {
+    let a: [u8; 3] = [1, 2, 3];
+    a[0] = 10;
+    a[1] += 5;
+    a[2]++;
+    return a[0] + a[1] + a[2];
+}
+func shared(): int {
+    let a = [1, 2, 3];
+    let b = a;
+    b[0] = 100;
+    return a[0] + sum(a[1:]);
+}
+func words(): string {
+    let w = ["a", "b"];
+    push(w, "c");
+    w[0] = w[2] + w[1];
+    let nested = [w, ["d"]];
+    return nested[0][0] + nested[1][0] + (len(nested) as string);
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        let live_strings = runtime::live_strings();
+        let live_arrays = runtime::live_arrays();
+
+        unsafe {
+            let squares: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("squares").unwrap());
+            let fixed: extern "C" fn() -> u8 = std::mem::transmute(compiler.get_function("fixed").unwrap());
+            let shared: extern "C" fn() -> i64 = std::mem::transmute(compiler.get_function("shared").unwrap());
+            let words: extern "C" fn() -> *const runtime::KennedyString = std::mem::transmute(compiler.get_function("words").unwrap());
+
+            assert_eq!(squares(10), 285);
+            assert_eq!(fixed(), 10 + 7 + 4);
+            assert_eq!(shared(), 105);
+
+            let word = words();
+            assert_eq!(runtime::string_to_rust(word), "cbd2");
+            runtime::kennedy_string_release(word as *mut _);
+        }
+
+        // every array and string made along the way was freed
+        assert_eq!(runtime::live_arrays(), live_arrays);
+        assert_eq!(runtime::live_strings(), live_strings);
+    }
+
+    #[test]
+    fn test_structs() {
+        let source = r#"
+struct Point { x: float, y: float }
+struct Named { name: string, at: Point, id: u8 }
+
+func length_squared(p: Point): float { return p.x * p.x + p.y * p.y; }
+func moved(): float {
+    let p = Point { x: 3.0, y: 0.0 };
+    let q = p;
+    q.y = 4.0;
+    return length_squared(p);
+}
+func named(id: u8): Named {
+    let n = Named { id: id, name: "origin", at: Point { x: 0.0, y: 0.0 } };
+    n.name += "!";
+    n.at.x = 1.5;
+    return n;
+}
+func total(): float {
+    let ps = [Point { x: 1.0, y: 2.0 }, Point { x: 3.0, y: 4.0 }];
+    ps[1].x += 10.0;
+    return ps[0].x + ps[1].x;
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        // what C would see behind a Named
+        #[repr(C)]
+        struct Point {
+            x: f32,
+            y: f32,
+        }
+        #[repr(C)]
+        struct Named {
+            name: *const runtime::KennedyString,
+            at: *const runtime::KennedyStruct,
+            id: u8,
+        }
+
+        let live_strings = runtime::live_strings();
+        let live_structs = runtime::live_structs();
+
+        unsafe {
+            let moved: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("moved").unwrap());
+            let named: extern "C" fn(u8) -> *mut runtime::KennedyStruct = std::mem::transmute(compiler.get_function("named").unwrap());
+            let total: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("total").unwrap());
+
+            // structs are shared, not copied
+            assert_eq!(moved(), 25.0);
+            assert_eq!(total(), 14.0);
+
+            let n = named(7);
+            let fields = &*((n as *const u8).add(runtime::STRUCT_FIELDS_OFFSET as usize) as *const Named);
+            let at = &*((fields.at as *const u8).add(runtime::STRUCT_FIELDS_OFFSET as usize) as *const Point);
+
+            assert_eq!(runtime::string_to_rust(fields.name), "origin!");
+            assert_eq!((at.x, at.y), (1.5, 0.0));
+            assert_eq!(fields.id, 7);
+            runtime::kennedy_struct_release(n);
+        }
+
+        assert_eq!(runtime::live_structs(), live_structs);
+        assert_eq!(runtime::live_strings(), live_strings);
+
+        let layout = compiler.struct_layout("Named").unwrap();
+        assert_eq!(layout.size as usize, std::mem::size_of::<Named>());
+    }
+
+    #[test]
+    fn test_enums_and_match() {
+        let source = r#"
+enum Shape { Circle(float), Rect(float, float), Labelled(string, Shape), Empty }
+
+func make(kind: int): Shape {
+    return match (kind) {
+        0 => Shape::Circle(2.0),
+        1 => Shape::Rect(3.0, 4.0),
+        2 => Shape::Labelled("box" + "!", Shape::Rect(1.0, 5.0)),
+        _ => Shape::Empty,
+    };
+}
+
+func area(s: Shape): float {
+    return match (s) {
+        Circle(r) => 3.0 * r * r,
+        Rect(w, h) => w * h,
+        Labelled(_, inner) => area(inner),
+        Empty => 0.0,
+    };
+}
+
+func label_length(kind: int): int {
+    let s = make(kind);
+    match (s) {
+        Labelled(label, _) => { return len(label); }
+        _ => { }
+    }
+    return 0 - 1;
+}
+
+func total(): float {
+    let shapes = [make(0), make(1), make(2), make(3)];
+    let sum = 0.0;
+    for (let i = 0; i < len(shapes); i++) {
+        sum += area(shapes[i]);
+    }
+    return sum;
+}
+
+func small(x: u8): u8 {
+    return match (x) { 0 => 10, 1 => 11, 2 => 12, 3 => 13, 255 => x, _ => 0 };
+}
+
+func flip(b: bool): int {
+    return match (b) { true => 0, false => 1 };
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        let live_strings = runtime::live_strings();
+        let live_structs = runtime::live_structs();
+
+        unsafe {
+            let area: extern "C" fn(*mut runtime::KennedyStruct) -> f32 = std::mem::transmute(compiler.get_function("area").unwrap());
+            let make: extern "C" fn(i64) -> *mut runtime::KennedyStruct = std::mem::transmute(compiler.get_function("make").unwrap());
+            let label_length: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("label_length").unwrap());
+            let total: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("total").unwrap());
+            let small: extern "C" fn(u8) -> u8 = std::mem::transmute(compiler.get_function("small").unwrap());
+            let flip: extern "C" fn(i8) -> i64 = std::mem::transmute(compiler.get_function("flip").unwrap());
+
+            // area takes over the reference it is given
+            for (kind, expected) in [(0, 12.0), (1, 12.0), (2, 5.0), (3, 0.0)] {
+                assert_eq!(area(make(kind)), expected);
+            }
+
+            assert_eq!(label_length(2), 4);
+            assert_eq!(label_length(1), -1);
+            assert_eq!(total(), 29.0);
+
+            assert_eq!((small(0), small(3), small(4), small(255)), (10, 13, 0, 255));
+            assert_eq!((flip(1), flip(0)), (0, 1));
+
+            // the tag comes first, then the values in C layout
+            let rect = make(1);
+            let fields = (rect as *const u8).add(runtime::STRUCT_FIELDS_OFFSET as usize);
+            assert_eq!(*(fields as *const u32), 1);
+            assert_eq!(*(fields.add(4) as *const f32), 3.0);
+            runtime::kennedy_struct_release(rect);
+        }
+
+        assert_eq!(runtime::live_structs(), live_structs);
+        assert_eq!(runtime::live_strings(), live_strings);
+    }
+
+    #[test]
+    fn test_closures() {
+        let source = r#"
+func adder(n: int): func(int): int {
+    return func(x: int): int { return x + n; };
+}
+
+func twice(f: func(int): int, x: int): int {
+    return f(f(x));
+}
+
+func double(x: int): int { return x * 2; }
+
+func compose(f: func(int): int, g: func(int): int): func(int): int {
+    return func(x: int): int { return g(f(x)); };
+}
+
+func run(x: int): int {
+    let add3 = adder(3);
+    let n = 100;
+    let snapshot = func(): int { return n; };
+    n = 1;
+    return twice(add3, x) + compose(double, adder(1))(x) + snapshot() + n;
+}
+
+func greeting(name: string): func(string): string {
+    let hello = "hello, " + name;
+    return func(suffix: string): string { return hello + suffix; };
+}
+
+func greet(): int {
+    let greet = greeting("kennedy");
+    let fs = [greet, func(s: string): string { return s; }];
+    return len(fs[0]("!")) + len(fs[1]("abc"));
+}
+
+func nested(a: int): int {
+    let outer = func(b: int): func(): int {
+        return func(): int { return a * 10 + b; };
+    };
+    return outer(2)();
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        let live_strings = runtime::live_strings();
+        let live_structs = runtime::live_structs();
+
+        unsafe {
+            let run: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("run").unwrap());
+            let greet: extern "C" fn() -> i64 = std::mem::transmute(compiler.get_function("greet").unwrap());
+            let nested: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("nested").unwrap());
+
+            // 4 + 3 + 3, then 4 * 2 + 1, then 100 captured before n changed, then n
+            assert_eq!(run(4), 10 + 9 + 100 + 1);
+            assert_eq!(greet(), "hello, kennedy!".len() as i64 + 3);
+            assert_eq!(nested(7), 72);
+        }
+
+        assert_eq!(runtime::live_structs(), live_structs);
+        assert_eq!(runtime::live_strings(), live_strings);
+    }
+
+    #[test]
+    fn test_generics() {
+        let source = r#"
+func max<T: numeric>(a: T, b: T): T {
+    if (a > b) { return a; }
+    return b;
+}
+
+func largest<T: numeric>(values: T[]): T {
+    let best = values[0];
+    for (let i = 1; i < len(values); i++) {
+        best = max(best, values[i]);
+    }
+    return best;
+}
+
+func map<T, U>(values: T[], f: func(T): U): U[] {
+    let result: U[] = [];
+    for (let i = 0; i < len(values); i++) {
+        push(result, f(values[i]));
+    }
+    return result;
+}
+
+func scale<T: numeric>(values: T[], by: T): T[] {
+    return map(values, func(x: T): T { return x * by; });
+}
+
+func ints(): int { return largest(scale([3, 9, 4], 2)); }
+func floats(): float { return largest([1.5, 0.5]) + max(2.0, 1.0); }
+func bytes(x: u8): u8 { return max(x, 100); }
+func lengths(): int {
+    let words = map(["a", "abc", "ab"], func(s: string): int { return len(s); });
+    return largest(words);
+}
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        let live_strings = runtime::live_strings();
+        let live_arrays = runtime::live_arrays();
+        let live_structs = runtime::live_structs();
+
+        unsafe {
+            let ints: extern "C" fn() -> i64 = std::mem::transmute(compiler.get_function("ints").unwrap());
+            let floats: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("floats").unwrap());
+            let bytes: extern "C" fn(u8) -> u8 = std::mem::transmute(compiler.get_function("bytes").unwrap());
+            let lengths: extern "C" fn() -> i64 = std::mem::transmute(compiler.get_function("lengths").unwrap());
+
+            assert_eq!(ints(), 18);
+            assert_eq!(floats(), 3.5);
+            assert_eq!((bytes(7), bytes(250)), (100, 250));
+            assert_eq!(lengths(), 3);
+
+            // instances are ordinary functions, named after their type arguments
+            let max_float: extern "C" fn(f32, f32) -> f32 = std::mem::transmute(compiler.get_function("max<float>").unwrap());
+            assert_eq!(max_float(-1.0, -2.0), -1.0);
+        }
+
+        assert!(compiler.get_function("max").is_err());
+        assert!(compiler.get_function("max<i64>").is_ok());
+
+        assert_eq!(runtime::live_structs(), live_structs);
+        assert_eq!(runtime::live_arrays(), live_arrays);
+        assert_eq!(runtime::live_strings(), live_strings);
+    }
+
+    #[test]
+    fn test_modules() {
+        let dir = std::env::temp_dir().join(format!("kennedy-compile-modules-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+
+        fs::write(dir.join("main.ken"), r#"
+import "lib/shapes.ken";
+import numbers;
+
+func area(s: shapes::Shape): float {
+    return shapes::area(s);
+}
+
+func total(): float {
+    let sq = shapes::Shape::Square(shapes::Point { x: 3.0, y: 3.0 });
+    return area(sq) + numbers::max(1.5, 0.5) + numbers::count() as float + numbers::ZERO;
+}
+"#).unwrap();
+
+        fs::write(dir.join("lib/shapes.ken"), r#"
+pub struct Point { x: float, y: float }
+pub enum Shape { Square(Point), Empty }
+
+pub func area(s: Shape): float {
+    return match (s) {
+        Square(p) => scale(p.x * p.y),
+        Shape::Empty => 0.0,
+    };
+}
+
+func scale(x: float): float { return x; }
+"#).unwrap();
+
+        // its own `count` isn't the one the root file would see
+        fs::write(dir.join("numbers.ken"), r#"
+pub func max<T: numeric>(a: T, b: T): T {
+    if (a > b) { return a; }
+    return b;
+}
+
+pub func count(): int { return counter()(); }
+func counter(): func(): int { return count_one; }
+func count_one(): int { return ONE; }
+const ONE = 1;
+pub const ZERO = 0.0;
+"#).unwrap();
+
+        fs::write(dir.join("private.ken"), "import \"lib/shapes.ken\";\nfunc f(): float {\n    return shapes::scale(1.0);\n}\n").unwrap();
+
+        let mut compiler = Compiler::default();
+        compiler.compile_file(dir.join("main.ken")).unwrap_or_else(|e| panic!("{}", e.to_string_with_source("")));
+
+        let live_structs = runtime::live_structs();
+
+        unsafe {
+            let total: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("total").unwrap());
+            assert_eq!(total(), 11.5);
+
+            let area: extern "C" fn(f32) -> f32 = std::mem::transmute(compiler.get_function("shapes::scale").unwrap());
+            assert_eq!(area(2.0), 2.0);
+        }
+
+        assert!(compiler.get_function("numbers::max<float>").is_ok());
+        assert_eq!(runtime::live_structs(), live_structs);
+
+        let error = Compiler::default().compile_file(dir.join("private.ken")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "private.ken: Semantic error at Span { start: 54, end: 67 }: Function `scale` of module `shapes` is not `pub`",
+        );
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_globals() {
+        let source = r#"
+const LIMIT: u8 = 200;
+const WRAPPED = LIMIT + 100;
+const SCALE = 1.5 * 2.0;
+const GREETING = "hello " + "world";
+let counter = 0;
+let name = GREETING;
+let enabled = not false;
+
+func next(): int {
+    counter++;
+    counter += 10;
+    return counter;
+}
+func rename(to: string): int {
+    let old = name;
+    name = to;
+    return len(old) + len(name);
+}
+func shadowed(counter: int): int { return counter; }
+func constants(): float { return (WRAPPED as float) * SCALE; }
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+        let live_strings = runtime::live_strings();
+
+        unsafe {
+            let next: extern "C" fn() -> i64 = std::mem::transmute(compiler.get_function("next").unwrap());
+            let rename: extern "C" fn(*const runtime::KennedyString) -> i64 = std::mem::transmute(compiler.get_function("rename").unwrap());
+            let shadowed: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("shadowed").unwrap());
+            let constants: extern "C" fn() -> f32 = std::mem::transmute(compiler.get_function("constants").unwrap());
+
+            // globals keep their values between calls
+            assert_eq!((next(), next()), (11, 22));
+            assert_eq!(*(compiler.get_global("counter").unwrap() as *const i64), 22);
+            assert_eq!(shadowed(5), 5);
+            assert_eq!(constants(), 44.0 * 3.0);
+            assert_eq!(*compiler.get_global("enabled").unwrap(), 1);
+
+            assert_eq!(rename(runtime::alloc_string(b"abc")), 11 + 3);
+            assert_eq!(rename(runtime::alloc_string(b"de")), 3 + 2);
+            let name = *(compiler.get_global("name").unwrap() as *const *const runtime::KennedyString);
+            assert_eq!(runtime::string_to_rust(name), "de");
+        }
+
+        // the string the global still holds is the only one left
+        assert_eq!(runtime::live_strings(), live_strings + 1);
+
+        let error = |source: &str| Compiler::default().compile(source).unwrap_err().to_string();
+        assert!(error("const A = 1;\nfunc f(): null { A = 2; }").contains("Cannot assign to constant `A`"));
+        assert!(error("let a = 1;\nconst B = a + 1;").contains("`a` is not a constant"));
+        assert!(error("const C: u8 = 256;").contains("does not fit in u8"));
+        assert!(error("const D = 1 / 0;").contains("Division by zero in constant expression"));
+    }
+
+    #[test]
+    fn test_extern_functions() {
+        let source = r#"
+extern func abs(x: i32): i32;
+extern func labs(x: int): int;
+extern func toupper(c: i32): i32;
+extern func sqrt(x: f64): f64;
+extern func sqrtf(x: float): float;
+
+func magnitude(x: i32): int { return labs(x as int) + abs(x) as int; }
+func shout(c: u8): u8 { return toupper(c as i32) as u8; }
+func apply(x: i32): i32 {
+    let f = abs;
+    return f(x);
+}
+func hypotenuse(a: f64, b: f64): f64 { return sqrt(a * a + b * b); }
+func roots(): f64 { return sqrt(2.0) + sqrtf(4.0) as f64; }
+"#;
+        let compiler = compile(source, OverflowMode::Wrapping);
+
+        unsafe {
+            let magnitude: extern "C" fn(i32) -> i64 = std::mem::transmute(compiler.get_function("magnitude").unwrap());
+            let shout: extern "C" fn(u8) -> u8 = std::mem::transmute(compiler.get_function("shout").unwrap());
+            let apply: extern "C" fn(i32) -> i32 = std::mem::transmute(compiler.get_function("apply").unwrap());
+            let hypotenuse: extern "C" fn(f64, f64) -> f64 = std::mem::transmute(compiler.get_function("hypotenuse").unwrap());
+            let roots: extern "C" fn() -> f64 = std::mem::transmute(compiler.get_function("roots").unwrap());
+
+            assert_eq!(magnitude(-7), 14);
+            assert_eq!(shout(b'k'), b'K');
+            assert_eq!(apply(-3), 3);
+            assert_eq!(hypotenuse(3.0, 4.0), 5.0);
+            assert_eq!(roots(), std::f64::consts::SQRT_2 + 2.0);
+        }
+
+        assert!(compiler.get_function("abs").is_err());
+
+        let error = |source: &str| Compiler::default().compile(source).unwrap_err().to_string();
+        assert!(error("extern func kennedy_no_such_function(): int;").contains("Cannot find extern function `kennedy_no_such_function`"));
+        assert!(error("extern func puts(s: string): i32;").contains("cannot be passed to C"));
+        assert!(error("extern func abs(x: i32): i32;\nfunc abs(x: i32): i32 { return x; }").contains("defined more than once"));
+    }
+
+    #[test]
+    fn test_host_functions() {
+        thread_local! {
+            static LOGGED: RefCell<Vec<String>> = RefCell::default();
+        }
+
+        extern "C" fn log(message: *const runtime::KennedyString, level: u8) {
+            // host functions own their string arguments
+            unsafe {
+                LOGGED.with(|logged| logged.borrow_mut().push(format!("{}: {}", level, runtime::string_to_rust(message))));
+                runtime::kennedy_string_release(message as *mut _);
+            }
+        }
+
+        extern "C" fn scale(x: f32, by: i64) -> f32 {
+            x * by as f32
+        }
+
+        let mut compiler = Compiler::default();
+        compiler.register_host_fn(
+            "log",
+            log as extern "C" fn(*const runtime::KennedyString, u8),
+            FunctionSignature::new(vec![ast::Type::String, ast::Type::U8], ast::Type::Null),
+        ).unwrap();
+        compiler.register_host_fn(
+            "scale",
+            scale as extern "C" fn(f32, i64) -> f32,
+            FunctionSignature::new(vec![ast::Type::Float, ast::Type::Int], ast::Type::Float),
+        ).unwrap();
+
+        let source = r#"
+func run(n: int): float {
+    log("running " + n as string, 2);
+    let f = scale;
+    return scale(1.5, n) + f(1.0, 1);
+}
+"#;
+        compiler.compile(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+        let live_strings = runtime::live_strings();
+
+        unsafe {
+            let run: extern "C" fn(i64) -> f32 = std::mem::transmute(compiler.get_function("run").unwrap());
+            assert_eq!(run(4), 7.0);
+        }
+
+        LOGGED.with(|logged| assert_eq!(*logged.borrow(), ["2: running 4"]));
+        assert_eq!(runtime::live_strings(), live_strings);
+
+        // calls are checked against the signature
+        let error = compiler.compile("func f(): null { log(1, 2); }").unwrap_err();
+        assert!(error.to_string().contains("Expected string, got int"), "{}", error);
+
+        // and the signature against the Rust function
+        let register = |signature| Compiler::default().register_host_fn("scale", scale as extern "C" fn(f32, i64) -> f32, signature)
+            .unwrap_err().to_string();
+        assert!(register(FunctionSignature::new(vec![ast::Type::Float, ast::Type::I32], ast::Type::Float))
+            .contains("Parameter 2 of host function `scale` is declared i32, but the Rust function takes i64"));
+        assert!(register(FunctionSignature::new(vec![ast::Type::Float], ast::Type::Float))
+            .contains("declared with 1 parameters, but the Rust function takes 2"));
+        assert!(register(FunctionSignature::new(vec![ast::Type::Float, ast::Type::Int], ast::Type::Null))
+            .contains("declared to return null, but the Rust function returns float"));
+
+        assert!(compiler.register_host_fn("len", scale as extern "C" fn(f32, i64) -> f32, FunctionSignature::new(vec![], ast::Type::Null)).is_err());
+    }
+
+    #[test]
+    fn test_compiler_options() {
+        let source = "func fib(n: int): int {\n    if (n < 2) { return n; }\n    return fib(n - 1) + fib(n - 2);\n}";
+
+        for opt_level in [OptLevel::None, OptLevel::Speed, OptLevel::SpeedAndSize] {
+            let options = CompilerOptions::new().opt_level(opt_level).verifier(false).pic(true);
+            let mut compiler = Compiler::new(options).unwrap();
+            compiler.compile(source).unwrap();
+
+            unsafe {
+                let fib: extern "C" fn(i64) -> i64 = std::mem::transmute(compiler.get_function("fib").unwrap());
+                assert_eq!(fib(20), 6765);
+            }
+        }
+
+        // object files can be for other machines, whose ELF machine numbers
+        // are at offset 18
+        for (target, machine) in [("aarch64-unknown-linux-gnu", 183u16), ("riscv64gc-unknown-linux-gnu", 243)] {
+            let options = CompilerOptions::new().target(target.parse().unwrap()).pic(true).opt_level(OptLevel::Speed);
+            let mut compiler = Compiler::object_with_options("fib", options).unwrap();
+            compiler.compile(source).unwrap();
+            let bytes = compiler.finish().unwrap();
+
+            assert_eq!(&bytes[..4], b"\x7fELF");
+            assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), machine, "{}", target);
+        }
+
+        // but code in memory can only be run on this one
+        let other = if cfg!(target_arch = "aarch64") { "x86_64-unknown-linux-gnu" } else { "aarch64-unknown-linux-gnu" };
+        let error = Compiler::new(CompilerOptions::new().target(other.parse().unwrap())).err().unwrap();
+        assert!(error.to_string().contains("Only object files can be compiled for another machine"));
+
+        let error = Compiler::object_with_options("x", CompilerOptions::new().target("avr-unknown-unknown".parse().unwrap())).err().unwrap();
+        assert!(error.to_string().contains("Target `avr-unknown-unknown` is not supported"), "{}", error);
+    }
+
+    #[test]
+    fn test_code_dumps() {
+        let source = r#"
+func add(a: int, b: int): int {
+    return a + b * 2;
+}
+
+func apply(): int {
+    let f = func(x: int): int { return x + 1; };
+    return f(2);
+}
+"#;
+        let mut compiler = Compiler::default();
+        compiler.compile(source).unwrap();
+        assert!(compiler.dumps().is_empty());
+
+        let mut compiler = Compiler::new(CompilerOptions::new().opt_level(OptLevel::Speed).dump_code(true)).unwrap();
+        compiler.compile(source).unwrap();
+
+        let idents: Vec<&str> = compiler.dumps().iter().map(|dump| dump.ident.as_str()).collect();
+        assert_eq!(idents, ["add", "apply", "apply::<lambda at 7:13>"]);
+
+        let add = compiler.dump("add").unwrap();
+        assert_eq!(add.clif, "\
+function u0:0(i64, i64) -> i64 system_v {
+block0(v0: i64, v1: i64):
+    v2 = iconst.i64 2
+    v3 = imul v1, v2  ; v2 = 2
+    v4 = iadd v0, v3
+    return v4
+
+block1:
+    trap unreachable
+}
+");
+        // the multiplication is strength reduced, and the unreachable block
+        // dropped
+        assert!(add.optimized_clif.contains("ishl"), "{}", add.optimized_clif);
+        assert!(!add.optimized_clif.contains("block1"), "{}", add.optimized_clif);
+        assert!(!add.disassembly.is_empty());
+        assert!(add.code_size > 0);
+
+        let text = add.to_string();
+        assert!(text.starts_with(";; add: clif\nfunction u0:0"), "{}", text);
+        assert!(text.contains(&format!(";; add: machine code, {} bytes", add.code_size)), "{}", text);
+
+        assert!(compiler.dump("sub").is_none());
+    }
+
+    #[test]
+    fn test_inlining() {
+        let source = r#"
+func sq(x: int): int { return x * x; }
+@noinline func cube(x: int): int { return x * x * x; }
+func fact(n: int): int { if (n < 2) { return 1; } return n * fact(n - 1); }
+func is_even(n: int): bool { if (n == 0) { return true; } return is_odd(n - 1); }
+func is_odd(n: int): bool { if (n == 0) { return false; } return is_even(n - 1); }
+func max<T: numeric>(a: T, b: T): T { if (a > b) { return a; } return b; }
+func greet(name: string): string { let greeting = "hi " + name; return greeting + "!"; }
+func count(n: int): null { let total = 0; for (let i = 0; i < n; i++) { if (i == 3) { return; } total++; } }
+func adder(n: int): func(int): int { return func(x: int): int { return x + n; }; }
+func squares(a: int, b: int): int { return sq(a) + sq(b); }
+func cubes(a: int, b: int): int { return cube(a) + cube(b); }
+
+func run(x: int): int {
+    count(x);
+    let total = sq(x) + cube(x) + fact(x) + max(x, 4) + adder(x)(1) + len(greet("kennedy"));
+    if (is_even(x)) { total += 1000; }
+    return total;
+}
+"#;
+        let compile = |inline_size: usize| {
+            let options = CompilerOptions::new().inline_size(inline_size).dump_code(true);
+            let mut compiler = Compiler::new(options).unwrap();
+            compiler.compile(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+            compiler
+        };
+
+        let calls = |compiler: &Compiler, ident: &str| {
+            compiler.dump(ident).unwrap().clif.matches(" call fn").count()
+        };
+
+        let called = compile(0);
+        let inlined = compile(inline::DEFAULT_INLINE_SIZE);
+        assert_eq!(calls(&called, "squares"), 2);
+        assert_eq!(calls(&inlined, "squares"), 0);
+        assert_eq!(calls(&inlined, "cubes"), 2);
+        // a function is never inlined into itself, however indirectly
+        assert_eq!(calls(&inlined, "fact"), 1);
+        assert_eq!(calls(&inlined, "is_even"), 1);
+
+        let live = runtime::live_strings();
+        let results = [&called, &inlined].map(|compiler| {
+            let run: extern "C" fn(i64) -> i64 = unsafe { std::mem::transmute(compiler.get_function("run").unwrap()) };
+            [0, 1, 5, 10].map(|x| run(x))
+        });
+        assert_eq!(results[0], [1017, 20, 292, 3630932]);
+        assert_eq!(results[1], results[0]);
+        assert_eq!(runtime::live_strings(), live);
+    }
+
+    #[test]
+    fn test_ir() {
+        let source = r#"
+func collatz(n: u64): u64 {
+    let steps: u64 = 0;
+    while (n != 1) {
+        if (n / 2 * 2 == n) { n = n / 2; } else { n = 3 * n + 1; }
+        steps++;
+    }
+    return steps;
+}
+func mix(x: i32): i32 {
+    let f = x as float * 1.5;
+    let small = x < 10 and not (x == 3) or x > 100;
+    let y = -x;
+    do { y += 7; } until (y > 20)
+    if (small) { return (f as i32) + y; }
+    return (small as i32) - y / 2;
+}
+func run(n: u64): i64 {
+    return (collatz(n) as i64) * 1000 + mix(n as i32) as i64;
+}
+func greeting(): int { return len("hello"); }
+"#;
+        let compile = |ir: bool| {
+            let mut compiler = Compiler::new(CompilerOptions::new().ir(ir).dump_code(true)).unwrap();
+            compiler.compile(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+            compiler
+        };
+
+        let results = [false, true].map(|ir| {
+            let compiler = compile(ir);
+            let run: extern "C" fn(u64) -> i64 = unsafe { std::mem::transmute(compiler.get_function("run").unwrap()) };
+            let greeting: extern "C" fn() -> i64 = unsafe { std::mem::transmute(compiler.get_function("greeting").unwrap()) };
+            ([1, 2, 3, 27, 200].map(|n| run(n)), greeting())
+        });
+        assert_eq!(results[1], results[0]);
+
+        // functions the IR can't represent are compiled without it
+        let compiler = compile(true);
+        assert!(compiler.dump("run").unwrap().ir.as_ref().unwrap().starts_with("func run(u64): i64\n"));
+        assert!(compiler.dump("greeting").unwrap().ir.is_none());
+        assert!(compiler.dump("run").unwrap().to_string().starts_with(";; run: kennedy ir\nfunc run"));
+    }
+
+    #[test]
+    fn test_object_file() {
+        let source = r#"
+const BASE = 40;
+let calls: u32 = 0;
+extern func kennedy_host_answer(): int;
+func answer(): int {
+    calls++;
+    return BASE + kennedy_host_answer() - 40 + 2;
+}
+"#;
+        let mut compiler = Compiler::object("answer").unwrap();
+        compiler.compile(source).unwrap();
+        let bytes = compiler.finish().unwrap();
+
+        // an object file for the host, exporting the functions and globals
+        // and importing the runtime and extern functions, even ones the
+        // compiler can't see
+        let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+        if cfg!(target_os = "linux") {
+            assert_eq!(&bytes[..4], b"\x7fELF");
+        }
+        assert!(contains(b"answer"));
+        assert!(contains(b"calls"));
+        assert!(contains(b"BASE"));
+        assert!(contains(b"kennedy_host_answer"));
+    }
+
+    #[test]
+    fn test_array_index_out_of_bounds_traps() {
+        let stderr = run_trapping("compiler::tests::test_array_index_out_of_bounds_traps", || {
+            let source = "func set(i: int): null {\n    let a = [1, 2, 3];\n    a[i] = 0;\n}";
+            let compiler = compile(source, OverflowMode::Wrapping);
+
+            unsafe {
+                let set: extern "C" fn(i64) = std::mem::transmute(compiler.get_function("set").unwrap());
+                set(2);
+                set(-1);
+            }
+        });
+
+        assert!(stderr.contains("Runtime error at 3:5: index out of bounds"), "{}", stderr);
+    }
+
+    /// Traps abort the process, so run the trapping case in a child copy of
+    /// the test binary and check what it printed
+    fn run_trapping(test_name: &str, run: impl FnOnce()) -> String {
+        if std::env::var("KENNEDY_TRAP_TEST").as_deref() == Ok(test_name) {
+            run();
+            std::process::exit(0);
+        }
+
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([test_name, "--exact", "--nocapture", "--test-threads=1"])
+            .env("KENNEDY_TRAP_TEST", test_name)
+            .output()
+            .unwrap();
+
+        assert!(!output.status.success(), "expected the child to trap");
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    #[test]
+    fn test_checked_overflow_traps() {
+        let stderr = run_trapping("compiler::tests::test_checked_overflow_traps", || {
+            let source = "func add(a: u8, b: u8): u8 {\n    return a + b;\n}";
+            let compiler = compile(source, OverflowMode::Checked);
+
+            unsafe {
+                let add: extern "C" fn(u8, u8) -> u8 = std::mem::transmute(compiler.get_function("add").unwrap());
+                add(200, 100);
+            }
+        });
+
+        assert!(stderr.contains("Runtime error at 2:12: integer overflow"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_inlined_overflow_traps() {
+        let stderr = run_trapping("compiler::tests::test_inlined_overflow_traps", || {
+            let source = "@inline func add(a: u8, b: u8): u8 {\n    return a + b;\n}\nfunc f(a: u8): u8 { return add(a, a); }";
+            let compiler = compile(source, OverflowMode::Checked);
+
+            unsafe {
+                let f: extern "C" fn(u8) -> u8 = std::mem::transmute(compiler.get_function("f").unwrap());
+                f(200);
+            }
+        });
+
+        // the error points at the inlined code, not the call
+        assert!(stderr.contains("Runtime error at 2:12: integer overflow"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_ir_overflow_traps() {
+        let stderr = run_trapping("compiler::tests::test_ir_overflow_traps", || {
+            let source = "func sum(n: u8): u8 {\n    let total: u8 = 0;\n    for (let i: u8 = 0; i < n; i++) { total += i; }\n    return total;\n}";
+            let mut compiler = Compiler::new(CompilerOptions::new().ir(true).overflow_mode(OverflowMode::Checked)).unwrap();
+            compiler.compile(source).unwrap();
+
+            unsafe {
+                let sum: extern "C" fn(u8) -> u8 = std::mem::transmute(compiler.get_function("sum").unwrap());
+                sum(100);
+            }
+        });
+
+        assert!(stderr.contains("Runtime error at 3:39: integer overflow"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_division_by_zero_traps() {
+        let stderr = run_trapping("compiler::tests::test_division_by_zero_traps", || {
+            let source = "func div(a: i32, b: i32): i32 { return a / b; }";
+            let compiler = compile(source, OverflowMode::Wrapping);
+
+            unsafe {
+                let div: extern "C" fn(i32, i32) -> i32 = std::mem::transmute(compiler.get_function("div").unwrap());
+                div(1, 0);
+            }
+        });
+
+        assert!(stderr.contains("Runtime error at 1:40: division by zero"), "{}", stderr);
+    }
+
+    #[test]
+    fn test_checks() {
+        let source = "func f(x: int): int {\n    let y = 1;\n    return x;\n}";
+
+        let mut compiler = Compiler::new(CompilerOptions::new()).unwrap();
+        compiler.compile(source).unwrap();
+        match compiler.warnings() {
+            [CompileError::Warning(message, span)] => {
+                assert_eq!(message, "Variable `y` is never used [unused_variables]");
+                assert_eq!(span.location(source), (2, 5));
+            }
+            warnings => panic!("{:?}", warnings),
+        }
+
+        let checks = Checks { unused_variables: checks::Severity::Deny, ..Checks::default() };
+        let mut compiler = Compiler::new(CompilerOptions::new().checks(checks)).unwrap();
+        let error = compiler.compile(source).unwrap_err();
+        assert!(matches!(&error, CompileError::SemanticError(message, _) if message == "Variable `y` is never used [unused_variables]"));
+        assert!(compiler.get_function("f").is_err());
+    }
+}
@@ -0,0 +1,201 @@
+//! How a `Compiler` compiles: optimization, checks and the target machine
+
+use cranelift::codegen::isa::{self, OwnedTargetIsa};
+use cranelift::codegen::settings::{self, Configurable};
+use target_lexicon::Triple;
+
+use crate::checks::Checks;
+use crate::lint::Lints;
+use crate::error::{CompileError, CompileResult};
+use crate::optimizer::Passes;
+
+use super::OverflowMode;
+
+/// How hard Cranelift works on the code it generates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OptLevel {
+    /// Compile as fast as possible
+    #[default]
+    None,
+    /// Generate fast code
+    Speed,
+    /// Generate fast code, as long as it isn't much bigger
+    SpeedAndSize,
+}
+
+impl OptLevel {
+    /// Value of Cranelift's `opt_level` setting
+    fn setting(self) -> &'static str {
+        match self {
+            OptLevel::None => "none",
+            OptLevel::Speed => "speed",
+            OptLevel::SpeedAndSize => "speed_and_size",
+        }
+    }
+}
+
+/// Options for a `Compiler`, built up from the defaults
+/// i.e. `CompilerOptions::new().opt_level(OptLevel::Speed).verifier(false)`
+#[derive(Debug, Clone)]
+pub struct CompilerOptions {
+    opt_level: OptLevel,
+    verifier: bool,
+    pic: bool,
+    target: Option<Triple>,
+    pub(super) overflow_mode: OverflowMode,
+    pub(super) dump_code: bool,
+    pub(super) passes: Passes,
+    pub(super) inline_size: usize,
+    pub(super) ir: bool,
+    pub(super) checks: Checks,
+    pub(super) lints: Lints,
+    pub(super) hotswap: bool,
+}
+
+impl Default for CompilerOptions {
+    fn default() -> Self {
+        Self {
+            opt_level: OptLevel::None,
+            verifier: true,
+            pic: false,
+            target: None,
+            overflow_mode: OverflowMode::default(),
+            dump_code: false,
+            passes: Passes::default(),
+            inline_size: 0,
+            ir: false,
+            checks: Checks::default(),
+            lints: Lints::default(),
+            hotswap: false,
+        }
+    }
+}
+
+impl CompilerOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn opt_level(mut self, opt_level: OptLevel) -> Self {
+        self.opt_level = opt_level;
+        self
+    }
+
+    /// Check the Cranelift IR of every function before compiling it (on by
+    /// default). Turning it off compiles faster, but a bug in the compiler
+    /// then shows up as bad machine code rather than an error
+    pub fn verifier(mut self, enabled: bool) -> Self {
+        self.verifier = enabled;
+        self
+    }
+
+    /// Generate position-independent code, which can be loaded at any
+    /// address, as shared libraries and most executables need
+    pub fn pic(mut self, enabled: bool) -> Self {
+        self.pic = enabled;
+        self
+    }
+
+    /// Compile for another machine than this one
+    /// Only object files can be compiled for another machine, and as
+    /// literals are laid out as the runtime on this one expects, it must
+    /// have the same pointer size and byte order
+    pub fn target(mut self, target: Triple) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn overflow_mode(mut self, overflow_mode: OverflowMode) -> Self {
+        self.overflow_mode = overflow_mode;
+        self
+    }
+
+    /// Keep the Cranelift IR and machine code of every function compiled,
+    /// see [`dump`](super::dump)
+    pub fn dump_code(mut self, enabled: bool) -> Self {
+        self.dump_code = enabled;
+        self
+    }
+
+    /// Simplify the syntax tree before compiling it, see
+    /// [`optimizer`](crate::optimizer)
+    pub fn passes(mut self, passes: Passes) -> Self {
+        self.passes = passes;
+        self
+    }
+
+    /// Inline calls to functions whose body has at most `limit` statements
+    /// and expressions, as well as those marked `@inline`, see
+    /// [`inline`](super::inline). Only `@inline` ones are by default
+    pub fn inline_size(mut self, limit: usize) -> Self {
+        self.inline_size = limit;
+        self
+    }
+
+    /// Compile functions through the [Kennedy IR](crate::ir), where it can
+    /// represent them; the others are translated straight from the syntax
+    /// tree as usual
+    pub fn ir(mut self, enabled: bool) -> Self {
+        self.ir = enabled;
+        self
+    }
+
+    /// How to report unused names and unreachable code, see
+    /// [`checks`](crate::checks). Most are warnings by default
+    pub fn checks(mut self, checks: Checks) -> Self {
+        self.checks = checks;
+        self
+    }
+
+    /// Level of each [lint](crate::lint), which functions can change for
+    /// themselves with attributes
+    pub fn lints(mut self, lints: Lints) -> Self {
+        self.lints = lints;
+        self
+    }
+
+    /// Compile into memory so that functions can be compiled again while
+    /// the program runs, with callers switching to the new version at once,
+    /// see [`Compiler::reload`](super::Compiler::reload). Implies `pic`, as
+    /// calls then go through a table of addresses. Only supported on x86_64
+    pub fn hotswap(mut self, enabled: bool) -> Self {
+        self.hotswap = enabled;
+        self
+    }
+
+    /// Whether code is compiled for the machine the compiler runs on
+    pub fn is_native(&self) -> bool {
+        self.target.as_ref().is_none_or(|target| *target == Triple::host())
+    }
+
+    /// Target ISA with these settings
+    pub fn isa(&self) -> CompileResult<OwnedTargetIsa> {
+        let setting = |e: settings::SetError| CompileError::CompileError(e.to_string());
+        let mut flag_builder = settings::builder();
+
+        // use_colocated_libcalls: use libcall functions that are colocated with the
+        // generated code. Meaning, the libcall functions are generated in the same
+        // object file as the generated code (default)
+        flag_builder.set("use_colocated_libcalls", "false").map_err(setting)?;
+        flag_builder.set("is_pic", bool_setting(self.pic || self.hotswap)).map_err(setting)?;
+        flag_builder.set("opt_level", self.opt_level.setting()).map_err(setting)?;
+        flag_builder.set("enable_verifier", bool_setting(self.verifier)).map_err(setting)?;
+
+        // ISA builder will be used to create the target ISA
+        let isa_builder = match &self.target {
+            Some(target) if !self.is_native() => isa::lookup(target.clone()).map_err(|e| {
+                CompileError::CompileError(format!("Target `{}` is not supported: {}", target, e))
+            })?,
+            _ => cranelift_native::builder().map_err(|msg| {
+                CompileError::CompileError(format!("Host machine is not supported: {}", msg))
+            })?,
+        };
+
+        isa_builder.finish(settings::Flags::new(flag_builder))
+            .map_err(|e| CompileError::CompileError(format!("Failed to create target ISA: {}", e)))
+    }
+}
+
+fn bool_setting(enabled: bool) -> &'static str {
+    if enabled { "true" } else { "false" }
+}
@@ -0,0 +1,61 @@
+//! Runtime support called from compiled Kennedy code
+//!
+//! The runtime itself is the `kennedy-runtime` crate, re-exported here. Its
+//! functions are registered with the JIT by name (see [`functions`]), so
+//! generated code can import them like any other function.
+
+use cranelift::prelude::types;
+use cranelift::prelude::Type;
+
+pub use kennedy_runtime::*;
+
+/// Type of a runtime function parameter or return value
+#[derive(Debug, Clone, Copy)]
+pub enum AbiType {
+    Value(Type),
+    /// Pointer sized integer
+    Pointer,
+}
+
+/// A runtime function, as compiled code sees it
+pub struct RuntimeFunction {
+    pub name: &'static str,
+    pub address: *const u8,
+    pub params: &'static [AbiType],
+    pub returns: &'static [AbiType],
+}
+
+const I8: AbiType = AbiType::Value(types::I8);
+const I32: AbiType = AbiType::Value(types::I32);
+const I64: AbiType = AbiType::Value(types::I64);
+const F32: AbiType = AbiType::Value(types::F32);
+const F64: AbiType = AbiType::Value(types::F64);
+const PTR: AbiType = AbiType::Pointer;
+
+/// Every runtime function compiled code may import
+pub fn functions() -> Vec<RuntimeFunction> {
+    vec![
+        RuntimeFunction { name: "kennedy_trap", address: kennedy_trap as *const u8, params: &[I32, I32, I32], returns: &[] },
+        RuntimeFunction { name: "kennedy_string_retain", address: kennedy_string_retain as *const u8, params: &[PTR], returns: &[] },
+        RuntimeFunction { name: "kennedy_string_release", address: kennedy_string_release as *const u8, params: &[PTR], returns: &[] },
+        RuntimeFunction { name: "kennedy_string_concat", address: kennedy_string_concat as *const u8, params: &[PTR, PTR], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_len", address: kennedy_string_len as *const u8, params: &[PTR], returns: &[I64] },
+        RuntimeFunction { name: "kennedy_string_eq", address: kennedy_string_eq as *const u8, params: &[PTR, PTR], returns: &[I8] },
+        RuntimeFunction { name: "kennedy_string_index", address: kennedy_string_index as *const u8, params: &[PTR, I64, I32, I32], returns: &[I8] },
+        RuntimeFunction { name: "kennedy_string_slice", address: kennedy_string_slice as *const u8, params: &[PTR, I64, I64, I32, I32], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_from_int", address: kennedy_string_from_int as *const u8, params: &[I64], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_from_uint", address: kennedy_string_from_uint as *const u8, params: &[I64], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_from_float", address: kennedy_string_from_float as *const u8, params: &[F32], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_from_f64", address: kennedy_string_from_f64 as *const u8, params: &[F64], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_string_from_bool", address: kennedy_string_from_bool as *const u8, params: &[I8], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_array_new", address: kennedy_array_new as *const u8, params: &[PTR, PTR, PTR], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_array_retain", address: kennedy_array_retain as *const u8, params: &[PTR], returns: &[] },
+        RuntimeFunction { name: "kennedy_array_release", address: kennedy_array_release as *const u8, params: &[PTR], returns: &[] },
+        RuntimeFunction { name: "kennedy_array_len", address: kennedy_array_len as *const u8, params: &[PTR], returns: &[I64] },
+        RuntimeFunction { name: "kennedy_array_push_slot", address: kennedy_array_push_slot as *const u8, params: &[PTR], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_array_slice", address: kennedy_array_slice as *const u8, params: &[PTR, I64, I64, I32, I32], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_struct_new", address: kennedy_struct_new as *const u8, params: &[PTR], returns: &[PTR] },
+        RuntimeFunction { name: "kennedy_struct_retain", address: kennedy_struct_retain as *const u8, params: &[PTR], returns: &[] },
+        RuntimeFunction { name: "kennedy_struct_release", address: kennedy_struct_release as *const u8, params: &[PTR], returns: &[] },
+    ]
+}
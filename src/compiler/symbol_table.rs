@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::hash::Hash;
 
 /// A symbol table
 #[derive(Debug, Clone)]
@@ -7,4 +8,73 @@ pub struct SymbolTable<K, V> {
     parent: Option<Box<SymbolTable<K, V>>>,
     /// The symbols
     symbols: HashMap<K, V>,
-}
\ No newline at end of file
+}
+
+impl<K: Eq + Hash, V> Default for SymbolTable<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash, V> SymbolTable<K, V> {
+    /// Create an empty symbol table with no parent
+    pub fn new() -> Self {
+        Self {
+            parent: None,
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// Enter a new nested scope
+    /// Symbols declared from now on shadow those in the enclosing scopes
+    pub fn push_scope(&mut self) {
+        let parent = std::mem::take(self);
+        self.parent = Some(Box::new(parent));
+    }
+
+    /// Leave the current scope, dropping every symbol declared in it
+    pub fn pop_scope(&mut self) {
+        if let Some(parent) = self.parent.take() {
+            *self = *parent;
+        }
+    }
+
+    /// Declare a symbol in the current scope
+    pub fn insert(&mut self, key: K, value: V) {
+        self.symbols.insert(key, value);
+    }
+
+    /// Look up a symbol, searching enclosing scopes
+    pub fn get(&self, key: &K) -> Option<&V> {
+        match self.symbols.get(key) {
+            Some(value) => Some(value),
+            None => self.parent.as_ref().and_then(|parent| parent.get(key)),
+        }
+    }
+
+    /// Look up a symbol mutably, searching enclosing scopes
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        match self.symbols.get_mut(key) {
+            Some(value) => Some(value),
+            None => self.parent.as_mut().and_then(|parent| parent.get_mut(key)),
+        }
+    }
+
+    /// Look up a symbol, along with the depth of the scope declaring it
+    pub fn get_with_depth(&self, key: &K) -> Option<(&V, usize)> {
+        match self.symbols.get(key) {
+            Some(value) => Some((value, self.depth())),
+            None => self.parent.as_ref().and_then(|parent| parent.get_with_depth(key)),
+        }
+    }
+
+    /// Number of scopes enclosing the current one
+    pub fn depth(&self) -> usize {
+        self.parent.as_ref().map_or(0, |parent| parent.depth() + 1)
+    }
+
+    /// Whether the symbol is declared in the current scope only
+    pub fn contains_local(&self, key: &K) -> bool {
+        self.symbols.contains_key(key)
+    }
+}
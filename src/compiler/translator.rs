@@ -0,0 +1,2007 @@
+//! Lowering of a type checked `ast::Function` into Cranelift IR
+
+use std::collections::HashMap;
+
+use cranelift::prelude::*;
+use cranelift::codegen::ir::{BlockCall, FuncRef, JumpTableData, TrapCode};
+use cranelift::frontend::Switch;
+use cranelift_module::{DataContext, DataId, FuncId, Linkage, Module};
+
+use crate::ast;
+use crate::builtins::Builtin;
+use crate::generics;
+use crate::error::{CompileError, CompileResult, Span};
+use crate::modules::SourceMap;
+use crate::type_checking::{FunctionSignature, Lambda};
+
+use super::inline::MAX_INLINE_DEPTH;
+use super::layout::StructLayout;
+use super::runtime::{self, TrapKind};
+use super::symbol_table::SymbolTable;
+
+/// What to do when integer arithmetic over/underflows its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowMode {
+    /// Results wrap around modulo 2^bits (two's complement)
+    #[default]
+    Wrapping,
+    /// Over/underflow stops the program with a runtime error pointing at
+    /// the offending expression
+    Checked,
+}
+
+/// A function declared in the module
+#[derive(Debug, Clone)]
+pub struct DeclaredFunction {
+    pub id: FuncId,
+    pub signature: FunctionSignature,
+    /// `Import` for extern functions, which are defined outside the module
+    pub linkage: Linkage,
+}
+
+/// A struct declared in the module
+#[derive(Debug, Clone)]
+pub struct DeclaredStruct {
+    pub layout: StructLayout,
+    /// Data object holding its `runtime::StructDescriptor`
+    pub descriptor: DataId,
+}
+
+/// An anonymous function declared in the module
+#[derive(Debug, Clone)]
+pub struct DeclaredLambda {
+    pub id: FuncId,
+    pub signature: FunctionSignature,
+    /// Where the closure keeps its captured values
+    pub captures: StructLayout,
+    /// Data object holding the closure's `runtime::StructDescriptor`
+    pub descriptor: DataId,
+}
+
+/// A global variable or constant declared in the module
+#[derive(Debug, Clone)]
+pub struct DeclaredGlobal {
+    /// Data object holding its value
+    pub id: DataId,
+    pub global_type: ast::Type,
+    /// Read-only, as it's a constant
+    pub constant: bool,
+}
+
+/// A named function used as a value
+#[derive(Debug, Clone)]
+pub struct FunctionValue {
+    /// Takes a closure like an anonymous function does, and calls through
+    /// to the named function
+    pub thunk: FuncId,
+    /// Immortal closure pointing at the thunk
+    pub closure: DataId,
+    /// Whether the thunk has been defined yet
+    pub defined: bool,
+}
+
+/// An enum declared in the module
+#[derive(Debug, Clone)]
+pub struct DeclaredEnum {
+    /// In declaration order, so indexed by tag
+    pub variants: Vec<DeclaredVariant>,
+}
+
+/// One variant of a declared enum
+#[derive(Debug, Clone)]
+pub struct DeclaredVariant {
+    pub ident: String,
+    /// The tag, then the values the variant carries
+    pub layout: StructLayout,
+    /// Data object holding its `runtime::StructDescriptor`, or for variants
+    /// carrying nothing, the one immortal value of the variant
+    pub data: DataId,
+}
+
+impl DeclaredEnum {
+    /// Tag and declaration of the variant called `ident`
+    pub fn variant(&self, ident: &str) -> Option<(usize, &DeclaredVariant)> {
+        self.variants.iter().enumerate().find(|(_, variant)| variant.ident == ident)
+    }
+}
+
+/// Name of the hidden variable holding the value being matched on
+/// Not a valid identifier, so it can't clash with the program's variables
+const MATCH_SCRUTINEE: &str = " match";
+
+/// Cranelift signature of a function with these parameter and return types
+/// Closures take the closure they were called through as a first, extra,
+/// parameter
+pub fn signature<M: Module>(
+    module: &M,
+    closure: bool,
+    params: &[ast::Type],
+    return_type: &ast::Type,
+) -> CompileResult<Signature> {
+    let mut sig = module.make_signature();
+    let pointer_type = module.target_config().pointer_type();
+
+    if closure {
+        sig.params.push(AbiParam::new(pointer_type));
+    }
+
+    for param in params {
+        sig.params.push(AbiParam::new(cranelift_type(param, pointer_type)?));
+    }
+
+    // functions returning null return nothing
+    if *return_type != ast::Type::Null {
+        sig.returns.push(AbiParam::new(cranelift_type(return_type, pointer_type)?));
+    }
+
+    Ok(sig)
+}
+
+/// Cranelift type used to hold a value of a Kennedy type
+pub fn cranelift_type(ty: &ast::Type, pointer_type: Type) -> CompileResult<Type> {
+    match ty {
+        ast::Type::Int | ast::Type::I64 | ast::Type::U64 => Ok(types::I64),
+        ast::Type::I32 | ast::Type::U32 => Ok(types::I32),
+        ast::Type::I16 | ast::Type::U16 => Ok(types::I16),
+        ast::Type::I8 | ast::Type::U8 => Ok(types::I8),
+        ast::Type::Float => Ok(types::F32),
+        ast::Type::F64 => Ok(types::F64),
+        // bools are 0 or 1, null is a placeholder that is never inspected
+        ast::Type::Bool | ast::Type::Null => Ok(types::I8),
+        // pointer to a runtime::KennedyString
+        ast::Type::String => Ok(pointer_type),
+        // pointer to a runtime::KennedyArray
+        ast::Type::Array(_) | ast::Type::FixedArray(..) => Ok(pointer_type),
+        // pointer to a runtime::KennedyStruct
+        ast::Type::Named(_) => Ok(pointer_type),
+        // pointer to a closure, a runtime::KennedyStruct holding the code
+        ast::Type::Function(..) => Ok(pointer_type),
+    }
+}
+
+/// How the runtime should treat an array element or struct field of a type
+pub fn element_kind(ty: &ast::Type) -> usize {
+    match ty {
+        ast::Type::String => runtime::ELEMENT_STRING,
+        ast::Type::Array(_) | ast::Type::FixedArray(..) => runtime::ELEMENT_ARRAY,
+        ast::Type::Named(_) | ast::Type::Function(..) => runtime::ELEMENT_STRUCT,
+        _ => runtime::ELEMENT_PLAIN,
+    }
+}
+
+/// Somewhere an assignment can store a value
+enum Place {
+    Variable(Variable),
+    /// The data object of a global, at this address
+    Global(Value),
+    /// An element of an array. The array is kept alive until the place is
+    /// finished with, and the element's address is worked out (and bounds
+    /// checked) on every access, as evaluating the right hand side of an
+    /// assignment may have moved the elements
+    Element {
+        array: Value,
+        array_type: ast::Type,
+        temporary: bool,
+        index: Value,
+        span: Span,
+    },
+    /// A field of a struct, at a fixed address within it. The struct is
+    /// kept alive until the place is finished with
+    Field {
+        object: Value,
+        object_type: ast::Type,
+        temporary: bool,
+        offset: i32,
+    },
+}
+
+/// Translates the body of one function
+pub struct FunctionTranslator<'a, M: Module> {
+    pub builder: FunctionBuilder<'a>,
+    pub module: &'a mut M,
+    /// Every function in the program
+    pub functions: &'a HashMap<String, DeclaredFunction>,
+    /// Every struct in the program
+    pub structs: &'a HashMap<String, DeclaredStruct>,
+    /// Every enum in the program
+    pub enums: &'a HashMap<String, DeclaredEnum>,
+    /// Every global in the program
+    pub globals: &'a HashMap<String, DeclaredGlobal>,
+    /// Every generic function in the program, whose instances are in
+    /// `functions`
+    pub generics: &'a HashMap<String, FunctionSignature>,
+    /// Every anonymous function in the program, by the function it is
+    /// written in and where
+    pub lambdas: &'a HashMap<(String, Span), DeclaredLambda>,
+    /// Named functions used as values so far
+    pub function_values: &'a mut HashMap<String, FunctionValue>,
+    /// Runtime functions, by name
+    pub runtime: &'a HashMap<&'static str, FuncId>,
+    /// Used to define data objects for string literals
+    pub data_ctx: &'a mut DataContext,
+    /// Data objects of string literals already defined in the module
+    pub string_literals: &'a mut HashMap<String, DataId>,
+    /// Source files, to turn spans into line/column for runtime errors
+    pub sources: &'a SourceMap,
+    /// Functions whose calls are inlined, by name
+    pub inlinable: &'a HashMap<String, &'a ast::Function>,
+    /// Type the type checker gave every expression, by the function it is
+    /// written in and where
+    pub expression_types: &'a HashMap<(String, Span), ast::Type>,
+    pub overflow_mode: OverflowMode,
+    /// Return type of the function being translated
+    pub return_type: ast::Type,
+    /// Top level function (or instance) the code being translated is in
+    pub(super) owner: String,
+
+    variables: SymbolTable<String, (Variable, ast::Type)>,
+    next_variable: usize,
+    /// Reference counted variables declared in each open scope, released
+    /// when it closes
+    owned_scopes: Vec<Vec<(Variable, ast::Type)>>,
+    /// Functions already imported into this function
+    func_refs: HashMap<FuncId, FuncRef>,
+    pub(super) pointer_type: Type,
+    /// Calls being inlined, innermost last
+    inlined: Vec<InlinedCall>,
+}
+
+/// A call whose callee's body is being translated in place of it, with
+/// what the caller was translating
+struct InlinedCall {
+    /// Where the callee's `return`s jump to, with the value returned
+    exit: Block,
+    owner: String,
+    return_type: ast::Type,
+    variables: SymbolTable<String, (Variable, ast::Type)>,
+    owned_scopes: Vec<Vec<(Variable, ast::Type)>>,
+}
+
+impl<'a, M: Module> FunctionTranslator<'a, M> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        builder: FunctionBuilder<'a>,
+        module: &'a mut M,
+        functions: &'a HashMap<String, DeclaredFunction>,
+        structs: &'a HashMap<String, DeclaredStruct>,
+        enums: &'a HashMap<String, DeclaredEnum>,
+        globals: &'a HashMap<String, DeclaredGlobal>,
+        generics: &'a HashMap<String, FunctionSignature>,
+        lambdas: &'a HashMap<(String, Span), DeclaredLambda>,
+        function_values: &'a mut HashMap<String, FunctionValue>,
+        runtime: &'a HashMap<&'static str, FuncId>,
+        data_ctx: &'a mut DataContext,
+        string_literals: &'a mut HashMap<String, DataId>,
+        sources: &'a SourceMap,
+        inlinable: &'a HashMap<String, &'a ast::Function>,
+        expression_types: &'a HashMap<(String, Span), ast::Type>,
+        overflow_mode: OverflowMode,
+    ) -> Self {
+        let pointer_type = module.target_config().pointer_type();
+
+        Self {
+            builder,
+            module,
+            functions,
+            structs,
+            enums,
+            globals,
+            generics,
+            lambdas,
+            function_values,
+            runtime,
+            data_ctx,
+            string_literals,
+            sources,
+            inlinable,
+            expression_types,
+            overflow_mode,
+            return_type: ast::Type::Null,
+            owner: String::new(),
+            variables: SymbolTable::new(),
+            next_variable: 0,
+            owned_scopes: Vec::new(),
+            func_refs: HashMap::new(),
+            pointer_type,
+            inlined: Vec::new(),
+        }
+    }
+
+    /// Translate a function body, consuming the translator
+    pub fn translate_function(mut self, function: &ast::Function) -> CompileResult<()> {
+        self.owner = function.ident.clone();
+        self.translate_body(&function.params, &function.return_type, &function.body, None)
+    }
+
+    /// Translate the body of an anonymous function, consuming the translator
+    /// Its first parameter is the closure it was called through, which holds
+    /// the values laid out by `captures`
+    pub fn translate_lambda(mut self, lambda: &Lambda, captures: &StructLayout) -> CompileResult<()> {
+        self.owner = lambda.owner.clone();
+        self.translate_body(&lambda.params, &lambda.return_type, &lambda.body, Some(captures))
+    }
+
+    fn translate_body(
+        mut self,
+        params: &ast::Parameters,
+        return_type: &ast::Type,
+        body: &ast::Block,
+        captures: Option<&StructLayout>,
+    ) -> CompileResult<()> {
+        self.return_type = return_type.clone();
+
+        // Create the entry block
+        // This is the first block that will be executed when the function is called
+        let entry_block = self.builder.create_block();
+
+        // Since this is the entry block, add block parameters
+        // according to the function parameters
+        self.builder.append_block_params_for_function_params(entry_block);
+
+        // Set the insertion point to the entry block
+        self.builder.switch_to_block(entry_block);
+
+        // Parameters become ordinary variables, owned by the callee
+        self.push_scope();
+        let mut values = self.builder.block_params(entry_block).to_vec();
+
+        // so do captured values; the closure itself is only borrowed
+        if let Some(captures) = captures {
+            let closure = values.remove(0);
+
+            for field in &captures.fields {
+                let value = self.builder.ins().load(
+                    cranelift_type(&field.field_type, self.pointer_type)?,
+                    MemFlags::trusted(),
+                    closure,
+                    runtime::STRUCT_FIELDS_OFFSET + field.offset as i32,
+                );
+                self.retain(value, &field.field_type);
+                self.declare_variable(&field.ident, &field.field_type, value)?;
+            }
+        }
+
+        for (param, value) in params.params.iter().zip(values) {
+            self.declare_variable(&param.ident, &param.param_type, value)?;
+        }
+
+        self.translate_block(body)?;
+        self.pop_scope();
+
+        // falling off the end is fine for functions that return nothing, and
+        // the type checker rejects any other that can
+        if !self.builder.is_unreachable() {
+            if self.return_type == ast::Type::Null {
+                self.builder.ins().return_(&[]);
+            } else {
+                self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
+        }
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+
+        Ok(())
+    }
+
+    /// Declare a variable initialised to `value`
+    /// Takes over ownership of `value` if it is reference counted
+    fn declare_variable(&mut self, ident: &str, ty: &ast::Type, value: Value) -> CompileResult<()> {
+        let variable = Variable::new(self.next_variable);
+        self.next_variable += 1;
+
+        self.builder.declare_var(variable, cranelift_type(ty, self.pointer_type)?);
+        self.builder.def_var(variable, value);
+        self.variables.insert(ident.to_string(), (variable, ty.clone()));
+
+        if ty.is_refcounted() {
+            self.owned_scopes.last_mut().unwrap().push((variable, ty.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn push_scope(&mut self) {
+        self.variables.push_scope();
+        self.owned_scopes.push(Vec::new());
+    }
+
+    /// Close a scope, releasing the objects held by its variables
+    fn pop_scope(&mut self) {
+        self.variables.pop_scope();
+
+        for (variable, ty) in self.owned_scopes.pop().unwrap() {
+            let value = self.builder.use_var(variable);
+            self.release_temporary(value, &ty);
+        }
+    }
+
+    /// Release the objects held by every variable in the function, before
+    /// returning from it
+    fn release_all_variables(&mut self) {
+        let variables: Vec<(Variable, ast::Type)> = self.owned_scopes.iter().flatten().cloned().collect();
+
+        for (variable, ty) in variables {
+            let value = self.builder.use_var(variable);
+            self.release_temporary(value, &ty);
+        }
+    }
+
+    /// Add a reference to a value, if it is reference counted
+    fn retain(&mut self, value: Value, ty: &ast::Type) {
+        match ty {
+            ast::Type::String => { self.call_runtime("kennedy_string_retain", &[value]); }
+            ast::Type::Array(_) | ast::Type::FixedArray(..) => { self.call_runtime("kennedy_array_retain", &[value]); }
+            ast::Type::Named(_) | ast::Type::Function(..) => { self.call_runtime("kennedy_struct_retain", &[value]); }
+            _ => {}
+        }
+    }
+
+    /// Release a value produced by an expression, once it's been used
+    fn release_temporary(&mut self, value: Value, ty: &ast::Type) {
+        match ty {
+            ast::Type::String => { self.call_runtime("kennedy_string_release", &[value]); }
+            ast::Type::Array(_) | ast::Type::FixedArray(..) => { self.call_runtime("kennedy_array_release", &[value]); }
+            ast::Type::Named(_) | ast::Type::Function(..) => { self.call_runtime("kennedy_struct_release", &[value]); }
+            _ => {}
+        }
+    }
+
+    fn variable(&self, ident: &String) -> CompileResult<(Variable, ast::Type)> {
+        self.variables.get(ident).cloned().ok_or_else(|| {
+            CompileError::CompileError(format!("Undeclared variable `{}`", ident))
+        })
+    }
+
+    /// Continue in a fresh block with no predecessors, so statements after a
+    /// `return` still have somewhere to go
+    fn start_unreachable_block(&mut self) {
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+    }
+
+    fn translate_block(&mut self, block: &ast::Block) -> CompileResult<()> {
+        self.push_scope();
+
+        block.statements.iter()
+            .try_for_each(|statement| self.translate_statement(statement))?;
+
+        self.pop_scope();
+        Ok(())
+    }
+
+    fn translate_statement(&mut self, statement: &ast::Statement) -> CompileResult<()> {
+        match statement {
+            ast::Statement::VariableDeclaration { ident, var_type, value, .. } => {
+                let (value, value_type) = self.translate_expression(value)?;
+                self.declare_variable(ident, var_type.as_ref().unwrap_or(&value_type), value)
+            }
+
+            ast::Statement::Assign { ident, value, .. } => {
+                let (place, var_type) = self.variable_place(ident)?;
+                let (value, _) = self.translate_expression(value)?;
+
+                // the variable lets go of its old value
+                self.write_place(&place, &var_type, value)?;
+                self.finish_place(place);
+                Ok(())
+            }
+
+            ast::Statement::Return { value, .. } => {
+                let return_type = self.return_type.clone();
+                let value = match value {
+                    Some(value) if return_type != ast::Type::Null => {
+                        Some(self.translate_expression(value)?.0)
+                    }
+                    Some(value) => {
+                        self.translate_expression(value)?;
+                        None
+                    }
+                    None => None,
+                };
+                self.release_all_variables();
+
+                // the body of an inlined call returns to the caller's code
+                // that follows it
+                let values: Vec<Value> = value.into_iter().collect();
+                match self.inlined.last() {
+                    Some(call) => { self.builder.ins().jump(call.exit, &values); }
+                    None => { self.builder.ins().return_(&values); }
+                }
+
+                self.start_unreachable_block();
+                Ok(())
+            }
+
+            ast::Statement::Block { block, .. } => self.translate_block(block),
+
+            ast::Statement::If { condition, then_branch, else_branch, .. } => {
+                let (condition, _) = self.translate_expression(condition)?;
+
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let merge_block = self.builder.create_block();
+
+                self.builder.ins().brif(condition, then_block, &[], else_block, &[]);
+
+                self.builder.switch_to_block(then_block);
+                self.translate_statement(then_branch)?;
+                self.builder.ins().jump(merge_block, &[]);
+
+                self.builder.switch_to_block(else_block);
+                if let Some(else_branch) = else_branch {
+                    self.translate_statement(else_branch)?;
+                }
+                self.builder.ins().jump(merge_block, &[]);
+
+                self.builder.switch_to_block(merge_block);
+                Ok(())
+            }
+
+            ast::Statement::While { condition, body, .. } => {
+                let header_block = self.builder.create_block();
+                let body_block = self.builder.create_block();
+                let exit_block = self.builder.create_block();
+
+                self.builder.ins().jump(header_block, &[]);
+
+                self.builder.switch_to_block(header_block);
+                let (condition, _) = self.translate_expression(condition)?;
+                self.builder.ins().brif(condition, body_block, &[], exit_block, &[]);
+
+                self.builder.switch_to_block(body_block);
+                self.translate_block(body)?;
+                self.builder.ins().jump(header_block, &[]);
+
+                self.builder.switch_to_block(exit_block);
+                Ok(())
+            }
+
+            ast::Statement::DoUntil { condition, body, .. } => {
+                let body_block = self.builder.create_block();
+                let exit_block = self.builder.create_block();
+
+                self.builder.ins().jump(body_block, &[]);
+
+                self.builder.switch_to_block(body_block);
+                self.translate_statement(body)?;
+                let (condition, _) = self.translate_expression(condition)?;
+                self.builder.ins().brif(condition, exit_block, &[], body_block, &[]);
+
+                self.builder.switch_to_block(exit_block);
+                Ok(())
+            }
+
+            ast::Statement::For { init, condition, increment, body, .. } => {
+                // the loop variable is only visible inside the loop
+                self.push_scope();
+
+                self.translate_statement(init)?;
+
+                let header_block = self.builder.create_block();
+                let body_block = self.builder.create_block();
+                let exit_block = self.builder.create_block();
+
+                self.builder.ins().jump(header_block, &[]);
+
+                self.builder.switch_to_block(header_block);
+                let (condition, _) = self.translate_expression(condition)?;
+                self.builder.ins().brif(condition, body_block, &[], exit_block, &[]);
+
+                self.builder.switch_to_block(body_block);
+                self.translate_block(body)?;
+                self.translate_statement(increment)?;
+                self.builder.ins().jump(header_block, &[]);
+
+                self.builder.switch_to_block(exit_block);
+
+                self.pop_scope();
+                Ok(())
+            }
+
+            ast::Statement::Expression { expression, .. } => {
+                let (value, ty) = self.translate_expression(expression)?;
+                self.release_temporary(value, &ty);
+                Ok(())
+            }
+        }
+    }
+
+    /// Translate an expression that is only read, not kept
+    /// Variables are borrowed rather than retained, and the returned flag
+    /// says whether the value is a temporary to release after use
+    fn translate_operand(&mut self, expr: &ast::Expression) -> CompileResult<(Value, ast::Type, bool)> {
+        if let ast::Expression::Identifier { ident, .. } = expr {
+            if let Some((variable, var_type)) = self.variables.get(ident).cloned() {
+                return Ok((self.builder.use_var(variable), var_type, false));
+            }
+        }
+
+        let (value, ty) = self.translate_expression(expr)?;
+        Ok((value, ty, true))
+    }
+
+    /// Type the type checker gave the expression at `span`
+    /// Literals take theirs from their context
+    fn expression_type(&self, span: &Span) -> CompileResult<ast::Type> {
+        self.expression_types.get(&(self.owner.clone(), span.clone())).cloned().ok_or_else(|| {
+            CompileError::SemanticError("The type checker has no type for this expression".to_string(), span.clone())
+        })
+    }
+
+    /// Release an operand from `translate_operand` if it was a temporary
+    fn release_operand(&mut self, value: Value, ty: &ast::Type, temporary: bool) {
+        if temporary {
+            self.release_temporary(value, ty);
+        }
+    }
+
+    /// Translate an expression, returning its value and Kennedy type
+    /// Reference counted results are owned by the caller, which must store or
+    /// release them
+    fn translate_expression(&mut self, expr: &ast::Expression) -> CompileResult<(Value, ast::Type)> {
+        if let Some(value) = expr.integer_literal_value() {
+            let literal_type = self.expression_type(expr.span())?;
+            let value = self.integer_constant(&literal_type, value)?;
+            return Ok((value, literal_type));
+        }
+
+        match expr {
+            ast::Expression::IntegerLiteral { .. } => unreachable!(),
+
+            ast::Expression::FloatLiteral { value, span } => match self.expression_type(span)? {
+                ast::Type::F64 => Ok((self.builder.ins().f64const(*value), ast::Type::F64)),
+                _ => Ok((self.builder.ins().f32const(*value as f32), ast::Type::Float)),
+            },
+
+            ast::Expression::BooleanLiteral { value, .. } => {
+                Ok((self.builder.ins().iconst(types::I8, *value as i64), ast::Type::Bool))
+            }
+
+            ast::Expression::NullLiteral { .. } => {
+                Ok((self.builder.ins().iconst(types::I8, 0), ast::Type::Null))
+            }
+
+            ast::Expression::StringLiteral { value, .. } => {
+                let data_id = self.string_literal(value)?;
+                let global = self.module.declare_data_in_func(data_id, self.builder.func);
+                Ok((self.builder.ins().global_value(self.pointer_type, global), ast::Type::String))
+            }
+
+            // named functions can be used as values too
+            ast::Expression::Identifier { ident, .. } if self.variables.get(ident).is_none() && self.functions.contains_key(ident) => {
+                self.function_value(ident)
+            }
+
+            ast::Expression::Identifier { ident, .. } => {
+                let (place, var_type) = self.variable_place(ident)?;
+                let value = self.read_place(&place, &var_type)?;
+
+                // the result gets its own reference
+                self.retain(value, &var_type);
+
+                Ok((value, var_type))
+            }
+
+            ast::Expression::Binary { left, operator: ast::BinaryOperator::And, right, .. } => {
+                self.translate_short_circuit(left, right, true)
+            }
+
+            ast::Expression::Binary { left, operator: ast::BinaryOperator::Or, right, .. } => {
+                self.translate_short_circuit(left, right, false)
+            }
+
+            ast::Expression::Binary { left, operator, right, span } => {
+                let (lhs, ty, left_temporary) = self.translate_operand(left)?;
+                let (rhs, _, right_temporary) = self.translate_operand(right)?;
+
+                let result = self.translate_binary(operator, lhs, rhs, &ty, span)?;
+
+                self.release_operand(lhs, &ty, left_temporary);
+                self.release_operand(rhs, &ty, right_temporary);
+
+                Ok(result)
+            }
+
+            ast::Expression::Unary { operator, right, span } => {
+                let (value, ty) = self.translate_expression(right)?;
+
+                let value = match operator {
+                    ast::UnaryOperator::Minus if ty.is_float() => self.builder.ins().fneg(value),
+                    ast::UnaryOperator::Minus => self.negate(value, &ty, span)?,
+                    ast::UnaryOperator::Bang => self.builder.ins().icmp_imm(IntCC::Equal, value, 0),
+                };
+
+                Ok((value, ty))
+            }
+
+            ast::Expression::Grouping { expression, .. } => self.translate_expression(expression),
+
+            ast::Expression::Function { span, .. } => self.translate_lambda_closure(span),
+
+            ast::Expression::Call { callee, arguments, span } => {
+                // variables shadow functions of the same name, as in the type checker
+                let named = match callee.as_ref() {
+                    ast::Expression::Identifier { ident, .. } if self.variables.get(ident).is_none() => Some(ident),
+                    _ => None,
+                };
+
+                let Some(ident) = named else {
+                    return self.translate_closure_call(callee, arguments, span);
+                };
+
+                if let Some(builtin) = Builtin::from_ident(ident) {
+                    return self.translate_builtin(builtin, arguments);
+                }
+
+                let generic_functions = self.generics;
+                if let Some(signature) = generic_functions.get(ident) {
+                    return self.translate_generic_call(ident, signature, arguments, span);
+                }
+
+                let function = self.functions.get(ident).cloned().ok_or_else(|| {
+                    CompileError::SemanticError(
+                        format!("Call to undefined function `{}`", ident),
+                        span.clone(),
+                    )
+                })?;
+
+                let mut args = Vec::new();
+                for argument in arguments {
+                    args.push(self.translate_expression(argument)?.0);
+                }
+
+                self.translate_direct_call(ident, &function, &args)
+            }
+
+            ast::Expression::Postfix { left: operand, operator, span } => {
+                let delta = match operator {
+                    ast::PostfixOperator::PlusPlus => ast::BinaryOperator::Plus,
+                    ast::PostfixOperator::MinusMinus => ast::BinaryOperator::Minus,
+                };
+
+                let (old, _, ty) = self.translate_increment(operand, delta, span)?;
+                Ok((old, ty))
+            }
+
+            ast::Expression::Prefix { operator, right: operand, span } => {
+                let delta = match operator {
+                    ast::PrefixOperator::PlusPlus => ast::BinaryOperator::Plus,
+                    ast::PrefixOperator::MinusMinus => ast::BinaryOperator::Minus,
+                };
+
+                let (_, new, ty) = self.translate_increment(operand, delta, span)?;
+                Ok((new, ty))
+            }
+
+            ast::Expression::Assign { left, operator, right, span } => {
+                let (place, ty) = self.translate_place(left)?;
+
+                let operator = match operator {
+                    ast::AssignOperator::Equal => None,
+                    ast::AssignOperator::PlusEqual => Some(ast::BinaryOperator::Plus),
+                    ast::AssignOperator::MinusEqual => Some(ast::BinaryOperator::Minus),
+                    ast::AssignOperator::StarEqual => Some(ast::BinaryOperator::Star),
+                    ast::AssignOperator::SlashEqual => Some(ast::BinaryOperator::Slash),
+                };
+
+                let new = match operator {
+                    None => self.translate_expression(right)?.0,
+                    Some(operator) => {
+                        let (rhs, _, right_temporary) = self.translate_operand(right)?;
+                        let old = self.read_place(&place, &ty)?;
+                        let (new, _) = self.translate_binary(&operator, old, rhs, &ty, span)?;
+                        self.release_operand(rhs, &ty, right_temporary);
+                        new
+                    }
+                };
+
+                // the place takes over `new`, and the result of the
+                // expression needs its own reference
+                self.write_place(&place, &ty, new)?;
+                self.retain(new, &ty);
+                self.finish_place(place);
+
+                Ok((new, ty))
+            }
+
+            ast::Expression::Cast { expression, target_type, .. } => {
+                let (value, source_type) = self.translate_expression(expression)?;
+                Ok((self.translate_cast(value, &source_type, target_type)?, target_type.clone()))
+            }
+
+            ast::Expression::Index { target, index, span } => {
+                let (target, target_type, temporary) = self.translate_operand(target)?;
+                let index = self.translate_index(index)?;
+
+                let result = match target_type.element_type().cloned() {
+                    Some(element_type) => {
+                        let address = self.element_address(target, index, &element_type, span)?;
+                        let element = self.load(address, &element_type)?;
+                        self.retain(element, &element_type);
+
+                        (element, element_type)
+                    }
+                    None => {
+                        let (line, column) = self.location(span);
+                        let byte = self.call_runtime("kennedy_string_index", &[target, index, line, column]).unwrap();
+
+                        (byte, ast::Type::U8)
+                    }
+                };
+
+                self.release_operand(target, &target_type, temporary);
+                Ok(result)
+            }
+
+            ast::Expression::Slice { target, start, end, span } => {
+                let (target, target_type, temporary) = self.translate_operand(target)?;
+
+                let (len, slice, slice_type) = match target_type.element_type() {
+                    Some(element_type) => ("kennedy_array_len", "kennedy_array_slice", ast::Type::Array(Box::new(element_type.clone()))),
+                    None => ("kennedy_string_len", "kennedy_string_slice", ast::Type::String),
+                };
+
+                let start = match start {
+                    Some(start) => self.translate_index(start)?,
+                    None => self.builder.ins().iconst(types::I64, 0),
+                };
+                let end = match end {
+                    Some(end) => self.translate_index(end)?,
+                    None => self.call_runtime(len, &[target]).unwrap(),
+                };
+                let (line, column) = self.location(span);
+
+                let slice = self.call_runtime(slice, &[target, start, end, line, column]).unwrap();
+                self.release_operand(target, &target_type, temporary);
+
+                Ok((slice, slice_type))
+            }
+
+            ast::Expression::ArrayLiteral { elements, span } => {
+                self.translate_array_literal(elements, span)
+            }
+
+            ast::Expression::StructLiteral { ident, fields, span } => {
+                let declared = self.declared_struct(ident, span)?;
+
+                let global = self.module.declare_data_in_func(declared.descriptor, self.builder.func);
+                let descriptor = self.builder.ins().global_value(self.pointer_type, global);
+                let object = self.call_runtime("kennedy_struct_new", &[descriptor]).unwrap();
+
+                // fields are evaluated in the order they're written, and the
+                // struct takes over each value
+                for (field, value) in fields {
+                    let layout = declared.layout.field(field).unwrap();
+                    let (value, _) = self.translate_expression(value)?;
+
+                    let offset = runtime::STRUCT_FIELDS_OFFSET + layout.offset as i32;
+                    self.builder.ins().store(MemFlags::trusted(), value, object, offset);
+                }
+
+                Ok((object, ast::Type::Named(ident.clone())))
+            }
+
+            ast::Expression::Field { target, field, span } => {
+                let (object, object_type, temporary) = self.translate_operand(target)?;
+                let (offset, field_type) = self.field_offset(&object_type, field, span)?;
+
+                let value = self.builder.ins().load(
+                    cranelift_type(&field_type, self.pointer_type)?,
+                    MemFlags::trusted(),
+                    object,
+                    offset,
+                );
+                self.retain(value, &field_type);
+                self.release_operand(object, &object_type, temporary);
+
+                Ok((value, field_type))
+            }
+
+            ast::Expression::Variant { enum_ident, variant, arguments, span } => {
+                let (tag, declared) = self.declared_variant(enum_ident, variant, span)?;
+
+                let global = self.module.declare_data_in_func(declared.data, self.builder.func);
+                let data = self.builder.ins().global_value(self.pointer_type, global);
+
+                // variants carrying nothing are a single immortal object
+                if arguments.is_empty() {
+                    return Ok((data, ast::Type::Named(enum_ident.clone())));
+                }
+
+                let object = self.call_runtime("kennedy_struct_new", &[data]).unwrap();
+
+                let tag = self.builder.ins().iconst(types::I32, tag as i64);
+                self.builder.ins().store(MemFlags::trusted(), tag, object, runtime::STRUCT_FIELDS_OFFSET);
+
+                // the first field is the tag
+                for (argument, field) in arguments.iter().zip(&declared.layout.fields[1..]) {
+                    let (value, _) = self.translate_expression(argument)?;
+
+                    let offset = runtime::STRUCT_FIELDS_OFFSET + field.offset as i32;
+                    self.builder.ins().store(MemFlags::trusted(), value, object, offset);
+                }
+
+                Ok((object, ast::Type::Named(enum_ident.clone())))
+            }
+
+            ast::Expression::Match { scrutinee, arms, span } => self.translate_match(scrutinee, arms, span),
+        }
+    }
+
+    /// Call the instance of a generic function for the type arguments
+    /// inferred from the arguments, the same way the type checker does
+    fn translate_generic_call(
+        &mut self,
+        ident: &str,
+        signature: &FunctionSignature,
+        arguments: &[ast::Expression],
+        span: &Span,
+    ) -> CompileResult<(Value, ast::Type)> {
+        let (args, type_args) = generics::infer_call(ident, signature, arguments, |argument, _| {
+            self.translate_expression(argument)
+        })?;
+
+        let instance = generics::instance_ident(ident, &type_args);
+        let function = self.functions.get(&instance).cloned().ok_or_else(|| {
+            CompileError::SemanticError(format!("Call to undefined function `{}`", instance), span.clone())
+        })?;
+
+        self.translate_direct_call(&instance, &function, &args)
+    }
+
+    /// Call a function by name with arguments already translated, or
+    /// translate its body in place of the call if it's inlined
+    pub(super) fn translate_direct_call(
+        &mut self,
+        ident: &str,
+        function: &DeclaredFunction,
+        args: &[Value],
+    ) -> CompileResult<(Value, ast::Type)> {
+        let inlinable = self.inlinable;
+        if let Some(body) = inlinable.get(ident).filter(|_| self.can_inline(ident)) {
+            return self.translate_inlined_call(body, args);
+        }
+
+        let func_ref = self.func_ref(function.id);
+        let call = self.builder.ins().call(func_ref, args);
+
+        let value = match self.builder.inst_results(call).first() {
+            Some(value) => *value,
+            None => self.builder.ins().iconst(types::I8, 0),
+        };
+
+        Ok((value, function.signature.return_type.clone()))
+    }
+
+    /// Whether a call to `ident` can be inlined here: not into a call to
+    /// itself, which would never end, nor too deep
+    fn can_inline(&self, ident: &str) -> bool {
+        self.inlined.len() < MAX_INLINE_DEPTH
+            && self.owner != ident
+            && self.inlined.iter().all(|call| call.owner != ident)
+    }
+
+    /// Translate the body of `function` as if it had been called with
+    /// `args`, whose ownership it takes like a callee does
+    fn translate_inlined_call(&mut self, function: &ast::Function, args: &[Value]) -> CompileResult<(Value, ast::Type)> {
+        let exit = self.builder.create_block();
+        if function.return_type != ast::Type::Null {
+            let ty = cranelift_type(&function.return_type, self.pointer_type)?;
+            self.builder.append_block_param(exit, ty);
+        }
+
+        // the callee sees its own variables, and none of the caller's
+        self.inlined.push(InlinedCall {
+            exit,
+            owner: std::mem::replace(&mut self.owner, function.ident.clone()),
+            return_type: std::mem::replace(&mut self.return_type, function.return_type.clone()),
+            variables: std::mem::take(&mut self.variables),
+            owned_scopes: std::mem::take(&mut self.owned_scopes),
+        });
+
+        self.push_scope();
+        for (param, value) in function.params.params.iter().zip(args) {
+            self.declare_variable(&param.ident, &param.param_type, *value)?;
+        }
+
+        self.translate_block(&function.body)?;
+        self.pop_scope();
+
+        if !self.builder.is_unreachable() {
+            if self.return_type == ast::Type::Null {
+                self.builder.ins().jump(exit, &[]);
+            } else {
+                self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+            }
+        }
+
+        let call = self.inlined.pop().unwrap();
+        self.owner = call.owner;
+        self.return_type = call.return_type;
+        self.variables = call.variables;
+        self.owned_scopes = call.owned_scopes;
+
+        self.builder.switch_to_block(exit);
+        let value = match self.builder.block_params(exit).first() {
+            Some(value) => *value,
+            None => self.builder.ins().iconst(types::I8, 0),
+        };
+
+        Ok((value, function.return_type.clone()))
+    }
+
+    /// Create the closure for the anonymous function written at `span`,
+    /// copying in the values it captures
+    fn translate_lambda_closure(&mut self, span: &Span) -> CompileResult<(Value, ast::Type)> {
+        let lambdas = self.lambdas;
+        let lambda = lambdas.get(&(self.owner.clone(), span.clone())).ok_or_else(|| {
+            CompileError::SemanticError("Unknown anonymous function".to_string(), span.clone())
+        })?;
+
+        let global = self.module.declare_data_in_func(lambda.descriptor, self.builder.func);
+        let descriptor = self.builder.ins().global_value(self.pointer_type, global);
+        let closure = self.call_runtime("kennedy_struct_new", &[descriptor]).unwrap();
+
+        let func_ref = self.func_ref(lambda.id);
+        let code = self.builder.ins().func_addr(self.pointer_type, func_ref);
+        self.builder.ins().store(MemFlags::trusted(), code, closure, runtime::STRUCT_FIELDS_OFFSET);
+
+        // the closure holds its own reference to each captured value
+        for field in &lambda.captures.fields {
+            let (variable, _) = self.variable(&field.ident)?;
+            let value = self.builder.use_var(variable);
+            self.retain(value, &field.field_type);
+
+            let offset = runtime::STRUCT_FIELDS_OFFSET + field.offset as i32;
+            self.builder.ins().store(MemFlags::trusted(), value, closure, offset);
+        }
+
+        let function_type = ast::Type::Function(
+            lambda.signature.params.clone(),
+            Box::new(lambda.signature.return_type.clone()),
+        );
+
+        Ok((closure, function_type))
+    }
+
+    /// An immortal closure calling the named function `ident`, defining it
+    /// the first time the function is used as a value
+    fn function_value(&mut self, ident: &str) -> CompileResult<(Value, ast::Type)> {
+        let function = self.functions[ident].clone();
+
+        if !self.function_values.contains_key(ident) {
+            let params = &function.signature.params;
+            let sig = signature(&*self.module, true, params, &function.signature.return_type)?;
+
+            let thunk = self.module.declare_anonymous_function(&sig)
+                .map_err(|e| CompileError::CompileError(e.to_string()))?;
+            let closure = self.module.declare_anonymous_data(false, false)
+                .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+            // the code pointer is filled in when the module is finalized
+            let code = vec![0; self.pointer_type.bytes() as usize];
+            self.data_ctx.define(runtime::immortal_struct_data(&code));
+            self.data_ctx.set_align(std::mem::align_of::<runtime::KennedyStruct>() as u64);
+
+            let func_ref = self.module.declare_func_in_data(thunk, self.data_ctx);
+            self.data_ctx.write_function_addr(runtime::STRUCT_FIELDS_OFFSET as u32, func_ref);
+
+            self.module.define_data(closure, self.data_ctx)
+                .map_err(|e| CompileError::CompileError(e.to_string()))?;
+            self.data_ctx.clear();
+
+            self.function_values.insert(ident.to_string(), FunctionValue { thunk, closure, defined: false });
+        }
+
+        let global = self.module.declare_data_in_func(self.function_values[ident].closure, self.builder.func);
+        let closure = self.builder.ins().global_value(self.pointer_type, global);
+
+        let function_type = ast::Type::Function(
+            function.signature.params,
+            Box::new(function.signature.return_type),
+        );
+
+        Ok((closure, function_type))
+    }
+
+    /// Call a closure, passing the closure itself along with the arguments
+    fn translate_closure_call(
+        &mut self,
+        callee: &ast::Expression,
+        arguments: &[ast::Expression],
+        span: &Span,
+    ) -> CompileResult<(Value, ast::Type)> {
+        let (closure, callee_type, temporary) = self.translate_operand(callee)?;
+
+        let ast::Type::Function(params, return_type) = &callee_type else {
+            return Err(CompileError::SemanticError(
+                format!("Cannot call {}", callee_type),
+                span.clone(),
+            ));
+        };
+
+        let mut args = vec![closure];
+        for argument in arguments {
+            args.push(self.translate_expression(argument)?.0);
+        }
+
+        let sig = signature(&*self.module, true, params, return_type)?;
+        let sig_ref = self.builder.import_signature(sig);
+        let code = self.builder.ins().load(self.pointer_type, MemFlags::trusted(), closure, runtime::STRUCT_FIELDS_OFFSET);
+        let call = self.builder.ins().call_indirect(sig_ref, code, &args);
+
+        let value = match self.builder.inst_results(call).first() {
+            Some(value) => *value,
+            None => self.builder.ins().iconst(types::I8, 0),
+        };
+
+        self.release_operand(closure, &callee_type, temporary);
+
+        Ok((value, *return_type.clone()))
+    }
+
+    /// `match (x) { ... }`
+    /// The value matched on is held by a hidden variable for the length of
+    /// the match, so it is released however the match is left. Enum values
+    /// jump on their tag through a jump table; integers and bools go through
+    /// a `Switch`, which uses jump tables for dense ranges of values
+    fn translate_match(
+        &mut self,
+        scrutinee: &ast::Expression,
+        arms: &[ast::MatchArm],
+        span: &Span,
+    ) -> CompileResult<(Value, ast::Type)> {
+        self.push_scope();
+
+        let (value, scrutinee_type) = self.translate_expression(scrutinee)?;
+        self.declare_variable(MATCH_SCRUTINEE, &scrutinee_type, value)?;
+
+        let arm_blocks: Vec<Block> = arms.iter().map(|_| self.builder.create_block()).collect();
+        let wildcard = arms.iter().position(|arm| matches!(arm.pattern, ast::Pattern::Wildcard { .. }));
+
+        // without a wildcard the arms cover every value, so nothing gets here
+        let otherwise = match wildcard {
+            Some(i) => arm_blocks[i],
+            None => {
+                let block = self.builder.create_block();
+                self.builder.set_cold_block(block);
+                block
+            }
+        };
+
+        match &scrutinee_type {
+            ast::Type::Named(ident) => {
+                let declared = self.declared_enum(ident, span)?;
+
+                let mut targets = vec![otherwise; declared.variants.len()];
+                for (arm, block) in arms.iter().zip(&arm_blocks) {
+                    if let ast::Pattern::Variant { variant, span, .. } = &arm.pattern {
+                        let (tag, _) = self.declared_variant(ident, variant, span)?;
+                        targets[tag] = *block;
+                    }
+                }
+
+                let tag = self.builder.ins().load(types::I32, MemFlags::trusted(), value, runtime::STRUCT_FIELDS_OFFSET);
+
+                let pool = &mut self.builder.func.dfg.value_lists;
+                let default = BlockCall::new(otherwise, &[], pool);
+                let targets: Vec<BlockCall> = targets.into_iter()
+                    .map(|block| BlockCall::new(block, &[], pool))
+                    .collect();
+
+                let table = self.builder.create_jump_table(JumpTableData::new(default, &targets));
+                self.builder.ins().br_table(tag, table);
+            }
+            _ => {
+                // entries are the values' bit patterns, zero extended
+                let bits = cranelift_type(&scrutinee_type, self.pointer_type)?.bits();
+                let mask = (1u128 << bits) - 1;
+
+                let mut switch = Switch::new();
+                for (arm, block) in arms.iter().zip(&arm_blocks) {
+                    match arm.pattern {
+                        ast::Pattern::Integer { value, .. } => switch.set_entry(value as u128 & mask, *block),
+                        ast::Pattern::Boolean { value, .. } => switch.set_entry(value as u128, *block),
+                        _ => {}
+                    }
+                }
+
+                switch.emit(&mut self.builder, value, otherwise);
+            }
+        }
+
+        if wildcard.is_none() {
+            self.builder.switch_to_block(otherwise);
+            self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+        }
+
+        let merge_block = self.builder.create_block();
+        for (arm, block) in arms.iter().zip(arm_blocks) {
+            self.translate_arm(&scrutinee_type, value, arm, block, merge_block)?;
+        }
+
+        let result_type = self.expression_type(span)?;
+
+        let result = self.builder.append_block_param(merge_block, cranelift_type(&result_type, self.pointer_type)?);
+        self.builder.switch_to_block(merge_block);
+        self.pop_scope();
+
+        Ok((result, result_type))
+    }
+
+    /// Translate one arm of a match into `block`, binding the values of the
+    /// variant it matched, then jump to `merge_block` with its value
+    /// Block bodies have no value, and pass a null placeholder
+    fn translate_arm(
+        &mut self,
+        scrutinee_type: &ast::Type,
+        scrutinee: Value,
+        arm: &ast::MatchArm,
+        block: Block,
+        merge_block: Block,
+    ) -> CompileResult<()> {
+        self.builder.switch_to_block(block);
+        self.push_scope();
+
+        if let (ast::Type::Named(ident), ast::Pattern::Variant { variant, bindings, span, .. }) = (scrutinee_type, &arm.pattern) {
+            let (_, declared) = self.declared_variant(ident, variant, span)?;
+
+            // the first field is the tag
+            for (binding, field) in bindings.iter().zip(&declared.layout.fields[1..]) {
+                if binding == "_" {
+                    continue;
+                }
+
+                let value = self.builder.ins().load(
+                    cranelift_type(&field.field_type, self.pointer_type)?,
+                    MemFlags::trusted(),
+                    scrutinee,
+                    runtime::STRUCT_FIELDS_OFFSET + field.offset as i32,
+                );
+                self.retain(value, &field.field_type);
+                self.declare_variable(binding, &field.field_type, value)?;
+            }
+        }
+
+        let value = match &arm.body {
+            ast::MatchBody::Expression(body) => self.translate_expression(body)?.0,
+            ast::MatchBody::Block(body) => {
+                self.translate_block(body)?;
+                self.builder.ins().iconst(types::I8, 0)
+            }
+        };
+
+        self.pop_scope();
+        self.builder.ins().jump(merge_block, &[value]);
+
+        Ok(())
+    }
+
+    /// The enum called `ident`
+    fn declared_enum(&self, ident: &str, span: &Span) -> CompileResult<&'a DeclaredEnum> {
+        let enums = self.enums;
+
+        enums.get(ident).ok_or_else(|| {
+            CompileError::SemanticError(format!("Unknown enum `{}`", ident), span.clone())
+        })
+    }
+
+    /// Tag and declaration of a variant of the enum called `enum_ident`
+    fn declared_variant(&self, enum_ident: &str, variant: &str, span: &Span) -> CompileResult<(usize, &'a DeclaredVariant)> {
+        self.declared_enum(enum_ident, span)?.variant(variant).ok_or_else(|| {
+            CompileError::SemanticError(
+                format!("Enum `{}` has no variant `{}`", enum_ident, variant),
+                span.clone(),
+            )
+        })
+    }
+
+    /// The struct called `ident`
+    fn declared_struct(&self, ident: &str, span: &Span) -> CompileResult<&'a DeclaredStruct> {
+        let structs = self.structs;
+
+        structs.get(ident).ok_or_else(|| {
+            CompileError::SemanticError(format!("Unknown struct `{}`", ident), span.clone())
+        })
+    }
+
+    /// Offset from the start of a struct, and type, of one of its fields
+    fn field_offset(&self, object_type: &ast::Type, field: &str, span: &Span) -> CompileResult<(i32, ast::Type)> {
+        let layout = match object_type {
+            ast::Type::Named(ident) => self.declared_struct(ident, span)?.layout.field(field),
+            _ => None,
+        };
+
+        let layout = layout.ok_or_else(|| {
+            CompileError::SemanticError(
+                format!("{} has no field `{}`", object_type, field),
+                span.clone(),
+            )
+        })?;
+
+        Ok((runtime::STRUCT_FIELDS_OFFSET + layout.offset as i32, layout.field_type.clone()))
+    }
+
+    /// `[a, b, c]`
+    fn translate_array_literal(&mut self, elements: &[ast::Expression], span: &Span) -> CompileResult<(Value, ast::Type)> {
+        let array_type = self.expression_type(span)?;
+        let element_type = array_type.element_type().cloned().ok_or_else(|| {
+            CompileError::SemanticError(format!("Expected an array type, got {}", array_type), span.clone())
+        })?;
+
+        // the array takes over each element
+        let mut values = Vec::new();
+        for element in elements {
+            values.push(self.translate_expression(element)?.0);
+        }
+
+        let element_size = cranelift_type(&element_type, self.pointer_type)?.bytes() as i64;
+        let element_kind = element_kind(&element_type);
+
+        let len = self.builder.ins().iconst(self.pointer_type, values.len() as i64);
+        let element_size = self.builder.ins().iconst(self.pointer_type, element_size);
+        let element_kind = self.builder.ins().iconst(self.pointer_type, element_kind as i64);
+        let array = self.call_runtime("kennedy_array_new", &[len, element_size, element_kind]).unwrap();
+
+        let data = self.builder.ins().load(self.pointer_type, MemFlags::trusted(), array, runtime::ARRAY_DATA_OFFSET);
+        for (i, value) in values.into_iter().enumerate() {
+            let offset = i as i32 * self.builder.func.dfg.value_type(value).bytes() as i32;
+            self.builder.ins().store(MemFlags::trusted(), value, data, offset);
+        }
+
+        Ok((array, array_type))
+    }
+
+    /// Address of `array[index]`, stopping with a runtime error pointing at
+    /// `span` if the index is out of bounds
+    fn element_address(
+        &mut self,
+        array: Value,
+        index: Value,
+        element_type: &ast::Type,
+        span: &Span,
+    ) -> CompileResult<Value> {
+        let element_size = cranelift_type(element_type, self.pointer_type)?.bytes() as i64;
+
+        // negative indices are huge unsigned ones, so one comparison covers both ends
+        let len = self.builder.ins().load(self.pointer_type, MemFlags::trusted(), array, runtime::ARRAY_LEN_OFFSET);
+        let len = self.extend(len, false, types::I64);
+        let out_of_bounds = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, index, len);
+        self.trap_if(out_of_bounds, TrapKind::IndexOutOfBounds, span);
+
+        let data = self.builder.ins().load(self.pointer_type, MemFlags::trusted(), array, runtime::ARRAY_DATA_OFFSET);
+        let mut offset = self.builder.ins().imul_imm(index, element_size);
+        if self.pointer_type != types::I64 {
+            offset = self.builder.ins().ireduce(self.pointer_type, offset);
+        }
+
+        Ok(self.builder.ins().iadd(data, offset))
+    }
+
+    fn load(&mut self, address: Value, ty: &ast::Type) -> CompileResult<Value> {
+        let cl_type = cranelift_type(ty, self.pointer_type)?;
+        Ok(self.builder.ins().load(cl_type, MemFlags::trusted(), address, 0))
+    }
+
+    /// Evaluate the target of an assignment
+    fn translate_place(&mut self, expr: &ast::Expression) -> CompileResult<(Place, ast::Type)> {
+        match expr {
+            ast::Expression::Index { target, index, span } => {
+                let (array, array_type, temporary) = self.translate_operand(target)?;
+                let element_type = array_type.element_type().cloned()
+                    .ok_or_else(|| invalid_assignment_target(expr))?;
+                let index = self.translate_index(index)?;
+
+                let place = Place::Element { array, array_type, temporary, index, span: span.clone() };
+                Ok((place, element_type))
+            }
+            ast::Expression::Field { target, field, span } => {
+                let (object, object_type, temporary) = self.translate_operand(target)?;
+                let (offset, field_type) = self.field_offset(&object_type, field, span)?;
+
+                Ok((Place::Field { object, object_type, temporary, offset }, field_type))
+            }
+            _ => self.variable_place(assignment_target(expr)?),
+        }
+    }
+
+    /// Where a variable is kept: a Cranelift variable, or the data object of
+    /// a global if no variable shadows it
+    fn variable_place(&mut self, ident: &String) -> CompileResult<(Place, ast::Type)> {
+        if let Some((variable, ty)) = self.variables.get(ident).cloned() {
+            return Ok((Place::Variable(variable), ty));
+        }
+
+        let Some(global) = self.globals.get(ident) else {
+            let (variable, ty) = self.variable(ident)?;
+            return Ok((Place::Variable(variable), ty));
+        };
+
+        let data = self.module.declare_data_in_func(global.id, self.builder.func);
+        let address = self.builder.ins().global_value(self.pointer_type, data);
+        Ok((Place::Global(address), global.global_type.clone()))
+    }
+
+    /// Current value of a place, borrowed
+    fn read_place(&mut self, place: &Place, ty: &ast::Type) -> CompileResult<Value> {
+        match place {
+            Place::Variable(variable) => Ok(self.builder.use_var(*variable)),
+            Place::Global(address) => self.load(*address, ty),
+            Place::Element { array, index, span, .. } => {
+                let address = self.element_address(*array, *index, ty, span)?;
+                self.load(address, ty)
+            }
+            Place::Field { object, offset, .. } => {
+                let cl_type = cranelift_type(ty, self.pointer_type)?;
+                Ok(self.builder.ins().load(cl_type, MemFlags::trusted(), *object, *offset))
+            }
+        }
+    }
+
+    /// Store a value in a place, which takes it over and lets go of its old value
+    fn write_place(&mut self, place: &Place, ty: &ast::Type, value: Value) -> CompileResult<()> {
+        match place {
+            Place::Variable(variable) => {
+                let old = self.builder.use_var(*variable);
+                self.release_temporary(old, ty);
+                self.builder.def_var(*variable, value);
+            }
+            Place::Global(address) => {
+                if ty.is_refcounted() {
+                    let old = self.load(*address, ty)?;
+                    self.release_temporary(old, ty);
+                }
+
+                self.builder.ins().store(MemFlags::trusted(), value, *address, 0);
+            }
+            Place::Element { array, index, span, .. } => {
+                let address = self.element_address(*array, *index, ty, span)?;
+
+                if ty.is_refcounted() {
+                    let old = self.load(address, ty)?;
+                    self.release_temporary(old, ty);
+                }
+
+                self.builder.ins().store(MemFlags::trusted(), value, address, 0);
+            }
+            Place::Field { object, offset, .. } => {
+                if ty.is_refcounted() {
+                    let old = self.read_place(place, ty)?;
+                    self.release_temporary(old, ty);
+                }
+
+                self.builder.ins().store(MemFlags::trusted(), value, *object, *offset);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Release whatever was keeping a place alive
+    fn finish_place(&mut self, place: Place) {
+        match place {
+            Place::Variable(_) | Place::Global(_) => {}
+            Place::Element { array, array_type, temporary, .. } => {
+                self.release_operand(array, &array_type, temporary);
+            }
+            Place::Field { object, object_type, temporary, .. } => {
+                self.release_operand(object, &object_type, temporary);
+            }
+        }
+    }
+
+    /// An index or slice bound, as a 64 bit integer
+    fn translate_index(&mut self, index: &ast::Expression) -> CompileResult<Value> {
+        let (index, index_type) = self.translate_expression(index)?;
+        Ok(self.extend(index, index_type.is_signed(), types::I64))
+    }
+
+    fn translate_builtin(&mut self, builtin: Builtin, arguments: &[ast::Expression]) -> CompileResult<(Value, ast::Type)> {
+        match builtin {
+            Builtin::Len => {
+                let (target, target_type, temporary) = self.translate_operand(&arguments[0])?;
+
+                let len = if target_type == ast::Type::String {
+                    self.call_runtime("kennedy_string_len", &[target]).unwrap()
+                } else {
+                    self.call_runtime("kennedy_array_len", &[target]).unwrap()
+                };
+                self.release_operand(target, &target_type, temporary);
+
+                Ok((len, ast::Type::Int))
+            }
+
+            Builtin::Push => {
+                let (array, array_type, temporary) = self.translate_operand(&arguments[0])?;
+
+                // the array takes over the new element
+                let (value, _) = self.translate_expression(&arguments[1])?;
+                let slot = self.call_runtime("kennedy_array_push_slot", &[array]).unwrap();
+                self.builder.ins().store(MemFlags::trusted(), value, slot, 0);
+
+                self.release_operand(array, &array_type, temporary);
+                Ok((self.builder.ins().iconst(types::I8, 0), ast::Type::Null))
+            }
+        }
+    }
+
+    /// Data object holding a string literal, defining it the first time
+    fn string_literal(&mut self, value: &str) -> CompileResult<DataId> {
+        string_literal(self.module, self.data_ctx, self.string_literals, value)
+    }
+
+    /// `and`/`or`, only evaluating the right operand when it matters
+    fn translate_short_circuit(
+        &mut self,
+        left: &ast::Expression,
+        right: &ast::Expression,
+        is_and: bool,
+    ) -> CompileResult<(Value, ast::Type)> {
+        let (lhs, _) = self.translate_expression(left)?;
+
+        let right_block = self.builder.create_block();
+        let merge_block = self.builder.create_block();
+        self.builder.append_block_param(merge_block, types::I8);
+
+        if is_and {
+            self.builder.ins().brif(lhs, right_block, &[], merge_block, &[lhs]);
+        } else {
+            self.builder.ins().brif(lhs, merge_block, &[lhs], right_block, &[]);
+        }
+
+        self.builder.switch_to_block(right_block);
+        let (rhs, _) = self.translate_expression(right)?;
+        self.builder.ins().jump(merge_block, &[rhs]);
+
+        self.builder.switch_to_block(merge_block);
+        Ok((self.builder.block_params(merge_block)[0], ast::Type::Bool))
+    }
+
+    /// Arithmetic and comparisons on two values of type `ty`
+    pub(super) fn translate_binary(
+        &mut self,
+        operator: &ast::BinaryOperator,
+        lhs: Value,
+        rhs: Value,
+        ty: &ast::Type,
+        span: &Span,
+    ) -> CompileResult<(Value, ast::Type)> {
+        use ast::BinaryOperator as Op;
+
+        if *ty == ast::Type::String {
+            let value = match operator {
+                Op::Plus => self.call_runtime("kennedy_string_concat", &[lhs, rhs]).unwrap(),
+                Op::EqualEqual => self.call_runtime("kennedy_string_eq", &[lhs, rhs]).unwrap(),
+                Op::BangEqual => {
+                    let equal = self.call_runtime("kennedy_string_eq", &[lhs, rhs]).unwrap();
+                    self.builder.ins().icmp_imm(IntCC::Equal, equal, 0)
+                }
+                _ => return Err(unsupported_operator(operator, ty, span)),
+            };
+
+            let result_type = if *operator == Op::Plus { ast::Type::String } else { ast::Type::Bool };
+            return Ok((value, result_type));
+        }
+
+        if let Some(cc) = comparison(operator) {
+            let value = if ty.is_float() {
+                self.builder.ins().fcmp(float_cc(cc), lhs, rhs)
+            } else {
+                self.builder.ins().icmp(int_cc(cc, ty.is_signed()), lhs, rhs)
+            };
+
+            return Ok((value, ast::Type::Bool));
+        }
+
+        let value = if ty.is_float() {
+            match operator {
+                Op::Plus => self.builder.ins().fadd(lhs, rhs),
+                Op::Minus => self.builder.ins().fsub(lhs, rhs),
+                Op::Star => self.builder.ins().fmul(lhs, rhs),
+                Op::Slash => self.builder.ins().fdiv(lhs, rhs),
+                _ => return Err(unsupported_operator(operator, ty, span)),
+            }
+        } else {
+            match operator {
+                Op::Plus => self.add(lhs, rhs, ty, span)?,
+                Op::Minus => self.sub(lhs, rhs, ty, span)?,
+                Op::Star => self.mul(lhs, rhs, ty, span)?,
+                Op::Slash => self.div(lhs, rhs, ty, span)?,
+                _ => return Err(unsupported_operator(operator, ty, span)),
+            }
+        };
+
+        Ok((value, ty.clone()))
+    }
+
+    /// `x++`, `++x`, `x--` and `--x`
+    /// Returns the old value, the new value and the type of the operand
+    fn translate_increment(
+        &mut self,
+        operand: &ast::Expression,
+        operator: ast::BinaryOperator,
+        span: &Span,
+    ) -> CompileResult<(Value, Value, ast::Type)> {
+        let (place, ty) = self.translate_place(operand)?;
+
+        let old = self.read_place(&place, &ty)?;
+        let one = self.integer_constant(&ty, 1)?;
+        let (new, _) = self.translate_binary(&operator, old, one, &ty, span)?;
+        self.write_place(&place, &ty, new)?;
+        self.finish_place(place);
+
+        Ok((old, new, ty))
+    }
+
+    pub(super) fn translate_cast(&mut self, value: Value, from: &ast::Type, to: &ast::Type) -> CompileResult<Value> {
+        let to_type = cranelift_type(to, self.pointer_type)?;
+
+        if from.same_as(to) {
+            return Ok(value);
+        }
+
+        if *to == ast::Type::String {
+            let string = if *from == ast::Type::Float {
+                self.call_runtime("kennedy_string_from_float", &[value])
+            } else if *from == ast::Type::F64 {
+                self.call_runtime("kennedy_string_from_f64", &[value])
+            } else if *from == ast::Type::Bool {
+                self.call_runtime("kennedy_string_from_bool", &[value])
+            } else if from.is_signed() {
+                let wide = self.extend(value, true, types::I64);
+                self.call_runtime("kennedy_string_from_int", &[wide])
+            } else {
+                let wide = self.extend(value, false, types::I64);
+                self.call_runtime("kennedy_string_from_uint", &[wide])
+            };
+
+            return Ok(string.unwrap());
+        }
+
+        // bools are already 0/1 integers
+        let from_signed = from.is_signed();
+
+        let value = match (from.is_float(), to.is_float()) {
+            // int -> int
+            (false, false) => {
+                let from_bits = cranelift_type(from, self.pointer_type)?.bits();
+
+                if to_type.bits() < from_bits {
+                    self.builder.ins().ireduce(to_type, value)
+                } else if to_type.bits() > from_bits {
+                    self.extend(value, from_signed, to_type)
+                } else {
+                    value
+                }
+            }
+
+            // int -> float
+            (false, true) => {
+                let wide = self.extend(value, from_signed, types::I64);
+
+                if from_signed {
+                    self.builder.ins().fcvt_from_sint(to_type, wide)
+                } else {
+                    self.builder.ins().fcvt_from_uint(to_type, wide)
+                }
+            }
+
+            // float -> int saturates to the 64 bit range, then truncates
+            (true, false) => {
+                let wide = if to.is_signed() {
+                    self.builder.ins().fcvt_to_sint_sat(types::I64, value)
+                } else {
+                    self.builder.ins().fcvt_to_uint_sat(types::I64, value)
+                };
+
+                if to_type == types::I64 {
+                    wide
+                } else {
+                    self.builder.ins().ireduce(to_type, wide)
+                }
+            }
+
+            // float <-> f64
+            (true, true) if *to == ast::Type::F64 => self.builder.ins().fpromote(to_type, value),
+            (true, true) => self.builder.ins().fdemote(to_type, value),
+        };
+
+        Ok(value)
+    }
+
+    /// Sign or zero extend `value` to `to`, if it isn't that wide already
+    fn extend(&mut self, value: Value, signed: bool, to: Type) -> Value {
+        if self.builder.func.dfg.value_type(value) == to {
+            value
+        } else if signed {
+            self.builder.ins().sextend(to, value)
+        } else {
+            self.builder.ins().uextend(to, value)
+        }
+    }
+
+    /// Constant of an integer type
+    pub(super) fn integer_constant(&mut self, ty: &ast::Type, value: i128) -> CompileResult<Value> {
+        let cl_type = cranelift_type(ty, self.pointer_type)?;
+
+        // narrow immediates are stored zero extended
+        let bits = cl_type.bits();
+        let value = if bits < 64 {
+            (value & ((1i128 << bits) - 1)) as i64
+        } else {
+            value as i64
+        };
+
+        Ok(self.builder.ins().iconst(cl_type, value))
+    }
+
+    /// Smallest value of a signed integer type
+    fn signed_min(&mut self, ty: &ast::Type) -> CompileResult<Value> {
+        let (min, _) = ty.integer_range().unwrap();
+        self.integer_constant(ty, min)
+    }
+
+    fn add(&mut self, lhs: Value, rhs: Value, ty: &ast::Type, span: &Span) -> CompileResult<Value> {
+        let result = self.builder.ins().iadd(lhs, rhs);
+
+        if self.overflow_mode == OverflowMode::Checked {
+            let overflow = if ty.is_signed() {
+                // overflowed if both operands have a different sign to the result
+                let a = self.builder.ins().bxor(lhs, result);
+                let b = self.builder.ins().bxor(rhs, result);
+                let both = self.builder.ins().band(a, b);
+                self.builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0)
+            } else {
+                self.builder.ins().icmp(IntCC::UnsignedLessThan, result, lhs)
+            };
+
+            self.trap_if(overflow, TrapKind::IntegerOverflow, span);
+        }
+
+        Ok(result)
+    }
+
+    fn sub(&mut self, lhs: Value, rhs: Value, ty: &ast::Type, span: &Span) -> CompileResult<Value> {
+        let result = self.builder.ins().isub(lhs, rhs);
+
+        if self.overflow_mode == OverflowMode::Checked {
+            let overflow = if ty.is_signed() {
+                // overflowed if the operands differ in sign and the result
+                // doesn't have the sign of the left operand
+                let a = self.builder.ins().bxor(lhs, rhs);
+                let b = self.builder.ins().bxor(lhs, result);
+                let both = self.builder.ins().band(a, b);
+                self.builder.ins().icmp_imm(IntCC::SignedLessThan, both, 0)
+            } else {
+                self.builder.ins().icmp(IntCC::UnsignedLessThan, lhs, rhs)
+            };
+
+            self.trap_if(overflow, TrapKind::IntegerOverflow, span);
+        }
+
+        Ok(result)
+    }
+
+    fn mul(&mut self, lhs: Value, rhs: Value, ty: &ast::Type, span: &Span) -> CompileResult<Value> {
+        if self.overflow_mode == OverflowMode::Wrapping {
+            return Ok(self.builder.ins().imul(lhs, rhs));
+        }
+
+        let cl_type = cranelift_type(ty, self.pointer_type)?;
+        let signed = ty.is_signed();
+
+        let (result, overflow) = if cl_type == types::I64 {
+            // the high half of the full product must just be the sign (or
+            // zero) extension of the low half
+            let result = self.builder.ins().imul(lhs, rhs);
+
+            let overflow = if signed {
+                let high = self.builder.ins().smulhi(lhs, rhs);
+                let sign = self.builder.ins().sshr_imm(result, 63);
+                self.builder.ins().icmp(IntCC::NotEqual, high, sign)
+            } else {
+                let high = self.builder.ins().umulhi(lhs, rhs);
+                self.builder.ins().icmp_imm(IntCC::NotEqual, high, 0)
+            };
+
+            (result, overflow)
+        } else {
+            // narrow products fit in 64 bits, so multiply there and check
+            // the result survives the round trip back to the narrow type
+            let wide_lhs = self.extend(lhs, signed, types::I64);
+            let wide_rhs = self.extend(rhs, signed, types::I64);
+            let wide = self.builder.ins().imul(wide_lhs, wide_rhs);
+
+            let result = self.builder.ins().ireduce(cl_type, wide);
+            let round_trip = self.extend(result, signed, types::I64);
+            let overflow = self.builder.ins().icmp(IntCC::NotEqual, round_trip, wide);
+
+            (result, overflow)
+        };
+
+        self.trap_if(overflow, TrapKind::IntegerOverflow, span);
+        Ok(result)
+    }
+
+    fn div(&mut self, lhs: Value, rhs: Value, ty: &ast::Type, span: &Span) -> CompileResult<Value> {
+        // dividing by zero is an error whatever the overflow mode
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+        self.trap_if(zero, TrapKind::DivisionByZero, span);
+
+        if !ty.is_signed() {
+            return Ok(self.builder.ins().udiv(lhs, rhs));
+        }
+
+        // MIN / -1 is the one signed division that overflows
+        let min = self.signed_min(ty)?;
+        let minus_one = self.integer_constant(ty, -1)?;
+        let is_min = self.builder.ins().icmp(IntCC::Equal, lhs, min);
+        let is_minus_one = self.builder.ins().icmp(IntCC::Equal, rhs, minus_one);
+        let overflow = self.builder.ins().band(is_min, is_minus_one);
+
+        if self.overflow_mode == OverflowMode::Checked {
+            self.trap_if(overflow, TrapKind::IntegerOverflow, span);
+        }
+
+        // sdiv would trap on MIN / -1, so divide by 1 instead: MIN is also
+        // the wrapped result
+        let one = self.integer_constant(ty, 1)?;
+        let divisor = self.builder.ins().select(overflow, one, rhs);
+
+        Ok(self.builder.ins().sdiv(lhs, divisor))
+    }
+
+    pub(super) fn negate(&mut self, value: Value, ty: &ast::Type, span: &Span) -> CompileResult<Value> {
+        if self.overflow_mode == OverflowMode::Checked {
+            // -MIN doesn't fit
+            let min = self.signed_min(ty)?;
+            let overflow = self.builder.ins().icmp(IntCC::Equal, value, min);
+            self.trap_if(overflow, TrapKind::IntegerOverflow, span);
+        }
+
+        Ok(self.builder.ins().ineg(value))
+    }
+
+    /// Stop with a runtime error pointing at `span` if `condition` is non-zero
+    fn trap_if(&mut self, condition: Value, kind: TrapKind, span: &Span) {
+        let trap_block = self.builder.create_block();
+        let continue_block = self.builder.create_block();
+        self.builder.set_cold_block(trap_block);
+
+        self.builder.ins().brif(condition, trap_block, &[], continue_block, &[]);
+
+        self.builder.switch_to_block(trap_block);
+        let kind = self.builder.ins().iconst(types::I32, kind as i64);
+        let (line, column) = self.location(span);
+        self.call_runtime("kennedy_trap", &[kind, line, column]);
+        // kennedy_trap never returns
+        self.builder.ins().trap(TrapCode::UnreachableCodeReached);
+
+        self.builder.switch_to_block(continue_block);
+    }
+
+    /// 1-based line and column of a span, as arguments for the runtime
+    fn location(&mut self, span: &Span) -> (Value, Value) {
+        let (line, column) = self.sources.location(span);
+
+        (
+            self.builder.ins().iconst(types::I32, line as i64),
+            self.builder.ins().iconst(types::I32, column as i64),
+        )
+    }
+
+    /// Call a runtime function, returning its result if it has one
+    fn call_runtime(&mut self, name: &str, args: &[Value]) -> Option<Value> {
+        let func_ref = self.func_ref(self.runtime[name]);
+        let call = self.builder.ins().call(func_ref, args);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    /// Import a module function into the function being built
+    fn func_ref(&mut self, id: FuncId) -> FuncRef {
+        if let Some(func_ref) = self.func_refs.get(&id) {
+            return *func_ref;
+        }
+
+        let func_ref = self.module.declare_func_in_func(id, self.builder.func);
+        self.func_refs.insert(id, func_ref);
+        func_ref
+    }
+}
+
+/// Comparison operators, in a form that doesn't depend on the operand type
+#[derive(Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+fn comparison(operator: &ast::BinaryOperator) -> Option<Comparison> {
+    match operator {
+        ast::BinaryOperator::EqualEqual => Some(Comparison::Equal),
+        ast::BinaryOperator::BangEqual => Some(Comparison::NotEqual),
+        ast::BinaryOperator::Less => Some(Comparison::Less),
+        ast::BinaryOperator::LessEqual => Some(Comparison::LessEqual),
+        ast::BinaryOperator::Greater => Some(Comparison::Greater),
+        ast::BinaryOperator::GreaterEqual => Some(Comparison::GreaterEqual),
+        _ => None,
+    }
+}
+
+fn int_cc(comparison: Comparison, signed: bool) -> IntCC {
+    match (comparison, signed) {
+        (Comparison::Equal, _) => IntCC::Equal,
+        (Comparison::NotEqual, _) => IntCC::NotEqual,
+        (Comparison::Less, true) => IntCC::SignedLessThan,
+        (Comparison::Less, false) => IntCC::UnsignedLessThan,
+        (Comparison::LessEqual, true) => IntCC::SignedLessThanOrEqual,
+        (Comparison::LessEqual, false) => IntCC::UnsignedLessThanOrEqual,
+        (Comparison::Greater, true) => IntCC::SignedGreaterThan,
+        (Comparison::Greater, false) => IntCC::UnsignedGreaterThan,
+        (Comparison::GreaterEqual, true) => IntCC::SignedGreaterThanOrEqual,
+        (Comparison::GreaterEqual, false) => IntCC::UnsignedGreaterThanOrEqual,
+    }
+}
+
+fn float_cc(comparison: Comparison) -> FloatCC {
+    match comparison {
+        Comparison::Equal => FloatCC::Equal,
+        Comparison::NotEqual => FloatCC::NotEqual,
+        Comparison::Less => FloatCC::LessThan,
+        Comparison::LessEqual => FloatCC::LessThanOrEqual,
+        Comparison::Greater => FloatCC::GreaterThan,
+        Comparison::GreaterEqual => FloatCC::GreaterThanOrEqual,
+    }
+}
+
+fn unsupported_operator(operator: &ast::BinaryOperator, ty: &ast::Type, span: &Span) -> CompileError {
+    CompileError::SemanticError(
+        format!("Operator {:?} cannot be applied to {}", operator, ty),
+        span.clone(),
+    )
+}
+
+/// Data object holding a string literal, defining it in the module the
+/// first time
+pub fn string_literal<M: Module>(
+    module: &mut M,
+    data_ctx: &mut DataContext,
+    string_literals: &mut HashMap<String, DataId>,
+    value: &str,
+) -> CompileResult<DataId> {
+    if let Some(data_id) = string_literals.get(value) {
+        return Ok(*data_id);
+    }
+
+    let data_id = module.declare_anonymous_data(false, false)
+        .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+    data_ctx.define(runtime::string_literal_data(value));
+    data_ctx.set_align(std::mem::align_of::<runtime::KennedyString>() as u64);
+    module.define_data(data_id, data_ctx)
+        .map_err(|e| CompileError::CompileError(e.to_string()))?;
+    data_ctx.clear();
+
+    string_literals.insert(value.to_string(), data_id);
+    Ok(data_id)
+}
+
+/// Name of the variable an assignment writes to
+fn assignment_target(expr: &ast::Expression) -> CompileResult<&String> {
+    match expr {
+        ast::Expression::Identifier { ident, .. } => Ok(ident),
+        _ => Err(invalid_assignment_target(expr)),
+    }
+}
+
+fn invalid_assignment_target(expr: &ast::Expression) -> CompileError {
+    CompileError::SemanticError(
+        "Invalid assignment target".to_string(),
+        expr.span().clone(),
+    )
+}
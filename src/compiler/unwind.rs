@@ -0,0 +1,94 @@
+//! Letting panics unwind through code compiled into memory
+//!
+//! Cranelift describes how to unwind each function it compiles, but the JIT
+//! doesn't tell the system's unwinder, which then can't get past compiled
+//! frames. The descriptions of the functions finished together are written
+//! into an `.eh_frame` section and registered with the unwinder, for as
+//! long as the compiler lives. That's what lets [`catch_traps`] turn a
+//! trap into an error rather than ending the process.
+//!
+//! Only Linux's unwinder (libgcc's) is told; elsewhere traps always abort.
+//!
+//! [`catch_traps`]: super::runtime::catch_traps
+
+use cranelift::codegen::isa::unwind::UnwindInfo;
+use cranelift::codegen::isa::TargetIsa;
+use cranelift_module::FuncId;
+
+use crate::error::{CompileError, CompileResult};
+
+/// Unwind descriptions of compiled functions, registered once the
+/// functions have addresses
+#[derive(Default)]
+pub struct UnwindRegistry {
+    /// Functions defined since the last registration
+    pending: Vec<(FuncId, UnwindInfo)>,
+    /// Sections registered so far, which the unwinder reads in place
+    frames: Vec<Box<[u8]>>,
+}
+
+impl UnwindRegistry {
+    /// Keep how to unwind a function just defined
+    pub fn add(&mut self, id: FuncId, info: UnwindInfo) {
+        self.pending.push((id, info));
+    }
+
+    /// Forget what's pending, for code that's never run
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Register the functions defined since the last registration, which
+    /// are finished, at the addresses `address` gives
+    #[cfg(target_os = "linux")]
+    pub fn register(&mut self, isa: &dyn TargetIsa, address: impl Fn(FuncId) -> *const u8) -> CompileResult<()> {
+        use gimli::write::{Address, EhFrame, EndianVec, FrameTable};
+
+        let pending = std::mem::take(&mut self.pending);
+        let Some(cie) = isa.create_systemv_cie() else {
+            return Ok(());
+        };
+
+        let mut table = FrameTable::default();
+        let cie = table.add_cie(cie);
+        for (id, info) in pending {
+            if let UnwindInfo::SystemV(info) = info {
+                table.add_fde(cie, info.to_fde(Address::Constant(address(id) as u64)));
+            }
+        }
+
+        let mut eh_frame = EhFrame(EndianVec::new(gimli::RunTimeEndian::default()));
+        table.write_eh_frame(&mut eh_frame)
+            .map_err(|e| CompileError::CompileError(format!("Failed to write unwind information: {}", e)))?;
+
+        // libgcc reads entries up to one of length zero
+        let mut frame = eh_frame.0.into_vec();
+        frame.extend_from_slice(&[0; 4]);
+        let frame = frame.into_boxed_slice();
+
+        unsafe { __register_frame(frame.as_ptr()) };
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn register(&mut self, _isa: &dyn TargetIsa, _address: impl Fn(FuncId) -> *const u8) -> CompileResult<()> {
+        self.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for UnwindRegistry {
+    fn drop(&mut self) {
+        for frame in &self.frames {
+            unsafe { __deregister_frame(frame.as_ptr()) };
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+extern "C" {
+    fn __register_frame(begin: *const u8);
+    fn __deregister_frame(begin: *const u8);
+}
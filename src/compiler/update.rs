@@ -0,0 +1,385 @@
+//! Compiling a new version of a program over the one already compiled
+//!
+//! A program that changes while it runs, like the definitions typed into
+//! the REPL, is compiled again with [`Compiler::update`], which only
+//! compiles what has changed. A function is compiled again if its source
+//! has, as printed (so spacing and comments don't count), and so is every
+//! function using one compiled again, as its code calls the old version.
+//!
+//! With `hotswap` on, the module calls functions through a table of
+//! addresses, and a changed function is defined again in place, so its
+//! callers switch to the new version as it's finished without being
+//! compiled again. They still are if its signature changed, or it was
+//! inlined into them.
+//!
+//! Structs and enums can't change, as values of the old ones may still be
+//! alive. Globals keep their data unless their type changes, or they're
+//! constants whose value does, whose users are then compiled again too.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::visit::{walk_expression, walk_statement};
+use crate::ast::{self, Expression, Statement, Visitor};
+use crate::checks;
+use crate::error::{CompileError, CompileResult, Span};
+use crate::lint;
+use crate::modules::SourceMap;
+use crate::optimizer::{self, Passes};
+
+use super::inline;
+use super::layout::StructLayout;
+use crate::type_checking::FunctionSignature;
+
+use super::translator::DeclaredFunction;
+use super::{Backend, Compiler};
+
+use cranelift_module::Linkage;
+
+impl<M: Backend> Compiler<M> {
+    /// Make the module match `ast`, a new version of the program compiled
+    /// by earlier updates, compiling what has changed since
+    /// Leaves the module as it was if the new version doesn't check
+    pub(crate) fn update(&mut self, ast: &ast::Program, sources: &SourceMap) -> CompileResult<()> {
+        let mut checker = self.check(ast)?;
+        self.check_types_unchanged(ast)?;
+
+        // checked as written, but only reported for what's compiled again
+        let diagnostics: Vec<CompileError> = checks::check(ast, &self.checks).into_iter()
+            .chain(lint::run(ast, &checker, &self.lints))
+            .collect();
+
+        let optimized;
+        let program = if self.passes != Passes::default() {
+            let mut program = ast.clone();
+            let report = optimizer::run(&mut program, &checker, self.passes, self.overflow_mode);
+
+            self.report.changes.extend(report.changes);
+            self.report.warnings.extend(report.warnings.into_iter().map(|warning| sources.attribute(warning)));
+
+            optimized = program;
+            checker = self.check(&optimized)?;
+            &optimized
+        } else {
+            ast
+        };
+
+        // globals whose data is defined again, as the old data can't hold
+        // the new value
+        let globals: HashSet<String> = program.globals.iter()
+            .filter(|global| match self.globals.get(&global.ident) {
+                None => true,
+                Some(declared) => {
+                    !declared.global_type.same_as(checker.global_type(&global.ident).unwrap())
+                        || (declared.constant || global.constant)
+                            && self.compiled_source.get(&global.ident) != Some(&global.to_string())
+                }
+            })
+            .map(|global| global.ident.clone())
+            .collect();
+
+        let functions: Vec<&ast::Function> = program.functions.iter()
+            .filter(|function| !function.is_generic())
+            .chain(checker.instances())
+            .collect();
+        let source: HashMap<&str, String> = functions.iter()
+            .map(|function| (function.ident.as_str(), function.to_string()))
+            .collect();
+
+        let mut stale: HashSet<String> = functions.iter()
+            .filter(|function| self.compiled_source.get(&function.ident) != source.get(function.ident.as_str()))
+            .map(|function| function.ident.clone())
+            .collect();
+
+        // code calling the old version of a function, or using the old data
+        // of a global, has to be compiled again, and so on up the callers,
+        // unless calls are swapped over to the new version: then only those
+        // the function was inlined into need compiling again
+        let inlinable = inline::inlinable(functions.iter().copied(), self.inline_size);
+        let by_ident: HashMap<&str, &ast::Function> = functions.iter()
+            .map(|function| (function.ident.as_str(), *function))
+            .collect();
+        let replaces_callers = |ident: &str| {
+            self.functions.contains_key(ident)
+                && (!self.swaps(by_ident[ident]) || inlinable.contains_key(ident))
+        };
+
+        let uses: Vec<(&str, HashSet<String>)> = functions.iter()
+            .map(|function| (function.ident.as_str(), names(function)))
+            .collect();
+        let mut changed: Vec<String> = stale.iter()
+            .filter(|ident| replaces_callers(ident))
+            .chain(globals.iter().filter(|ident| self.globals.contains_key(*ident)))
+            .cloned()
+            .collect();
+
+        while let Some(ident) = changed.pop() {
+            // calls name the generic function rather than its instances
+            let ident = generic_name(&ident);
+            for (function, names) in &uses {
+                if names.contains(ident) && stale.insert(function.to_string()) && replaces_callers(function) {
+                    changed.push(function.to_string());
+                }
+            }
+        }
+
+        let spans: Vec<&Span> = ast.functions.iter()
+            .filter(|function| stale.contains(&function.ident)
+                || function.is_generic() && stale.iter().any(|ident| generic_name(ident) == function.ident))
+            .map(|function| &function.span)
+            .chain(ast.globals.iter().filter(|global| globals.contains(&global.ident)).map(|global| &global.span))
+            .chain(ast.structs.iter().filter(|declaration| !self.structs.contains_key(&declaration.ident)).map(|declaration| &declaration.span))
+            .chain(ast.enums.iter().filter(|declaration| !self.enums.contains_key(&declaration.ident)).map(|declaration| &declaration.span))
+            .collect();
+        let fresh = |span: &Span| spans.iter().any(|within| within.start <= span.start && span.end <= within.end);
+
+        let mut warnings = Vec::new();
+        for diagnostic in diagnostics {
+            match &diagnostic {
+                CompileError::Warning(_, span) if fresh(span) => warnings.push(sources.attribute(diagnostic)),
+                CompileError::Warning(..) => {}
+                CompileError::SyntaxError(_, span) | CompileError::SemanticError(_, span) if !fresh(span) => {}
+                _ => return Err(diagnostic),
+            }
+        }
+        self.warnings.extend(warnings);
+
+        // the rest are the same as before, as checked above
+        for declaration in &program.structs {
+            if !self.structs.contains_key(&declaration.ident) {
+                self.declare_struct(declaration)?;
+            }
+        }
+
+        for declaration in &program.enums {
+            if !self.enums.contains_key(&declaration.ident) {
+                self.declare_enum(declaration)?;
+            }
+        }
+
+        let kept = program.globals.iter()
+            .map(|global| global.ident.clone())
+            .filter(|ident| !globals.contains(ident))
+            .collect();
+        self.define_globals(&program.globals, &checker, &kept)?;
+
+        self.generics.clear();
+        for function in program.functions.iter().filter(|function| function.is_generic()) {
+            self.generics.insert(function.ident.clone(), FunctionSignature::of(function));
+        }
+
+        for function in &program.externs {
+            self.declare_extern(function)?;
+        }
+
+        let compiled: Vec<&ast::Function> = functions.iter()
+            .copied()
+            .filter(|function| stale.contains(&function.ident))
+            .collect();
+
+        for function in &compiled {
+            match self.functions.get(&function.ident) {
+                Some(declared) if self.swaps(function) => self.module.prepare_redefine(declared.id)?,
+                Some(declared) if declared.linkage == Linkage::Export => self.redeclare_function(function)?,
+                _ => self.declare_function(function)?,
+            }
+        }
+
+        self.define_functions(&functions, &compiled, &checker, sources)?;
+        self.record_source(&program.globals, &compiled);
+        Ok(())
+    }
+
+    /// Whether a new version of a function compiled before can be swapped
+    /// in for the old one, as the calls to it stay the same
+    fn swaps(&self, function: &ast::Function) -> bool {
+        self.hotswap && self.functions.get(&function.ident).is_some_and(|declared| {
+            declared.linkage == Linkage::Export && declared.signature == FunctionSignature::of(function)
+        })
+    }
+
+    /// Make sure every struct and enum compiled before is declared the same
+    /// way in a new version of the program
+    fn check_types_unchanged(&self, ast: &ast::Program) -> CompileResult<()> {
+        let pointer_type = self.module.target_config().pointer_type();
+        let cannot_change = |kind: &str, ident: &str, span: &Span| CompileError::SemanticError(
+            format!("{} `{}` cannot be changed, as values of it may still be in use", kind, ident),
+            span.clone(),
+        );
+
+        for declaration in &ast.structs {
+            if let Some(declared) = self.structs.get(&declaration.ident) {
+                if declared.layout != StructLayout::of(declaration, pointer_type)? {
+                    return Err(cannot_change("Struct", &declaration.ident, &declaration.span));
+                }
+            }
+        }
+
+        for declaration in &ast.enums {
+            if let Some(declared) = self.enums.get(&declaration.ident) {
+                let same = declared.variants.len() == declaration.variants.len()
+                    && declared.variants.iter().zip(&declaration.variants).all(|(declared, variant)| {
+                        declared.ident == variant.ident
+                            && StructLayout::of_variant(variant, pointer_type).is_ok_and(|layout| layout == declared.layout)
+                    });
+
+                if !same {
+                    return Err(cannot_change("Enum", &declaration.ident, &declaration.span));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Declare a new version of a function compiled before, taking its name
+    /// over from the old one, which is left for the code still using it
+    fn redeclare_function(&mut self, function: &ast::Function) -> CompileResult<()> {
+        let sig = self.signature(function)?;
+        let id = self.module.declare_anonymous_function(&sig)
+            .map_err(|e| CompileError::CompileError(e.to_string()))?;
+
+        self.functions.insert(function.ident.clone(), DeclaredFunction {
+            id,
+            signature: FunctionSignature::of(function),
+            linkage: Linkage::Export,
+        });
+
+        // its thunk calls the old version
+        self.function_values.remove(&function.ident);
+        Ok(())
+    }
+}
+
+/// The generic function an instance like `id<int>` is of, or the name of
+/// any other function as it is
+fn generic_name(ident: &str) -> &str {
+    ident.split('<').next().unwrap_or(ident)
+}
+
+/// Every name a function uses: of its variables, and of the functions and
+/// globals it refers to
+fn names(function: &ast::Function) -> HashSet<String> {
+    let mut names = Names(HashSet::new());
+    names.visit_function(function);
+    names.0
+}
+
+struct Names(HashSet<String>);
+
+impl Visitor for Names {
+    fn visit_statement(&mut self, statement: &Statement) {
+        if let Statement::Assign { ident, .. } = statement {
+            self.0.insert(ident.clone());
+        }
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        if let Expression::Identifier { ident, .. } = expression {
+            self.0.insert(ident.clone());
+        }
+        walk_expression(self, expression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::CompilerOptions;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    fn update(compiler: &mut Compiler, source: &str) -> CompileResult<()> {
+        let program = Parser::new(lex(source.to_string())?).parse()?;
+        compiler.update(&program, &SourceMap::default())
+    }
+
+    fn call(compiler: &Compiler, ident: &str) -> i64 {
+        let f: extern "C" fn() -> i64 = unsafe { std::mem::transmute(compiler.get_function(ident).unwrap()) };
+        f()
+    }
+
+    #[test]
+    fn test_update() {
+        let mut compiler = Compiler::new(CompilerOptions::default()).unwrap();
+
+        update(&mut compiler, "
+const SCALE: int = 10;
+let total: int = 1;
+func f(): int { return 1; }
+func g(): int { return f() * SCALE + total; }
+func h(): int { total += 1; return total; }").unwrap();
+        assert_eq!(call(&compiler, "g"), 11);
+        assert_eq!(call(&compiler, "h"), 2);
+        let h = compiler.get_function("h").unwrap();
+
+        // `g` calls the new `f`, and `h` is left as it was, along with the
+        // value of `total`
+        update(&mut compiler, "
+const SCALE: int = 10;
+let total: int = 100;
+func f(): int { return 2; }
+func g(): int { return f() * SCALE + total; }
+func h(): int { total += 1; return total; }").unwrap();
+        assert_eq!(call(&compiler, "g"), 22);
+        assert_eq!(compiler.get_function("h").unwrap(), h);
+        assert_eq!(call(&compiler, "h"), 3);
+
+        // a changed constant is compiled into its users again
+        update(&mut compiler, "
+const SCALE: int = 100;
+let total: int = 0;
+func f(): int { return 2; }
+func g(): int { return f() * SCALE + total; }
+func h(): int { total += 1; return total; }").unwrap();
+        assert_eq!(call(&compiler, "g"), 203);
+        assert_eq!(compiler.get_function("h").unwrap(), h);
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn test_hotswap() {
+        let mut compiler = Compiler::new(CompilerOptions::new().hotswap(true)).unwrap();
+
+        compiler.compile("
+let calls: int = 0;
+func f(): int { calls += 1; return 1; }
+func g(): int { return f() * 10; }
+func h(): func(): int { return f; }").unwrap();
+        assert_eq!(call(&compiler, "g"), 10);
+        let g = compiler.get_function("g").unwrap();
+        let h = compiler.get_function("h").unwrap();
+
+        // neither `g` nor `h` is compiled again, but they call (and return)
+        // the new `f`
+        compiler.reload("
+let calls: int = 0;
+func f(): int { calls += 2; return 2; }
+func g(): int { return f() * 10; }
+func h(): func(): int { return f; }").unwrap();
+        assert_eq!(compiler.get_function("g").unwrap(), g);
+        assert_eq!(compiler.get_function("h").unwrap(), h);
+        assert_eq!(call(&compiler, "g"), 20);
+        assert_eq!(unsafe { *(compiler.get_global("calls").unwrap() as *const i64) }, 3);
+
+        // a new signature needs new calls
+        compiler.reload("
+let calls: int = 0;
+func f(n: int): int { return n; }
+func g(): int { return f(3) * 10; }").unwrap();
+        assert_ne!(compiler.get_function("g").unwrap(), g);
+        assert_eq!(call(&compiler, "g"), 30);
+    }
+
+    #[test]
+    fn test_update_errors() {
+        let mut compiler = Compiler::new(CompilerOptions::default()).unwrap();
+        update(&mut compiler, "struct P { x: int } func f(): int { return 1; }").unwrap();
+
+        let error = update(&mut compiler, "struct P { x: float } func f(): int { return 1; }").unwrap_err();
+        assert!(error.to_string().contains("Struct `P` cannot be changed"), "{}", error);
+
+        assert!(update(&mut compiler, "struct P { x: int } func f(): int { return true; }").is_err());
+        assert_eq!(call(&compiler, "f"), 1);
+    }
+}
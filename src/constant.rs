@@ -0,0 +1,358 @@
+//! Evaluating expressions at compile time
+//!
+//! Initializers of globals are evaluated when the program is compiled, and
+//! the results stored in the data objects backing them. Evaluation follows
+//! the same rules as compiled code: integer literals take their type from
+//! context, arithmetic wraps or is checked depending on the overflow mode,
+//! and dividing by zero is an error.
+
+use std::collections::HashMap;
+
+use crate::ast::{BinaryOperator, Expression, Type, UnaryOperator};
+use crate::compiler::OverflowMode;
+use crate::error::{CompileError, CompileResult, Span};
+
+/// A value known at compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// Always within the range of its integer type
+    Integer(i128),
+    Float(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+}
+
+/// Evaluates expressions made of literals, operators, casts and constants
+pub struct Evaluator<'a> {
+    /// Constants that may be referred to, with their types
+    pub constants: &'a HashMap<String, (Constant, Type)>,
+    pub overflow_mode: OverflowMode,
+}
+
+impl<'a> Evaluator<'a> {
+    /// Value and type of an expression the type checker has accepted
+    /// `expected` gives integer literals their width, as in the type checker
+    pub fn evaluate(&self, expr: &Expression, expected: Option<&Type>) -> CompileResult<(Constant, Type)> {
+        if let Some(value) = expr.integer_literal_value() {
+            let literal_type = match expected {
+                Some(expected) if expected.is_integer() => expected.clone(),
+                _ => Type::Int,
+            };
+
+            // the type checker made sure it fits
+            return Ok((Constant::Integer(value), literal_type));
+        }
+
+        match expr {
+            Expression::FloatLiteral { value, .. } => match expected {
+                Some(Type::F64) => Ok((Constant::F64(*value), Type::F64)),
+                _ => Ok((Constant::Float(*value as f32), Type::Float)),
+            },
+            Expression::StringLiteral { value, .. } => Ok((Constant::String(value.clone()), Type::String)),
+            Expression::BooleanLiteral { value, .. } => Ok((Constant::Bool(*value), Type::Bool)),
+
+            Expression::Identifier { ident, span } => self.constants.get(ident).cloned().ok_or_else(|| {
+                CompileError::SemanticError(format!("`{}` is not a constant", ident), span.clone())
+            }),
+
+            Expression::Grouping { expression, .. } => self.evaluate(expression, expected),
+
+            Expression::Unary { operator, right, span } => {
+                let (value, ty) = self.evaluate(right, expected)?;
+
+                let value = match (operator, value) {
+                    (UnaryOperator::Minus, Constant::Integer(value)) => self.integer(-value, &ty, span)?,
+                    (UnaryOperator::Minus, Constant::Float(value)) => Constant::Float(-value),
+                    (UnaryOperator::Minus, Constant::F64(value)) => Constant::F64(-value),
+                    (UnaryOperator::Bang, Constant::Bool(value)) => Constant::Bool(!value),
+                    _ => return Err(not_constant(expr)),
+                };
+
+                Ok((value, ty))
+            }
+
+            Expression::Binary { left, operator: BinaryOperator::And, right, .. } => {
+                self.short_circuit(left, right, true)
+            }
+
+            Expression::Binary { left, operator: BinaryOperator::Or, right, .. } => {
+                self.short_circuit(left, right, false)
+            }
+
+            Expression::Binary { left, operator, right, span } => {
+                let operand_expected = match operator {
+                    BinaryOperator::Plus | BinaryOperator::Minus
+                    | BinaryOperator::Star | BinaryOperator::Slash => expected,
+                    _ => None,
+                };
+
+                // as in the type checker, a literal on the left takes the
+                // type of the right
+                let (mut lhs, mut ty) = self.evaluate(left, operand_expected)?;
+                let (rhs, right_type) = self.evaluate(right, Some(&ty))?;
+
+                if !ty.same_as(&right_type) && left.is_number_literal() {
+                    (lhs, ty) = self.evaluate(left, Some(&right_type))?;
+                }
+
+                self.binary(operator, lhs, rhs, &ty, span)
+            }
+
+            Expression::Cast { expression, target_type, .. } => {
+                let (value, source_type) = self.evaluate(expression, None)?;
+                Ok((cast(value, &source_type, target_type), target_type.clone()))
+            }
+
+            _ => Err(not_constant(expr)),
+        }
+    }
+
+    /// `and`/`or`, only evaluating the right operand when it matters
+    fn short_circuit(&self, left: &Expression, right: &Expression, is_and: bool) -> CompileResult<(Constant, Type)> {
+        match self.evaluate(left, Some(&Type::Bool))? {
+            (Constant::Bool(value), _) if value != is_and => Ok((Constant::Bool(value), Type::Bool)),
+            _ => self.evaluate(right, Some(&Type::Bool)),
+        }
+    }
+
+    fn binary(
+        &self,
+        operator: &BinaryOperator,
+        lhs: Constant,
+        rhs: Constant,
+        ty: &Type,
+        span: &Span,
+    ) -> CompileResult<(Constant, Type)> {
+        let value = match (lhs, rhs) {
+            (Constant::Integer(a), Constant::Integer(b)) => {
+                integer_binary(operator, a, b, ty, self.overflow_mode).map_err(|error| match error {
+                    ArithmeticError::Overflow => overflow(ty, span),
+                    ArithmeticError::DivisionByZero => CompileError::SemanticError(
+                        "Division by zero in constant expression".to_string(),
+                        span.clone(),
+                    ),
+                })?
+            }
+
+            (Constant::Float(a), Constant::Float(b)) => float_binary(operator, a as f64, b as f64, ty),
+            (Constant::F64(a), Constant::F64(b)) => float_binary(operator, a, b, ty),
+
+            (Constant::String(a), Constant::String(b)) => match operator {
+                BinaryOperator::Plus => Constant::String(a + &b),
+                _ => Constant::Bool(compare(operator, a.cmp(&b))),
+            },
+
+            (Constant::Bool(a), Constant::Bool(b)) => Constant::Bool(compare(operator, a.cmp(&b))),
+
+            _ => return Err(CompileError::SemanticError(
+                format!("Operator {:?} cannot be applied to {}", operator, ty),
+                span.clone(),
+            )),
+        };
+
+        let result_type = match value {
+            Constant::Bool(_) => Type::Bool,
+            _ => ty.clone(),
+        };
+
+        Ok((value, result_type))
+    }
+
+    /// The result of integer arithmetic, wrapped into range or an error,
+    /// depending on the overflow mode
+    fn integer(&self, value: i128, ty: &Type, span: &Span) -> CompileResult<Constant> {
+        integer(value, ty, self.overflow_mode).map_err(|_| overflow(ty, span))
+    }
+}
+
+/// Why integer arithmetic has no result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticError {
+    /// Past the range of the type, in checked mode
+    Overflow,
+    DivisionByZero,
+}
+
+/// Arithmetic or a comparison on two integers of type `ty`, with the same
+/// results as compiled code: a `Constant::Integer` or a `Constant::Bool`
+pub fn integer_binary(
+    operator: &BinaryOperator,
+    a: i128,
+    b: i128,
+    ty: &Type,
+    overflow_mode: OverflowMode,
+) -> Result<Constant, ArithmeticError> {
+    use BinaryOperator as Op;
+
+    match operator {
+        Op::Plus => integer(a + b, ty, overflow_mode),
+        Op::Minus => integer(a - b, ty, overflow_mode),
+        Op::Star => match a.checked_mul(b) {
+            Some(product) => integer(product, ty, overflow_mode),
+            // past even i128, so certainly past the type
+            None if overflow_mode == OverflowMode::Checked => Err(ArithmeticError::Overflow),
+            None => Ok(Constant::Integer(wrap(a.wrapping_mul(b), ty))),
+        },
+        // dividing by zero is an error whatever the overflow mode
+        Op::Slash if b == 0 => Err(ArithmeticError::DivisionByZero),
+        // MIN / -1 is out of range, so checked or wrapped like the rest
+        Op::Slash => integer(a / b, ty, overflow_mode),
+        _ => Ok(Constant::Bool(compare(operator, a.cmp(&b)))),
+    }
+}
+
+/// Arithmetic or a comparison on two floats of type `ty`: a float
+/// constant or a `Constant::Bool`
+/// `float` operands are widened, which rounds to the same results as `f32`
+/// arithmetic would, as a double holds more than twice a float's precision
+pub fn float_binary(operator: &BinaryOperator, a: f64, b: f64, ty: &Type) -> Constant {
+    use BinaryOperator as Op;
+
+    match operator {
+        Op::Plus => float(a + b, ty),
+        Op::Minus => float(a - b, ty),
+        Op::Star => float(a * b, ty),
+        Op::Slash => float(a / b, ty),
+        // comparisons with NaN are false, except !=
+        Op::BangEqual => Constant::Bool(a != b),
+        _ => Constant::Bool(a.partial_cmp(&b).is_some_and(|ordering| compare(operator, ordering))),
+    }
+}
+
+/// A float constant of type `ty`
+fn float(value: f64, ty: &Type) -> Constant {
+    match ty {
+        Type::F64 => Constant::F64(value),
+        _ => Constant::Float(value as f32),
+    }
+}
+
+/// The result of integer arithmetic, wrapped into range or an error,
+/// depending on the overflow mode
+pub fn integer(value: i128, ty: &Type, overflow_mode: OverflowMode) -> Result<Constant, ArithmeticError> {
+    let (min, max) = ty.integer_range().unwrap();
+
+    if (min..=max).contains(&value) {
+        Ok(Constant::Integer(value))
+    } else if overflow_mode == OverflowMode::Checked {
+        Err(ArithmeticError::Overflow)
+    } else {
+        Ok(Constant::Integer(wrap(value, ty)))
+    }
+}
+
+/// Whether an ordering satisfies a comparison operator
+pub fn compare(operator: &BinaryOperator, ordering: std::cmp::Ordering) -> bool {
+    use std::cmp::Ordering;
+
+    match operator {
+        BinaryOperator::EqualEqual => ordering == Ordering::Equal,
+        BinaryOperator::BangEqual => ordering != Ordering::Equal,
+        BinaryOperator::Greater => ordering == Ordering::Greater,
+        BinaryOperator::GreaterEqual => ordering != Ordering::Less,
+        BinaryOperator::Less => ordering == Ordering::Less,
+        BinaryOperator::LessEqual => ordering != Ordering::Greater,
+        _ => unreachable!("{:?} is not a comparison", operator),
+    }
+}
+
+/// `value` reduced modulo 2^bits into the range of an integer type, as
+/// two's complement arithmetic would leave it
+pub fn wrap(value: i128, ty: &Type) -> i128 {
+    let modulus = 1i128 << ty.bits().unwrap();
+    let (_, max) = ty.integer_range().unwrap();
+
+    let value = value.rem_euclid(modulus);
+    if value > max { value - modulus } else { value }
+}
+
+/// `value as target`, with the same results as compiled casts
+pub fn cast(value: Constant, source_type: &Type, target_type: &Type) -> Constant {
+    if source_type.same_as(target_type) {
+        return value;
+    }
+
+    match (value, target_type) {
+        (Constant::Integer(value), Type::String) => Constant::String(value.to_string()),
+        (Constant::Float(value), Type::String) => Constant::String(value.to_string()),
+        (Constant::F64(value), Type::String) => Constant::String(value.to_string()),
+        (Constant::Bool(value), Type::String) => Constant::String(value.to_string()),
+
+        (Constant::Integer(value), Type::Float) => Constant::Float(value as f32),
+        (Constant::Integer(value), Type::F64) => Constant::F64(value as f64),
+        (Constant::Float(value), Type::F64) => Constant::F64(value as f64),
+        (Constant::F64(value), Type::Float) => Constant::Float(value as f32),
+        (Constant::Integer(value), _) => Constant::Integer(wrap(value, target_type)),
+        (Constant::Bool(value), _) => Constant::Integer(value as i128),
+
+        (Constant::Float(value), _) => float_to_integer(value as f64, target_type),
+        (Constant::F64(value), _) => float_to_integer(value, target_type),
+
+        (value, _) => value,
+    }
+}
+
+/// Saturates to the 64 bit range, then truncates, as compiled casts do
+fn float_to_integer(value: f64, target_type: &Type) -> Constant {
+    let wide = if target_type.is_signed() { value as i64 as i128 } else { value as u64 as i128 };
+    Constant::Integer(wrap(wide, target_type))
+}
+
+fn overflow(ty: &Type, span: &Span) -> CompileError {
+    CompileError::SemanticError(
+        format!("Integer overflow in constant expression of type {}", ty),
+        span.clone(),
+    )
+}
+
+fn not_constant(expr: &Expression) -> CompileError {
+    CompileError::SemanticError(
+        "Expression cannot be evaluated at compile time".to_string(),
+        expr.span().clone(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    /// Evaluate the initializer of `let x = <source>;`
+    fn evaluate(source: &str, overflow_mode: OverflowMode) -> CompileResult<Constant> {
+        let tokens = lex(format!("let x = {};", source))?;
+        let program = Parser::new(tokens).parse()?;
+
+        let constants = HashMap::from([("LIMIT".to_string(), (Constant::Integer(200), Type::U8))]);
+        let evaluator = Evaluator { constants: &constants, overflow_mode };
+        evaluator.evaluate(&program.globals[0].value, None).map(|(value, _)| value)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let wrapping = |source| evaluate(source, OverflowMode::Wrapping).unwrap();
+
+        assert_eq!(wrapping("1 + 2 * 3"), Constant::Integer(7));
+        assert_eq!(wrapping("LIMIT + 100"), Constant::Integer(44));
+        assert_eq!(wrapping("-7 / 2"), Constant::Integer(-3));
+        assert_eq!(wrapping("(0 as i64 - 9223372036854775807 - 1) / -1"), Constant::Integer(i64::MIN as i128));
+        assert_eq!(wrapping("300 as u8"), Constant::Integer(44));
+        assert_eq!(wrapping("-1 as i8 as u16"), Constant::Integer(65535));
+        assert_eq!(wrapping("3.9 as i8"), Constant::Integer(3));
+        assert_eq!(wrapping("1.5 * 2.0 > 2.5 and not false"), Constant::Bool(true));
+        assert_eq!(wrapping("0.1 as f64"), Constant::F64(0.1f32 as f64));
+        assert_eq!(wrapping("1 as f64 / 3.0"), Constant::F64(1.0 / 3.0));
+        assert_eq!(wrapping("-(2.5 as f64) as i8"), Constant::Integer(-2));
+        assert_eq!(wrapping("\"n = \" + 4 as string"), Constant::String("n = 4".to_string()));
+
+        assert!(evaluate("LIMIT + 100", OverflowMode::Checked).unwrap_err().to_string()
+            .contains("Integer overflow in constant expression of type u8"));
+        assert!(evaluate("1 / (2 - 2)", OverflowMode::Wrapping).unwrap_err().to_string()
+            .contains("Division by zero"));
+        assert!(evaluate("len(\"abc\")", OverflowMode::Wrapping).unwrap_err().to_string()
+            .contains("cannot be evaluated at compile time"));
+        assert!(evaluate("y + 1", OverflowMode::Wrapping).unwrap_err().to_string()
+            .contains("`y` is not a constant"));
+    }
+}
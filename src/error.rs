@@ -1,16 +1,48 @@
 use std::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
 pub struct Span {
     pub start: usize,
     pub end: usize,
 }
 
+impl Span {
+    /// 1-based line and column of the start of the span within `source`.
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let mut line = 1;
+        let mut col = 1;
+
+        for (i, c) in source.chars().enumerate() {
+            if i == self.start {
+                break;
+            }
+
+            if c == '\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+
+        (line, col)
+    }
+}
+
 #[derive(Debug)]
 pub enum CompileError {
     SyntaxError(String, Span),
     SemanticError(String, Span),
+    /// Something allowed, but likely a mistake; it doesn't stop compilation
+    Warning(String, Span),
     CompileError(String),
+    /// An error in one of the files of a program made of several, with the
+    /// span relative to that file
+    InFile {
+        path: String,
+        source: String,
+        error: Box<CompileError>,
+    },
 }
 
 impl fmt::Display for CompileError {
@@ -18,6 +50,9 @@ impl fmt::Display for CompileError {
         match self {
             CompileError::SyntaxError(msg, span) => write!(f, "Syntax error at {:?}: {}", span, msg),
             CompileError::SemanticError(msg, span) => write!(f, "Semantic error at {:?}: {}", span, msg),
+            CompileError::Warning(msg, span) => write!(f, "Warning at {:?}: {}", span, msg),
+            CompileError::CompileError(msg) => write!(f, "Compile error: {}", msg),
+            CompileError::InFile { path, error, .. } => write!(f, "{}: {}", path, error),
         }
     }
 }
@@ -25,84 +60,68 @@ impl fmt::Display for CompileError {
 impl CompileError {
     /// To string with source.
     pub fn to_string_with_source(&self, source: &str) -> String {
-        let mut line = 0;
-        let mut col = 0;
-        let mut start = 0;
-        let mut end = 0;
-
-        let span = match self {
-            CompileError::SyntaxError(_, span) => span,
-            CompileError::SemanticError(_, span) => span,
-        };
-
-        for (i, c) in source.chars().enumerate() {
-            if c == '\n' {
-                line += 1;
-                col = 0;
-                start = 0;
-                end = 0;
-                continue;
+        let (kind, msg, span) = match self {
+            CompileError::SyntaxError(msg, span) => ("Syntax error", msg, span),
+            CompileError::SemanticError(msg, span) => ("Semantic error", msg, span),
+            CompileError::Warning(msg, span) => ("Warning", msg, span),
+            // no span to point at, so there's no source to show
+            CompileError::CompileError(_) => return self.to_string(),
+            // the span is in another file, which the error carries
+            CompileError::InFile { path, source, error } => {
+                return format!("{}: {}", path, error.to_string_with_source(source));
             }
+        };
 
-            col += 1;
-
-            if i == span.start {
-                start = col;
-                end = col;
-            }
+        let (line, column) = span.location(source);
+        let lines: Vec<&str> = source.lines().collect();
+        let text = |line: usize| lines.get(line - 1).copied().unwrap_or("");
+        let width = line.to_string().len();
 
-            if span.start <= i && i < span.end {
-                end = col;
-            }
+        let mut rv = format!("{} at {}:{}: {}\n", kind, line, column, msg);
 
-            if i == span.end {
-                break;
-            }
+        // up to 2 lines before, for context
+        for before in line.saturating_sub(2).max(1)..line {
+            rv.push_str(&format!("\t{:>width$} | {}\n", before, text(before), width = width));
         }
 
-        // closure to add a line
-        let add_line = |rv: &mut String, line_number: usize| {
-            rv.push_str(&format!("\t{} | {}\n",
-                line_number + 1,
-                source.lines().nth(line_number).expect("Line not found"),
-            ));
-        };
-
-        // let mut rv = format!("Syntax error at {}:{}: Unexpected token `return`\n",
-        //     line + 1,
-        //     start + 1,
-        // );
-
-        let mut rv = format!("{} at {}:{}: {}\n",
-            match self {
-                CompileError::SyntaxError(_, _) => "Syntax error",
-                CompileError::SemanticError(_, _) => "Semantic error",
-            },
-            line + 1,
-            start + 1,
-            match self {
-                CompileError::SyntaxError(msg, _) => msg,
-                CompileError::SemanticError(msg, _) => msg,
-            },
-        );
-
-        // add 3 lines before
-        for i in 1..3 {
-            // if we're at the start of the file, don't add a line
-            if line >= i {
-                add_line(&mut rv, line - i);
-            }
-        }
+        // the caret stops at the end of the span's first line
+        let rest = text(line).chars().count().saturating_sub(column - 1);
+        let length = (span.end.saturating_sub(span.start)).min(rest).max(1);
 
         rv.push_str(&format!("\t{} | {}\n\t{}{}",
-            line + 1,
-            source.lines().nth(line).expect("Line not found"),
-            " ".repeat(start + 3),
-            "^".repeat(end - start + 1)
+            line,
+            text(line),
+            " ".repeat(width + 3 + column - 1),
+            "^".repeat(length),
         ));
 
         rv
     }
 }
 
-pub type CompileResult<T> = Result<T, CompileError>;
\ No newline at end of file
+pub type CompileResult<T> = Result<T, CompileError>;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_string_with_source() {
+        let source = "func f(): int {\n    let x = 1;\n    return y;\n}";
+        let error = CompileError::SemanticError("Unknown variable `y`".to_string(), Span { start: 42, end: 43 });
+
+        assert_eq!(error.to_string_with_source(source), "\
+Semantic error at 3:12: Unknown variable `y`
+\t1 | func f(): int {
+\t2 |     let x = 1;
+\t3 |     return y;
+\t               ^");
+
+        // a span over several lines is shown at its start
+        let error = CompileError::SemanticError("Non-exhaustive match".to_string(), Span { start: 20, end: 45 });
+        assert_eq!(error.to_string_with_source(source), "\
+Semantic error at 2:5: Non-exhaustive match
+\t1 | func f(): int {
+\t2 |     let x = 1;
+\t        ^^^^^^^^^^");
+    }
+}
@@ -0,0 +1,100 @@
+//! Canonical formatting of Kennedy source, as `kennedy fmt` does it
+//!
+//! The source is parsed and printed back as its syntax tree displays, with
+//! its comments put back in between: on the line of what they followed, or
+//! on lines of their own before what they preceded. Single blank lines
+//! between statements and declarations are kept.
+//!
+//! Formatting formatted source changes nothing.
+
+use crate::error::{CompileError, CompileResult};
+use crate::lexer;
+use crate::parser::Parser;
+use crate::printer::Printer;
+
+/// The canonical form of `source`
+/// Imports are left as written, so the modules they name needn't exist
+pub fn format(source: &str) -> CompileResult<String> {
+    let (tokens, comments) = lexer::lex_with_comments(source.to_string())?;
+    let program = Parser::new(tokens).parse()?;
+
+    let mut printer = Printer::with_comments(source, &comments);
+    printer.program(&program);
+    let formatted = printer.finish();
+
+    // never hand back source meaning something else: it must parse to the
+    // same program, with every comment still there
+    let (tokens, reformatted_comments) = lexer::lex_with_comments(formatted.clone())?;
+    let reparsed = Parser::new(tokens).parse()?;
+
+    if reparsed.to_string() != program.to_string() || reformatted_comments.len() != comments.len() {
+        return Err(CompileError::CompileError(
+            "Formatting would change the program, so it was left as it is".to_string(),
+        ));
+    }
+
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comments() {
+        let source = "\
+// leading
+func main(): int { // on the brace
+    let x = 1;   // after x
+    /* before y */ let y = f(x, /* inside */ 2);
+
+
+    // at the end
+}
+/* last */";
+
+        assert_eq!(format(source).unwrap(), "\
+// leading
+func main(): int { // on the brace
+    let x = 1; // after x
+    /* before y */
+    let y = f(x, 2); /* inside */
+
+    // at the end
+}
+/* last */
+");
+    }
+
+    #[test]
+    fn test_idempotent() {
+        let source = "\
+import math; // math
+const A=1;const B=2;
+
+let c = 3;
+struct P { x: float, /* y */ y: float }
+func f(): null { while (true) { // forever
+    if (A == 1) { return; } /* done */ else { // never
+    }}
+    match (B) { 1 => {} _ => {} }
+}
+// bye
+";
+        let formatted = format(source).unwrap();
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        assert!(formatted.starts_with("import math; // math\n\nconst A = 1;\nconst B = 2;\n\nlet c = 3;\n"), "{}", formatted);
+        assert!(formatted.ends_with("}\n// bye\n"), "{}", formatted);
+
+        // every comment is kept
+        for comment in ["// math", "/* y */", "// forever", "/* done */", "// never", "// bye"] {
+            assert!(formatted.contains(comment), "{} missing from\n{}", comment, formatted);
+        }
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = format("func main(): int { return 1 }").unwrap_err();
+        assert!(matches!(error, CompileError::SyntaxError(..)), "{}", error);
+    }
+}
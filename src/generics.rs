@@ -0,0 +1,178 @@
+//! Generic functions
+//!
+//! `func max<T: numeric>(a: T, b: T): T` is never compiled as written.
+//! Each call infers the type arguments from its arguments, and the function
+//! is checked and compiled once per distinct list of type arguments
+//! (monomorphization), as an ordinary function called e.g. `max<i64>`.
+//! Inside the body, type parameters are written as `Type::Named`.
+
+use std::collections::HashMap;
+
+use crate::ast::{Expression, Function, Type, VisitorMut};
+use crate::error::{CompileError, CompileResult};
+use crate::type_checking::FunctionSignature;
+
+/// Type arguments bound so far, by type parameter
+pub type Substitution = HashMap<String, Type>;
+
+/// Name of the instance of a generic function for these type arguments
+/// Aliases are resolved, so `max<int>` and `max<i64>` are the same instance
+pub fn instance_ident(ident: &str, type_args: &[Type]) -> String {
+    let type_args: Vec<String> = type_args.iter().map(|ty| ty.canonical().to_string()).collect();
+    format!("{}<{}>", ident, type_args.join(", "))
+}
+
+/// `ty` with the type parameters in `substitution` replaced
+pub fn substitute(ty: &Type, substitution: &Substitution) -> Type {
+    match ty {
+        Type::Named(ident) => substitution.get(ident).cloned().unwrap_or_else(|| ty.clone()),
+        Type::Array(element) => Type::Array(Box::new(substitute(element, substitution))),
+        Type::FixedArray(element, len) => Type::FixedArray(Box::new(substitute(element, substitution)), *len),
+        Type::Function(params, return_type) => Type::Function(
+            params.iter().map(|param| substitute(param, substitution)).collect(),
+            Box::new(substitute(return_type, substitution)),
+        ),
+        _ => ty.clone(),
+    }
+}
+
+/// Whether every type parameter in `ty` is bound
+fn is_resolved(ty: &Type, type_params: &[String], substitution: &Substitution) -> bool {
+    match ty {
+        Type::Named(ident) => !type_params.contains(ident) || substitution.contains_key(ident),
+        Type::Array(element) | Type::FixedArray(element, _) => is_resolved(element, type_params, substitution),
+        Type::Function(params, return_type) => {
+            params.iter().all(|param| is_resolved(param, type_params, substitution))
+                && is_resolved(return_type, type_params, substitution)
+        }
+        _ => true,
+    }
+}
+
+/// Match a parameter type against the type of an argument, binding the
+/// type parameters it mentions
+/// False if they can't be made the same
+fn unify(param: &Type, arg: &Type, type_params: &[String], substitution: &mut Substitution) -> bool {
+    match (param, arg) {
+        (Type::Named(ident), _) if type_params.contains(ident) => match substitution.get(ident) {
+            Some(bound) => bound.same_as(arg),
+            None => {
+                substitution.insert(ident.clone(), arg.clone());
+                true
+            }
+        },
+        (Type::Array(param), Type::Array(arg)) => unify(param, arg, type_params, substitution),
+        (Type::FixedArray(param, param_len), Type::FixedArray(arg, arg_len)) => {
+            param_len == arg_len && unify(param, arg, type_params, substitution)
+        }
+        (Type::Function(params, param_return), Type::Function(args, arg_return)) => {
+            params.len() == args.len()
+                && params.iter().zip(args).all(|(param, arg)| unify(param, arg, type_params, substitution))
+                && unify(param_return, arg_return, type_params, substitution)
+        }
+        _ => param.same_as(arg),
+    }
+}
+
+/// Infer the type arguments of a call to the generic function `ident`
+///
+/// `check` types (or translates) one argument given the type expected of
+/// it, if known yet. Integer literals take their type from context, so the
+/// other arguments go first, in order, and literals last; literals have no
+/// side effects, so this doesn't change what the program does.
+///
+/// Returns what `check` gave for each argument, in order, and the type
+/// arguments.
+pub fn infer_call<T>(
+    ident: &str,
+    signature: &FunctionSignature,
+    arguments: &[Expression],
+    mut check: impl FnMut(&Expression, Option<&Type>) -> CompileResult<(T, Type)>,
+) -> CompileResult<(Vec<T>, Vec<Type>)> {
+    let type_params = &signature.type_params;
+    let mut substitution = Substitution::new();
+    let mut results: Vec<Option<T>> = arguments.iter().map(|_| None).collect();
+
+    let order = (0..arguments.len())
+        .filter(|i| !arguments[*i].is_integer_literal())
+        .chain((0..arguments.len()).filter(|i| arguments[*i].is_integer_literal()));
+
+    for i in order {
+        let (argument, param) = (&arguments[i], &signature.params[i]);
+
+        let expected = is_resolved(param, type_params, &substitution)
+            .then(|| substitute(param, &substitution));
+        let (result, arg_type) = check(argument, expected.as_ref())?;
+
+        if !unify(param, &arg_type, type_params, &mut substitution) {
+            return Err(CompileError::SemanticError(
+                format!("Expected {}, got {}", substitute(param, &substitution), arg_type),
+                argument.span().clone(),
+            ));
+        }
+
+        results[i] = Some(result);
+    }
+
+    let type_args = type_params.iter()
+        .map(|type_param| substitution.get(type_param).cloned().ok_or_else(|| {
+            CompileError::CompileError(format!(
+                "Cannot infer type parameter `{}` of `{}` from its arguments",
+                type_param, ident,
+            ))
+        }))
+        .collect::<CompileResult<Vec<Type>>>()?;
+
+    Ok((results.into_iter().map(Option::unwrap).collect(), type_args))
+}
+
+/// The instance of a generic function for these type arguments: an
+/// ordinary function with every type parameter replaced
+pub fn instantiate(function: &Function, type_args: &[Type]) -> Function {
+    let substitution: Substitution = function.type_params.iter()
+        .map(|type_param| type_param.ident.clone())
+        .zip(type_args.iter().cloned())
+        .collect();
+
+    let mut instance = function.clone();
+    instance.ident = instance_ident(&function.ident, type_args);
+    instance.type_params.clear();
+    Substituter(&substitution).visit_function_mut(&mut instance);
+    instance
+}
+
+/// Replaces type parameters wherever a type is written
+struct Substituter<'a>(&'a Substitution);
+
+impl VisitorMut for Substituter<'_> {
+    fn visit_type_mut(&mut self, ty: &mut Type) {
+        *ty = substitute(ty, self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unify() {
+        let type_params = vec!["T".to_string(), "U".to_string()];
+        let t = || Type::Named("T".to_string());
+        let u = || Type::Named("U".to_string());
+
+        let mut substitution = Substitution::new();
+        let param = Type::Function(vec![t()], Box::new(Type::Array(Box::new(u()))));
+        let arg = Type::Function(vec![Type::Int], Box::new(Type::Array(Box::new(Type::String))));
+
+        assert!(unify(&param, &arg, &type_params, &mut substitution));
+        assert_eq!(substitution["T"], Type::Int);
+        assert_eq!(substitution["U"], Type::String);
+
+        // already bound, and `int` is `i64`
+        assert!(unify(&t(), &Type::I64, &type_params, &mut substitution));
+        assert!(!unify(&t(), &Type::Float, &type_params, &mut substitution));
+        assert!(!unify(&Type::Array(Box::new(t())), &Type::Int, &type_params, &mut substitution));
+
+        assert_eq!(instance_ident("pair", &[Type::Int, Type::Array(Box::new(Type::String))]), "pair<i64, string[]>");
+    }
+}
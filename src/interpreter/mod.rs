@@ -0,0 +1,1422 @@
+//! Running programs without compiling them
+//!
+//! The interpreter walks the syntax tree of a checked program, so it runs
+//! anywhere and starts straight away. It follows the same rules as compiled
+//! code, down to where runtime errors are reported: integer literals take
+//! their type from context, arithmetic wraps or stops depending on the
+//! overflow mode, and strings, arrays and structs are shared rather than
+//! copied. That makes it a reference to test the compiler against.
+//!
+//! Extern functions have no machine code to run here, so calling one is a
+//! runtime error.
+
+pub mod value;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fs;
+
+use crate::ast::{self, BinaryOperator, Type};
+use crate::builtins::Builtin;
+use crate::compiler::runtime::TrapKind;
+use crate::compiler::symbol_table::SymbolTable;
+use crate::compiler::OverflowMode;
+use crate::constant::{self, ArithmeticError, Constant, Evaluator};
+use crate::error::{CompileError, CompileResult, Span};
+use crate::generics;
+use crate::modules::{self, SourceMap};
+use crate::type_checking::{FunctionSignature, Lambda, TypeChecker};
+
+pub use value::{Closure, StructValue, Value, VariantValue};
+use value::Target;
+
+/// Most calls that may be in progress at once by default, so runaway
+/// recursion is an error rather than using up memory
+/// About as deep as compiled code of a small function gets on an 8 MB
+/// stack; interpreted calls take far more room than that, which comes from
+/// the heap as they go deeper
+pub const MAX_CALL_DEPTH: usize = 500_000;
+
+/// Stack left below which a call continues on a new segment
+const STACK_RED_ZONE: usize = 256 << 10;
+
+/// Size of each segment the stack grows by
+const STACK_SEGMENT: usize = 16 << 20;
+
+/// Why an interpreted program stopped
+/// Lines and columns are 1-based, within the file the code is in
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// A runtime error compiled code would stop with at the same place
+    Trap { kind: TrapKind, line: usize, column: usize },
+    /// The end of a function returning a value was reached without a
+    /// `return`, where compiled code would crash
+    Unreachable { line: usize, column: usize },
+    /// A call nested more than `depth` deep, the interpreter's limit
+    StackOverflow { depth: usize, line: usize, column: usize },
+    /// A call to an extern function
+    Extern { ident: String, line: usize, column: usize },
+    /// A call from outside the program that doesn't fit the function
+    InvalidCall(String),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuntimeError::Trap { kind, line, column } => write!(f, "Runtime error at {}:{}: {}", line, column, kind),
+            RuntimeError::Unreachable { line, column } => {
+                write!(f, "Runtime error at {}:{}: reached the end of a function without returning a value", line, column)
+            }
+            RuntimeError::StackOverflow { depth, line, column } => {
+                write!(f, "Runtime error at {}:{}: more than {} nested calls", line, column, depth)
+            }
+            RuntimeError::Extern { ident, line, column } => {
+                write!(f, "Runtime error at {}:{}: extern function `{}` cannot be interpreted", line, column, ident)
+            }
+            RuntimeError::InvalidCall(message) => write!(f, "Invalid call: {}", message),
+        }
+    }
+}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// Why evaluation stopped before the end of what it was evaluating
+enum Exit {
+    /// A `return`, with the value returned
+    Return(Value),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Exit {
+    fn from(error: RuntimeError) -> Self {
+        Exit::Error(error)
+    }
+}
+
+type Exec<T> = Result<T, Exit>;
+
+/// A function that can be called by name
+#[derive(Clone)]
+enum Declared {
+    Function(Rc<ast::Function>),
+    Extern(FunctionSignature),
+}
+
+impl Declared {
+    fn signature(&self) -> FunctionSignature {
+        match self {
+            Declared::Function(function) => FunctionSignature::of(function),
+            Declared::Extern(signature) => signature.clone(),
+        }
+    }
+}
+
+/// A call being run
+struct Frame {
+    /// Function (or instance) the code is written in, which lambdas and
+    /// matches are identified by
+    owner: String,
+    return_type: Type,
+    variables: SymbolTable<String, (Value, Type)>,
+}
+
+/// Somewhere a value can be assigned to
+enum Place {
+    Variable(String),
+    Global(String),
+    Element { array: Rc<RefCell<Vec<Value>>>, index: i128, span: Span },
+    Field { object: Rc<StructValue>, field: usize },
+}
+
+pub struct Interpreter {
+    /// What integer arithmetic does when it over/underflows
+    overflow_mode: OverflowMode,
+
+    /// Every file loaded so far, laid end to end so spans from any of them
+    /// can be told apart
+    sources: SourceMap,
+
+    /// Every function, instance of a generic function and extern function
+    /// loaded, by name
+    functions: HashMap<String, Declared>,
+
+    /// Generic functions, by name
+    /// Calls run the instances the type checker found
+    generics: HashMap<String, FunctionSignature>,
+
+    /// Anonymous functions, by the function they're written in and where
+    lambdas: HashMap<(String, Span), Rc<Lambda>>,
+
+    /// Type of every expression, by the function it's written in and where
+    /// Literals take their type from their context, which the type checker
+    /// already worked out
+    expression_types: HashMap<(String, Span), Type>,
+
+    structs: HashMap<String, ast::Struct>,
+    enums: HashMap<String, ast::Enum>,
+
+    /// Current value and type of every global
+    globals: HashMap<String, (Value, Type)>,
+
+    /// Calls in progress
+    depth: usize,
+    /// Most calls that may be in progress at once
+    max_depth: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new(OverflowMode::default())
+    }
+}
+
+impl Interpreter {
+    pub fn new(overflow_mode: OverflowMode) -> Self {
+        Self {
+            overflow_mode,
+            sources: SourceMap::default(),
+            functions: HashMap::new(),
+            generics: HashMap::new(),
+            lambdas: HashMap::new(),
+            expression_types: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            globals: HashMap::new(),
+            depth: 0,
+            max_depth: MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Set how many calls may be in progress at once, `MAX_CALL_DEPTH` by
+    /// default
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Check and load every function in `source`, as `Compiler::compile`
+    /// compiles them
+    /// Globals are initialized straight away
+    pub fn load(&mut self, source: &str) -> CompileResult<()> {
+        self.load_source(None, source)
+    }
+
+    /// Check and load the program in the file at `path`, and every module
+    /// it imports, as `Compiler::compile_file` compiles them
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> CompileResult<()> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|e| {
+            CompileError::CompileError(format!("Cannot read `{}`: {}", path.display(), e))
+        })?;
+
+        self.load_source(Some(path), &source)
+    }
+
+    fn load_source(&mut self, path: Option<&Path>, source: &str) -> CompileResult<()> {
+        let program = modules::load(path, source, &mut self.sources)
+            .map_err(|error| self.sources.attribute(error))?;
+
+        self.load_program(&program).map_err(|error| self.sources.attribute(error))
+    }
+
+    fn load_program(&mut self, program: &ast::Program) -> CompileResult<()> {
+        let mut checker = TypeChecker::new();
+        checker.check_program(program)?;
+
+        for declaration in &program.structs {
+            self.structs.insert(declaration.ident.clone(), declaration.clone());
+        }
+
+        for declaration in &program.enums {
+            self.enums.insert(declaration.ident.clone(), declaration.clone());
+        }
+
+        // initializers are worked out just as the compiler works them out
+        let mut constants = HashMap::new();
+        for global in &program.globals {
+            let global_type = checker.global_type(&global.ident).unwrap().clone();
+            let evaluator = Evaluator { constants: &constants, overflow_mode: self.overflow_mode };
+            let (value, _) = evaluator.evaluate(&global.value, Some(&global_type))?;
+
+            if global.constant {
+                constants.insert(global.ident.clone(), (value.clone(), global_type.clone()));
+            }
+
+            self.globals.insert(global.ident.clone(), (constant_value(value), global_type));
+        }
+
+        for function in program.functions.iter().filter(|function| function.is_generic()) {
+            self.generics.insert(function.ident.clone(), FunctionSignature::of(function));
+        }
+
+        for function in &program.externs {
+            self.functions.insert(function.ident.clone(), Declared::Extern(FunctionSignature::of_extern(function)));
+        }
+
+        let functions = program.functions.iter()
+            .filter(|function| !function.is_generic())
+            .chain(checker.instances());
+
+        for function in functions {
+            self.functions.insert(function.ident.clone(), Declared::Function(Rc::new(function.clone())));
+        }
+
+        for lambda in checker.lambdas() {
+            self.lambdas.insert((lambda.owner.clone(), lambda.span.clone()), Rc::new(lambda.clone()));
+        }
+
+        self.expression_types.extend(checker.expression_types().iter().map(|(key, ty)| (key.clone(), ty.clone())));
+
+        Ok(())
+    }
+
+    /// Call the function `ident` with `args`
+    /// Functions of imported modules are qualified with the module, i.e.
+    /// `call("math::add", ..)`
+    pub fn call(&mut self, ident: &str, args: &[Value]) -> RuntimeResult<Value> {
+        let function = match self.functions.get(ident) {
+            Some(Declared::Function(function)) => function.clone(),
+            Some(Declared::Extern(_)) => {
+                return Err(RuntimeError::InvalidCall(format!("`{}` is an extern function", ident)));
+            }
+            None => return Err(RuntimeError::InvalidCall(format!("No function named `{}`", ident))),
+        };
+
+        let params = &function.params.params;
+        if params.len() != args.len() {
+            return Err(RuntimeError::InvalidCall(format!(
+                "`{}` takes {} arguments but {} were given",
+                ident, params.len(), args.len(),
+            )));
+        }
+
+        for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+            if !fits(arg, &param.param_type) {
+                return Err(RuntimeError::InvalidCall(format!(
+                    "Argument {} of `{}` should be {}, got {}",
+                    i + 1, ident, param.param_type, arg,
+                )));
+            }
+        }
+
+        self.call_function(ident, args.to_vec(), &function.span)
+    }
+
+    /// Current value of a global
+    pub fn global(&self, ident: &str) -> Option<&Value> {
+        self.globals.get(ident).map(|(value, _)| value)
+    }
+
+    /// Call a function, instance or extern function by name
+    fn call_function(&mut self, ident: &str, args: Vec<Value>, span: &Span) -> RuntimeResult<Value> {
+        match self.functions[ident].clone() {
+            Declared::Function(function) => self.run(
+                &function.ident,
+                &function.params,
+                &function.return_type,
+                &function.body,
+                Vec::new(),
+                args,
+                span,
+            ),
+            Declared::Extern(_) => {
+                let (line, column) = self.sources.location(span);
+                Err(RuntimeError::Extern { ident: ident.to_string(), line, column })
+            }
+        }
+    }
+
+    /// Call a function value
+    fn call_closure(&mut self, closure: &Closure, args: Vec<Value>, span: &Span) -> RuntimeResult<Value> {
+        match &closure.target {
+            Target::Function(ident) => self.call_function(ident, args, span),
+            Target::Lambda(lambda, captured) => {
+                let captures = lambda.captures.iter()
+                    .zip(captured)
+                    .map(|((ident, ty), value)| (ident.clone(), value.clone(), ty.clone()))
+                    .collect();
+
+                self.run(&lambda.owner, &lambda.params, &lambda.return_type, &lambda.body, captures, args, span)
+            }
+        }
+    }
+
+    /// Run the body of a function, with its captured values and parameters
+    /// as variables
+    /// `span` is where it was called from
+    #[allow(clippy::too_many_arguments)]
+    fn run(
+        &mut self,
+        owner: &str,
+        params: &ast::Parameters,
+        return_type: &Type,
+        body: &ast::Block,
+        captures: Vec<(String, Value, Type)>,
+        args: Vec<Value>,
+        span: &Span,
+    ) -> RuntimeResult<Value> {
+        if self.depth == self.max_depth {
+            let (line, column) = self.sources.location(span);
+            return Err(RuntimeError::StackOverflow { depth: self.max_depth, line, column });
+        }
+
+        let mut frame = Frame {
+            owner: owner.to_string(),
+            return_type: return_type.clone(),
+            variables: SymbolTable::new(),
+        };
+
+        for (ident, value, ty) in captures {
+            frame.variables.insert(ident, (value, ty));
+        }
+
+        for (param, value) in params.params.iter().zip(args) {
+            frame.variables.insert(param.ident.clone(), (value, param.param_type.clone()));
+        }
+
+        // each call takes kilobytes of the interpreter's own stack, so it
+        // grows onto the heap rather than running out
+        self.depth += 1;
+        let result = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, || self.block(&mut frame, body));
+        self.depth -= 1;
+
+        match result {
+            Err(Exit::Return(value)) => Ok(value),
+            Err(Exit::Error(error)) => Err(error),
+            Ok(()) if *return_type == Type::Null => Ok(Value::Null),
+            // compiled code traps at the closing brace
+            Ok(()) => {
+                let end = Span { start: body.span.end.saturating_sub(1), end: body.span.end };
+                let (line, column) = self.sources.location(&end);
+                Err(RuntimeError::Unreachable { line, column })
+            }
+        }
+    }
+
+    fn block(&mut self, frame: &mut Frame, block: &ast::Block) -> Exec<()> {
+        frame.variables.push_scope();
+
+        for statement in &block.statements {
+            self.statement(frame, statement)?;
+        }
+
+        frame.variables.pop_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, frame: &mut Frame, statement: &ast::Statement) -> Exec<()> {
+        match statement {
+            ast::Statement::VariableDeclaration { ident, var_type, value, .. } => {
+                let (value, value_type) = self.expression(frame, value)?;
+                let var_type = var_type.clone().unwrap_or(value_type);
+                frame.variables.insert(ident.clone(), (value, var_type));
+                Ok(())
+            }
+
+            ast::Statement::Assign { ident, value, .. } => {
+                let (place, _) = self.variable_place(frame, ident);
+                let (value, _) = self.expression(frame, value)?;
+                self.write(frame, &place, value)?;
+                Ok(())
+            }
+
+            ast::Statement::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => {
+                        let return_type = frame.return_type.clone();
+                        let (value, _) = self.expression(frame, value)?;
+                        if return_type == Type::Null { Value::Null } else { value }
+                    }
+                    None => Value::Null,
+                };
+
+                Err(Exit::Return(value))
+            }
+
+            ast::Statement::Block { block, .. } => self.block(frame, block),
+
+            ast::Statement::If { condition, then_branch, else_branch, .. } => {
+                if self.condition(frame, condition)? {
+                    self.statement(frame, then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.statement(frame, else_branch)
+                } else {
+                    Ok(())
+                }
+            }
+
+            ast::Statement::While { condition, body, .. } => {
+                while self.condition(frame, condition)? {
+                    self.block(frame, body)?;
+                }
+                Ok(())
+            }
+
+            ast::Statement::DoUntil { condition, body, .. } => {
+                loop {
+                    self.statement(frame, body)?;
+                    if self.condition(frame, condition)? {
+                        return Ok(());
+                    }
+                }
+            }
+
+            ast::Statement::For { init, condition, increment, body, .. } => {
+                // the loop variable is only visible inside the loop
+                frame.variables.push_scope();
+
+                self.statement(frame, init)?;
+                while self.condition(frame, condition)? {
+                    self.block(frame, body)?;
+                    self.statement(frame, increment)?;
+                }
+
+                frame.variables.pop_scope();
+                Ok(())
+            }
+
+            ast::Statement::Expression { expression, .. } => {
+                self.expression(frame, expression)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Value of a `bool` expression
+    fn condition(&mut self, frame: &mut Frame, condition: &ast::Expression) -> Exec<bool> {
+        let (value, _) = self.expression(frame, condition)?;
+        Ok(value == Value::Bool(true))
+    }
+
+    /// Evaluate an expression, returning its value and the type the type
+    /// checker gave it
+    /// Each kind of expression is evaluated by a function of its own, which
+    /// keeps this one's stack frame small for deeply nested calls
+    fn expression(
+        &mut self,
+        frame: &mut Frame,
+        expr: &ast::Expression,
+    ) -> Exec<(Value, Type)> {
+        if let Some(value) = expr.integer_literal_value() {
+            // the type checker made sure it fits
+            return Ok((Value::Integer(value), self.expression_type(frame, expr.span())));
+        }
+
+        match expr {
+            ast::Expression::IntegerLiteral { .. } => unreachable!(),
+
+            ast::Expression::FloatLiteral { value, .. } => match self.expression_type(frame, expr.span()) {
+                Type::F64 => Ok((Value::F64(*value), Type::F64)),
+                _ => Ok((Value::Float(*value as f32), Type::Float)),
+            },
+            ast::Expression::BooleanLiteral { value, .. } => Ok((Value::Bool(*value), Type::Bool)),
+            ast::Expression::NullLiteral { .. } => Ok((Value::Null, Type::Null)),
+            ast::Expression::StringLiteral { value, .. } => Ok((Value::string(value), Type::String)),
+
+            ast::Expression::Identifier { ident, .. } => self.identifier(frame, ident),
+
+            ast::Expression::Binary { left, operator: BinaryOperator::And, right, .. } => {
+                self.short_circuit(frame, left, right, true)
+            }
+
+            ast::Expression::Binary { left, operator: BinaryOperator::Or, right, .. } => {
+                self.short_circuit(frame, left, right, false)
+            }
+
+            ast::Expression::Binary { left, operator, right, span } => {
+                self.binary_expression(frame, left, operator, right, span)
+            }
+
+            ast::Expression::Unary { operator, right, span } => self.unary(frame, operator, right, span),
+
+            ast::Expression::Grouping { expression, .. } => self.expression(frame, expression),
+
+            ast::Expression::Function { span, .. } => Ok(self.lambda(frame, span)),
+
+            ast::Expression::Call { callee, arguments, span } => self.call_expression(frame, callee, arguments, span),
+
+            ast::Expression::Postfix { left: operand, operator, span } => {
+                let delta = match operator {
+                    ast::PostfixOperator::PlusPlus => BinaryOperator::Plus,
+                    ast::PostfixOperator::MinusMinus => BinaryOperator::Minus,
+                };
+
+                let (old, _, ty) = self.increment(frame, operand, delta, span)?;
+                Ok((old, ty))
+            }
+
+            ast::Expression::Prefix { operator, right: operand, span } => {
+                let delta = match operator {
+                    ast::PrefixOperator::PlusPlus => BinaryOperator::Plus,
+                    ast::PrefixOperator::MinusMinus => BinaryOperator::Minus,
+                };
+
+                let (_, new, ty) = self.increment(frame, operand, delta, span)?;
+                Ok((new, ty))
+            }
+
+            ast::Expression::Assign { left, operator, right, span } => self.assign(frame, left, operator, right, span),
+
+            ast::Expression::Cast { expression, target_type, .. } => {
+                let (value, source_type) = self.expression(frame, expression)?;
+                Ok((cast(value, &source_type, target_type), target_type.clone()))
+            }
+
+            ast::Expression::Index { target, index, span } => self.index_expression(frame, target, index, span),
+
+            ast::Expression::Slice { target, start, end, span } => {
+                self.slice(frame, target, start.as_deref(), end.as_deref(), span)
+            }
+
+            ast::Expression::ArrayLiteral { elements, span } => self.array_literal(frame, elements, span),
+
+            ast::Expression::StructLiteral { ident, fields, .. } => self.struct_literal(frame, ident, fields),
+
+            ast::Expression::Field { target, field, .. } => {
+                let (object, object_type) = self.expression(frame, target)?;
+                let (i, field_type) = self.field(&object_type, field);
+
+                let Value::Struct(object) = object else {
+                    unreachable!("field of {}", object);
+                };
+
+                let value = object.fields.borrow()[i].1.clone();
+                Ok((value, field_type))
+            }
+
+            ast::Expression::Variant { enum_ident, variant, arguments, .. } => {
+                self.variant(frame, enum_ident, variant, arguments)
+            }
+
+            ast::Expression::Match { scrutinee, arms, span } => self.match_expression(frame, scrutinee, arms, span),
+        }
+    }
+
+    /// A variable, global or named function
+    fn identifier(&self, frame: &Frame, ident: &String) -> Exec<(Value, Type)> {
+        // named functions can be used as values too
+        if frame.variables.get(ident).is_none() && self.functions.contains_key(ident) {
+            let signature = self.functions[ident].signature();
+            let closure = Closure { target: Target::Function(ident.clone()) };
+            let function_type = Type::Function(signature.params, Box::new(signature.return_type));
+
+            return Ok((Value::Function(Rc::new(closure)), function_type));
+        }
+
+        let (place, var_type) = self.variable_place(frame, ident);
+        Ok((self.read(frame, &place)?, var_type))
+    }
+
+    fn binary_expression(
+        &mut self,
+        frame: &mut Frame,
+        left: &ast::Expression,
+        operator: &BinaryOperator,
+        right: &ast::Expression,
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (lhs, ty) = self.expression(frame, left)?;
+        let (rhs, _) = self.expression(frame, right)?;
+
+        Ok(self.binary(operator, lhs, rhs, &ty, span)?)
+    }
+
+    fn unary(
+        &mut self,
+        frame: &mut Frame,
+        operator: &ast::UnaryOperator,
+        right: &ast::Expression,
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (value, ty) = self.expression(frame, right)?;
+
+        let value = match (operator, value) {
+            (ast::UnaryOperator::Minus, Value::Float(value)) => Value::Float(-value),
+            (ast::UnaryOperator::Minus, Value::F64(value)) => Value::F64(-value),
+            (ast::UnaryOperator::Minus, Value::Integer(value)) => self.negate(value, &ty, span)?,
+            (ast::UnaryOperator::Bang, Value::Bool(value)) => Value::Bool(!value),
+            (operator, value) => unreachable!("{:?} applied to {}", operator, value),
+        };
+
+        Ok((value, ty))
+    }
+
+    /// Type the type checker gave the expression at `span`
+    fn expression_type(&self, frame: &Frame, span: &Span) -> Type {
+        self.expression_types[&(frame.owner.clone(), span.clone())].clone()
+    }
+
+    /// The closure for the anonymous function written at `span`
+    fn lambda(&self, frame: &Frame, span: &Span) -> (Value, Type) {
+        let lambda = self.lambdas[&(frame.owner.clone(), span.clone())].clone();
+
+        // the closure gets its own copy of each captured value
+        let captured = lambda.captures.iter()
+            .map(|(ident, _)| frame.variables.get(ident).unwrap().0.clone())
+            .collect();
+
+        let params = lambda.params.params.iter().map(|param| param.param_type.clone()).collect();
+        let function_type = Type::Function(params, Box::new(lambda.return_type.clone()));
+        let closure = Closure { target: Target::Lambda(lambda, captured) };
+
+        (Value::Function(Rc::new(closure)), function_type)
+    }
+
+    fn call_expression(
+        &mut self,
+        frame: &mut Frame,
+        callee: &ast::Expression,
+        arguments: &[ast::Expression],
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        // variables shadow functions of the same name, as in the type checker
+        let named = match callee {
+            ast::Expression::Identifier { ident, .. } if frame.variables.get(ident).is_none() => Some(ident),
+            _ => None,
+        };
+
+        let Some(ident) = named else {
+            return self.closure_call(frame, callee, arguments, span);
+        };
+
+        if let Some(builtin) = Builtin::from_ident(ident) {
+            return self.builtin(frame, builtin, arguments);
+        }
+
+        if let Some(signature) = self.generics.get(ident).cloned() {
+            return self.generic_call(frame, ident, &signature, arguments, span);
+        }
+
+        let signature = self.functions[ident].signature();
+
+        let mut args = Vec::new();
+        for argument in arguments {
+            args.push(self.expression(frame, argument)?.0);
+        }
+
+        let value = self.call_function(ident, args, span)?;
+        Ok((value, signature.return_type))
+    }
+
+    fn assign(
+        &mut self,
+        frame: &mut Frame,
+        left: &ast::Expression,
+        operator: &ast::AssignOperator,
+        right: &ast::Expression,
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (place, ty) = self.place(frame, left)?;
+
+        let operator = match operator {
+            ast::AssignOperator::Equal => None,
+            ast::AssignOperator::PlusEqual => Some(BinaryOperator::Plus),
+            ast::AssignOperator::MinusEqual => Some(BinaryOperator::Minus),
+            ast::AssignOperator::StarEqual => Some(BinaryOperator::Star),
+            ast::AssignOperator::SlashEqual => Some(BinaryOperator::Slash),
+        };
+
+        let new = match operator {
+            None => self.expression(frame, right)?.0,
+            Some(operator) => {
+                let (rhs, _) = self.expression(frame, right)?;
+                let old = self.read(frame, &place)?;
+                self.binary(&operator, old, rhs, &ty, span)?.0
+            }
+        };
+
+        self.write(frame, &place, new.clone())?;
+        Ok((new, ty))
+    }
+
+    fn index_expression(
+        &mut self,
+        frame: &mut Frame,
+        target: &ast::Expression,
+        index: &ast::Expression,
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (target, target_type) = self.expression(frame, target)?;
+        let index = self.index(frame, index)?;
+
+        match (target, target_type.element_type()) {
+            (Value::Array(elements), Some(element_type)) => {
+                let element = self.element(&elements.borrow(), index, span)?.clone();
+                Ok((element, element_type.clone()))
+            }
+            (Value::String(bytes), None) => {
+                let byte = self.element(&bytes, index, span)?;
+                Ok((Value::Integer(*byte as i128), Type::U8))
+            }
+            (target, _) => unreachable!("indexing into {}", target),
+        }
+    }
+
+    fn slice(
+        &mut self,
+        frame: &mut Frame,
+        target: &ast::Expression,
+        start: Option<&ast::Expression>,
+        end: Option<&ast::Expression>,
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (target, target_type) = self.expression(frame, target)?;
+
+        let len = match &target {
+            Value::Array(elements) => elements.borrow().len(),
+            Value::String(bytes) => bytes.len(),
+            target => unreachable!("slicing {}", target),
+        };
+
+        let start = match start {
+            Some(start) => self.index(frame, start)?,
+            None => 0,
+        };
+        let end = match end {
+            Some(end) => self.index(frame, end)?,
+            None => len as i128,
+        };
+
+        if start < 0 || start > end || end > len as i128 {
+            return Err(self.trap(TrapKind::IndexOutOfBounds, span).into());
+        }
+        let range = start as usize..end as usize;
+
+        match (target, target_type.element_type()) {
+            (Value::Array(elements), Some(element_type)) => {
+                let slice = Value::array(elements.borrow()[range].to_vec());
+                Ok((slice, Type::Array(Box::new(element_type.clone()))))
+            }
+            (Value::String(bytes), _) => Ok((Value::String(bytes[range].into()), Type::String)),
+            (target, _) => unreachable!("slicing {}", target),
+        }
+    }
+
+    /// `[a, b, c]`
+    fn array_literal(&mut self, frame: &mut Frame, elements: &[ast::Expression], span: &Span) -> Exec<(Value, Type)> {
+        let mut values = Vec::new();
+        for element in elements {
+            values.push(self.expression(frame, element)?.0);
+        }
+
+        Ok((Value::array(values), self.expression_type(frame, span)))
+    }
+
+    fn struct_literal(&mut self, frame: &mut Frame, ident: &str, fields: &[(String, ast::Expression)]) -> Exec<(Value, Type)> {
+        let declaration = self.structs[ident].clone();
+        let mut values: Vec<(String, Value)> = declaration.fields.iter()
+            .map(|field| (field.ident.clone(), Value::Null))
+            .collect();
+
+        // fields are evaluated in the order they're written
+        for (field, value) in fields {
+            let i = declaration.fields.iter().position(|declared| declared.ident == *field).unwrap();
+            let (value, _) = self.expression(frame, value)?;
+            values[i].1 = value;
+        }
+
+        let object = StructValue { ident: ident.to_string(), fields: RefCell::new(values) };
+        Ok((Value::Struct(Rc::new(object)), Type::Named(ident.to_string())))
+    }
+
+    fn variant(
+        &mut self,
+        frame: &mut Frame,
+        enum_ident: &str,
+        variant: &str,
+        arguments: &[ast::Expression],
+    ) -> Exec<(Value, Type)> {
+        let (tag, _) = self.enums[enum_ident].variant(variant).unwrap();
+
+        let mut values = Vec::new();
+        for argument in arguments {
+            values.push(self.expression(frame, argument)?.0);
+        }
+
+        let value = VariantValue { enum_ident: enum_ident.to_string(), variant: variant.to_string(), tag, values };
+        Ok((Value::Variant(Rc::new(value)), Type::Named(enum_ident.to_string())))
+    }
+
+    /// `and`/`or`, only evaluating the right operand when it matters
+    fn short_circuit(
+        &mut self,
+        frame: &mut Frame,
+        left: &ast::Expression,
+        right: &ast::Expression,
+        is_and: bool,
+    ) -> Exec<(Value, Type)> {
+        if self.condition(frame, left)? != is_and {
+            return Ok((Value::Bool(!is_and), Type::Bool));
+        }
+
+        self.expression(frame, right)
+    }
+
+    /// Call the instance of a generic function for the type arguments
+    /// inferred from the arguments, the same way the type checker does
+    fn generic_call(
+        &mut self,
+        frame: &mut Frame,
+        ident: &str,
+        signature: &FunctionSignature,
+        arguments: &[ast::Expression],
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        // inference only fails for programs the type checker rejects, so an
+        // error here is one the program ran into
+        let mut exit = None;
+        let inferred = generics::infer_call(ident, signature, arguments, |argument, _| {
+            self.expression(frame, argument).map_err(|e| {
+                exit = Some(e);
+                CompileError::CompileError(String::new())
+            })
+        });
+
+        if let Some(exit) = exit {
+            return Err(exit);
+        }
+
+        let (args, type_args) = inferred.expect("the type checker inferred the call");
+        let instance = generics::instance_ident(ident, &type_args);
+        let return_type = self.functions[&instance].signature().return_type;
+
+        Ok((self.call_function(&instance, args, span)?, return_type))
+    }
+
+    /// Call a function value
+    fn closure_call(
+        &mut self,
+        frame: &mut Frame,
+        callee: &ast::Expression,
+        arguments: &[ast::Expression],
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (closure, callee_type) = self.expression(frame, callee)?;
+
+        let (Value::Function(closure), Type::Function(_, return_type)) = (closure, callee_type) else {
+            unreachable!("the type checker only allows calling functions");
+        };
+
+        let mut args = Vec::new();
+        for argument in arguments {
+            args.push(self.expression(frame, argument)?.0);
+        }
+
+        Ok((self.call_closure(&closure, args, span)?, *return_type))
+    }
+
+    /// `match (x) { ... }`, with the type the type checker gave it
+    fn match_expression(
+        &mut self,
+        frame: &mut Frame,
+        scrutinee: &ast::Expression,
+        arms: &[ast::MatchArm],
+        span: &Span,
+    ) -> Exec<(Value, Type)> {
+        let (value, _) = self.expression(frame, scrutinee)?;
+
+        // patterns are distinct and any wildcard comes last, so the first
+        // arm that matches is the only one that does
+        let arm = arms.iter().find(|arm| match (&arm.pattern, &value) {
+            (ast::Pattern::Variant { variant, .. }, Value::Variant(value)) => *variant == value.variant,
+            (ast::Pattern::Integer { value: pattern, .. }, Value::Integer(value)) => pattern == value,
+            (ast::Pattern::Boolean { value: pattern, .. }, Value::Bool(value)) => pattern == value,
+            (ast::Pattern::Wildcard { .. }, _) => true,
+            _ => false,
+        }).expect("the type checker made sure matches are exhaustive");
+
+        frame.variables.push_scope();
+
+        if let (ast::Pattern::Variant { bindings, .. }, Value::Variant(variant)) = (&arm.pattern, &value) {
+            let field_types = &self.enums[&variant.enum_ident].variants[variant.tag].fields;
+
+            for ((binding, value), field_type) in bindings.iter().zip(&variant.values).zip(field_types) {
+                if binding != "_" {
+                    frame.variables.insert(binding.clone(), (value.clone(), field_type.clone()));
+                }
+            }
+        }
+
+        let value = match &arm.body {
+            ast::MatchBody::Expression(body) => self.expression(frame, body)?.0,
+            ast::MatchBody::Block(body) => {
+                self.block(frame, body)?;
+                Value::Null
+            }
+        };
+
+        frame.variables.pop_scope();
+        Ok((value, self.expression_type(frame, span)))
+    }
+
+    fn builtin(&mut self, frame: &mut Frame, builtin: Builtin, arguments: &[ast::Expression]) -> Exec<(Value, Type)> {
+        match builtin {
+            Builtin::Len => {
+                let len = match self.expression(frame, &arguments[0])?.0 {
+                    Value::String(bytes) => bytes.len(),
+                    Value::Array(elements) => elements.borrow().len(),
+                    value => unreachable!("len({})", value),
+                };
+
+                Ok((Value::Integer(len as i128), Type::Int))
+            }
+
+            Builtin::Push => {
+                let (array, _) = self.expression(frame, &arguments[0])?;
+                let (value, _) = self.expression(frame, &arguments[1])?;
+
+                let Value::Array(elements) = array else {
+                    unreachable!("push onto {}", array);
+                };
+
+                elements.borrow_mut().push(value);
+                Ok((Value::Null, Type::Null))
+            }
+        }
+    }
+
+    /// `x++`, `++x`, `x--` and `--x`
+    /// Returns the old value, the new value and the type of the operand
+    fn increment(
+        &mut self,
+        frame: &mut Frame,
+        operand: &ast::Expression,
+        operator: BinaryOperator,
+        span: &Span,
+    ) -> Exec<(Value, Value, Type)> {
+        let (place, ty) = self.place(frame, operand)?;
+
+        let old = self.read(frame, &place)?;
+        let (new, _) = self.binary(&operator, old.clone(), Value::Integer(1), &ty, span)?;
+        self.write(frame, &place, new.clone())?;
+
+        Ok((old, new, ty))
+    }
+
+    /// Arithmetic and comparisons on two values of type `ty`
+    fn binary(
+        &self,
+        operator: &BinaryOperator,
+        lhs: Value,
+        rhs: Value,
+        ty: &Type,
+        span: &Span,
+    ) -> RuntimeResult<(Value, Type)> {
+        let value = match (lhs, rhs) {
+            (Value::Integer(a), Value::Integer(b)) => {
+                let value = constant::integer_binary(operator, a, b, ty, self.overflow_mode)
+                    .map_err(|error| self.arithmetic_trap(error, span))?;
+                constant_value(value)
+            }
+
+            (Value::Float(a), Value::Float(b)) => constant_value(constant::float_binary(operator, a as f64, b as f64, ty)),
+            (Value::F64(a), Value::F64(b)) => constant_value(constant::float_binary(operator, a, b, ty)),
+
+            (Value::String(a), Value::String(b)) => match operator {
+                BinaryOperator::Plus => Value::String([&a[..], &b[..]].concat().into()),
+                _ => Value::Bool(constant::compare(operator, a.cmp(&b))),
+            },
+
+            (Value::Bool(a), Value::Bool(b)) => Value::Bool(constant::compare(operator, a.cmp(&b))),
+
+            (lhs, rhs) => unreachable!("{:?} applied to {} and {}", operator, lhs, rhs),
+        };
+
+        let result_type = match value {
+            Value::Bool(_) => Type::Bool,
+            _ => ty.clone(),
+        };
+
+        Ok((value, result_type))
+    }
+
+    fn negate(&self, value: i128, ty: &Type, span: &Span) -> RuntimeResult<Value> {
+        constant::integer(-value, ty, self.overflow_mode)
+            .map(constant_value)
+            .map_err(|error| self.arithmetic_trap(error, span))
+    }
+
+    /// The runtime error for arithmetic that has no result
+    fn arithmetic_trap(&self, error: ArithmeticError, span: &Span) -> RuntimeError {
+        match error {
+            ArithmeticError::Overflow => self.trap(TrapKind::IntegerOverflow, span),
+            ArithmeticError::DivisionByZero => self.trap(TrapKind::DivisionByZero, span),
+        }
+    }
+
+    /// An index or slice bound
+    fn index(&mut self, frame: &mut Frame, index: &ast::Expression) -> Exec<i128> {
+        match self.expression(frame, index)?.0 {
+            Value::Integer(index) => Ok(index),
+            index => unreachable!("index {}", index),
+        }
+    }
+
+    /// `elements[index]`, or a runtime error pointing at `span` if the index
+    /// is out of bounds
+    fn element<'e, T>(&self, elements: &'e [T], index: i128, span: &Span) -> RuntimeResult<&'e T> {
+        usize::try_from(index).ok()
+            .and_then(|index| elements.get(index))
+            .ok_or_else(|| self.trap(TrapKind::IndexOutOfBounds, span))
+    }
+
+    /// Position and type of a field of a struct
+    fn field(&self, object_type: &Type, field: &str) -> (usize, Type) {
+        let Type::Named(ident) = object_type else {
+            unreachable!("field of {}", object_type);
+        };
+
+        let declaration = &self.structs[ident];
+        let i = declaration.fields.iter().position(|declared| declared.ident == field).unwrap();
+        (i, declaration.fields[i].field_type.clone())
+    }
+
+    /// Evaluate the target of an assignment
+    fn place(&mut self, frame: &mut Frame, expr: &ast::Expression) -> Exec<(Place, Type)> {
+        match expr {
+            ast::Expression::Index { target, index, span } => {
+                let (array, array_type) = self.expression(frame, target)?;
+                let element_type = array_type.element_type().cloned().unwrap();
+                let index = self.index(frame, index)?;
+
+                let Value::Array(array) = array else {
+                    unreachable!("assigning into {}", array);
+                };
+
+                Ok((Place::Element { array, index, span: span.clone() }, element_type))
+            }
+            ast::Expression::Field { target, field, .. } => {
+                let (object, object_type) = self.expression(frame, target)?;
+                let (field, field_type) = self.field(&object_type, field);
+
+                let Value::Struct(object) = object else {
+                    unreachable!("field of {}", object);
+                };
+
+                Ok((Place::Field { object, field }, field_type))
+            }
+            ast::Expression::Identifier { ident, .. } => Ok(self.variable_place(frame, ident)),
+            _ => unreachable!("the type checker rejects other assignment targets"),
+        }
+    }
+
+    /// Where a variable is kept: in the frame, or in a global if no
+    /// variable shadows it
+    fn variable_place(&self, frame: &Frame, ident: &String) -> (Place, Type) {
+        match frame.variables.get(ident) {
+            Some((_, ty)) => (Place::Variable(ident.clone()), ty.clone()),
+            None => (Place::Global(ident.clone()), self.globals[ident].1.clone()),
+        }
+    }
+
+    fn read(&self, frame: &Frame, place: &Place) -> RuntimeResult<Value> {
+        match place {
+            Place::Variable(ident) => Ok(frame.variables.get(ident).unwrap().0.clone()),
+            Place::Global(ident) => Ok(self.globals[ident].0.clone()),
+            Place::Element { array, index, span } => Ok(self.element(&array.borrow(), *index, span)?.clone()),
+            Place::Field { object, field } => Ok(object.fields.borrow()[*field].1.clone()),
+        }
+    }
+
+    fn write(&mut self, frame: &mut Frame, place: &Place, value: Value) -> RuntimeResult<()> {
+        match place {
+            Place::Variable(ident) => frame.variables.get_mut(ident).unwrap().0 = value,
+            Place::Global(ident) => self.globals.get_mut(ident).unwrap().0 = value,
+            Place::Element { array, index, span } => {
+                self.element(&array.borrow(), *index, span)?;
+                array.borrow_mut()[*index as usize] = value;
+            }
+            Place::Field { object, field } => object.fields.borrow_mut()[*field].1 = value,
+        }
+
+        Ok(())
+    }
+
+    /// A runtime error pointing at `span`
+    fn trap(&self, kind: TrapKind, span: &Span) -> RuntimeError {
+        let (line, column) = self.sources.location(span);
+        RuntimeError::Trap { kind, line, column }
+    }
+}
+
+/// `value as target`, with the same results as compiled casts
+fn cast(value: Value, source_type: &Type, target_type: &Type) -> Value {
+    let value = match value {
+        Value::Integer(value) => Constant::Integer(value),
+        Value::Float(value) => Constant::Float(value),
+        Value::F64(value) => Constant::F64(value),
+        Value::Bool(value) => Constant::Bool(value),
+        // only numbers and bools can be cast to another type
+        value => return value,
+    };
+
+    constant_value(constant::cast(value, source_type, target_type))
+}
+
+/// A global's initial value
+fn constant_value(constant: Constant) -> Value {
+    match constant {
+        Constant::Integer(value) => Value::Integer(value),
+        Constant::Float(value) => Value::Float(value),
+        Constant::F64(value) => Value::F64(value),
+        Constant::Bool(value) => Value::Bool(value),
+        Constant::String(text) => Value::string(&text),
+    }
+}
+
+/// Whether a value passed in from outside could be of type `ty`
+fn fits(value: &Value, ty: &Type) -> bool {
+    match (value, ty) {
+        (Value::Integer(value), ty) if ty.is_integer() => {
+            let (min, max) = ty.integer_range().unwrap();
+            (min..=max).contains(value)
+        }
+        (Value::Float(_), Type::Float)
+        | (Value::F64(_), Type::F64)
+        | (Value::Bool(_), Type::Bool)
+        | (Value::String(_), Type::String)
+        | (Value::Null, Type::Null)
+        | (Value::Function(_), Type::Function(..)) => true,
+        (Value::Array(elements), Type::FixedArray(_, len)) => elements.borrow().len() == *len,
+        (Value::Array(_), Type::Array(_)) => true,
+        (Value::Struct(object), Type::Named(ident)) => object.ident == *ident,
+        (Value::Variant(variant), Type::Named(ident)) => variant.enum_ident == *ident,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Compiler, CompilerOptions};
+
+    fn load(source: &str, overflow_mode: OverflowMode) -> Interpreter {
+        let mut interpreter = Interpreter::new(overflow_mode);
+        interpreter.load(source).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(source)));
+        interpreter
+    }
+
+    #[test]
+    fn test_run() {
+        let source = r#"
+const LIMIT: u8 = 200;
+let calls = 0;
+
+struct Point { x: float, y: float }
+enum Shape { Circle(float), Rect(Point, Point), Empty }
+
+func area(s: Shape): float {
+    calls++;
+    return match (s) {
+        Circle(r) => 3.0 * r * r,
+        Rect(a, b) => (b.x - a.x) * (b.y - a.y),
+        Empty => 0.0,
+    };
+}
+
+func max<T: numeric>(a: T, b: T): T {
+    if (a > b) { return a; }
+    return b;
+}
+func biggest(): float { return max(1.0, max(2, 1) as float); }
+
+func adder(n: int): func(int): int {
+    return func (x: int): int { return x + n; };
+}
+
+func strings(): string {
+    let s = "hello";
+    return s[1:4] + " " + (len(s) * 2) as string + " " + (s[0] as i8) as string;
+}
+
+func shared(): int[] {
+    let a = [1, 2];
+    let b = a;
+    push(b, 3);
+    a[0] = 10;
+    return a;
+}
+
+func bytes(): u8 {
+    let b = LIMIT;
+    b += 100;
+    return b;
+}
+"#;
+        let mut interpreter = load(source, OverflowMode::Wrapping);
+
+        let rect = interpreter.call("area", &[]).unwrap_err();
+        assert!(matches!(rect, RuntimeError::InvalidCall(_)), "{}", rect);
+
+        let circle = Value::Variant(Rc::new(VariantValue {
+            enum_ident: "Shape".to_string(),
+            variant: "Circle".to_string(),
+            tag: 0,
+            values: vec![Value::Float(2.0)],
+        }));
+        assert_eq!(interpreter.call("area", &[circle]).unwrap(), Value::Float(12.0));
+        assert_eq!(interpreter.global("calls"), Some(&Value::Integer(1)));
+
+        assert_eq!(interpreter.call("max<float>", &[1.5f32.into(), (-2.0f32).into()]).unwrap(), Value::Float(1.5));
+        assert_eq!(interpreter.call("strings", &[]).unwrap(), Value::from("ell 10 104"));
+        assert_eq!(interpreter.call("shared", &[]).unwrap().to_string(), "[10, 2, 3]");
+        assert_eq!(interpreter.call("bytes", &[]).unwrap(), Value::Integer(44));
+
+        let add = interpreter.call("adder", &[5.into()]).unwrap();
+        assert_eq!(add.to_string(), "<func>");
+        let Value::Function(add) = add else { panic!() };
+        assert_eq!(interpreter.call_closure(&add, vec![Value::Integer(2)], &Span::default()).unwrap(), Value::Integer(7));
+
+        // checked arithmetic stops where compiled code does
+        let mut checked = load(source, OverflowMode::Checked);
+        assert!(checked.load("const X: u8 = 200 + 100;").is_err());
+        assert_eq!(
+            checked.call("bytes", &[]).unwrap_err().to_string(),
+            "Runtime error at 42:5: integer overflow",
+        );
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let source = "\
+func div(a: i32, b: i32): i32 { return a / b; }
+func get(i: int): int {
+    let a = [1, 2, 3];
+    return a[i];
+}
+func deep(n: int): int { return deep(n + 1); }
+extern func abs(x: i32): i32;
+func call_abs(): i32 { return abs(-1); }
+func negate(x: i8): i8 { return -x; }
+func count(n: int): int { if (n == 0) { return 0; } return count(n - 1) + 1; }";
+        let mut interpreter = load(source, OverflowMode::Checked);
+
+        let error = |interpreter: &mut Interpreter, ident: &str, args: &[Value]| {
+            interpreter.call(ident, args).unwrap_err().to_string()
+        };
+
+        assert_eq!(error(&mut interpreter, "div", &[1.into(), 0.into()]), "Runtime error at 1:40: division by zero");
+        assert_eq!(error(&mut interpreter, "div", &[(i32::MIN as i64).into(), (-1).into()]), "Runtime error at 1:40: integer overflow");
+        assert_eq!(error(&mut interpreter, "get", &[3.into()]), "Runtime error at 4:12: index out of bounds");
+        assert_eq!(error(&mut interpreter, "get", &[(-1).into()]), "Runtime error at 4:12: index out of bounds");
+        assert_eq!(error(&mut interpreter, "call_abs", &[]), "Runtime error at 8:31: extern function `abs` cannot be interpreted");
+        assert_eq!(error(&mut interpreter, "negate", &[(-128).into()]), "Runtime error at 9:33: integer overflow");
+        assert!(error(&mut interpreter, "div", &[1.into()]).contains("takes 2 arguments but 1 were given"));
+        assert!(error(&mut interpreter, "negate", &[200.into()]).contains("should be i8"));
+
+        // deeper than the test thread's stack holds, which grows onto the heap
+        assert_eq!(interpreter.call("count", &[5000.into()]).unwrap(), Value::Integer(5000));
+
+        interpreter.set_max_call_depth(1000);
+        assert_eq!(error(&mut interpreter, "deep", &[0.into()]), "Runtime error at 6:33: more than 1000 nested calls");
+
+        // the interpreter is still usable afterwards
+        assert_eq!(interpreter.call("get", &[2.into()]).unwrap(), Value::Integer(3));
+
+        let mut wrapping = load(source, OverflowMode::Wrapping);
+        assert_eq!(wrapping.call("div", &[(i32::MIN as i64).into(), (-1).into()]).unwrap(), Value::Integer(i32::MIN as i128));
+        assert_eq!(wrapping.call("negate", &[(-128).into()]).unwrap(), Value::Integer(-128));
+    }
+
+    /// Run every function of `source` on each argument, interpreted and
+    /// compiled, and check they agree
+    fn differential(source: &str, overflow_mode: OverflowMode, functions: &[&str], inputs: &[i64]) {
+        let mut interpreter = load(source, overflow_mode);
+        let mut compiler = Compiler::new(CompilerOptions::new().overflow_mode(overflow_mode)).unwrap();
+        compiler.compile(source).unwrap();
+
+        for ident in functions {
+            let function: extern "C" fn(i64) -> i64 = unsafe { std::mem::transmute(compiler.get_function(ident).unwrap()) };
+
+            for input in inputs {
+                let interpreted = interpreter.call(ident, &[(*input).into()]).unwrap();
+                assert_eq!(interpreted, Value::Integer(function(*input) as i128), "{}({})", ident, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_matches_compiler() {
+        let source = r#"
+struct Counter { n: int, seen: int[] }
+enum Step { Add(int), Twice, Stop }
+
+func steps(x: int): int {
+    let c = Counter { n: x, seen: [] };
+    let plan = [Step::Add(3), Step::Twice, Step::Add(-7), Step::Stop, Step::Twice];
+    for (let i = 0; i < len(plan); i++) {
+        let go = match (plan[i]) {
+            Add(k) => { c.n += k; }
+            Twice => { c.n = c.n * 2; }
+            Stop => { return c.n + len(c.seen); }
+        };
+        push(c.seen, c.n);
+    }
+    return -1;
+}
+
+func narrow(x: int): int {
+    let a = x as i8;
+    let b: u8 = 250;
+    b += a as u8;
+    let c = (a * 3) as i16 - 1000;
+    return (b as int) + (c as int) + (0 - x) as u16 as int;
+}
+
+func floats(x: int): int {
+    let f = x as float / 3.0;
+    let g = f * f - 1.5;
+    if (g != g or g > 1000000.0) { return -1; }
+    return (g as int) + (f < 0.0) as int + (g as string == "0") as int;
+}
+
+func doubles(x: int): int {
+    let d: f64 = x as f64 / 3.0 + 0.1;
+    let f = d as float * 2.0;
+    let back = -(f as f64) + 2.0 * d;
+    return (d * 1000.0) as int + (back * 1000000000.0) as i32 as int + len(d as string);
+}
+
+func closures(x: int): int {
+    let total = 0;
+    let rounds = 0;
+    let add = func (y: int): int { return x + y; };
+    let twice = func (y: int): int { return add(add(y)); };
+    let fs = [add, twice];
+    do {
+        total = fs[rem(total, 2)](total) + match (rem(total, 3)) { 0 => 1, 1 => 2, _ => 3 };
+    } until (++rounds == 20 or total > 100 or total < -100)
+    return total;
+}
+
+func rem(a: int, b: int): int {
+    let r = a - a / b * b;
+    if (r < 0) { return r + b; }
+    return r;
+}
+
+func divide(x: int): int {
+    let q = 100 / (rem(x, 7) + 8);
+    let big = 9223372036854775807;
+    return q + big / (x * x + 1) - (x * 4611686018427387904) / 3;
+}
+
+func strings(x: int): int {
+    let s = "";
+    let i = 0;
+    while (len(s) < 12) {
+        s = s + (x * i) as string;
+        i++;
+    }
+    return s[3] as int + len(s[2:]) * 100;
+}
+"#;
+        let inputs = [0, 1, -1, 5, -7, 100, 127, 128, -129, 1000, 12345, i32::MAX as i64, i64::MIN, i64::MAX];
+        let functions = ["steps", "narrow", "floats", "doubles", "closures", "divide", "strings"];
+
+        differential(source, OverflowMode::Wrapping, &functions, &inputs);
+        differential(source, OverflowMode::Checked, &["steps", "closures", "strings"], &[0, 1, -1, 5, 100]);
+    }
+}
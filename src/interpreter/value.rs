@@ -0,0 +1,220 @@
+//! Values of an interpreted program
+//!
+//! Strings, arrays and structs are shared the way compiled code shares
+//! them: copying one copies a reference, so every variable holding the same
+//! array sees the same elements. `Rc` does the reference counting the
+//! runtime does for compiled code.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::type_checking::Lambda;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// A value of any of the integer types, always within its type's range
+    Integer(i128),
+    Float(f32),
+    F64(f64),
+    Bool(bool),
+    /// Strings are immutable bytes, as in compiled code; slicing one in the
+    /// middle of a character leaves bytes that aren't UTF-8
+    String(Rc<[u8]>),
+    /// Growable and fixed length arrays
+    Array(Rc<RefCell<Vec<Value>>>),
+    Struct(Rc<StructValue>),
+    /// A value of an enum
+    Variant(Rc<VariantValue>),
+    /// A named function or a closure
+    Function(Rc<Closure>),
+    Null,
+}
+
+/// A struct, with its fields in declaration order
+#[derive(Debug, PartialEq)]
+pub struct StructValue {
+    pub ident: String,
+    pub fields: RefCell<Vec<(String, Value)>>,
+}
+
+/// A variant of an enum, with the values it carries
+#[derive(Debug, PartialEq)]
+pub struct VariantValue {
+    pub enum_ident: String,
+    pub variant: String,
+    /// Position of the variant in the enum's declaration
+    pub tag: usize,
+    pub values: Vec<Value>,
+}
+
+/// Something a function value calls
+#[derive(Debug)]
+pub struct Closure {
+    pub(super) target: Target,
+}
+
+#[derive(Debug)]
+pub(super) enum Target {
+    /// A function declared in the program, by name
+    Function(String),
+    /// An anonymous function, with the values it captured when it was
+    /// created, in the order of `Lambda::captures`
+    Lambda(Rc<Lambda>, Vec<Value>),
+}
+
+impl Closure {
+    /// Name of the function it calls, `None` for anonymous functions
+    pub fn ident(&self) -> Option<&str> {
+        match &self.target {
+            Target::Function(ident) => Some(ident),
+            Target::Lambda(..) => None,
+        }
+    }
+}
+
+/// Function values are only equal to themselves
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Value {
+    /// A string holding `text`
+    pub fn string(text: &str) -> Self {
+        Value::String(text.as_bytes().into())
+    }
+
+    /// An array holding `elements`
+    pub fn array(elements: Vec<Value>) -> Self {
+        Value::Array(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn as_integer(&self) -> Option<i128> {
+        match self {
+            Value::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            Value::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::F64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The text of a string, with bytes that aren't UTF-8 replaced
+    pub fn as_string(&self) -> Option<String> {
+        match self {
+            Value::String(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            _ => None,
+        }
+    }
+
+    /// Write the value, quoting strings if it's inside another value
+    fn write(&self, f: &mut fmt::Formatter, nested: bool) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{}", value),
+            Value::F64(value) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(bytes) if nested => write!(f, "{:?}", String::from_utf8_lossy(bytes)),
+            Value::String(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, true)?;
+                }
+                write!(f, "]")
+            }
+            Value::Struct(object) => {
+                write!(f, "{} {{ ", object.ident)?;
+                for (i, (ident, value)) in object.fields.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", ident)?;
+                    value.write(f, true)?;
+                }
+                write!(f, " }}")
+            }
+            Value::Variant(variant) => {
+                write!(f, "{}::{}", variant.enum_ident, variant.variant)?;
+                if variant.values.is_empty() {
+                    return Ok(());
+                }
+
+                write!(f, "(")?;
+                for (i, value) in variant.values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    value.write(f, true)?;
+                }
+                write!(f, ")")
+            }
+            Value::Function(closure) => match closure.ident() {
+                Some(ident) => write!(f, "<func {}>", ident),
+                None => write!(f, "<func>"),
+            },
+            Value::Null => write!(f, "null"),
+        }
+    }
+}
+
+/// Values display as they'd be written in source, except that strings
+/// are only quoted inside other values
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, false)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value as i128)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::F64(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::string(value)
+    }
+}
@@ -0,0 +1,829 @@
+//! Building the IR of a type checked function
+//!
+//! Variables are turned into values as the body is walked, following Braun
+//! et al., "Simple and Efficient Construction of Static Single Assignment
+//! Form": a block that reads a variable it didn't assign takes the value
+//! from its predecessors, through a parameter if there's more than one.
+//! Parameters that turn out to receive the same value from every
+//! predecessor are removed once the whole function is built.
+//!
+//! Every value takes the type the type checker recorded for the expression
+//! it comes from, so an integer literal has the type its context gave it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{self, BinaryOperator, Type};
+use crate::builtins::Builtin;
+use crate::compiler::symbol_table::SymbolTable;
+use crate::error::Span;
+use crate::type_checking::{FunctionSignature, TypeChecker};
+
+use super::{Block, BlockData, Edge, Function, Instruction, InstructionKind, Terminator, Value};
+
+/// Something in a function that the IR can't represent yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    /// What it is, i.e. "strings"
+    pub what: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the IR can't represent {} yet", self.what)
+    }
+}
+
+type BuildResult<T> = Result<T, Unsupported>;
+
+/// Build the IR of a function `checker` accepted
+/// `functions` are the signatures of the functions it may call; generic
+/// ones aren't among them, so calls to them aren't supported
+pub fn build(
+    function: &ast::Function,
+    checker: &TypeChecker,
+    functions: &HashMap<String, FunctionSignature>,
+) -> BuildResult<Function> {
+    if function.is_generic() {
+        return Err(unsupported("generic functions", &function.span));
+    }
+
+    for param in &function.params.params {
+        scalar(&param.param_type, &function.span)?;
+    }
+    scalar(&function.return_type, &function.span)?;
+
+    let mut builder = Builder {
+        checker,
+        owner: &function.ident,
+        functions,
+        return_type: function.return_type.clone(),
+        blocks: Vec::new(),
+        value_types: Vec::new(),
+        current: Block(0),
+        variables: SymbolTable::new(),
+        variable_types: Vec::new(),
+        definitions: HashMap::new(),
+    };
+
+    let entry = builder.create_block();
+    builder.switch_to(entry);
+    builder.seal(entry);
+
+    builder.variables.push_scope();
+    for param in &function.params.params {
+        let value = builder.append_param(entry, &param.param_type);
+        builder.declare_variable(&param.ident, &param.param_type, value);
+    }
+
+    builder.block(&function.body)?;
+    builder.variables.pop_scope();
+
+    // falling off the end is fine for functions that return nothing
+    let end = if function.return_type == Type::Null { Terminator::Return(None) } else { Terminator::Unreachable };
+    builder.terminate(end);
+
+    Ok(builder.finish(function))
+}
+
+/// A variable of the source, which may have a different value in each block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Variable(usize);
+
+/// A block still being built
+struct PartialBlock {
+    params: Vec<Value>,
+    instructions: Vec<Instruction>,
+    /// `None` until the block is finished
+    terminator: Option<Terminator>,
+    predecessors: Vec<Block>,
+    /// Whether all its predecessors are known
+    sealed: bool,
+    /// Variables given parameters before it was sealed, which don't have
+    /// their arguments yet
+    incomplete: Vec<Variable>,
+}
+
+struct Builder<'a> {
+    checker: &'a TypeChecker,
+    /// Function being built, which the checker's types are recorded under
+    owner: &'a str,
+    functions: &'a HashMap<String, FunctionSignature>,
+    return_type: Type,
+    blocks: Vec<PartialBlock>,
+    value_types: Vec<Type>,
+    /// Block instructions are added to
+    current: Block,
+    variables: SymbolTable<String, Variable>,
+    variable_types: Vec<Type>,
+    /// Value of each variable at the end of the blocks that assign it, or
+    /// read it from a parameter
+    definitions: HashMap<(Variable, Block), Value>,
+}
+
+impl Builder<'_> {
+    fn create_block(&mut self) -> Block {
+        self.blocks.push(PartialBlock {
+            params: Vec::new(),
+            instructions: Vec::new(),
+            terminator: None,
+            predecessors: Vec::new(),
+            sealed: false,
+            incomplete: Vec::new(),
+        });
+
+        Block(self.blocks.len() - 1)
+    }
+
+    fn switch_to(&mut self, block: Block) {
+        self.current = block;
+    }
+
+    fn new_value(&mut self, ty: &Type) -> Value {
+        self.value_types.push(ty.clone());
+        Value(self.value_types.len() - 1)
+    }
+
+    fn append_param(&mut self, block: Block, ty: &Type) -> Value {
+        let value = self.new_value(ty);
+        self.blocks[block.0].params.push(value);
+        value
+    }
+
+    /// Add an instruction to the current block, returning its result
+    fn ins(&mut self, kind: InstructionKind, ty: &Type, span: &Span) -> Value {
+        let result = self.new_value(ty);
+        self.blocks[self.current.0].instructions.push(Instruction { result, kind, span: span.clone() });
+        result
+    }
+
+    /// End the current block
+    fn terminate(&mut self, terminator: Terminator) {
+        let current = self.current;
+
+        for edge in terminator.edges() {
+            let predecessors = &mut self.blocks[edge.block.0].predecessors;
+            if !predecessors.contains(&current) {
+                predecessors.push(current);
+            }
+        }
+
+        self.blocks[current.0].terminator = Some(terminator);
+    }
+
+    fn jump(&mut self, block: Block) {
+        self.terminate(Terminator::Jump(Edge { block, args: Vec::new() }));
+    }
+
+    fn branch(&mut self, condition: Value, then: Block, otherwise: Block) {
+        self.terminate(Terminator::Branch {
+            condition,
+            then: Edge { block: then, args: Vec::new() },
+            otherwise: Edge { block: otherwise, args: Vec::new() },
+        });
+    }
+
+    /// Note that a block has all its predecessors, giving the parameters
+    /// added before then their arguments
+    fn seal(&mut self, block: Block) {
+        for variable in std::mem::take(&mut self.blocks[block.0].incomplete) {
+            self.add_arguments(variable, block);
+        }
+
+        self.blocks[block.0].sealed = true;
+    }
+
+    /// Continue in a fresh block with no predecessors, so statements after a
+    /// `return` still have somewhere to go
+    fn start_unreachable_block(&mut self) {
+        let block = self.create_block();
+        self.switch_to(block);
+        self.seal(block);
+    }
+
+    fn declare_variable(&mut self, ident: &str, ty: &Type, value: Value) {
+        let variable = Variable(self.variable_types.len());
+        self.variable_types.push(ty.clone());
+        self.variables.insert(ident.to_string(), variable);
+        self.definitions.insert((variable, self.current), value);
+    }
+
+    fn write_variable(&mut self, variable: Variable, value: Value) {
+        self.definitions.insert((variable, self.current), value);
+    }
+
+    /// Value of a variable at the end of `block`
+    fn read_variable(&mut self, variable: Variable, block: Block) -> Value {
+        if let Some(value) = self.definitions.get(&(variable, block)) {
+            return *value;
+        }
+
+        let ty = self.variable_types[variable.0].clone();
+        let data = &self.blocks[block.0];
+
+        let value = if !data.sealed {
+            let param = self.append_param(block, &ty);
+            self.blocks[block.0].incomplete.push(variable);
+            param
+        } else if let [predecessor] = data.predecessors[..] {
+            self.read_variable(variable, predecessor)
+        } else {
+            // defined before its arguments are looked up, so a loop back
+            // to the block finds it
+            let param = self.append_param(block, &ty);
+            self.definitions.insert((variable, block), param);
+            self.add_arguments(variable, block);
+            param
+        };
+
+        self.definitions.insert((variable, block), value);
+        value
+    }
+
+    /// Pass the value of `variable` to the parameter of `block` just added
+    /// for it, from each of its predecessors
+    fn add_arguments(&mut self, variable: Variable, block: Block) {
+        for predecessor in self.blocks[block.0].predecessors.clone() {
+            let value = self.read_variable(variable, predecessor);
+
+            let terminator = self.blocks[predecessor.0].terminator.as_mut().expect("predecessors are terminated");
+            for edge in terminator.edges_mut() {
+                if edge.block == block {
+                    edge.args.push(value);
+                }
+            }
+        }
+    }
+
+    fn variable(&self, ident: &String, span: &Span) -> BuildResult<(Variable, Type)> {
+        let variable = *self.variables.get(ident).ok_or_else(|| unsupported("globals", span))?;
+        Ok((variable, self.variable_types[variable.0].clone()))
+    }
+
+    /// Type the checker gave an expression
+    fn expression_type(&self, expr: &ast::Expression) -> BuildResult<Type> {
+        self.checker.expression_type(self.owner, expr.span())
+            .cloned()
+            .ok_or_else(|| unsupported("expressions the type checker has no type for", expr.span()))
+    }
+
+    fn block(&mut self, block: &ast::Block) -> BuildResult<()> {
+        self.variables.push_scope();
+        block.statements.iter().try_for_each(|statement| self.statement(statement))?;
+        self.variables.pop_scope();
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &ast::Statement) -> BuildResult<()> {
+        match statement {
+            ast::Statement::VariableDeclaration { ident, var_type, value, span } => {
+                let (value, value_type) = self.expression(value)?;
+                let ty = var_type.clone().unwrap_or(value_type);
+                scalar(&ty, span)?;
+                self.declare_variable(ident, &ty, value);
+                Ok(())
+            }
+
+            ast::Statement::Assign { ident, value, span } => {
+                let (variable, _) = self.variable(ident, span)?;
+                let (value, _) = self.expression(value)?;
+                self.write_variable(variable, value);
+                Ok(())
+            }
+
+            ast::Statement::Return { value, .. } => {
+                let return_type = self.return_type.clone();
+                let value = match value {
+                    Some(value) => Some(self.expression(value)?.0),
+                    None => None,
+                };
+
+                self.terminate(Terminator::Return(value.filter(|_| return_type != Type::Null)));
+                self.start_unreachable_block();
+                Ok(())
+            }
+
+            ast::Statement::Block { block, .. } => self.block(block),
+
+            ast::Statement::If { condition, then_branch, else_branch, .. } => {
+                let (condition, _) = self.expression(condition)?;
+
+                let then_block = self.create_block();
+                let else_block = self.create_block();
+                let merge_block = self.create_block();
+
+                self.branch(condition, then_block, else_block);
+                self.seal(then_block);
+                self.seal(else_block);
+
+                self.switch_to(then_block);
+                self.statement(then_branch)?;
+                self.jump(merge_block);
+
+                self.switch_to(else_block);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch)?;
+                }
+                self.jump(merge_block);
+
+                self.switch_to(merge_block);
+                self.seal(merge_block);
+                Ok(())
+            }
+
+            ast::Statement::While { condition, body, .. } => self.looping(condition, |builder| builder.block(body)),
+
+            ast::Statement::DoUntil { condition, body, .. } => {
+                let body_block = self.create_block();
+                let exit_block = self.create_block();
+
+                self.jump(body_block);
+
+                self.switch_to(body_block);
+                self.statement(body)?;
+                let (condition, _) = self.expression(condition)?;
+                self.branch(condition, exit_block, body_block);
+                self.seal(body_block);
+                self.seal(exit_block);
+
+                self.switch_to(exit_block);
+                Ok(())
+            }
+
+            ast::Statement::For { init, condition, increment, body, .. } => {
+                // the loop variable is only visible inside the loop
+                self.variables.push_scope();
+                self.statement(init)?;
+                self.looping(condition, |builder| {
+                    builder.block(body)?;
+                    builder.statement(increment)
+                })?;
+                self.variables.pop_scope();
+                Ok(())
+            }
+
+            ast::Statement::Expression { expression, .. } => {
+                self.expression(expression)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// A loop checking `condition` before each run of `body`
+    fn looping(
+        &mut self,
+        condition: &ast::Expression,
+        body: impl FnOnce(&mut Self) -> BuildResult<()>,
+    ) -> BuildResult<()> {
+        let header_block = self.create_block();
+        let body_block = self.create_block();
+        let exit_block = self.create_block();
+
+        self.jump(header_block);
+
+        self.switch_to(header_block);
+        let (condition, _) = self.expression(condition)?;
+        self.branch(condition, body_block, exit_block);
+        self.seal(body_block);
+        self.seal(exit_block);
+
+        self.switch_to(body_block);
+        body(self)?;
+        self.jump(header_block);
+        self.seal(header_block);
+
+        self.switch_to(exit_block);
+        Ok(())
+    }
+
+    /// Value and type of an expression
+    fn expression(&mut self, expr: &ast::Expression) -> BuildResult<(Value, Type)> {
+        if let Some(value) = expr.integer_literal_value() {
+            let ty = self.expression_type(expr)?;
+            return Ok((self.ins(InstructionKind::Integer(value), &ty, expr.span()), ty));
+        }
+
+        match expr {
+            ast::Expression::FloatLiteral { value, span } => {
+                let ty = self.expression_type(expr)?;
+                Ok((self.ins(InstructionKind::Float(*value), &ty, span), ty))
+            }
+
+            ast::Expression::BooleanLiteral { value, span } => {
+                Ok((self.ins(InstructionKind::Bool(*value), &Type::Bool, span), Type::Bool))
+            }
+
+            ast::Expression::NullLiteral { span } => Ok((self.ins(InstructionKind::Null, &Type::Null, span), Type::Null)),
+
+            ast::Expression::Identifier { ident, span } => {
+                if self.variables.get(ident).is_none() && self.functions.contains_key(ident) {
+                    return Err(unsupported("functions used as values", span));
+                }
+
+                let (variable, ty) = self.variable(ident, span)?;
+                Ok((self.read_variable(variable, self.current), ty))
+            }
+
+            ast::Expression::Binary { left, operator: BinaryOperator::And, right, .. } => self.short_circuit(left, right, true),
+
+            ast::Expression::Binary { left, operator: BinaryOperator::Or, right, .. } => self.short_circuit(left, right, false),
+
+            ast::Expression::Binary { left, operator, right, span } => {
+                if matches!(operator, BinaryOperator::StarStar | BinaryOperator::SlashSlash) {
+                    return Err(unsupported(&format!("`{}`", operator), span));
+                }
+
+                let (lhs, _) = self.expression(left)?;
+                let (rhs, _) = self.expression(right)?;
+
+                let result_type = self.expression_type(expr)?;
+                let value = self.ins(InstructionKind::Binary(operator.clone(), lhs, rhs), &result_type, span);
+                Ok((value, result_type))
+            }
+
+            ast::Expression::Unary { operator, right, span } => {
+                let (value, ty) = self.expression(right)?;
+
+                let kind = match operator {
+                    ast::UnaryOperator::Minus => InstructionKind::Negate(value),
+                    ast::UnaryOperator::Bang => InstructionKind::Not(value),
+                };
+
+                Ok((self.ins(kind, &ty, span), ty))
+            }
+
+            ast::Expression::Grouping { expression, .. } => self.expression(expression),
+
+            ast::Expression::Call { callee, arguments, span } => {
+                let ident = match callee.as_ref() {
+                    ast::Expression::Identifier { ident, .. } if self.variables.get(ident).is_none() => ident,
+                    _ => return Err(unsupported("calls to closures", span)),
+                };
+
+                if Builtin::from_ident(ident).is_some() {
+                    return Err(unsupported("built-in functions", span));
+                }
+
+                let functions = self.functions;
+                let signature = functions.get(ident).ok_or_else(|| unsupported("calls to generic functions", span))?;
+
+                let mut args = Vec::new();
+                for argument in arguments {
+                    args.push(self.expression(argument)?.0);
+                }
+
+                let return_type = signature.return_type.clone();
+                let value = self.ins(InstructionKind::Call(ident.clone(), args), &return_type, span);
+                Ok((value, return_type))
+            }
+
+            ast::Expression::Postfix { left: operand, operator, span } => {
+                let delta = match operator {
+                    ast::PostfixOperator::PlusPlus => BinaryOperator::Plus,
+                    ast::PostfixOperator::MinusMinus => BinaryOperator::Minus,
+                };
+
+                let (old, _, ty) = self.increment(operand, delta, span)?;
+                Ok((old, ty))
+            }
+
+            ast::Expression::Prefix { operator, right: operand, span } => {
+                let delta = match operator {
+                    ast::PrefixOperator::PlusPlus => BinaryOperator::Plus,
+                    ast::PrefixOperator::MinusMinus => BinaryOperator::Minus,
+                };
+
+                let (_, new, ty) = self.increment(operand, delta, span)?;
+                Ok((new, ty))
+            }
+
+            ast::Expression::Assign { left, operator, right, span } => {
+                let (variable, ty) = self.assignment_target(left)?;
+
+                let operator = match operator {
+                    ast::AssignOperator::Equal => None,
+                    ast::AssignOperator::PlusEqual => Some(BinaryOperator::Plus),
+                    ast::AssignOperator::MinusEqual => Some(BinaryOperator::Minus),
+                    ast::AssignOperator::StarEqual => Some(BinaryOperator::Star),
+                    ast::AssignOperator::SlashEqual => Some(BinaryOperator::Slash),
+                };
+
+                let (new, _) = self.expression(right)?;
+                let new = match operator {
+                    None => new,
+                    Some(operator) => {
+                        let old = self.read_variable(variable, self.current);
+                        self.ins(InstructionKind::Binary(operator, old, new), &ty, span)
+                    }
+                };
+
+                self.write_variable(variable, new);
+                Ok((new, ty))
+            }
+
+            ast::Expression::Cast { expression, target_type, span } => {
+                scalar(target_type, span)?;
+                let (value, source_type) = self.expression(expression)?;
+
+                if source_type.same_as(target_type) {
+                    return Ok((value, target_type.clone()));
+                }
+
+                Ok((self.ins(InstructionKind::Cast(value), target_type, span), target_type.clone()))
+            }
+
+            ast::Expression::StringLiteral { span, .. } => Err(unsupported("strings", span)),
+            ast::Expression::Function { span, .. } => Err(unsupported("anonymous functions", span)),
+            ast::Expression::Index { span, .. }
+            | ast::Expression::Slice { span, .. }
+            | ast::Expression::ArrayLiteral { span, .. } => Err(unsupported("arrays and strings", span)),
+            ast::Expression::StructLiteral { span, .. } | ast::Expression::Field { span, .. } => {
+                Err(unsupported("structs", span))
+            }
+            ast::Expression::Variant { span, .. } | ast::Expression::Match { span, .. } => {
+                Err(unsupported("enums and `match`", span))
+            }
+
+            ast::Expression::IntegerLiteral { .. } => unreachable!(),
+        }
+    }
+
+    /// `and` and `or`, which only evaluate their right operand if the left
+    /// doesn't decide the result
+    fn short_circuit(&mut self, left: &ast::Expression, right: &ast::Expression, is_and: bool) -> BuildResult<(Value, Type)> {
+        let (lhs, _) = self.expression(left)?;
+
+        let right_block = self.create_block();
+        let merge_block = self.create_block();
+        let result = self.append_param(merge_block, &Type::Bool);
+
+        let (then, otherwise) = if is_and {
+            (Edge { block: right_block, args: Vec::new() }, Edge { block: merge_block, args: vec![lhs] })
+        } else {
+            (Edge { block: merge_block, args: vec![lhs] }, Edge { block: right_block, args: Vec::new() })
+        };
+        self.terminate(Terminator::Branch { condition: lhs, then, otherwise });
+        self.seal(right_block);
+
+        self.switch_to(right_block);
+        let (rhs, _) = self.expression(right)?;
+        self.terminate(Terminator::Jump(Edge { block: merge_block, args: vec![rhs] }));
+
+        self.switch_to(merge_block);
+        self.seal(merge_block);
+        Ok((result, Type::Bool))
+    }
+
+    /// `x++`, `++x`, `x--` and `--x`
+    /// Returns the old value, the new value and the type of the variable
+    fn increment(&mut self, operand: &ast::Expression, operator: BinaryOperator, span: &Span) -> BuildResult<(Value, Value, Type)> {
+        let (variable, ty) = self.assignment_target(operand)?;
+
+        let old = self.read_variable(variable, self.current);
+        let one = self.ins(InstructionKind::Integer(1), &ty, span);
+        let new = self.ins(InstructionKind::Binary(operator, old, one), &ty, span);
+        self.write_variable(variable, new);
+
+        Ok((old, new, ty))
+    }
+
+    /// Variable assigned to by `x = ...`, `x++` and the like
+    fn assignment_target(&self, target: &ast::Expression) -> BuildResult<(Variable, Type)> {
+        match target {
+            ast::Expression::Identifier { ident, span } => self.variable(ident, span),
+            _ => Err(unsupported("assignments to elements and fields", target.span())),
+        }
+    }
+
+    /// The finished function, without the blocks that never run and the
+    /// parameters that always get the same value
+    fn finish(mut self, function: &ast::Function) -> Function {
+        self.thread_jumps();
+        let order = self.reverse_postorder();
+        self.remove_trivial_params(&order);
+
+        // blocks and values are renumbered in order
+        let block_numbers: HashMap<Block, Block> = order.iter().enumerate().map(|(i, block)| (*block, Block(i))).collect();
+        let mut value_numbers: HashMap<Value, Value> = HashMap::new();
+        let mut value_types = Vec::new();
+        let mut number = |value: Value, value_types: &mut Vec<Type>| {
+            value_numbers.insert(value, Value(value_types.len()));
+            value_types.push(self.value_types[value.0].clone());
+        };
+
+        for block in &order {
+            let data = &self.blocks[block.0];
+            for param in &data.params {
+                number(*param, &mut value_types);
+            }
+            for instruction in &data.instructions {
+                number(instruction.result, &mut value_types);
+            }
+        }
+
+        let value = |value: &Value| value_numbers[value];
+        let edge = |edge: &Edge| Edge { block: block_numbers[&edge.block], args: edge.args.iter().map(value).collect() };
+
+        let blocks = order.iter().map(|block| {
+            let data = &self.blocks[block.0];
+
+            BlockData {
+                params: data.params.iter().map(value).collect(),
+                instructions: data.instructions.iter().map(|instruction| Instruction {
+                    result: value(&instruction.result),
+                    kind: match &instruction.kind {
+                        InstructionKind::Binary(operator, lhs, rhs) => {
+                            InstructionKind::Binary(operator.clone(), value(lhs), value(rhs))
+                        }
+                        InstructionKind::Negate(operand) => InstructionKind::Negate(value(operand)),
+                        InstructionKind::Not(operand) => InstructionKind::Not(value(operand)),
+                        InstructionKind::Cast(operand) => InstructionKind::Cast(value(operand)),
+                        InstructionKind::Call(ident, args) => InstructionKind::Call(ident.clone(), args.iter().map(value).collect()),
+                        constant => constant.clone(),
+                    },
+                    span: instruction.span.clone(),
+                }).collect(),
+                terminator: match data.terminator.as_ref().expect("blocks are terminated") {
+                    Terminator::Jump(target) => Terminator::Jump(edge(target)),
+                    Terminator::Branch { condition, then, otherwise } => Terminator::Branch {
+                        condition: value(condition),
+                        then: edge(then),
+                        otherwise: edge(otherwise),
+                    },
+                    Terminator::Return(returned) => Terminator::Return(returned.as_ref().map(value)),
+                    Terminator::Unreachable => Terminator::Unreachable,
+                },
+            }
+        }).collect();
+
+        Function {
+            ident: function.ident.clone(),
+            params: function.params.params.iter().map(|param| param.param_type.clone()).collect(),
+            return_type: function.return_type.clone(),
+            blocks,
+            value_types,
+        }
+    }
+
+    /// Jump straight past blocks that do nothing but jump
+    fn thread_jumps(&mut self) {
+        for i in 0..self.blocks.len() {
+            for j in 0..2 {
+                let Some(terminator) = &self.blocks[i].terminator else { continue };
+                let Some(edge) = terminator.edges().get(j).map(|edge| (*edge).clone()) else { continue };
+                let target = self.thread(edge);
+
+                // a branch to the same block either way would need the
+                // arguments to match
+                let others = self.blocks[i].terminator.as_ref().unwrap().edges();
+                if others.iter().enumerate().any(|(k, other)| k != j && other.block == target.block) {
+                    continue;
+                }
+
+                *self.blocks[i].terminator.as_mut().unwrap().edges_mut()[j] = target;
+            }
+        }
+    }
+
+    /// Where an edge ends up, going through blocks that only jump
+    fn thread(&self, mut edge: Edge) -> Edge {
+        // a loop of empty blocks never gets anywhere
+        for _ in 0..self.blocks.len() {
+            let data = &self.blocks[edge.block.0];
+            match &data.terminator {
+                // the entry block can't be skipped, as the function starts there
+                Some(Terminator::Jump(next)) if data.params.is_empty() && data.instructions.is_empty() && edge.block.0 != 0 => {
+                    edge = next.clone();
+                }
+                _ => break,
+            }
+        }
+
+        edge
+    }
+
+    /// The blocks that can run, each after those that dominate it
+    fn reverse_postorder(&self) -> Vec<Block> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut postorder = Vec::new();
+        // blocks with the index of the next successor to visit
+        let mut stack = vec![(Block(0), 0)];
+        visited[0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            // visited last to first, so the first comes first in the order
+            let successors: Vec<Block> = self.blocks[block.0].terminator.as_ref()
+                .map(|terminator| terminator.edges().iter().rev().map(|edge| edge.block).collect())
+                .unwrap_or_default();
+
+            match successors.get(next) {
+                Some(successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((*successor, 0));
+                    }
+                }
+                None => postorder.push(block),
+            }
+        }
+
+        postorder.reverse();
+        postorder
+    }
+
+    /// Replace parameters that get the same value from every predecessor,
+    /// or only themselves, with that value
+    fn remove_trivial_params(&mut self, order: &[Block]) {
+        let mut replacements: HashMap<Value, Value> = HashMap::new();
+        let resolve = |replacements: &HashMap<Value, Value>, mut value: Value| {
+            while let Some(replacement) = replacements.get(&value) {
+                value = *replacement;
+            }
+            value
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for block in order.iter().skip(1) {
+                for i in (0..self.blocks[block.0].params.len()).rev() {
+                    let param = self.blocks[block.0].params[i];
+
+                    let mut incoming: Vec<Value> = Vec::new();
+                    for predecessor in order {
+                        let Some(terminator) = &self.blocks[predecessor.0].terminator else { continue };
+                        for edge in terminator.edges().into_iter().filter(|edge| edge.block == *block) {
+                            let value = resolve(&replacements, edge.args[i]);
+                            if value != param && !incoming.contains(&value) {
+                                incoming.push(value);
+                            }
+                        }
+                    }
+
+                    let [value] = incoming[..] else { continue };
+                    replacements.insert(param, value);
+                    self.blocks[block.0].params.remove(i);
+
+                    for predecessor in order {
+                        if let Some(terminator) = &mut self.blocks[predecessor.0].terminator {
+                            for edge in terminator.edges_mut().into_iter().filter(|edge| edge.block == *block) {
+                                edge.args.remove(i);
+                            }
+                        }
+                    }
+
+                    changed = true;
+                }
+            }
+        }
+
+        // uses of the removed parameters get the values they were replaced by
+        for block in order {
+            let data = &mut self.blocks[block.0];
+
+            for instruction in &mut data.instructions {
+                match &mut instruction.kind {
+                    InstructionKind::Binary(_, lhs, rhs) => {
+                        *lhs = resolve(&replacements, *lhs);
+                        *rhs = resolve(&replacements, *rhs);
+                    }
+                    InstructionKind::Negate(operand) | InstructionKind::Not(operand) | InstructionKind::Cast(operand) => {
+                        *operand = resolve(&replacements, *operand);
+                    }
+                    InstructionKind::Call(_, args) => {
+                        args.iter_mut().for_each(|arg| *arg = resolve(&replacements, *arg));
+                    }
+                    _ => {}
+                }
+            }
+
+            if let Some(terminator) = &mut data.terminator {
+                match terminator {
+                    Terminator::Branch { condition, .. } => *condition = resolve(&replacements, *condition),
+                    Terminator::Return(Some(value)) => *value = resolve(&replacements, *value),
+                    _ => {}
+                }
+
+                for edge in terminator.edges_mut() {
+                    edge.args.iter_mut().for_each(|arg| *arg = resolve(&replacements, *arg));
+                }
+            }
+        }
+    }
+}
+
+fn unsupported(what: &str, span: &Span) -> Unsupported {
+    Unsupported { what: what.to_string(), span: span.clone() }
+}
+
+/// Only integers, floats, bools and `null` can be values of the IR so far
+fn scalar(ty: &Type, span: &Span) -> BuildResult<()> {
+    if ty.is_numeric() || matches!(ty, Type::Bool | Type::Null) {
+        Ok(())
+    } else {
+        Err(unsupported(&format!("values of type {}", ty), span))
+    }
+}
@@ -0,0 +1,388 @@
+//! Kennedy IR: functions as a control flow graph of typed SSA values
+//!
+//! The IR sits between the syntax tree and Cranelift. Variables are gone,
+//! replaced by values each defined once, and loops and branches by blocks
+//! that end in a jump, branch or return. Where control flow joins, values
+//! are passed to the block as parameters rather than merged by phi nodes,
+//! as in Cranelift.
+//!
+//! Values keep their Kennedy type, and arithmetic keeps the span of the
+//! expression it came from, so lowering it traps on overflow or division by
+//! zero exactly as the syntax tree would, pointing at the same place.
+//!
+//! Only functions of integers, floats and bools can be built so far;
+//! [`build`] says what it couldn't represent in the others, which the
+//! compiler translates straight from the syntax tree instead. What it's to
+//! cover next is planned in `docs/ir.md`. A function displays as text:
+//!
+//! ```text
+//! func max(int, int): int
+//! block0(v0: int, v1: int):
+//!     v2: bool = gt v0, v1
+//!     br v2, block1, block2
+//! block1:
+//!     return v0
+//! block2:
+//!     return v1
+//! ```
+
+mod build;
+
+use std::fmt;
+
+use crate::ast::{BinaryOperator, Type};
+use crate::error::Span;
+
+pub use build::{build, Unsupported};
+
+/// A value, defined once by an instruction or as a block parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub(crate) usize);
+
+/// A block of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Block(pub(crate) usize);
+
+impl Value {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl Block {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub ident: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+    /// The entry block first, which takes the parameters; every block
+    /// comes after those that dominate it
+    pub blocks: Vec<BlockData>,
+    /// Type of every value, by index
+    value_types: Vec<Type>,
+}
+
+impl Function {
+    pub fn entry(&self) -> Block {
+        Block(0)
+    }
+
+    pub fn block(&self, block: Block) -> &BlockData {
+        &self.blocks[block.0]
+    }
+
+    pub fn value_type(&self, value: Value) -> &Type {
+        &self.value_types[value.0]
+    }
+
+    /// Number of values in the function, which are numbered from zero
+    pub fn value_count(&self) -> usize {
+        self.value_types.len()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockData {
+    pub params: Vec<Value>,
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    /// Calls to functions returning nothing define a `null` value
+    pub result: Value,
+    pub kind: InstructionKind,
+    /// Expression the instruction was built from
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstructionKind {
+    /// An integer of the result's type
+    Integer(i128),
+    /// Of type `float` or `f64`, rounded to its width when it's lowered
+    Float(f64),
+    Bool(bool),
+    Null,
+    /// Arithmetic or a comparison of two values of the same type; never
+    /// `and` or `or`, which are branches
+    Binary(BinaryOperator, Value, Value),
+    Negate(Value),
+    Not(Value),
+    /// Conversion to the result's type
+    Cast(Value),
+    /// Call to a named function
+    Call(String, Vec<Value>),
+}
+
+impl InstructionKind {
+    /// Values the instruction uses
+    pub fn arguments(&self) -> Vec<Value> {
+        match self {
+            InstructionKind::Integer(_) | InstructionKind::Float(_) | InstructionKind::Bool(_) | InstructionKind::Null => {
+                Vec::new()
+            }
+            InstructionKind::Binary(_, lhs, rhs) => vec![*lhs, *rhs],
+            InstructionKind::Negate(value) | InstructionKind::Not(value) | InstructionKind::Cast(value) => vec![*value],
+            InstructionKind::Call(_, args) => args.clone(),
+        }
+    }
+}
+
+/// How a block ends
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Edge),
+    /// To `then` if the condition is true, `otherwise` if not
+    Branch { condition: Value, then: Edge, otherwise: Edge },
+    /// With nothing for functions returning `null`
+    Return(Option<Value>),
+    /// The end of a function that must return a value, but didn't
+    Unreachable,
+}
+
+impl Terminator {
+    /// Blocks it may continue in, with the values it passes them
+    pub fn edges(&self) -> Vec<&Edge> {
+        match self {
+            Terminator::Jump(edge) => vec![edge],
+            Terminator::Branch { then, otherwise, .. } => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    fn edges_mut(&mut self) -> Vec<&mut Edge> {
+        match self {
+            Terminator::Jump(edge) => vec![edge],
+            Terminator::Branch { then, otherwise, .. } => vec![then, otherwise],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+/// A jump to a block, passing values for its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub block: Block,
+    pub args: Vec<Value>,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+impl fmt::Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block{}", self.0)
+    }
+}
+
+impl fmt::Display for Edge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.block)?;
+        if !self.args.is_empty() {
+            write!(f, "({})", list(&self.args))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "func {}({}): {}", self.ident, list(&self.params), self.return_type)?;
+
+        for (i, block) in self.blocks.iter().enumerate() {
+            write!(f, "{}", Block(i))?;
+            if !block.params.is_empty() {
+                let params: Vec<String> = block.params.iter()
+                    .map(|param| format!("{}: {}", param, self.value_type(*param)))
+                    .collect();
+                write!(f, "({})", params.join(", "))?;
+            }
+            writeln!(f, ":")?;
+
+            for instruction in &block.instructions {
+                let result = instruction.result;
+                writeln!(f, "    {}: {} = {}", result, self.value_type(result), instruction.kind)?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for InstructionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstructionKind::Integer(value) => write!(f, "const {}", value),
+            InstructionKind::Float(value) => write!(f, "const {:?}", value),
+            InstructionKind::Bool(value) => write!(f, "const {}", value),
+            InstructionKind::Null => write!(f, "const null"),
+            InstructionKind::Binary(operator, lhs, rhs) => write!(f, "{} {}, {}", operator_name(operator), lhs, rhs),
+            InstructionKind::Negate(value) => write!(f, "neg {}", value),
+            InstructionKind::Not(value) => write!(f, "not {}", value),
+            InstructionKind::Cast(value) => write!(f, "cast {}", value),
+            InstructionKind::Call(ident, args) => write!(f, "call {}({})", ident, list(args)),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Terminator::Jump(edge) => write!(f, "jump {}", edge),
+            Terminator::Branch { condition, then, otherwise } => write!(f, "br {}, {}, {}", condition, then, otherwise),
+            Terminator::Return(Some(value)) => write!(f, "return {}", value),
+            Terminator::Return(None) => write!(f, "return"),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+fn operator_name(operator: &BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Plus => "add",
+        BinaryOperator::Minus => "sub",
+        BinaryOperator::Star => "mul",
+        BinaryOperator::Slash => "div",
+        BinaryOperator::StarStar => "pow",
+        BinaryOperator::SlashSlash => "idiv",
+        BinaryOperator::EqualEqual => "eq",
+        BinaryOperator::BangEqual => "ne",
+        BinaryOperator::Greater => "gt",
+        BinaryOperator::GreaterEqual => "ge",
+        BinaryOperator::Less => "lt",
+        BinaryOperator::LessEqual => "le",
+        BinaryOperator::Or => "or",
+        BinaryOperator::And => "and",
+    }
+}
+
+fn list<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(T::to_string).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+    use crate::type_checking::{FunctionSignature, TypeChecker};
+
+    /// IR of every function in `source`
+    fn build_all(source: &str) -> Vec<Result<Function, Unsupported>> {
+        let program = Parser::new(lex(source.to_string()).unwrap()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+
+        let functions: HashMap<String, FunctionSignature> = program.functions.iter()
+            .filter(|function| !function.is_generic())
+            .map(|function| (function.ident.clone(), FunctionSignature::of(function)))
+            .collect();
+
+        program.functions.iter().map(|function| build(function, &checker, &functions)).collect()
+    }
+
+    #[test]
+    fn test_build() {
+        let functions = build_all("\
+func sum(n: u8): u8 {
+    let total: u8 = 0;
+    for (let i: u8 = 1; i <= n; i++) { total += i; }
+    return total;
+}
+func first_three(x: int): bool {
+    let y = x;
+    do { y = y - 1; } until (y < 0 or y == 3 and x > 1)
+    return y == 3;
+}
+func sign(x: float): int {
+    if (x < 0.0) { return -1; } else if (x > 0.0) { return 1; }
+    return 0;
+}");
+        let dumps: Vec<String> = functions.into_iter().map(|function| function.unwrap().to_string()).collect();
+
+        assert_eq!(dumps[0], "\
+func sum(u8): u8
+block0(v0: u8):
+    v1: u8 = const 0
+    v2: u8 = const 1
+    jump block1(v2, v1)
+block1(v3: u8, v4: u8):
+    v5: bool = le v3, v0
+    br v5, block2, block3
+block2:
+    v6: u8 = add v4, v3
+    v7: u8 = const 1
+    v8: u8 = add v3, v7
+    jump block1(v8, v6)
+block3:
+    return v4
+");
+
+        // `and` and `or` branch, passing their result to where they join
+        assert_eq!(dumps[1], "\
+func first_three(int): bool
+block0(v0: int):
+    jump block1(v0)
+block1(v1: int):
+    v2: int = const 1
+    v3: int = sub v1, v2
+    v4: int = const 0
+    v5: bool = lt v3, v4
+    br v5, block5(v5), block2
+block2:
+    v6: int = const 3
+    v7: bool = eq v3, v6
+    br v7, block3, block4(v7)
+block3:
+    v8: int = const 1
+    v9: bool = gt v0, v8
+    jump block4(v9)
+block4(v10: bool):
+    jump block5(v10)
+block5(v11: bool):
+    br v11, block6, block1(v3)
+block6:
+    v12: int = const 3
+    v13: bool = eq v3, v12
+    return v13
+");
+        assert!(dumps[2].contains("block1:\n    v3: int = const -1\n    return v3\n"), "{}", dumps[2]);
+
+        // literals have the types the checker gave them
+        let function = build_all("func small(x: u8): bool { return 200 < x and x < 2.5 as u8; }").remove(0).unwrap();
+        assert!(function.to_string().contains("v1: u8 = const 200\n"), "{}", function);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let functions = build_all("\
+let count = 0;
+func greet(name: string): null {}
+func id<T>(x: T): T { return x; }
+func increment(): int { count++; return count; }
+func length(): int { return len([1, 2]); }
+func call(): int { return id(1); }");
+
+        let errors: Vec<String> = functions.into_iter().map(|function| function.unwrap_err().to_string()).collect();
+        assert_eq!(errors, [
+            "the IR can't represent values of type string yet",
+            "the IR can't represent generic functions yet",
+            "the IR can't represent globals yet",
+            "the IR can't represent built-in functions yet",
+            "the IR can't represent calls to generic functions yet",
+        ]);
+    }
+}
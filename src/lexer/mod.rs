@@ -6,15 +6,29 @@ pub use crate::error::{
     Span
 };
 pub use tokens::{
+    Comment,
     Token,
     TokenType
 };
 
+/// Words read as keywords (or type names) rather than identifiers
+pub const KEYWORDS: &[&str] = &[
+    "if", "else", "return", "let", "while", "for", "do", "until", "func", "struct", "enum", "match", "import",
+    "pub", "const", "extern", "true", "false", "and", "or", "not", "as", "int", "float", "string", "bool", "null",
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f64",
+];
+
 #[allow(dead_code)]
 /// Lex a source string into a list of tokens
 pub fn lex(source: String) -> CompileResult<Vec<Token>> {
+    lex_with_comments(source).map(|(tokens, _)| tokens)
+}
+
+/// Lex a source string into a list of tokens, keeping its comments too
+pub fn lex_with_comments(source: String) -> CompileResult<(Vec<Token>, Vec<Comment>)> {
     let mut chars = source.chars().peekable();
     let mut tokens = Vec::new();
+    let mut comments = Vec::new();
     #[allow(dead_code, unused_assignments)]
     let mut start_char = 0;
     let mut current_char = 0;
@@ -33,8 +47,6 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
         start_char = current_char;
         current_char += 1;
 
-        println!("{}: {:?}\t{:?}", current_char, c, &tokens);
-
         match c {
             // whitespace
             ' ' | '\r' | '\t' | '\n' => {},
@@ -43,11 +55,24 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
             ')' => add_token(TokenType::RightParen, &mut tokens, start_char, current_char),
             '{' => add_token(TokenType::LeftBrace, &mut tokens, start_char, current_char),
             '}' => add_token(TokenType::RightBrace, &mut tokens, start_char, current_char),
+            '[' => add_token(TokenType::LeftBracket, &mut tokens, start_char, current_char),
+            ']' => add_token(TokenType::RightBracket, &mut tokens, start_char, current_char),
             ',' => add_token(TokenType::Comma, &mut tokens, start_char, current_char),
             '.' => add_token(TokenType::Dot, &mut tokens, start_char, current_char),
             ';' => add_token(TokenType::Semicolon, &mut tokens, start_char, current_char),
-            ':' => add_token(TokenType::Colon, &mut tokens, start_char, current_char),
+            '@' => add_token(TokenType::At, &mut tokens, start_char, current_char),
             // One or two character tokens
+            ':' => {
+                // check for :: next char
+                if let Some(':') = chars.peek() {
+                    // consume the next char
+                    chars.next();
+                    current_char += 1;
+                    add_token(TokenType::ColonColon, &mut tokens, start_char, current_char);
+                } else {
+                    add_token(TokenType::Colon, &mut tokens, start_char, current_char);
+                }
+            }
             '!' => {
                 // check for != next char
                 if let Some('=') = chars.peek() {
@@ -60,12 +85,17 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
                 }
             },
             '=' => {
-                // check for == next char
+                // check for == or => next char
                 if let Some('=') = chars.peek() {
                     // consume the next char
                     chars.next();
                     current_char += 1;
                     add_token(TokenType::EqualEqual, &mut tokens, start_char, current_char);
+                } else if let Some('>') = chars.peek() {
+                    // consume the next char
+                    chars.next();
+                    current_char += 1;
+                    add_token(TokenType::FatArrow, &mut tokens, start_char, current_char);
                 } else {
                     add_token(TokenType::Equal, &mut tokens, start_char, current_char);
                 }
@@ -96,32 +126,30 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
             '/' => {
                 // check for // or /* next char
                 if let Some('/') = chars.peek() {
-                    // consume the next char
-                    chars.next();
-                    current_char += 1;
-                    // consume the rest of the line
-                    while let Some(c) = chars.next() {
+                    let mut text = String::from("/");
+                    // consume the rest of the line, leaving the newline
+                    while let Some(c) = chars.next_if(|c| *c != '\n') {
                         current_char += 1;
-                        if c == '\n' {
-                            break;
-                        }
+                        text.push(c);
                     }
+                    comments.push(Comment { text, span: Span { start: start_char, end: current_char } });
                 } else if let Some('*') = chars.peek() {
-                    // consume the next char
-                    chars.next();
-                    current_char += 1;
+                    let mut text = String::from("/");
                     // consume the rest of the comment
                     while let Some(c) = chars.next() {
                         current_char += 1;
-                        if c == '*' {
+                        text.push(c);
+                        if c == '*' && text.len() > 2 {
                             if let Some('/') = chars.peek() {
                                 // consume the next char
                                 chars.next();
                                 current_char += 1;
+                                text.push('/');
                                 break;
                             }
                         }
                     }
+                    comments.push(Comment { text, span: Span { start: start_char, end: current_char } });
                 } else {
                     add_token(TokenType::Slash, &mut tokens, start_char, current_char);
                 }
@@ -185,7 +213,7 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
             // string literal?
             '"' => {
                 let mut string = String::new();
-                while let Some(c) = chars.next() {
+                for c in chars.by_ref() {
                     current_char += 1;
                     if c == '"' {
                         break;
@@ -223,7 +251,15 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
                     }
                     add_token(TokenType::FloatLiteral(number.parse::<f64>().unwrap()), &mut tokens, start_char, current_char);
                 } else {
-                    add_token(TokenType::IntegerLiteral(number.parse::<i64>().unwrap()), &mut tokens, start_char, current_char);
+                    // as large as a `u64`, checked against the literal's type later
+                    let value = number.parse::<u64>().map_err(|_| CompileError::SyntaxError(
+                        format!("Integer literal {} is too large", number),
+                        Span {
+                            start: start_char,
+                            end: current_char,
+                        }
+                    ))?;
+                    add_token(TokenType::IntegerLiteral(value as i128), &mut tokens, start_char, current_char);
                 }
             },
 
@@ -248,12 +284,28 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
                     "while" => add_token(TokenType::While, &mut tokens, start_char, current_char),
                     "for" => add_token(TokenType::For, &mut tokens, start_char, current_char),
                     "func" => add_token(TokenType::Function, &mut tokens, start_char, current_char),
+                    "struct" => add_token(TokenType::Struct, &mut tokens, start_char, current_char),
+                    "enum" => add_token(TokenType::Enum, &mut tokens, start_char, current_char),
+                    "match" => add_token(TokenType::Match, &mut tokens, start_char, current_char),
+                    "import" => add_token(TokenType::Import, &mut tokens, start_char, current_char),
+                    "pub" => add_token(TokenType::Pub, &mut tokens, start_char, current_char),
+                    "const" => add_token(TokenType::Const, &mut tokens, start_char, current_char),
+                    "extern" => add_token(TokenType::Extern, &mut tokens, start_char, current_char),
                     // types
                     "int" => add_token(TokenType::Int, &mut tokens, start_char, current_char),
                     "float" => add_token(TokenType::Float, &mut tokens, start_char, current_char),
                     "string" => add_token(TokenType::String, &mut tokens, start_char, current_char),
                     "bool" => add_token(TokenType::Bool, &mut tokens, start_char, current_char),
                     "null" => add_token(TokenType::Null, &mut tokens, start_char, current_char),
+                    "i8" => add_token(TokenType::I8, &mut tokens, start_char, current_char),
+                    "i16" => add_token(TokenType::I16, &mut tokens, start_char, current_char),
+                    "i32" => add_token(TokenType::I32, &mut tokens, start_char, current_char),
+                    "i64" => add_token(TokenType::I64, &mut tokens, start_char, current_char),
+                    "u8" => add_token(TokenType::U8, &mut tokens, start_char, current_char),
+                    "u16" => add_token(TokenType::U16, &mut tokens, start_char, current_char),
+                    "u32" => add_token(TokenType::U32, &mut tokens, start_char, current_char),
+                    "u64" => add_token(TokenType::U64, &mut tokens, start_char, current_char),
+                    "f64" => add_token(TokenType::F64, &mut tokens, start_char, current_char),
                     // "break" => add_token(TokenType::Break, &mut tokens, start_char, current_char),
                     // "continue" => add_token(TokenType::Continue, &mut tokens, start_char, current_char),
                     "do" => add_token(TokenType::Do, &mut tokens, start_char, current_char),
@@ -263,6 +315,7 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
                     "or" => add_token(TokenType::Or, &mut tokens, start_char, current_char),
                     "and" => add_token(TokenType::And, &mut tokens, start_char, current_char),
                     "not" => add_token(TokenType::Not, &mut tokens, start_char, current_char),
+                    "as" => add_token(TokenType::As, &mut tokens, start_char, current_char),
                     _ => add_token(TokenType::Ident(identifier), &mut tokens, start_char, current_char),
                 }
             },
@@ -287,7 +340,7 @@ pub fn lex(source: String) -> CompileResult<Vec<Token>> {
         },
     });
 
-    Ok(tokens)
+    Ok((tokens, comments))
 }
 
 #[cfg(test)]
@@ -301,4 +354,16 @@ mod tests {
         println!("{:#?}", tokens);
 
     }
+
+    #[test]
+    fn test_comments() {
+        let source = "a; // one\n/* two\n*/ b; /**/";
+        let (tokens, comments) = lex_with_comments(source.to_string()).unwrap();
+
+        assert_eq!(tokens.len(), 5);
+        let texts: Vec<&str> = comments.iter().map(|comment| comment.text.as_str()).collect();
+        assert_eq!(texts, ["// one", "/* two\n*/", "/**/"]);
+        assert_eq!(comments[0].span, Span { start: 3, end: 9 });
+        assert!(comments[0].is_line() && !comments[1].is_line());
+    }
 }
\ No newline at end of file
@@ -7,20 +7,22 @@ use std::fmt;
 pub enum TokenType {
     // Single-character tokens
     LeftParen, RightParen, LeftBrace, RightBrace,       // ( ) { }
+    LeftBracket, RightBracket,                          // [ ]
     Comma, Dot, Minus, Plus, Semicolon, Slash, Star,    // , . - + ; / *
-    Colon,                                              // :
+    Colon, At,                                          // : @
     // One or two character tokens
     Bang, BangEqual,                                  // ! !=
-    Equal, EqualEqual,                                // = ==
+    Equal, EqualEqual, FatArrow,                      // = == =>
     Greater, GreaterEqual,                            // > >=
     Less, LessEqual,                                  // < <=
     PlusPlus, MinusMinus,                             // ++ --
     StarStar, SlashSlash,                             // ** //
     PlusEqual, MinusEqual,                            // += -=
     StarEqual, SlashEqual,                            // *= /=
+    ColonColon,                                       // ::
     // Literals
     StringLiteral(String),                            // "..."
-    IntegerLiteral(i64),                              // 123
+    IntegerLiteral(i128),                              // 123
     FloatLiteral(f64),                                // 123.456
     Ident(String),                               // ...
     // Keywords
@@ -28,8 +30,14 @@ pub enum TokenType {
     True, False,                                      // true false
     For, Do, Until,                                   // for do until
     Or, And, Not,                                     // or and not
+    As,                                               // as
+    Struct, Enum, Match,                              // struct enum match
+    Import, Pub, Const, Extern,                       // import pub const extern
     // Types
     Int, Float, Bool, String, Null,                   // int float bool string null
+    I8, I16, I32, I64,                                // i8 i16 i32 i64
+    U8, U16, U32, U64,                                // u8 u16 u32 u64
+    F64,                                              // f64
     // End of file
     Eof,
 }
@@ -47,6 +55,22 @@ impl Token {
     }
 }
 
+/// A `// line` or `/* block */` comment, which the parser never sees but
+/// the formatter keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// The whole comment, including `//` or `/*` and `*/`
+    pub text: String,
+    pub span: Span,
+}
+
+impl Comment {
+    /// Whether it's a `//` comment, which runs to the end of its line
+    pub fn is_line(&self) -> bool {
+        self.text.starts_with("//")
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?} {:?}", self.token_type, self.span)
@@ -60,6 +84,8 @@ impl fmt::Display for TokenType {
             TokenType::RightParen => write!(f, ")"),
             TokenType::LeftBrace => write!(f, "{{"),
             TokenType::RightBrace => write!(f, "}}"),
+            TokenType::LeftBracket => write!(f, "["),
+            TokenType::RightBracket => write!(f, "]"),
             TokenType::Comma => write!(f, ","),
             TokenType::Dot => write!(f, "."),
             TokenType::Minus => write!(f, "-"),
@@ -68,6 +94,9 @@ impl fmt::Display for TokenType {
             TokenType::Slash => write!(f, "/"),
             TokenType::Star => write!(f, "*"),
             TokenType::Colon => write!(f, ":"),
+            TokenType::At => write!(f, "@"),
+            TokenType::ColonColon => write!(f, "::"),
+            TokenType::FatArrow => write!(f, "=>"),
             TokenType::Bang => write!(f, "!"),
             TokenType::BangEqual => write!(f, "!="),
             TokenType::Equal => write!(f, "="),
@@ -102,11 +131,28 @@ impl fmt::Display for TokenType {
             TokenType::Or => write!(f, "or"),
             TokenType::And => write!(f, "and"),
             TokenType::Not => write!(f, "not"),
+            TokenType::As => write!(f, "as"),
+            TokenType::Struct => write!(f, "struct"),
+            TokenType::Enum => write!(f, "enum"),
+            TokenType::Match => write!(f, "match"),
+            TokenType::Import => write!(f, "import"),
+            TokenType::Pub => write!(f, "pub"),
+            TokenType::Const => write!(f, "const"),
+            TokenType::Extern => write!(f, "extern"),
             TokenType::Int => write!(f, "int"),
             TokenType::Float => write!(f, "float"),
             TokenType::Bool => write!(f, "bool"),
             TokenType::String => write!(f, "string"),
             TokenType::Null => write!(f, "null"),
+            TokenType::I8 => write!(f, "i8"),
+            TokenType::I16 => write!(f, "i16"),
+            TokenType::I32 => write!(f, "i32"),
+            TokenType::I64 => write!(f, "i64"),
+            TokenType::U8 => write!(f, "u8"),
+            TokenType::U16 => write!(f, "u16"),
+            TokenType::U32 => write!(f, "u32"),
+            TokenType::U64 => write!(f, "u64"),
+            TokenType::F64 => write!(f, "f64"),
             TokenType::Eof => write!(f, "EOF"),
         }
     }
@@ -4,6 +4,7 @@ extern crate cranelift;
 extern crate cranelift_codegen;
 extern crate cranelift_jit;
 extern crate cranelift_native;
+extern crate cranelift_object;
 extern crate target_lexicon;
 extern crate memmap2;
 
@@ -13,6 +14,21 @@ pub mod ast;
 pub mod precedence;
 pub mod compiler;
 mod error;
+mod builtins;
+mod type_checking;
+mod generics;
+mod modules;
+mod constant;
+mod printer;
+pub mod formatter;
+pub mod interpreter;
+pub mod optimizer;
+pub mod ir;
+pub mod checks;
+pub mod lint;
+pub mod lsp;
+pub mod analysis;
+pub mod repl;
 
-pub use error::{CompileError, CompileResult};
+pub use error::{CompileError, CompileResult, Span};
 
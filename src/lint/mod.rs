@@ -0,0 +1,251 @@
+//! Lints: code that is valid, but often a mistake or hard to read
+//!
+//! Each [`Lint`] has an ID, like `float_equality`, and a level, a
+//! [`Severity`] saying whether what it finds is ignored, a warning or an
+//! error. [`Lints`] holds the level of every lint for a compile, which
+//! `kennedy build` sets with `-A`, `-W` and `-D`. A function can change
+//! them for its own body (and its name) with attributes:
+//!
+//! ```text
+//! @allow(float_equality, naming)
+//! func isZero(x: float): bool { return x == 0.0; }
+//! ```
+//!
+//! The [checks](crate::checks) for unused names and unreachable code can be
+//! allowed, warned about or denied the same way, by their own IDs.
+//!
+//! Lints run on the program as written, once it type checks. Floats are
+//! only told apart in functions that aren't generic, as those are the only
+//! ones the checker has types for.
+
+mod rules;
+
+use crate::ast::{Function, Program};
+use crate::checks::Checks;
+use crate::error::{CompileError, Span};
+use crate::type_checking::TypeChecker;
+
+pub use crate::checks::Severity;
+
+/// Something a lint looks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// `==` or `!=` between floats, which rounding makes unreliable
+    FloatEquality,
+    /// `{}` as a statement, branch or loop body
+    EmptyBlock,
+    /// A variable or parameter of an anonymous function with the name of a
+    /// parameter it hides
+    ShadowedParameter,
+    /// A loop whose condition is always true, with no `return` in it
+    InfiniteLoop,
+    /// `x = x`
+    SelfAssignment,
+    /// Names that don't follow the convention for what they name:
+    /// `snake_case` functions, variables and fields, `UpperCamelCase`
+    /// types, variants and type parameters, `SCREAMING_SNAKE_CASE`
+    /// constants
+    Naming,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 6] = [
+        Lint::FloatEquality,
+        Lint::EmptyBlock,
+        Lint::ShadowedParameter,
+        Lint::InfiniteLoop,
+        Lint::SelfAssignment,
+        Lint::Naming,
+    ];
+
+    /// How the lint is named on the command line and in attributes
+    pub fn id(self) -> &'static str {
+        match self {
+            Lint::FloatEquality => "float_equality",
+            Lint::EmptyBlock => "empty_block",
+            Lint::ShadowedParameter => "shadowed_parameter",
+            Lint::InfiniteLoop => "infinite_loop",
+            Lint::SelfAssignment => "self_assignment",
+            Lint::Naming => "naming",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.id() == id)
+    }
+
+    /// Level of the lint unless it's set otherwise
+    pub fn default_level(self) -> Severity {
+        match self {
+            Lint::SelfAssignment => Severity::Deny,
+            _ => Severity::Warn,
+        }
+    }
+}
+
+/// Level of every lint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lints {
+    /// By position in `Lint::ALL`
+    levels: [Severity; Lint::ALL.len()],
+}
+
+impl Default for Lints {
+    fn default() -> Self {
+        Self { levels: Lint::ALL.map(Lint::default_level) }
+    }
+}
+
+impl Lints {
+    /// Every lint at its default level
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn level(&self, lint: Lint) -> Severity {
+        self.levels[lint as usize]
+    }
+
+    pub fn set(&mut self, lint: Lint, level: Severity) {
+        self.levels[lint as usize] = level;
+    }
+
+    /// Levels within a function, changed by its `@allow`, `@warn` and
+    /// `@deny` attributes
+    fn within(&self, function: &Function) -> Lints {
+        let mut lints = *self;
+        for (id, level) in attribute_levels(function) {
+            if let Some(lint) = Lint::from_id(id) {
+                lints.set(lint, level);
+            }
+        }
+        lints
+    }
+}
+
+/// Whether `id` names a lint or a check, which attributes can set the
+/// level of
+pub fn is_known(id: &str) -> bool {
+    Lint::from_id(id).is_some() || Checks::IDS.contains(&id)
+}
+
+/// IDs of the lints and checks a function's attributes set the level of,
+/// in the order they're written
+pub fn attribute_levels(function: &Function) -> impl Iterator<Item = (&str, Severity)> {
+    function.attributes.iter().flat_map(|attribute| {
+        let level = Severity::from_ident(&attribute.ident);
+        attribute.arguments.iter().filter_map(move |id| level.map(|level| (id.as_str(), level)))
+    })
+}
+
+/// Something a lint found, before its level is known
+struct Finding {
+    lint: Lint,
+    message: String,
+    span: Span,
+}
+
+/// What the lints find in a program that type checks, at their levels, in
+/// the order it appears in the source
+/// Warnings are `CompileError::Warning`s, and denied lints
+/// `CompileError::SemanticError`s, both ending with the lint's ID
+pub fn run(program: &Program, checker: &TypeChecker, lints: &Lints) -> Vec<CompileError> {
+    let mut findings: Vec<(Severity, Finding)> = Vec::new();
+    let mut add = |found: Vec<Finding>, lints: &Lints| {
+        findings.extend(found.into_iter().map(|finding| (lints.level(finding.lint), finding)));
+    };
+
+    add(rules::declarations(program), lints);
+    for function in &program.functions {
+        add(rules::function(function, checker), &lints.within(function));
+    }
+
+    findings.sort_by_key(|(_, finding)| finding.span.start);
+    findings.into_iter()
+        .filter_map(|(level, finding)| level.report(finding.message, finding.lint.id(), finding.span))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    /// What the lints find in `source`, as `line:column: message`, with
+    /// errors marked
+    fn lint(source: &str, lints: &Lints) -> Vec<String> {
+        let program = Parser::new(lex(source.to_string()).unwrap()).parse().unwrap();
+        let mut checker = TypeChecker::new();
+        checker.check_program(&program).unwrap();
+
+        run(&program, &checker, lints).iter()
+            .map(|diagnostic| match diagnostic {
+                CompileError::Warning(message, span) => {
+                    let (line, column) = span.location(source);
+                    format!("{}:{}: {}", line, column, message)
+                }
+                CompileError::SemanticError(message, span) => {
+                    let (line, column) = span.location(source);
+                    format!("{}:{}: error: {}", line, column, message)
+                }
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lints() {
+        let found = lint("\
+struct point { x: float, y: float }
+enum Shape { Circle(float), unit_square }
+const limit = 10;
+func sameX(a: point, b: point): bool { return a.x == b.x; }
+func step(n: int, m: int): int {
+    if (n > 0) {} else { n = n; }
+    let n = 2;
+    while (true) { m++; }
+}
+func count(m: int): int {
+    while (true) { if (m > 10) { return m; } m++; }
+}
+func close(a: float, b: float): bool { return a - b < 0.001 and a != 0.0 + b; }
+func first<item>(x: item): item { return x; }", &Lints::new());
+
+        assert_eq!(found, [
+            "1:8: Struct `point` should be UpperCamelCase, i.e. `Point` [naming]",
+            "2:29: Variant `unit_square` should be UpperCamelCase, i.e. `UnitSquare` [naming]",
+            "3:7: Constant `limit` should be SCREAMING_SNAKE_CASE, i.e. `LIMIT` [naming]",
+            "4:6: Function `sameX` should be snake_case, i.e. `same_x` [naming]",
+            "4:47: Floats compared with `==`, which rounding makes unreliable [float_equality]",
+            "6:16: Empty block [empty_block]",
+            "6:26: error: `n` is assigned to itself [self_assignment]",
+            "7:5: `n` shadows a parameter [shadowed_parameter]",
+            "8:5: Loop never ends, as nothing in it returns [infinite_loop]",
+            "13:65: Floats compared with `!=`, which rounding makes unreliable [float_equality]",
+            "14:12: Type parameter `item` should be UpperCamelCase, i.e. `Item` [naming]",
+        ]);
+    }
+
+    #[test]
+    fn test_levels() {
+        let source = "\
+@allow(float_equality) @deny(empty_block)
+func isZero(x: float): bool { if (x > 1.0) {} return x == 0.0; }
+func equal(a: float, b: float): bool { return a == b; }";
+
+        assert_eq!(lint(source, &Lints::new()), [
+            "2:6: Function `isZero` should be snake_case, i.e. `is_zero` [naming]",
+            "2:44: error: Empty block [empty_block]",
+            "3:47: Floats compared with `==`, which rounding makes unreliable [float_equality]",
+        ]);
+
+        let mut lints = Lints::new();
+        lints.set(Lint::Naming, Severity::Allow);
+        lints.set(Lint::FloatEquality, Severity::Deny);
+        assert_eq!(lint(source, &lints), [
+            "2:44: error: Empty block [empty_block]",
+            "3:47: error: Floats compared with `==`, which rounding makes unreliable [float_equality]",
+        ]);
+    }
+}
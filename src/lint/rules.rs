@@ -0,0 +1,298 @@
+//! What each lint looks for
+
+use crate::ast::visit::{walk_expression, walk_match_arm, walk_statement};
+use crate::ast::{
+    AssignOperator, BinaryOperator, Block, Expression, Function, MatchArm, Parameters, Pattern, Program, Statement,
+    Type, Visitor,
+};
+use crate::error::Span;
+use crate::type_checking::TypeChecker;
+
+use super::{Finding, Lint};
+
+/// Names of what a program declares outside its functions that don't
+/// follow the naming convention
+pub fn declarations(program: &Program) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for global in &program.globals {
+        let case = if global.constant { Case::ScreamingSnake } else { Case::Snake };
+        let what = if global.constant { "Constant" } else { "Global" };
+        findings.extend(naming(what, &global.ident, case, &global.ident_span));
+    }
+
+    for declaration in &program.structs {
+        findings.extend(naming("Struct", &declaration.ident, Case::UpperCamel, &declaration.ident_span));
+        for field in &declaration.fields {
+            findings.extend(naming("Field", &field.ident, Case::Snake, &field.span));
+        }
+    }
+
+    for declaration in &program.enums {
+        findings.extend(naming("Enum", &declaration.ident, Case::UpperCamel, &declaration.ident_span));
+        for variant in &declaration.variants {
+            findings.extend(naming("Variant", &variant.ident, Case::UpperCamel, &variant.span));
+        }
+    }
+
+    // extern functions are named by the C code that defines them
+
+    findings
+}
+
+/// What the lints find in a function, its name and parameters included
+pub fn function(function: &Function, checker: &TypeChecker) -> Vec<Finding> {
+    let mut linter = Linter {
+        owner: &function.ident,
+        checker,
+        params: vec![function.params.params.iter().map(|param| param.ident.clone()).collect()],
+        findings: Vec::new(),
+    };
+
+    linter.findings.extend(naming("Function", &function.ident, Case::Snake, &function.ident_span));
+    for type_param in &function.type_params {
+        linter.findings.extend(naming("Type parameter", &type_param.ident, Case::UpperCamel, &type_param.span));
+    }
+    for param in &function.params.params {
+        linter.findings.extend(naming("Parameter", &param.ident, Case::Snake, &param.span));
+    }
+
+    linter.visit_block(&function.body);
+    linter.findings
+}
+
+struct Linter<'a> {
+    owner: &'a str,
+    checker: &'a TypeChecker,
+    /// Parameters of the function and the anonymous functions the walk is
+    /// in, innermost last
+    params: Vec<Vec<String>>,
+    findings: Vec<Finding>,
+}
+
+impl Linter<'_> {
+    fn find(&mut self, lint: Lint, message: String, span: &Span) {
+        self.findings.push(Finding { lint, message, span: span.clone() });
+    }
+
+    /// A variable or parameter declared in the function
+    fn declare(&mut self, what: &str, ident: &str, span: &Span) {
+        if self.params.iter().flatten().any(|param| param == ident) {
+            self.find(Lint::ShadowedParameter, format!("`{}` shadows a parameter", ident), span);
+        }
+
+        self.findings.extend(naming(what, ident, Case::Snake, span));
+    }
+
+    /// Declare the parameters of an anonymous function, which then hide
+    /// those of the functions it's in
+    fn lambda_params(&mut self, params: &Parameters) {
+        for param in &params.params {
+            self.declare("Parameter", &param.ident, &param.span);
+        }
+        self.params.push(params.params.iter().map(|param| param.ident.clone()).collect());
+    }
+
+    fn empty_block(&mut self, block: &Block) {
+        if block.statements.is_empty() {
+            self.find(Lint::EmptyBlock, "Empty block".to_string(), &block.span);
+        }
+    }
+
+    fn is_float(&self, expression: &Expression) -> bool {
+        self.checker.expression_type(self.owner, expression.span()).is_some_and(Type::is_float)
+    }
+}
+
+impl Visitor for Linter<'_> {
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { ident, span, .. } => self.declare("Variable", ident, span),
+            Statement::Assign { ident, value: Expression::Identifier { ident: value, .. }, span } if ident == value => {
+                self.find(Lint::SelfAssignment, format!("`{}` is assigned to itself", ident), span);
+            }
+            Statement::Block { block, .. } => self.empty_block(block),
+            Statement::While { condition, body, span } | Statement::For { condition, body, span, .. } => {
+                self.empty_block(body);
+                let forever = matches!(condition, Expression::BooleanLiteral { value: true, .. });
+                if forever && !body.statements.iter().any(returns) {
+                    self.find(Lint::InfiniteLoop, "Loop never ends, as nothing in it returns".to_string(), span);
+                }
+            }
+            Statement::DoUntil { condition, body, span } => {
+                let forever = matches!(condition, Expression::BooleanLiteral { value: false, .. });
+                if forever && !returns(body) {
+                    self.find(Lint::InfiniteLoop, "Loop never ends, as nothing in it returns".to_string(), span);
+                }
+            }
+            _ => {}
+        }
+
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Binary { left, operator, right, span }
+                if matches!(operator, BinaryOperator::EqualEqual | BinaryOperator::BangEqual)
+                    && (self.is_float(left) || self.is_float(right)) =>
+            {
+                let message = format!("Floats compared with `{}`, which rounding makes unreliable", operator);
+                self.find(Lint::FloatEquality, message, span);
+            }
+            Expression::Assign { left, operator: AssignOperator::Equal, right, span } => {
+                if let Some(place) = same_place(left, right) {
+                    self.find(Lint::SelfAssignment, format!("`{}` is assigned to itself", place), span);
+                }
+            }
+            Expression::Function { params, body, .. } => {
+                self.lambda_params(params);
+                self.visit_block(body);
+                self.params.pop();
+                return;
+            }
+            _ => {}
+        }
+
+        walk_expression(self, expression);
+    }
+
+    fn visit_match_arm(&mut self, arm: &MatchArm) {
+        if let Pattern::Variant { bindings, span, .. } = &arm.pattern {
+            for binding in bindings.iter().filter(|binding| *binding != "_") {
+                self.declare("Variable", binding, span);
+            }
+        }
+
+        walk_match_arm(self, arm);
+    }
+}
+
+/// Whether a statement is or contains a `return`, other than in an
+/// anonymous function
+fn returns(statement: &Statement) -> bool {
+    struct Finder {
+        found: bool,
+    }
+
+    impl Visitor for Finder {
+        fn visit_statement(&mut self, statement: &Statement) {
+            self.found |= matches!(statement, Statement::Return { .. });
+            walk_statement(self, statement);
+        }
+
+        fn visit_expression(&mut self, expression: &Expression) {
+            if !matches!(expression, Expression::Function { .. }) {
+                walk_expression(self, expression);
+            }
+        }
+    }
+
+    let mut finder = Finder { found: false };
+    finder.visit_statement(statement);
+    finder.found
+}
+
+/// The variable or field both sides of an assignment name, as written, if
+/// they're the same
+fn same_place(left: &Expression, right: &Expression) -> Option<String> {
+    match (left, right) {
+        (Expression::Identifier { ident: left, .. }, Expression::Identifier { ident: right, .. }) if left == right => {
+            Some(left.clone())
+        }
+        (
+            Expression::Field { target: left, field: left_field, .. },
+            Expression::Field { target: right, field: right_field, .. },
+        ) if left_field == right_field => same_place(left, right).map(|target| format!("{}.{}", target, left_field)),
+        (Expression::Grouping { expression, .. }, right) => same_place(expression, right),
+        (left, Expression::Grouping { expression, .. }) => same_place(left, expression),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    /// `snake_case`
+    Snake,
+    /// `SCREAMING_SNAKE_CASE`
+    ScreamingSnake,
+    /// `UpperCamelCase`
+    UpperCamel,
+}
+
+impl Case {
+    fn name(self) -> &'static str {
+        match self {
+            Case::Snake => "snake_case",
+            Case::ScreamingSnake => "SCREAMING_SNAKE_CASE",
+            Case::UpperCamel => "UpperCamelCase",
+        }
+    }
+
+    /// `ident` in this case
+    fn convert(self, ident: &str) -> String {
+        let words = words(ident);
+        match self {
+            Case::Snake => words.join("_"),
+            Case::ScreamingSnake => words.join("_").to_uppercase(),
+            Case::UpperCamel => words.iter()
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars.next().map_or(String::new(), |first| first.to_uppercase().chain(chars).collect())
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The lowercase words of an identifier, split at underscores and before
+/// capitals that start a word
+fn words(ident: &str) -> Vec<String> {
+    let chars: Vec<char> = ident.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        let starts_word = c.is_uppercase() && i > 0 && (
+            chars[i - 1].is_lowercase() || chars[i - 1].is_ascii_digit()
+                || chars.get(i + 1).is_some_and(|next| next.is_lowercase()) && chars[i - 1].is_uppercase()
+        );
+
+        if c == '_' || starts_word {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            if c == '_' {
+                continue;
+            }
+        }
+
+        word.extend(c.to_lowercase());
+    }
+
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// A finding about `ident` unless it's written in `case`
+/// Names of imported items are checked without their module, and leading
+/// underscores are ignored
+fn naming(what: &str, ident: &str, case: Case, span: &Span) -> Option<Finding> {
+    let name = ident.rsplit("::").next().unwrap_or(ident).trim_start_matches('_');
+    if name.is_empty() {
+        return None;
+    }
+
+    let expected = case.convert(name);
+    if expected == name {
+        return None;
+    }
+
+    Some(Finding {
+        lint: Lint::Naming,
+        message: format!("{} `{}` should be {}, i.e. `{}`", what, name, case.name(), expected),
+        span: span.clone(),
+    })
+}
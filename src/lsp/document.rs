@@ -0,0 +1,449 @@
+//! What the server knows about an open document
+//!
+//! Each version of a document is loaded, type checked and put through the
+//! checks and lints as `kennedy build` would, and every name in it is
+//! resolved to what it refers to by an [`Analysis`]. Requests are then
+//! answered from that, by character offset into the text.
+//!
+//! Only what's written in the document itself is indexed. Names from the
+//! modules it imports are used to check it, but go nowhere.
+
+use std::path::Path;
+
+use crate::analysis::{self, Analysis, DefinitionKind};
+use crate::ast::{self, Program};
+use crate::checks::{self, Checks};
+use crate::error::{CompileError, Span};
+use crate::lexer::KEYWORDS;
+use crate::lint::{self, Lints};
+use crate::modules::{self, SourceMap};
+use crate::type_checking::TypeChecker;
+
+/// Something wrong with a document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// An error rather than a warning
+    pub error: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Extern,
+    Global,
+    Constant,
+    Struct,
+    Field,
+    Enum,
+    Parameter,
+    Variable,
+}
+
+/// A name declared in the document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the name is written in its declaration
+    pub span: Span,
+    /// The declaration in full, i.e. the whole function
+    pub declaration: Span,
+    /// Its type or signature, as code
+    pub detail: String,
+    /// Where it can be used by name, `None` for fields
+    pub visible: Option<Span>,
+}
+
+/// A completion for the word being typed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    /// What the word is, `None` for keywords
+    pub kind: Option<SymbolKind>,
+    pub detail: Option<String>,
+}
+
+struct Index {
+    analysis: Analysis,
+    /// What's shown of each of the analysis' definitions
+    definitions: Vec<Definition>,
+}
+
+pub struct Document {
+    text: Vec<char>,
+    /// Offset of the start of each line
+    lines: Vec<usize>,
+    diagnostics: Vec<Diagnostic>,
+    /// `None` if the document doesn't parse
+    index: Option<Index>,
+}
+
+impl Document {
+    /// Analyze the text of a document, found at `path` if it's been saved,
+    /// which is where its imports are looked for
+    pub fn new(path: Option<&Path>, text: &str) -> Document {
+        let chars: Vec<char> = text.chars().collect();
+        let lines = std::iter::once(0)
+            .chain(chars.iter().enumerate().filter(|(_, c)| **c == '\n').map(|(i, _)| i + 1))
+            .collect();
+
+        let mut document = Document { text: chars, lines, diagnostics: Vec::new(), index: None };
+
+        let mut sources = SourceMap::default();
+        let path = path.filter(|path| path.exists());
+        let program = match modules::load(path, text, &mut sources) {
+            Ok(program) => program,
+            Err(error) => {
+                document.report(sources.attribute(error));
+                return document;
+            }
+        };
+
+        let mut checker = TypeChecker::new();
+        match checker.check_program(&program) {
+            Ok(()) => {
+                let diagnostics = checks::check(&program, &Checks::default()).into_iter()
+                    .chain(lint::run(&program, &checker, &Lints::default()));
+                for diagnostic in diagnostics {
+                    document.report(sources.attribute(diagnostic));
+                }
+            }
+            // what the checker worked out before it stopped is still worth
+            // indexing
+            Err(error) => document.report(sources.attribute(error)),
+        }
+
+        let analysis = Analysis::checked(&program, &checker);
+        let definitions = analysis.definitions().iter()
+            .map(|definition| document.describe(&program, definition))
+            .collect();
+        document.index = Some(Index { analysis, definitions });
+        document
+    }
+
+    fn report(&mut self, error: CompileError) {
+        let diagnostic = match error {
+            CompileError::SyntaxError(message, span) | CompileError::SemanticError(message, span) => {
+                Diagnostic { message, span, error: true }
+            }
+            CompileError::Warning(message, span) => Diagnostic { message, span, error: false },
+            CompileError::CompileError(message) => Diagnostic { message, span: Span { start: 0, end: 0 }, error: true },
+            // an imported module is broken, which shows at the top
+            CompileError::InFile { path, error, .. } => {
+                let message = format!("{}: {}", path, error);
+                Diagnostic { message, span: Span { start: 0, end: 0 }, error: true }
+            }
+        };
+
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// How a definition of the analysis is shown
+    fn describe(&self, program: &Program, definition: &analysis::Definition) -> Definition {
+        let name = &definition.ident;
+        let typed = |prefix: &str| match &definition.ty {
+            Some(ty) => format!("{}{}: {}", prefix, name, ty),
+            None => format!("{}{}", prefix, name),
+        };
+
+        let kind = match definition.kind {
+            DefinitionKind::Function => SymbolKind::Function,
+            DefinitionKind::Extern => SymbolKind::Extern,
+            DefinitionKind::Global => SymbolKind::Global,
+            DefinitionKind::Constant => SymbolKind::Constant,
+            DefinitionKind::Struct => SymbolKind::Struct,
+            DefinitionKind::Field => SymbolKind::Field,
+            DefinitionKind::Enum => SymbolKind::Enum,
+            DefinitionKind::Parameter => SymbolKind::Parameter,
+            DefinitionKind::Variable | DefinitionKind::Binding => SymbolKind::Variable,
+        };
+        let detail = match definition.kind {
+            DefinitionKind::Function => program.functions.iter()
+                .find(|function| function.span == definition.span)
+                .map_or_else(|| format!("func {}", name), signature),
+            DefinitionKind::Extern => program.externs.iter()
+                .find(|function| function.span == definition.span)
+                .map_or_else(
+                    || format!("extern func {}", name),
+                    |function| format!("extern func {}({}): {}", name, parameters(&function.params), function.return_type),
+                ),
+            DefinitionKind::Global | DefinitionKind::Variable => typed("let "),
+            DefinitionKind::Constant => typed("const "),
+            DefinitionKind::Struct => format!("struct {}", name),
+            DefinitionKind::Enum => format!("enum {}", name),
+            DefinitionKind::Field | DefinitionKind::Parameter | DefinitionKind::Binding => typed(""),
+        };
+
+        // a binding is written after the variant, within its pattern
+        let mut declaration = definition.span.clone();
+        if definition.kind == DefinitionKind::Binding {
+            let end = declaration.end.min(self.text.len());
+            if let Some(open) = self.text.get(declaration.start..end).and_then(|text| text.iter().position(|&c| c == '(')) {
+                declaration.start += open + 1;
+            }
+        }
+
+        let in_document = definition.span.start < self.text.len();
+        let visible = match (&definition.scope, definition.kind) {
+            (_, DefinitionKind::Field) => None,
+            (_, _) if !in_document => None,
+            (Some(scope), _) => Some(scope.clone()),
+            (None, _) => Some(Span { start: 0, end: self.text.len() }),
+        };
+
+        Definition {
+            name: name.clone(),
+            kind,
+            span: self.name_span(&declaration, name),
+            declaration: definition.span.clone(),
+            detail,
+            visible,
+        }
+    }
+
+    /// Where `name` is first written within `span`, or all of `span` if it
+    /// isn't (a qualified name, say)
+    fn name_span(&self, span: &Span, name: &str) -> Span {
+        let name: Vec<char> = name.chars().collect();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let end = span.end.min(self.text.len());
+
+        (span.start..end.saturating_sub(name.len()) + 1)
+            .find(|&start| {
+                self.text[start..].starts_with(&name)
+                    && (start == 0 || !is_word(self.text[start - 1]))
+                    && self.text.get(start + name.len()).is_none_or(|c| !is_word(*c))
+            })
+            .map_or_else(|| span.clone(), |start| Span { start, end: start + name.len() })
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Character offset of a zero-based line and column, in UTF-16 code
+    /// units as the protocol counts them
+    pub fn offset(&self, line: usize, character: usize) -> usize {
+        let Some(&start) = self.lines.get(line) else {
+            return self.text.len();
+        };
+
+        let mut units = 0;
+        let mut offset = start;
+        while offset < self.text.len() && self.text[offset] != '\n' && units < character {
+            units += self.text[offset].len_utf16();
+            offset += 1;
+        }
+        offset
+    }
+
+    /// Zero-based line and UTF-16 column of a character offset
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.text.len());
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.lines[line]..offset].iter().map(|c| c.len_utf16()).sum();
+        (line, character)
+    }
+
+    /// The definition of the name at `offset`, whether it's where it's
+    /// declared or a use of it
+    fn definition_at(&self, offset: usize) -> Option<(usize, &Definition)> {
+        let index = self.index.as_ref()?;
+        let covers = |span: &Span| span.start <= offset && offset <= span.end;
+
+        let definition = index.analysis.resolve(offset)
+            .or_else(|| index.definitions.iter().position(|definition| covers(&definition.span)))?;
+
+        // declarations in modules the document imports come after it
+        let definition = (definition, &index.definitions[definition]);
+        (definition.1.span.start < self.text.len()).then_some(definition)
+    }
+
+    /// What's at `offset`, and where it is: the declaration of a name, or
+    /// the type of the innermost expression
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        if let Some((_, definition)) = self.definition_at(offset) {
+            return Some((definition.span.clone(), definition.detail.clone()));
+        }
+
+        let index = self.index.as_ref()?;
+        index.analysis.expression_at(offset).map(|(span, ty)| (span.clone(), ty.to_string()))
+    }
+
+    /// Where the name at `offset` is declared
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        self.definition_at(offset).map(|(_, definition)| definition.span.clone())
+    }
+
+    /// Every use of the name at `offset`, and its declaration if asked for,
+    /// in the order they're written
+    pub fn references(&self, offset: usize, include_declaration: bool) -> Vec<Span> {
+        let (Some((definition, declared)), Some(index)) = (self.definition_at(offset), &self.index) else {
+            return Vec::new();
+        };
+
+        let mut spans: Vec<Span> = index.analysis.references(definition).into_iter().cloned().collect();
+        if include_declaration {
+            spans.push(declared.span.clone());
+        }
+
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// Keywords, and the names that can be used at `offset`
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let mut completions: Vec<Completion> = KEYWORDS.iter()
+            .chain(&["len", "push"])
+            .map(|keyword| Completion { label: keyword.to_string(), kind: None, detail: None })
+            .collect();
+
+        let Some(index) = &self.index else {
+            return completions;
+        };
+
+        // the innermost of names declared more than once, which comes last
+        let mut visible: Vec<&Definition> = Vec::new();
+        let in_scope = index.definitions.iter().filter(|definition| {
+            definition.visible.as_ref().is_some_and(|span| span.start <= offset && offset <= span.end)
+        });
+        for definition in in_scope {
+            visible.retain(|other| other.name != definition.name);
+            visible.push(definition);
+        }
+
+        completions.extend(visible.into_iter().map(|definition| Completion {
+            label: definition.name.clone(),
+            kind: Some(definition.kind),
+            detail: Some(definition.detail.clone()),
+        }));
+        completions
+    }
+
+    /// The functions declared in the document
+    pub fn symbols(&self) -> Vec<&Definition> {
+        self.index.iter()
+            .flat_map(|index| &index.definitions)
+            .filter(|definition| definition.kind == SymbolKind::Function && definition.span.start < self.text.len())
+            .collect()
+    }
+}
+
+/// `func name<T>(a: int): int`
+fn signature(function: &ast::Function) -> String {
+    let type_params: Vec<String> = function.type_params.iter()
+        .map(|param| match param.bound {
+            Some(bound) => format!("{}: {}", param.ident, bound),
+            None => param.ident.clone(),
+        })
+        .collect();
+    let type_params = if type_params.is_empty() { String::new() } else { format!("<{}>", type_params.join(", ")) };
+
+    format!("func {}{}({}): {}", function.ident, type_params, parameters(&function.params), function.return_type)
+}
+
+fn parameters(params: &ast::Parameters) -> String {
+    params.params.iter()
+        .map(|param| format!("{}: {}", param.ident, param.param_type))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+struct Point { x: float, y: float }
+enum Shape { Circle(float), Square(float) }
+const SCALE = 2.0;
+func area(s: Shape): float {
+    return match (s) { Circle(r) => 3.0 * r * r, Square(side) => side * side };
+}
+func norm(p: Point): float {
+    let total = p.x * p.x + p.y * p.y;
+    return total * SCALE;
+}";
+
+    /// Offset of the `n`th (from zero) occurrence of `needle` in `text`
+    fn find(text: &str, needle: &str, n: usize) -> usize {
+        let byte = text.match_indices(needle).nth(n).unwrap().0;
+        text[..byte].chars().count()
+    }
+
+    /// The text of a span of `SOURCE`
+    fn text(span: &Span) -> String {
+        SOURCE.chars().skip(span.start).take(span.end - span.start).collect()
+    }
+
+    #[test]
+    fn test_navigation() {
+        let document = Document::new(None, SOURCE);
+        assert!(document.diagnostics().is_empty(), "{:?}", document.diagnostics());
+
+        // a use of `total` goes to its declaration
+        let total = find(SOURCE, "total", 1);
+        let definition = document.definition(total).unwrap();
+        assert_eq!((text(&definition), definition.start), ("total".to_string(), find(SOURCE, "total", 0)));
+        assert_eq!(document.hover(total).map(|(_, hover)| hover), Some("let total: float".to_string()));
+
+        // fields, through the value's type
+        let references = document.references(find(SOURCE, "x", 0), true);
+        assert_eq!(references.iter().map(|span| span.start).collect::<Vec<_>>(), [
+            find(SOURCE, "x: float", 0),
+            find(SOURCE, ".x", 0) + 1,
+            find(SOURCE, ".x", 1) + 1,
+        ]);
+
+        // bindings take the type of the variant's value
+        assert_eq!(document.hover(find(SOURCE, "r * r", 0)).map(|(_, hover)| hover), Some("r: float".to_string()));
+        assert_eq!(document.hover(find(SOURCE, "p: Point", 0)).map(|(_, hover)| hover), Some("p: Point".to_string()));
+        assert_eq!(
+            document.hover(find(SOURCE, "norm", 0)).map(|(_, hover)| hover),
+            Some("func norm(p: Point): float".to_string()),
+        );
+
+        // otherwise the innermost expression with a type
+        let (span, hover) = document.hover(find(SOURCE, "* SCALE", 0)).unwrap();
+        assert_eq!((text(&span), hover), ("total * SCALE".to_string(), "float".to_string()));
+
+        let symbols: Vec<&str> = document.symbols().iter().map(|symbol| symbol.name.as_str()).collect();
+        assert_eq!(symbols, ["area", "norm"]);
+    }
+
+    #[test]
+    fn test_completions() {
+        let document = Document::new(None, SOURCE);
+        let labels = |offset: usize| -> Vec<String> {
+            document.completions(offset).into_iter()
+                .filter(|completion| completion.kind.is_some())
+                .map(|completion| completion.label)
+                .collect()
+        };
+
+        assert_eq!(labels(find(SOURCE, "return total", 0)), ["SCALE", "area", "norm", "Point", "Shape", "p", "total"]);
+        // `total` is declared after, and `r` in another arm
+        assert_eq!(labels(find(SOURCE, "let total", 0)), ["SCALE", "area", "norm", "Point", "Shape", "p"]);
+        assert!(!labels(find(SOURCE, "side * side", 0)).contains(&"r".to_string()));
+        assert!(document.completions(0).iter().any(|completion| completion.label == "func"));
+    }
+
+    #[test]
+    fn test_diagnostics_and_positions() {
+        let source = "func f(): int {\n    let s = \"😀\"; return x;\n}";
+        let document = Document::new(None, source);
+
+        // columns count UTF-16 units, of which the emoji is two
+        let diagnostic = &document.diagnostics()[0];
+        assert!(diagnostic.error);
+        assert_eq!(diagnostic.message, "Use of undeclared variable `x`");
+        assert_eq!(document.position(diagnostic.span.start), (1, 25));
+        assert_eq!(document.offset(1, 25), find(source, "x", 0));
+
+        let document = Document::new(None, "func f(): int { return 1 }");
+        assert_eq!(document.diagnostics().len(), 1);
+        assert!(document.definition(5).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+//! A language server for Kennedy, spoken over stdin and stdout
+//!
+//! `kennedy-lsp` lets editors show what `kennedy build` would say about a
+//! file as it's edited, and find their way around it: the type of what's
+//! under the cursor, where a name is declared and used, the names that
+//! can be typed at a point, and the functions in the file.
+//!
+//! Messages are JSON-RPC, each preceded by a `Content-Length` header, as
+//! the [Language Server Protocol] specifies. Documents are synced whole on
+//! every change, and positions count UTF-16 code units, as the protocol
+//! expects by default.
+//!
+//! [Language Server Protocol]: https://microsoft.github.io/language-server-protocol/
+
+mod document;
+mod json;
+mod server;
+
+use std::io::{self, BufRead, Write};
+
+pub use document::{Completion, Definition, Diagnostic, Document, SymbolKind};
+pub use json::Json;
+pub use server::Server;
+
+/// Serve the client on the other end of `input` and `output` until it
+/// says to exit, or hangs up
+/// Returns whether it asked the server to shut down first, as it should
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::new();
+
+    while let Some(body) = read_message(&mut input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(error) => {
+                write_message(&mut output, &server::parse_error(error))?;
+                continue;
+            }
+        };
+
+        if message.get("method").and_then(Json::as_str) == Some("exit") {
+            return Ok(server.shut_down);
+        }
+
+        for reply in server.handle(&message) {
+            write_message(&mut output, &reply)?;
+        }
+    }
+
+    Ok(false)
+}
+
+/// The body of the next message, or `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    String::from_utf8(body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a session of `messages`, returning what the server sent back
+    /// and whether it shut down
+    fn session(messages: &[String]) -> (Vec<Json>, bool) {
+        let mut input = Vec::new();
+        for message in messages {
+            write!(input, "Content-Length: {}\r\n\r\n{}", message.len(), message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        let shut_down = run(input.as_slice(), &mut output).unwrap();
+
+        let mut replies = Vec::new();
+        let mut output = output.as_slice();
+        while let Some(body) = read_message(&mut output).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        (replies, shut_down)
+    }
+
+    fn request(id: usize, method: &str, params: &str) -> String {
+        format!(r#"{{"jsonrpc":"2.0","id":{},"method":"{}","params":{}}}"#, id, method, params)
+    }
+
+    fn notify(method: &str, params: &str) -> String {
+        format!(r#"{{"jsonrpc":"2.0","method":"{}","params":{}}}"#, method, params)
+    }
+
+    #[test]
+    fn test_session() {
+        let document = r#"{"uri":"file:///tmp/no%20such/main.ken","text":"func sq(x: int): int { return x * x; }\nfunc f(): int { /* 😀 */ let e = sq(2); return e; }"}"#;
+        let at = |line: usize, character: usize| {
+            format!(
+                r#"{{"textDocument":{{"uri":"file:///tmp/no%20such/main.ken"}},"position":{{"line":{},"character":{}}},"context":{{"includeDeclaration":true}}}}"#,
+                line, character,
+            )
+        };
+
+        let (replies, shut_down) = session(&[
+            request(1, "initialize", r#"{"capabilities":{}}"#),
+            notify("initialized", "{}"),
+            format!(r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{}}}}}"#, document),
+            request(2, "textDocument/hover", &at(1, 47)),
+            request(3, "textDocument/definition", &at(1, 33)),
+            request(4, "textDocument/references", &at(0, 6)),
+            request(5, "textDocument/documentSymbol", &at(0, 0)),
+            request(6, "textDocument/formatting", &at(0, 0)),
+            "{not json".to_string(),
+            request(7, "shutdown", "null"),
+            notify("exit", "null"),
+        ]);
+        assert!(shut_down);
+
+        let result = |id: usize| {
+            replies.iter().find(|reply| reply.get("id").and_then(Json::as_u64) == Some(id as u64)).unwrap()
+                .get("result").unwrap().to_string()
+        };
+
+        assert!(result(1).contains(r#""hoverProvider":true"#), "{}", result(1));
+        assert_eq!(
+            replies[1].to_string(),
+            r#"{"jsonrpc":"2.0","method":"textDocument/publishDiagnostics","params":{"uri":"file:///tmp/no%20such/main.ken","diagnostics":[]}}"#,
+        );
+        assert_eq!(
+            result(2),
+            r#"{"contents":{"kind":"markdown","value":"```kennedy\nlet e: int\n```"},"range":{"start":{"line":1,"character":29},"end":{"line":1,"character":30}}}"#,
+        );
+        assert_eq!(
+            result(3),
+            r#"{"uri":"file:///tmp/no%20such/main.ken","range":{"start":{"line":0,"character":5},"end":{"line":0,"character":7}}}"#,
+        );
+        assert_eq!(result(4).matches(r#""uri""#).count(), 2);
+        assert!(result(5).contains(r#""name":"sq","detail":"func sq(x: int): int","kind":12"#), "{}", result(5));
+
+        let error = |id: Option<u64>| {
+            replies.iter().find(|reply| reply.get("id").and_then(Json::as_u64) == id && reply.get("error").is_some())
+                .unwrap().at(&["error", "code"]).unwrap().to_string()
+        };
+        assert_eq!(error(Some(6)), "-32601");
+        assert_eq!(error(None), "-32700");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let open = notify(
+            "textDocument/didOpen",
+            r#"{"textDocument":{"uri":"file:///a.ken","text":"func f(): int {\n    let x = 1;\n    return y;\n}"}}"#,
+        );
+        let change = notify(
+            "textDocument/didChange",
+            r#"{"textDocument":{"uri":"file:///a.ken"},"contentChanges":[{"text":"func f(x: int): int { return 1; }"}]}"#,
+        );
+
+        let (replies, shut_down) = session(&[open, change]);
+        assert!(!shut_down);
+
+        assert_eq!(
+            replies[0].at(&["params", "diagnostics"]).unwrap().to_string(),
+            r#"[{"range":{"start":{"line":2,"character":11},"end":{"line":2,"character":12}},"severity":1,"source":"kennedy","message":"Use of undeclared variable `y`"}]"#,
+        );
+        assert_eq!(
+            replies[1].at(&["params", "diagnostics"]).unwrap().to_string(),
+            r#"[{"range":{"start":{"line":0,"character":7},"end":{"line":0,"character":13}},"severity":2,"source":"kennedy","message":"Parameter `x` is never used [unused_parameters]"}]"#,
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Answering the client's requests, in protocol terms
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::error::Span;
+
+use super::document::{Document, SymbolKind};
+use super::json::Json;
+
+/// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+#[derive(Default)]
+pub struct Server {
+    /// By URI
+    documents: HashMap<String, Document>,
+    /// Whether the client has asked the server to shut down, so it can
+    /// exit cleanly
+    pub shut_down: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle a request or notification, returning what to send back: the
+    /// response to a request, and any notifications
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let id = message.get("id").cloned();
+        let params = message.get("params").unwrap_or(&Json::Null);
+
+        let Some(method) = message.get("method").and_then(Json::as_str) else {
+            // responses to requests the server never makes
+            return Vec::new();
+        };
+
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            "textDocument/didOpen" => {
+                let (Some(uri), Some(text)) = (
+                    params.at(&["textDocument", "uri"]).and_then(Json::as_str),
+                    params.at(&["textDocument", "text"]).and_then(Json::as_str),
+                ) else {
+                    return Vec::new();
+                };
+                return vec![self.update(uri, text)];
+            }
+            "textDocument/didChange" => {
+                // the whole text is sent, as that's the only way the server
+                // syncs
+                let uri = params.at(&["textDocument", "uri"]).and_then(Json::as_str);
+                let text = params.get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+
+                let (Some(uri), Some(text)) = (uri, text) else {
+                    return Vec::new();
+                };
+                return vec![self.update(uri, text)];
+            }
+            "textDocument/didClose" => {
+                let Some(uri) = params.at(&["textDocument", "uri"]).and_then(Json::as_str) else {
+                    return Vec::new();
+                };
+                self.documents.remove(uri);
+                return vec![notification("textDocument/publishDiagnostics", Json::object([
+                    ("uri", uri.into()),
+                    ("diagnostics", Json::Array(Vec::new())),
+                ]))];
+            }
+            "textDocument/hover"
+            | "textDocument/definition"
+            | "textDocument/references"
+            | "textDocument/completion"
+            | "textDocument/documentSymbol" => self.query(method, params),
+            // notifications the server has nothing to do about, like
+            // `initialized`
+            _ if id.is_none() => return Vec::new(),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        };
+
+        match id {
+            Some(id) => vec![response(id, result)],
+            None => Vec::new(),
+        }
+    }
+
+    /// Analyze a new version of a document, and publish what's wrong with it
+    fn update(&mut self, uri: &str, text: &str) -> Json {
+        let document = Document::new(path(uri).as_deref(), text);
+
+        let diagnostics = document.diagnostics().iter()
+            .map(|diagnostic| Json::object([
+                ("range", range(&document, &diagnostic.span)),
+                ("severity", Json::Number(if diagnostic.error { 1.0 } else { 2.0 })),
+                ("source", "kennedy".into()),
+                ("message", diagnostic.message.clone().into()),
+            ]))
+            .collect();
+
+        self.documents.insert(uri.to_string(), document);
+        notification("textDocument/publishDiagnostics", Json::object([
+            ("uri", uri.into()),
+            ("diagnostics", Json::Array(diagnostics)),
+        ]))
+    }
+
+    /// Answer a request about a position in (or the whole of) a document
+    fn query(&self, method: &str, params: &Json) -> Result<Json, (i32, String)> {
+        let uri = params.at(&["textDocument", "uri"]).and_then(Json::as_str)
+            .ok_or((INVALID_PARAMS, "no `textDocument.uri`".to_string()))?;
+        let document = self.documents.get(uri)
+            .ok_or((INVALID_REQUEST, format!("`{}` isn't open", uri)))?;
+
+        if method == "textDocument/documentSymbol" {
+            let symbols = document.symbols().into_iter()
+                .map(|symbol| Json::object([
+                    ("name", symbol.name.clone().into()),
+                    ("detail", symbol.detail.clone().into()),
+                    ("kind", Json::Number(12.0)),
+                    ("range", range(document, &symbol.declaration)),
+                    ("selectionRange", range(document, &symbol.span)),
+                ]))
+                .collect();
+            return Ok(Json::Array(symbols));
+        }
+
+        let line = params.at(&["position", "line"]).and_then(Json::as_u64);
+        let character = params.at(&["position", "character"]).and_then(Json::as_u64);
+        let (Some(line), Some(character)) = (line, character) else {
+            return Err((INVALID_PARAMS, "no `position`".to_string()));
+        };
+        let offset = document.offset(line as usize, character as usize);
+
+        let location = |span: Span| Json::object([("uri", uri.into()), ("range", range(document, &span))]);
+
+        Ok(match method {
+            "textDocument/hover" => match document.hover(offset) {
+                Some((span, hover)) => Json::object([
+                    ("contents", Json::object([
+                        ("kind", "markdown".into()),
+                        ("value", format!("```kennedy\n{}\n```", hover).into()),
+                    ])),
+                    ("range", range(document, &span)),
+                ]),
+                None => Json::Null,
+            },
+            "textDocument/definition" => document.definition(offset).map_or(Json::Null, location),
+            "textDocument/references" => {
+                let include_declaration = matches!(params.at(&["context", "includeDeclaration"]), Some(Json::Bool(true)));
+                Json::Array(document.references(offset, include_declaration).into_iter().map(location).collect())
+            }
+            "textDocument/completion" => {
+                let items = document.completions(offset).into_iter()
+                    .map(|completion| {
+                        let mut item = vec![
+                            ("label", completion.label.into()),
+                            ("kind", Json::Number(completion_kind(completion.kind))),
+                        ];
+                        if let Some(detail) = completion.detail {
+                            item.push(("detail", detail.into()));
+                        }
+                        Json::object(item)
+                    })
+                    .collect();
+                Json::Array(items)
+            }
+            _ => unreachable!("`{}` isn't a query", method),
+        })
+    }
+}
+
+/// What the server can do, in answer to `initialize`
+fn capabilities() -> Json {
+    Json::object([
+        ("capabilities", Json::object([
+            // the whole document is sent on every change
+            ("textDocumentSync", Json::Number(1.0)),
+            ("hoverProvider", true.into()),
+            ("definitionProvider", true.into()),
+            ("referencesProvider", true.into()),
+            ("completionProvider", Json::object([])),
+            ("documentSymbolProvider", true.into()),
+        ])),
+        ("serverInfo", Json::object([
+            ("name", "kennedy-lsp".into()),
+            ("version", env!("CARGO_PKG_VERSION").into()),
+        ])),
+    ])
+}
+
+fn response(id: Json, result: Result<Json, (i32, String)>) -> Json {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err((code, message)) => ("error", Json::object([
+            ("code", Json::Number(code as f64)),
+            ("message", message.into()),
+        ])),
+    };
+
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), outcome])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([("jsonrpc", "2.0".into()), ("method", method.into()), ("params", params)])
+}
+
+/// An error response to a message that couldn't be read
+pub fn parse_error(message: String) -> Json {
+    response(Json::Null, Err((PARSE_ERROR, message)))
+}
+
+fn range(document: &Document, span: &Span) -> Json {
+    let position = |offset: usize| {
+        let (line, character) = document.position(offset);
+        Json::object([("line", line.into()), ("character", character.into())])
+    };
+
+    Json::object([("start", position(span.start)), ("end", position(span.end))])
+}
+
+/// `CompletionItemKind` of a completion
+fn completion_kind(kind: Option<SymbolKind>) -> f64 {
+    match kind {
+        None => 14.0,
+        Some(SymbolKind::Function | SymbolKind::Extern) => 3.0,
+        Some(SymbolKind::Field) => 5.0,
+        Some(SymbolKind::Variable | SymbolKind::Parameter | SymbolKind::Global) => 6.0,
+        Some(SymbolKind::Enum) => 13.0,
+        Some(SymbolKind::Constant) => 21.0,
+        Some(SymbolKind::Struct) => 22.0,
+    }
+}
+
+/// Path of a `file://` URI, decoding any `%XX` escapes
+fn path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| encoded.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
@@ -0,0 +1,281 @@
+//! The `kennedy` command line driver
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use Kennedy::compiler::inline::DEFAULT_INLINE_SIZE;
+use Kennedy::compiler::{Compiler, CompilerOptions, OptLevel, OverflowMode};
+use Kennedy::checks::Checks;
+use Kennedy::interpreter::{Interpreter, Value};
+use Kennedy::lint::{Lint, Lints, Severity};
+use Kennedy::optimizer::Passes;
+use Kennedy::repl::Repl;
+use Kennedy::{formatter, CompileError};
+
+const USAGE: &str = "\
+usage: kennedy <command> [options] <file>...
+
+commands:
+    build       compile a program into an object file
+    fmt         format source files in place
+    run         interpret a program's `main` without compiling it
+    repl        compile and run definitions and statements as they're typed
+
+build options:
+    -o <path>       where to write the object file (default: <file>.o)
+    -O <level>      optimization level: none, speed or speed_and_size; any
+                    but none also folds constants and removes dead code, and
+                    speed inlines calls to small functions
+    --ir            compile functions through the Kennedy IR where it can
+                    represent them
+    --dump-code     print the Cranelift IR and machine code of every function,
+                    and the Kennedy IR of those compiled through it
+    -A <lint>       allow a lint or check, ignoring what it finds
+    -W <lint>       warn about what a lint or check finds
+    -D <lint>       deny a lint or check, failing the build if it finds
+                    anything
+
+lints: float_equality, empty_block, shadowed_parameter, infinite_loop,
+self_assignment (denied by default) and naming
+checks: unused_variables, unused_parameters, unused_functions (allowed by
+default) and unreachable_code
+
+Object files call the runtime in libkennedy_runtime.a, which cargo builds
+next to this binary, and have no entry point of their own. Link them with a
+C `main` calling their functions, and the system libraries Rust needs:
+
+    cc main.c program.o libkennedy_runtime.a -lpthread -ldl -lm -o program
+
+fmt options:
+    --check         list the files that aren't formatted instead, and fail if
+                    there are any
+
+run and repl options:
+    --checked       trap on integer overflow instead of wrapping";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        Some("fmt") => fmt(&args[1..]),
+        Some("run") => run(&args[1..]),
+        Some("repl") => repl(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command `{}`\n\n{}", command, USAGE)),
+        None => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// `kennedy build`
+fn build(args: &[String]) -> Result<(), String> {
+    let mut options = CompilerOptions::new().pic(true);
+    let mut checks = Checks::default();
+    let mut lints = Lints::default();
+    let mut output = None;
+    let mut input = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(value(&mut args, "-o")?)),
+            "-O" => {
+                let level = opt_level(value(&mut args, "-O")?)?;
+                let passes = if level == OptLevel::None { Passes::default() } else { Passes::all() };
+                // `@inline` functions are inlined whatever the level
+                let inline_size = if level == OptLevel::Speed { DEFAULT_INLINE_SIZE } else { 0 };
+                options = options.opt_level(level).passes(passes).inline_size(inline_size);
+            }
+            "--ir" => options = options.ir(true),
+            "--dump-code" => options = options.dump_code(true),
+            "-A" | "-W" | "-D" => {
+                let id = value(&mut args, arg)?;
+                let level = match arg.as_str() {
+                    "-A" => Severity::Allow,
+                    "-W" => Severity::Warn,
+                    _ => Severity::Deny,
+                };
+
+                match Lint::from_id(id) {
+                    Some(lint) => lints.set(lint, level),
+                    None if checks.set(id, level) => {}
+                    None => return Err(format!("unknown lint `{}`\n\n{}", id, USAGE)),
+                }
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`\n\n{}", flag, USAGE)),
+            file if input.is_none() => input = Some(PathBuf::from(file)),
+            file => return Err(format!("unexpected argument `{}`", file)),
+        }
+    }
+
+    let options = options.checks(checks).lints(lints);
+    let input = input.ok_or_else(|| format!("no file to build\n\n{}", USAGE))?;
+    let output = output.unwrap_or_else(|| input.with_extension("o"));
+    let name = input.file_stem().and_then(|stem| stem.to_str()).unwrap_or("kennedy");
+
+    let mut compiler = Compiler::object_with_options(name, options).map_err(|e| e.to_string())?;
+    compiler.compile_file(&input).map_err(|e| report(&input, &e))?;
+
+    for warning in compiler.warnings().iter().chain(&compiler.optimizer_report().warnings) {
+        eprintln!("{}\n", report(&input, warning));
+    }
+
+    for dump in compiler.dumps() {
+        println!("{}\n", dump);
+    }
+
+    let object = compiler.finish().map_err(|e| e.to_string())?;
+    fs::write(&output, object).map_err(|e| format!("cannot write `{}`: {}", output.display(), e))
+}
+
+/// `kennedy fmt`
+fn fmt(args: &[String]) -> Result<(), String> {
+    let mut check = false;
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`\n\n{}", flag, USAGE)),
+            file => files.push(PathBuf::from(file)),
+        }
+    }
+
+    if files.is_empty() {
+        return Err(format!("no files to format\n\n{}", USAGE));
+    }
+
+    let mut unformatted = Vec::new();
+    for file in &files {
+        let source = fs::read_to_string(file).map_err(|e| format!("cannot read `{}`: {}", file.display(), e))?;
+        let formatted = formatter::format(&source)
+            .map_err(|e| format!("{}: {}", file.display(), e.to_string_with_source(&source)))?;
+
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            unformatted.push(file.display().to_string());
+        } else {
+            fs::write(file, formatted).map_err(|e| format!("cannot write `{}`: {}", file.display(), e))?;
+        }
+    }
+
+    if unformatted.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("not formatted:\n{}", unformatted.join("\n")))
+    }
+}
+
+/// `kennedy run`
+fn run(args: &[String]) -> Result<(), String> {
+    let mut overflow_mode = OverflowMode::Wrapping;
+    let mut input = None;
+
+    for arg in args {
+        match arg.as_str() {
+            "--checked" => overflow_mode = OverflowMode::Checked,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{}`\n\n{}", flag, USAGE)),
+            file if input.is_none() => input = Some(PathBuf::from(file)),
+            file => return Err(format!("unexpected argument `{}`", file)),
+        }
+    }
+
+    let input = input.ok_or_else(|| format!("no file to run\n\n{}", USAGE))?;
+
+    let mut interpreter = Interpreter::new(overflow_mode);
+    interpreter.load_file(&input).map_err(|e| report(&input, &e))?;
+
+    match interpreter.call("main", &[]).map_err(|e| e.to_string())? {
+        Value::Null => {}
+        value => println!("{}", value),
+    }
+    Ok(())
+}
+
+/// `kennedy repl`
+fn repl(args: &[String]) -> Result<(), String> {
+    let mut options = CompilerOptions::new();
+    for arg in args {
+        match arg.as_str() {
+            "--checked" => options = options.overflow_mode(OverflowMode::Checked),
+            arg => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+
+    let mut repl = Repl::new(options).map_err(|e| e.to_string())?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        // lines are read until the input's brackets are closed
+        let mut input = String::new();
+        loop {
+            print!("{}", if input.is_empty() { "> " } else { "... " });
+            io::stdout().flush().map_err(|e| e.to_string())?;
+
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+            input.push_str(&line.map_err(|e| e.to_string())?);
+            input.push('\n');
+
+            if !Repl::is_incomplete(&input) {
+                break;
+            }
+        }
+
+        match repl.eval(&input) {
+            Ok(evaluated) => {
+                for warning in &evaluated.warnings {
+                    eprintln!("{}", warning.to_string_with_source(&input));
+                }
+                for value in &evaluated.values {
+                    println!("{}", value);
+                }
+                if let Some(trap) = &evaluated.trap {
+                    eprintln!("{}", trap);
+                }
+            }
+            Err(error) => eprintln!("{}", error.to_string_with_source(&input)),
+        }
+    }
+}
+
+/// The argument following an option
+fn value<'a>(args: &mut impl Iterator<Item = &'a String>, option: &str) -> Result<&'a str, String> {
+    args.next().map(String::as_str).ok_or_else(|| format!("`{}` needs a value", option))
+}
+
+fn opt_level(level: &str) -> Result<OptLevel, String> {
+    match level {
+        "none" => Ok(OptLevel::None),
+        "speed" => Ok(OptLevel::Speed),
+        "speed_and_size" => Ok(OptLevel::SpeedAndSize),
+        _ => Err(format!("unknown optimization level `{}`", level)),
+    }
+}
+
+/// An error pointing into the source it's about
+fn report(path: &Path, error: &CompileError) -> String {
+    match fs::read_to_string(path) {
+        Ok(source) => error.to_string_with_source(&source),
+        Err(_) => error.to_string(),
+    }
+}
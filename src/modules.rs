@@ -0,0 +1,724 @@
+//! Programs made of several files
+//!
+//! `import math;` (or `import "lib/math.ken";`) loads `math.ken` from the
+//! importing file's directory. Its `pub` functions, globals, structs and
+//! enums can then be used as `math::add`, `math::PI`, `math::Point` and
+//! `math::Shape::Circle`.
+//!
+//! Every file is parsed, then the modules are merged into one program in
+//! which what a module declares is named after the module (`math::add`).
+//! The root file's names are left as written, as are extern functions,
+//! which are looked up by their C names. Names are resolved per
+//! module, so each module has its own namespace.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Block, Enum, Expression, ExternFunction, Function, Global, MatchBody, Pattern, Program, Statement, Struct, Type};
+use crate::compiler::symbol_table::SymbolTable;
+use crate::error::{CompileError, CompileResult, Span};
+use crate::lexer::{lex, Token};
+use crate::parser::Parser;
+
+/// Every file of a program, laid end to end so that a span identifies the
+/// file it's in as well as where it is in that file
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+pub struct SourceFile {
+    /// How errors refer to the file, `None` for source given as a string
+    pub name: Option<String>,
+    pub text: String,
+    /// Where the file starts, in characters
+    start: usize,
+}
+
+impl SourceMap {
+    /// Add a file after the others, returning its index
+    pub fn add(&mut self, name: Option<String>, text: String) -> usize {
+        // leave a gap, so the end of one file isn't the start of the next
+        let start = self.files.last().map_or(0, |file| file.start + file.text.chars().count() + 1);
+        self.files.push(SourceFile { name, text, start });
+        self.files.len() - 1
+    }
+
+    /// Add a file after the others and lex it, with the tokens' spans
+    /// relative to the map rather than the file
+    pub(crate) fn lex(&mut self, name: Option<String>, text: String) -> CompileResult<Vec<Token>> {
+        let file = self.add(name, text.clone());
+        let start = self.files[file].start;
+
+        let mut tokens = lex(text).map_err(|error| match error {
+            CompileError::SyntaxError(message, span) => CompileError::SyntaxError(message, offset(&span, start, false)),
+            error => error,
+        })?;
+        for token in &mut tokens {
+            token.span = offset(&token.span, start, false);
+        }
+
+        Ok(tokens)
+    }
+
+    /// The file a span is in
+    fn file(&self, span: &Span) -> &SourceFile {
+        self.files.iter().rev()
+            .find(|file| file.start <= span.start)
+            .unwrap_or(&self.files[0])
+    }
+
+    /// 1-based line and column of the start of a span, within its file
+    pub fn location(&self, span: &Span) -> (usize, usize) {
+        let file = self.file(span);
+        offset(span, file.start, true).location(&file.text)
+    }
+
+    /// Point an error at the file it's in, with the span relative to that
+    /// file
+    pub fn attribute(&self, error: CompileError) -> CompileError {
+        let span = match &error {
+            CompileError::SyntaxError(_, span)
+            | CompileError::SemanticError(_, span)
+            | CompileError::Warning(_, span) => span,
+            CompileError::CompileError(_) | CompileError::InFile { .. } => return error,
+        };
+
+        let file = self.file(span);
+        let error = match error {
+            CompileError::SyntaxError(message, span) => {
+                CompileError::SyntaxError(message, offset(&span, file.start, true))
+            }
+            CompileError::SemanticError(message, span) => {
+                CompileError::SemanticError(message, offset(&span, file.start, true))
+            }
+            CompileError::Warning(message, span) => {
+                CompileError::Warning(message, offset(&span, file.start, true))
+            }
+            _ => unreachable!(),
+        };
+
+        self.in_file(file, error)
+    }
+
+    /// Wrap an error whose span (if any) is relative to `file` with the
+    /// file's name and text
+    fn in_file(&self, file: &SourceFile, error: CompileError) -> CompileError {
+        match &file.name {
+            Some(name) => CompileError::InFile {
+                path: name.clone(),
+                source: file.text.clone(),
+                error: Box::new(error),
+            },
+            None => error,
+        }
+    }
+}
+
+/// Move a span `by` characters later, or earlier if `back`
+fn offset(span: &Span, by: usize, back: bool) -> Span {
+    if back {
+        Span { start: span.start - by, end: span.end - by }
+    } else {
+        Span { start: span.start + by, end: span.end + by }
+    }
+}
+
+/// A parsed file
+struct Module {
+    /// Index of its file in the source map
+    file: usize,
+    /// What the names it declares are prefixed with, empty for the root
+    prefix: String,
+    program: Program,
+    /// Modules it imports, by the name they're imported as
+    imports: HashMap<String, usize>,
+}
+
+/// Loads a file and everything it imports, depth first
+struct Loader<'a> {
+    sources: &'a mut SourceMap,
+    /// Directory the root file is in, which file names are shown relative to
+    root: PathBuf,
+    modules: Vec<Module>,
+    /// Index of each module loaded, by canonical path
+    loaded: HashMap<PathBuf, usize>,
+    /// Files being loaded, each imported by the one before it
+    stack: Vec<PathBuf>,
+    prefixes: HashSet<String>,
+}
+
+/// Load the program in `source` and every module it imports, merged into
+/// one program
+/// `path` is the file `source` came from; imports in source given as a
+/// string are relative to the working directory
+pub fn load(path: Option<&Path>, source: &str, sources: &mut SourceMap) -> CompileResult<Program> {
+    let path = path.map(|path| path.canonicalize().map_err(|e| {
+        CompileError::CompileError(format!("Cannot read `{}`: {}", path.display(), e))
+    })).transpose()?;
+
+    let root = match &path {
+        Some(path) => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        None => std::env::current_dir().and_then(|dir| dir.canonicalize()).unwrap_or_default(),
+    };
+
+    let mut loader = Loader {
+        sources,
+        root,
+        modules: Vec::new(),
+        loaded: HashMap::new(),
+        stack: Vec::new(),
+        prefixes: HashSet::new(),
+    };
+
+    loader.load_module(path, source.to_string(), String::new())?;
+    loader.merge()
+}
+
+impl<'a> Loader<'a> {
+    /// Parse a file and load its imports, returning its module's index
+    fn load_module(&mut self, path: Option<PathBuf>, text: String, prefix: String) -> CompileResult<usize> {
+        let name = path.as_ref().map(|path| {
+            path.strip_prefix(&self.root).unwrap_or(path).display().to_string()
+        });
+        let tokens = self.sources.lex(name, text)?;
+        let file = self.sources.files.len() - 1;
+
+        let program = Parser::new(tokens).parse()?;
+
+        // a file given as a string imports from the working directory
+        let directory = path.as_deref().and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+
+        if let Some(path) = &path {
+            self.stack.push(path.clone());
+        }
+
+        let mut imports = HashMap::new();
+        for import in &program.imports {
+            let index = self.import(&directory, &import.module, &import.path, &import.span)?;
+            imports.insert(import.module.clone(), index);
+        }
+
+        if let Some(path) = &path {
+            self.stack.pop();
+            self.loaded.insert(path.clone(), self.modules.len());
+        }
+
+        self.modules.push(Module { file, prefix, program, imports });
+        Ok(self.modules.len() - 1)
+    }
+
+    /// Load the module at `path`, relative to `directory`, unless it's
+    /// already loaded
+    fn import(&mut self, directory: &Path, module: &str, path: &str, span: &Span) -> CompileResult<usize> {
+        let canonical = directory.join(path).canonicalize().map_err(|e| CompileError::SemanticError(
+            format!("Cannot import `{}`: {}", path, e),
+            span.clone(),
+        ))?;
+
+        if let Some(i) = self.stack.iter().position(|loading| *loading == canonical) {
+            let cycle: Vec<String> = self.stack[i..].iter()
+                .chain([&canonical])
+                .map(|path| path.file_name().unwrap_or_default().to_string_lossy().to_string())
+                .collect();
+
+            return Err(CompileError::SemanticError(
+                format!("Import cycle: {}", cycle.join(" -> ")),
+                span.clone(),
+            ));
+        }
+
+        if let Some(index) = self.loaded.get(&canonical) {
+            return Ok(*index);
+        }
+
+        let text = fs::read_to_string(&canonical).map_err(|e| CompileError::SemanticError(
+            format!("Cannot import `{}`: {}", path, e),
+            span.clone(),
+        ))?;
+
+        // different files may be imported under the same name
+        let mut prefix = module.to_string();
+        let mut n = 1;
+        while !self.prefixes.insert(prefix.clone()) {
+            n += 1;
+            prefix = format!("{}{}", module, n);
+        }
+
+        self.load_module(Some(canonical), text, prefix)
+    }
+
+    /// Resolve the names in every module and put them together
+    fn merge(self) -> CompileResult<Program> {
+        let namespaces: Vec<Namespace> = self.modules.iter().map(Namespace::of).collect();
+        let mut merged = Program {
+            imports: Vec::new(),
+            globals: Vec::new(),
+            externs: Vec::new(),
+            functions: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
+        };
+
+        for (index, module) in self.modules.into_iter().enumerate() {
+            let mut resolver = Resolver {
+                namespaces: &namespaces,
+                imports: &module.imports,
+                module: index,
+                variables: SymbolTable::new(),
+                type_params: Vec::new(),
+            };
+
+            let mut program = module.program;
+            resolver.resolve(&mut program).map_err(|error| match error {
+                // errors without a span can't be attributed later
+                CompileError::CompileError(_) => {
+                    self.sources.in_file(&self.sources.files[module.file], error)
+                }
+                error => error,
+            })?;
+
+            merged.globals.extend(program.globals);
+            merged.externs.extend(program.externs);
+            merged.functions.extend(program.functions);
+            merged.structs.extend(program.structs);
+            merged.enums.extend(program.enums);
+        }
+
+        Ok(merged)
+    }
+}
+
+/// Name of something declared in the module with this prefix, once the
+/// modules are merged
+fn global(prefix: &str, ident: &str) -> String {
+    if prefix.is_empty() {
+        ident.to_string()
+    } else {
+        format!("{}::{}", prefix, ident)
+    }
+}
+
+/// What a name declared by a module refers to in the merged program
+struct Item {
+    /// What sort of thing it is, for error messages
+    kind: &'static str,
+    global: String,
+    public: bool,
+}
+
+/// Names a module declares
+struct Namespace {
+    /// Functions and globals
+    values: HashMap<String, Item>,
+    /// Structs and enums
+    types: HashMap<String, Item>,
+}
+
+impl Namespace {
+    fn of(module: &Module) -> Namespace {
+        let item = |kind, ident: &str, public| {
+            (ident.to_string(), Item { kind, global: global(&module.prefix, ident), public })
+        };
+        let program = &module.program;
+
+        Namespace {
+            values: program.functions.iter().map(|function| item("Function", &function.ident, function.public))
+                .chain(program.globals.iter().map(|global| item("Global", &global.ident, global.public)))
+                .chain(program.externs.iter().map(|function| (function.ident.clone(), Item {
+                    kind: "Extern function",
+                    global: function.ident.clone(),
+                    public: function.public,
+                })))
+                .collect(),
+            types: program.structs.iter().map(|declaration| item("Struct", &declaration.ident, declaration.public))
+                .chain(program.enums.iter().map(|declaration| item("Enum", &declaration.ident, declaration.public)))
+                .collect(),
+        }
+    }
+}
+
+/// Rewrites the names a module uses to the names in the merged program,
+/// enforcing visibility
+struct Resolver<'a> {
+    namespaces: &'a [Namespace],
+    imports: &'a HashMap<String, usize>,
+    /// Index of the module being resolved
+    module: usize,
+    /// Variables in scope, which are never qualified
+    variables: SymbolTable<String, ()>,
+    /// Type parameters of the function being resolved
+    type_params: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+    fn resolve(&mut self, program: &mut Program) -> CompileResult<()> {
+        for global in &mut program.globals {
+            self.resolve_global(global)?;
+        }
+
+        for function in &mut program.externs {
+            self.resolve_extern(function)?;
+        }
+
+        for declaration in &mut program.structs {
+            self.resolve_struct(declaration)?;
+        }
+
+        for declaration in &mut program.enums {
+            self.resolve_enum(declaration)?;
+        }
+
+        for function in &mut program.functions {
+            self.resolve_function(function)?;
+        }
+
+        Ok(())
+    }
+
+    /// Look up `ident`, which may be qualified with an imported module, in
+    /// the values or types of a namespace
+    /// `kind` says what was looked for, should it be missing
+    fn lookup(
+        &self,
+        ident: &str,
+        kind: &str,
+        items: impl Fn(&Namespace) -> &HashMap<String, Item>,
+        span: Option<&Span>,
+    ) -> CompileResult<Option<String>> {
+        let error = |message: String| match span {
+            Some(span) => CompileError::SemanticError(message, span.clone()),
+            None => CompileError::CompileError(message),
+        };
+
+        let Some((module, ident)) = ident.split_once("::") else {
+            return Ok(items(&self.namespaces[self.module]).get(ident).map(|item| item.global.clone()));
+        };
+
+        let Some(index) = self.imports.get(module) else {
+            return Ok(None);
+        };
+
+        match items(&self.namespaces[*index]).get(ident) {
+            Some(item) if item.public => Ok(Some(item.global.clone())),
+            Some(item) => Err(error(format!("{} `{}` of module `{}` is not `pub`", item.kind, ident, module))),
+            None => Err(error(format!("Module `{}` has no {} `{}`", module, kind, ident))),
+        }
+    }
+
+    fn resolve_type_ident(&self, ident: &mut String, span: Option<&Span>) -> CompileResult<()> {
+        if self.type_params.contains(ident) {
+            return Ok(());
+        }
+
+        if let Some(global) = self.lookup(ident, "type", |namespace| &namespace.types, span)? {
+            *ident = global;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_type(&self, ty: &mut Type) -> CompileResult<()> {
+        match ty {
+            Type::Named(ident) => self.resolve_type_ident(ident, None),
+            Type::Array(element) | Type::FixedArray(element, _) => self.resolve_type(element),
+            Type::Function(params, return_type) => {
+                for param in params {
+                    self.resolve_type(param)?;
+                }
+                self.resolve_type(return_type)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn resolve_struct(&mut self, declaration: &mut Struct) -> CompileResult<()> {
+        self.resolve_type_ident(&mut declaration.ident, None)?;
+
+        for field in &mut declaration.fields {
+            self.resolve_type(&mut field.field_type)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_enum(&mut self, declaration: &mut Enum) -> CompileResult<()> {
+        self.resolve_type_ident(&mut declaration.ident, None)?;
+
+        for variant in &mut declaration.variants {
+            for field in &mut variant.fields {
+                self.resolve_type(field)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_global(&mut self, global: &mut Global) -> CompileResult<()> {
+        self.type_params.clear();
+        self.variables = SymbolTable::new();
+
+        if let Some(resolved) = self.lookup(&global.ident, "global", |namespace| &namespace.values, None)? {
+            global.ident = resolved;
+        }
+
+        if let Some(global_type) = &mut global.global_type {
+            self.resolve_type(global_type)?;
+        }
+
+        self.resolve_expression(&mut global.value)
+    }
+
+    fn resolve_extern(&mut self, function: &mut ExternFunction) -> CompileResult<()> {
+        self.type_params.clear();
+
+        self.resolve_type(&mut function.return_type)?;
+        for param in &mut function.params.params {
+            self.resolve_type(&mut param.param_type)?;
+        }
+
+        Ok(())
+    }
+
+    fn resolve_function(&mut self, function: &mut Function) -> CompileResult<()> {
+        self.type_params = function.type_params.iter().map(|type_param| type_param.ident.clone()).collect();
+        self.variables = SymbolTable::new();
+
+        if let Some(global) = self.lookup(&function.ident, "function", |namespace| &namespace.values, None)? {
+            function.ident = global;
+        }
+
+        self.resolve_type(&mut function.return_type)?;
+        for param in &mut function.params.params {
+            self.resolve_type(&mut param.param_type)?;
+            self.variables.insert(param.ident.clone(), ());
+        }
+
+        self.resolve_block(&mut function.body)
+    }
+
+    fn resolve_block(&mut self, block: &mut Block) -> CompileResult<()> {
+        self.variables.push_scope();
+        for statement in &mut block.statements {
+            self.resolve_statement(statement)?;
+        }
+        self.variables.pop_scope();
+
+        Ok(())
+    }
+
+    fn resolve_statement(&mut self, statement: &mut Statement) -> CompileResult<()> {
+        match statement {
+            Statement::VariableDeclaration { ident, var_type, value, .. } => {
+                if let Some(var_type) = var_type {
+                    self.resolve_type(var_type)?;
+                }
+                self.resolve_expression(value)?;
+                self.variables.insert(ident.clone(), ());
+            }
+            Statement::Assign { value, .. } => self.resolve_expression(value)?,
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.resolve_expression(value)?;
+                }
+            }
+            Statement::Block { block, .. } => self.resolve_block(block)?,
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                self.resolve_expression(condition)?;
+                self.resolve_statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    self.resolve_statement(else_branch)?;
+                }
+            }
+            Statement::While { condition, body, .. } => {
+                self.resolve_expression(condition)?;
+                self.resolve_block(body)?;
+            }
+            Statement::Expression { expression, .. } => self.resolve_expression(expression)?,
+            Statement::DoUntil { condition, body, .. } => {
+                self.resolve_statement(body)?;
+                self.resolve_expression(condition)?;
+            }
+            Statement::For { init, condition, increment, body, .. } => {
+                self.variables.push_scope();
+                self.resolve_statement(init)?;
+                self.resolve_expression(condition)?;
+                self.resolve_statement(increment)?;
+                self.resolve_block(body)?;
+                self.variables.pop_scope();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn resolve_expression(&mut self, expr: &mut Expression) -> CompileResult<()> {
+        match expr {
+            Expression::IntegerLiteral { .. }
+            | Expression::FloatLiteral { .. }
+            | Expression::StringLiteral { .. }
+            | Expression::BooleanLiteral { .. }
+            | Expression::NullLiteral { .. } => {}
+
+            Expression::Identifier { ident, span } => {
+                // variables shadow functions and globals
+                if self.variables.get(ident).is_none() {
+                    if let Some(global) = self.lookup(ident, "function or global", |namespace| &namespace.values, Some(span))? {
+                        *ident = global;
+                    }
+                }
+            }
+            Expression::Binary { left, right, .. } | Expression::Assign { left, right, .. } => {
+                self.resolve_expression(left)?;
+                self.resolve_expression(right)?;
+            }
+            Expression::Unary { right, .. } | Expression::Prefix { right, .. } => self.resolve_expression(right)?,
+            Expression::Postfix { left, .. } => self.resolve_expression(left)?,
+            Expression::Grouping { expression, .. } => self.resolve_expression(expression)?,
+            Expression::Function { params, return_type, body, .. } => {
+                self.resolve_type(return_type)?;
+
+                self.variables.push_scope();
+                for param in &mut params.params {
+                    self.resolve_type(&mut param.param_type)?;
+                    self.variables.insert(param.ident.clone(), ());
+                }
+                self.resolve_block(body)?;
+                self.variables.pop_scope();
+            }
+            Expression::Call { callee, arguments, .. } => {
+                self.resolve_expression(callee)?;
+                for argument in arguments {
+                    self.resolve_expression(argument)?;
+                }
+            }
+            Expression::Cast { expression, target_type, .. } => {
+                self.resolve_expression(expression)?;
+                self.resolve_type(target_type)?;
+            }
+            Expression::Index { target, index, .. } => {
+                self.resolve_expression(target)?;
+                self.resolve_expression(index)?;
+            }
+            Expression::Slice { target, start, end, .. } => {
+                self.resolve_expression(target)?;
+                for bound in [start, end].into_iter().flatten() {
+                    self.resolve_expression(bound)?;
+                }
+            }
+            Expression::ArrayLiteral { elements, .. } => {
+                for element in elements {
+                    self.resolve_expression(element)?;
+                }
+            }
+            Expression::StructLiteral { ident, fields, span } => {
+                self.resolve_type_ident(ident, Some(span))?;
+                for (_, value) in fields {
+                    self.resolve_expression(value)?;
+                }
+            }
+            Expression::Field { target, .. } => self.resolve_expression(target)?,
+            Expression::Variant { enum_ident, arguments, span, .. } => {
+                self.resolve_type_ident(enum_ident, Some(span))?;
+                for argument in arguments {
+                    self.resolve_expression(argument)?;
+                }
+            }
+            Expression::Match { scrutinee, arms, .. } => {
+                self.resolve_expression(scrutinee)?;
+
+                for arm in arms {
+                    self.variables.push_scope();
+
+                    if let Pattern::Variant { enum_ident, bindings, span, .. } = &mut arm.pattern {
+                        if let Some(enum_ident) = enum_ident {
+                            self.resolve_type_ident(enum_ident, Some(span))?;
+                        }
+                        for binding in bindings.iter() {
+                            self.variables.insert(binding.clone(), ());
+                        }
+                    }
+
+                    match &mut arm.body {
+                        MatchBody::Expression(body) => self.resolve_expression(body)?,
+                        MatchBody::Block(body) => self.resolve_block(body)?,
+                    }
+
+                    self.variables.pop_scope();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory with these files in it
+    fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kennedy-modules-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        for (file, text) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+
+        dir
+    }
+
+    fn load_file(path: &Path) -> CompileResult<Program> {
+        let source = fs::read_to_string(path).unwrap();
+        let mut sources = SourceMap::default();
+        load(Some(path), &source, &mut sources).map_err(|error| sources.attribute(error))
+    }
+
+    #[test]
+    fn test_load_and_resolve() {
+        let dir = project("resolve", &[
+            ("main.ken", "import \"lib/geometry.ken\";\nfunc main(): float {\n    let p = geometry::Point { x: 1.0, y: 2.0 };\n    return geometry::norm(p);\n}"),
+            ("lib/geometry.ken", "import util;\npub struct Point { x: float, y: float }\npub func norm(p: Point): float {\n    return util::square(p.x) + square(p.y);\n}\nfunc square(x: float): float { return x; }"),
+            ("lib/util.ken", "pub func square(x: float): float { return x * x; }"),
+        ]);
+
+        let program = load_file(&dir.join("main.ken")).unwrap();
+
+        let idents: HashSet<&str> = program.functions.iter().map(|function| function.ident.as_str()).collect();
+        assert_eq!(idents, HashSet::from(["main", "geometry::norm", "geometry::square", "util::square"]));
+
+        let norm = program.functions.iter().find(|function| function.ident == "geometry::norm").unwrap();
+        assert_eq!(norm.params.params[0].param_type, Type::Named("geometry::Point".to_string()));
+        assert_eq!(program.structs[0].ident, "geometry::Point");
+    }
+
+    #[test]
+    fn test_visibility_and_cycles() {
+        let dir = project("errors", &[
+            ("private.ken", "import hidden;\nfunc main(): int {\n    return hidden::secret();\n}"),
+            ("hidden.ken", "func secret(): int { return 1; }"),
+            ("a.ken", "import b;\nfunc main(): int { return 0; }"),
+            ("b.ken", "import a;"),
+            ("broken.ken", "import bad;"),
+            ("bad.ken", "\nfunc f(): int { return 1 }"),
+        ]);
+
+        let error = load_file(&dir.join("private.ken")).unwrap_err();
+        let message = error.to_string_with_source("");
+        assert!(message.starts_with("private.ken: Semantic error at 3:12"), "{}", message);
+        assert!(message.contains("Function `secret` of module `hidden` is not `pub`"), "{}", message);
+
+        let error = load_file(&dir.join("a.ken")).unwrap_err();
+        assert!(error.to_string().contains("Import cycle: a.ken -> b.ken -> a.ken"), "{}", error);
+
+        // the syntax error is reported against the imported file
+        let error = load_file(&dir.join("broken.ken")).unwrap_err();
+        let message = error.to_string_with_source("");
+        assert!(message.starts_with("bad.ken: Syntax error at 2:26"), "{}", message);
+    }
+}
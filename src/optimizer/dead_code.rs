@@ -0,0 +1,160 @@
+//! Removing statements that never run, and warning about the conditions
+//! that keep them from running
+
+use crate::ast::visit::{walk_statement, walk_statement_mut};
+use crate::ast::{Block, Expression, Program, Statement, Visitor, VisitorMut};
+use crate::compiler::OverflowMode;
+use crate::error::{CompileError, Span};
+
+use super::{constant_condition, Change};
+
+/// Remove statements after ones that always return, branches that are
+/// never taken and loops that are never entered
+pub fn eliminate(program: &mut Program, overflow_mode: OverflowMode) -> Vec<Change> {
+    let mut eliminator = Eliminator { overflow_mode, changes: Vec::new() };
+    eliminator.visit_program_mut(program);
+    eliminator.changes
+}
+
+/// A warning for every condition of an `if` or loop that is always true or
+/// always false
+/// Loops written to run until they return, like `while (true)`, are fine
+pub fn constant_conditions(program: &Program, overflow_mode: OverflowMode) -> Vec<CompileError> {
+    let mut finder = ConditionFinder { overflow_mode, warnings: Vec::new() };
+    finder.visit_program(program);
+    finder.warnings
+}
+
+struct Eliminator {
+    overflow_mode: OverflowMode,
+    changes: Vec<Change>,
+}
+
+impl VisitorMut for Eliminator {
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        let mut statements = Vec::new();
+
+        for statement in std::mem::take(&mut block.statements) {
+            if let Some(mut statement) = self.simplify(statement) {
+                walk_statement_mut(self, &mut statement);
+                statements.push(statement);
+            }
+        }
+
+        if let Some(last) = statements.iter().position(returns) {
+            let unreachable = statements.split_off(last + 1);
+
+            if let (Some(first), Some(end)) = (unreachable.first(), unreachable.last()) {
+                let span = Span { start: first.span().start, end: end.span().end };
+                self.changes.push(Change::Unreachable { span });
+            }
+        }
+
+        block.statements = statements;
+    }
+
+    /// Statements that aren't in a block, like the branches of an `if`,
+    /// become an empty block when removed
+    fn visit_statement_mut(&mut self, statement: &mut Statement) {
+        let span = statement.span().clone();
+        let empty = Statement::Block { block: Box::new(Block { statements: Vec::new(), span: span.clone() }), span };
+
+        let original = std::mem::replace(statement, empty.clone());
+        *statement = self.simplify(original).unwrap_or(empty);
+        walk_statement_mut(self, statement);
+    }
+}
+
+impl Eliminator {
+    /// What runs of a statement with a constant condition, or `None` if
+    /// nothing does
+    fn simplify(&mut self, statement: Statement) -> Option<Statement> {
+        let condition = match &statement {
+            Statement::If { condition, .. }
+            | Statement::While { condition, .. }
+            | Statement::DoUntil { condition, .. }
+            | Statement::For { condition, .. } => condition,
+            _ => return Some(statement),
+        };
+
+        let Some(value) = constant_condition(condition, self.overflow_mode) else {
+            return Some(statement);
+        };
+
+        let span = statement.span().clone();
+        let runs = match statement {
+            Statement::If { then_branch, else_branch, .. } => {
+                let taken = if value { Some(then_branch) } else { else_branch };
+                taken.map(|branch| *branch)
+            }
+
+            // loops that end straight away, having run their body once or
+            // not at all
+            Statement::While { .. } if !value => None,
+            Statement::DoUntil { body, .. } if value => Some(*body),
+            // the loop variable stays out of scope of what follows
+            Statement::For { init, .. } if !value => Some(Statement::Block {
+                block: Box::new(Block { statements: vec![*init], span: span.clone() }),
+                span: span.clone(),
+            }),
+
+            // loops that run until they return
+            statement => return Some(statement),
+        };
+
+        self.changes.push(Change::ConstantCondition { value, span });
+
+        // what's left may have a constant condition of its own
+        runs.and_then(|statement| self.simplify(statement))
+    }
+}
+
+/// Whether a statement always returns, so nothing after it runs
+fn returns(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return { .. } => true,
+        Statement::Block { block, .. } => block.statements.iter().any(returns),
+        Statement::If { then_branch, else_branch: Some(else_branch), .. } => {
+            returns(then_branch) && returns(else_branch)
+        }
+        // the body runs at least once
+        Statement::DoUntil { body, .. } => returns(body),
+        _ => false,
+    }
+}
+
+struct ConditionFinder {
+    overflow_mode: OverflowMode,
+    warnings: Vec<CompileError>,
+}
+
+impl Visitor for ConditionFinder {
+    fn visit_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::If { condition, .. } => self.check(condition, None),
+            Statement::While { condition, .. } | Statement::For { condition, .. } => self.check(condition, Some(true)),
+            Statement::DoUntil { condition, .. } => self.check(condition, Some(false)),
+            _ => {}
+        }
+
+        walk_statement(self, statement);
+    }
+}
+
+impl ConditionFinder {
+    /// `forever` is the value that keeps a loop going
+    fn check(&mut self, condition: &Expression, forever: Option<bool>) {
+        let Some(value) = constant_condition(condition, self.overflow_mode) else {
+            return;
+        };
+
+        if forever == Some(value) && matches!(condition, Expression::BooleanLiteral { .. }) {
+            return;
+        }
+
+        self.warnings.push(CompileError::Warning(
+            format!("Condition is always {}", value),
+            condition.span().clone(),
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+//! Constant folding: replacing expressions made of literals with their
+//! values
+//!
+//! An integer literal takes its type from its context, so `2 * 3` may be
+//! an `int` or a `u8`, and `200 + 100` wraps in one but not the other. The
+//! type checker has worked out which it is, and the expression is evaluated
+//! as that type, exactly as the compiled code would. The literal it's
+//! replaced with takes the same type from the same context, unless a cast
+//! fixed the type, which the replacement keeps.
+
+use std::collections::HashMap;
+
+use crate::ast::visit::{walk_expression_mut, walk_function_mut};
+use crate::ast::{Expression, Function, Global, Program, Span, UnaryOperator, VisitorMut};
+use crate::compiler::OverflowMode;
+use crate::constant::{Constant, Evaluator};
+use crate::type_checking::TypeChecker;
+
+use super::Change;
+
+/// Fold every expression of the program's functions that can be
+/// Generic functions are left alone, as their expressions have a type
+/// per instance; so are globals, which are evaluated when compiled anyway
+pub fn fold(program: &mut Program, checker: &TypeChecker, overflow_mode: OverflowMode) -> Vec<Change> {
+    let constants = HashMap::new();
+    let mut folder = Folder {
+        checker,
+        evaluator: Evaluator { constants: &constants, overflow_mode },
+        owner: String::new(),
+        changes: Vec::new(),
+    };
+
+    folder.visit_program_mut(program);
+    folder.changes
+}
+
+struct Folder<'a> {
+    checker: &'a TypeChecker,
+    evaluator: Evaluator<'a>,
+    /// Function whose expressions are being folded
+    owner: String,
+    changes: Vec<Change>,
+}
+
+impl VisitorMut for Folder<'_> {
+    fn visit_global_mut(&mut self, _global: &mut Global) {}
+
+    fn visit_function_mut(&mut self, function: &mut Function) {
+        if function.is_generic() {
+            return;
+        }
+
+        self.owner = function.ident.clone();
+        walk_function_mut(self, function);
+    }
+
+    fn visit_expression_mut(&mut self, expression: &mut Expression) {
+        match self.fold(expression) {
+            Some(folded) => {
+                self.changes.push(Change::Folded {
+                    from: expression.to_string(),
+                    to: folded.to_string(),
+                    span: expression.span().clone(),
+                });
+                *expression = folded;
+            }
+            None => walk_expression_mut(self, expression),
+        }
+    }
+}
+
+impl Folder<'_> {
+    /// The value of an expression made of literals, as a literal
+    /// `None` if it isn't one, or evaluating it would trap
+    fn fold(&self, expression: &Expression) -> Option<Expression> {
+        if !is_constant(expression) || is_literal(expression) {
+            return None;
+        }
+
+        let ty = self.checker.expression_type(&self.owner, expression.span())?;
+        let (value, value_type) = self.evaluator.evaluate(expression, Some(ty)).ok()?;
+        if !value_type.same_as(ty) {
+            return None;
+        }
+
+        let span = expression.span().clone();
+        let folded = match value {
+            Constant::Integer(value) if has_cast(expression) => Expression::Cast {
+                expression: Box::new(integer_literal(value, &span)?),
+                target_type: ty.clone(),
+                span,
+            },
+            Constant::Integer(value) => integer_literal(value, &span)?,
+            // there's no literal for infinity or NaN
+            Constant::Float(value) if !value.is_finite() => return None,
+            Constant::Float(value) => Expression::FloatLiteral { value: value as f64, span },
+            Constant::F64(value) if !value.is_finite() => return None,
+            // a cast of a literal would go through `float`, losing precision
+            Constant::F64(_) if has_cast(expression) => return None,
+            Constant::F64(value) => Expression::FloatLiteral { value, span },
+            Constant::Bool(value) => Expression::BooleanLiteral { value, span },
+            Constant::String(value) => Expression::StringLiteral { value, span },
+        };
+
+        // casts of literals are as folded as they get
+        (folded.to_string() != expression.to_string()).then_some(folded)
+    }
+}
+
+/// Whether an expression is made of literals, operators and casts
+fn is_constant(expression: &Expression) -> bool {
+    match expression {
+        Expression::IntegerLiteral { .. }
+        | Expression::FloatLiteral { .. }
+        | Expression::StringLiteral { .. }
+        | Expression::BooleanLiteral { .. } => true,
+        Expression::Grouping { expression, .. } | Expression::Cast { expression, .. } => is_constant(expression),
+        Expression::Unary { right, .. } => is_constant(right),
+        Expression::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+/// Whether an expression is a literal, maybe negated or parenthesised
+fn is_literal(expression: &Expression) -> bool {
+    match expression {
+        Expression::FloatLiteral { .. } | Expression::StringLiteral { .. } | Expression::BooleanLiteral { .. } => true,
+        Expression::Grouping { expression, .. } => is_literal(expression),
+        _ => expression.is_integer_literal(),
+    }
+}
+
+/// Whether the type of an expression is fixed by a cast, rather than
+/// taken from its context
+fn has_cast(expression: &Expression) -> bool {
+    match expression {
+        Expression::Cast { .. } => true,
+        Expression::Grouping { expression, .. } => has_cast(expression),
+        Expression::Unary { right, .. } => has_cast(right),
+        Expression::Binary { left, right, .. } => has_cast(left) || has_cast(right),
+        _ => false,
+    }
+}
+
+/// An integer literal, negated if need be
+/// `None` for values past the range of `u64` literals, which can't be
+/// written
+fn integer_literal(value: i128, span: &Span) -> Option<Expression> {
+    u64::try_from(value.abs()).ok()?;
+    let literal = Expression::IntegerLiteral { value: value.abs(), span: span.clone() };
+
+    if value < 0 {
+        Some(Expression::Unary { operator: UnaryOperator::Minus, right: Box::new(literal), span: span.clone() })
+    } else {
+        Some(literal)
+    }
+}
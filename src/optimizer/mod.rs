@@ -0,0 +1,274 @@
+//! Simplifying programs before they're compiled
+//!
+//! Cranelift optimizes the code it generates, but some things are easier to
+//! see in the syntax tree: expressions made of nothing but literals, code
+//! after a `return`, and branches whose condition is always the same. Each
+//! pass rewrites an [`ast::Program`](crate::ast::Program) in place and
+//! reports what it changed.
+//!
+//! No pass changes what a program does. Folding follows the rules compiled
+//! code follows, so an expression that overflows in checked mode, or divides
+//! by zero, is left for the program to trap on when it runs.
+
+mod dead_code;
+mod fold;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{Expression, Program, Type};
+use crate::compiler::OverflowMode;
+use crate::constant::{Constant, Evaluator};
+use crate::error::{CompileError, CompileResult, Span};
+use crate::type_checking::TypeChecker;
+
+/// Which passes to run, none by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Passes {
+    /// Replace expressions made of literals with their values
+    pub fold_constants: bool,
+    /// Remove statements that never run
+    pub dead_code: bool,
+}
+
+impl Passes {
+    pub fn all() -> Self {
+        Self { fold_constants: true, dead_code: true }
+    }
+}
+
+/// Something a pass changed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// An expression replaced by its value
+    Folded { from: String, to: String, span: Span },
+    /// Statements after one that always returns, removed
+    Unreachable { span: Span },
+    /// A branch or loop whose condition is always `value`, replaced by
+    /// what actually runs
+    ConstantCondition { value: bool, span: Span },
+}
+
+impl Change {
+    /// Where the change was made, in the program as it was
+    pub fn span(&self) -> &Span {
+        match self {
+            Change::Folded { span, .. } | Change::Unreachable { span } | Change::ConstantCondition { span, .. } => span,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Folded { from, to, .. } => write!(f, "folded `{}` into `{}`", from, to),
+            Change::Unreachable { .. } => write!(f, "removed statements after a return"),
+            Change::ConstantCondition { value, .. } => {
+                write!(f, "removed code that never runs, as its condition is always {}", value)
+            }
+        }
+    }
+}
+
+/// What the passes did to a program
+#[derive(Debug, Default)]
+pub struct Report {
+    /// In the order they were made
+    pub changes: Vec<Change>,
+    /// `CompileError::Warning`s about conditions that are always true or
+    /// always false, whichever passes run
+    pub warnings: Vec<CompileError>,
+}
+
+/// Run `passes` over a program, which must type check
+/// The program may not import modules, unless `modules::load` has merged
+/// them in already
+pub fn optimize(program: &mut Program, passes: Passes, overflow_mode: OverflowMode) -> CompileResult<Report> {
+    let mut checker = TypeChecker::new();
+    checker.check_program(program)?;
+
+    Ok(run(program, &checker, passes, overflow_mode))
+}
+
+/// Run `passes` over a program `checker` has checked
+pub(crate) fn run(program: &mut Program, checker: &TypeChecker, passes: Passes, overflow_mode: OverflowMode) -> Report {
+    // warn about conditions as they're written, before any are folded
+    let mut report = Report { changes: Vec::new(), warnings: dead_code::constant_conditions(program, overflow_mode) };
+
+    if passes.dead_code {
+        report.changes.extend(dead_code::eliminate(program, overflow_mode));
+    }
+
+    if passes.fold_constants {
+        report.changes.extend(fold::fold(program, checker, overflow_mode));
+    }
+
+    report
+}
+
+/// Value of a condition made of literals
+/// Conditions that would trap, dividing by zero say, are left to do so
+fn constant_condition(condition: &Expression, overflow_mode: OverflowMode) -> Option<bool> {
+    let constants = HashMap::new();
+    let evaluator = Evaluator { constants: &constants, overflow_mode };
+
+    match evaluator.evaluate(condition, Some(&Type::Bool)) {
+        Ok((Constant::Bool(value), _)) => Some(value),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{Compiler, CompilerOptions};
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    /// The program in `source` after `passes`, and what they did to it
+    fn optimized(source: &str, passes: Passes, overflow_mode: OverflowMode) -> (String, Report) {
+        let mut program = Parser::new(lex(source.to_string()).unwrap()).parse().unwrap();
+        let report = optimize(&mut program, passes, overflow_mode).unwrap();
+        (program.to_string(), report)
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let passes = Passes { fold_constants: true, dead_code: false };
+        let (program, report) = optimized("\
+func f(x: u8): u8 {
+    let a = 2 * 3 + 1;
+    let b: u8 = (200 + 100) / 3 + x;
+    let c = -(4 * 2) as int + (2 as u8 * 3) as int;
+    let d = 1.5 * 2.0 > 2.5 and not false;
+    let e = \"n = \" + (6 * 7) as string;
+    let g = 1 / (2 - 2) + 9223372036854775807 + 1;
+    return b;
+}", passes, OverflowMode::Wrapping);
+
+        assert_eq!(program, "\
+func f(x: u8): u8 {
+    let a = 7;
+    let b: u8 = 14 + x;
+    let c = (-2) as int;
+    let d = true;
+    let e = \"n = 42\";
+    let g = 1 / 0 + 9223372036854775807 + 1;
+    return b;
+}
+", "{}", program);
+
+        assert_eq!(report.changes[0], Change::Folded {
+            from: "2 * 3 + 1".to_string(),
+            to: "7".to_string(),
+            span: Span { start: 32, end: 41 },
+        });
+        assert!(report.warnings.is_empty());
+
+        // in checked mode, an overflow is left to trap when the program runs
+        let (program, _) = optimized(
+            "func f(): i8 { let a: i8 = 100 + 100; return 2 * 3; }",
+            passes,
+            OverflowMode::Checked,
+        );
+        assert!(program.contains("let a: i8 = 100 + 100;"), "{}", program);
+        assert!(program.contains("return 6;"), "{}", program);
+    }
+
+    #[test]
+    fn test_dead_code() {
+        let passes = Passes { fold_constants: false, dead_code: true };
+        let (program, report) = optimized("\
+func f(x: int): int {
+    if (1 > 2) { x = 1; } else if (true) { x = 2; } else { x = 3; }
+    while (false) { x = 4; }
+    for (let i = 0; 1 == 2; i++) { x = 5; }
+    if (x == 2) {
+        return 1;
+    } else {
+        let f = func (y: int): int { return y; y = 6; };
+        return f(x);
+        x = 7;
+    }
+    x = 8;
+    return x;
+}", passes, OverflowMode::Wrapping);
+
+        assert_eq!(program, "\
+func f(x: int): int {
+    {
+        x = 2;
+    }
+    {
+        let i = 0;
+    }
+    if (x == 2) {
+        return 1;
+    } else {
+        let f = func(y: int): int {
+            return y;
+        };
+        return f(x);
+    }
+}
+", "{}", program);
+
+        let changes: Vec<String> = report.changes.iter().map(Change::to_string).collect();
+        assert_eq!(changes, [
+            "removed code that never runs, as its condition is always false",
+            "removed code that never runs, as its condition is always true",
+            "removed code that never runs, as its condition is always false",
+            "removed code that never runs, as its condition is always false",
+            "removed statements after a return",
+            "removed statements after a return",
+            "removed statements after a return",
+        ]);
+    }
+
+    #[test]
+    fn test_constant_condition_warnings() {
+        let (_, report) = optimized("\
+func f(x: int): int {
+    if (1 > 2) { x = 1; }
+    while (true) { if (x > 1) { return x; } x++; }
+    do { x++; } until (false)
+    while (1 == 1) { return 1; }
+    if (1 / 0 == 1) { x = 2; }
+    let g = func (): bool { return true; };
+    for (let i = 0; true; i++) { return i; }
+}", Passes::default(), OverflowMode::Wrapping);
+
+        let warnings: Vec<String> = report.warnings.iter().map(CompileError::to_string).collect();
+        assert_eq!(warnings, [
+            "Warning at Span { start: 30, end: 35 }: Condition is always false",
+            "Warning at Span { start: 140, end: 146 }: Condition is always true",
+        ]);
+        assert!(report.changes.is_empty());
+    }
+
+    /// A program runs the same whichever passes it's compiled with
+    #[test]
+    fn test_same_results() {
+        let source = "\
+func f(x: int): int {
+    let total: u8 = 250 + 10;
+    if (2 * 3 == 6) { total += x as u8; } else { return -1; }
+    while (3 < 2) { total = 0; }
+    let big = 4611686018427387904 * 2 + x;
+    return (total as int) + big / (1 + 2) - (-(9223372036854775807 - 1) - 2) / -1;
+    return 0;
+}";
+        let compile = |passes: Passes| {
+            let mut compiler = Compiler::new(CompilerOptions::new().passes(passes)).unwrap();
+            compiler.compile(source).unwrap();
+            let f: extern "C" fn(i64) -> i64 = unsafe { std::mem::transmute(compiler.get_function("f").unwrap()) };
+            (compiler.optimizer_report().changes.len(), [0, 1, -5, 1000].map(|x| f(x)))
+        };
+
+        let (unchanged, expected) = compile(Passes::default());
+        let (changes, results) = compile(Passes::all());
+        assert_eq!(unchanged, 0);
+        assert!(changes >= 5, "{}", changes);
+        assert_eq!(results, expected);
+    }
+}
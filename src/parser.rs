@@ -1,21 +1,27 @@
 #![allow(dead_code)]
 
+use std::collections::HashSet;
+use std::path::Path;
+
 use crate::ast::{
-    Program, Function, Parameters, Parameter, Block, Statement, Expression, Type,
-    BinaryOperator, UnaryOperator,
+    Program, Import, Global, ExternFunction, Attribute, Function, TypeParam, Bound, Struct, Field, Enum, Variant, Parameters, Parameter, Block, Statement,
+    Expression, Type, MatchArm, MatchBody, Pattern,
+    BinaryOperator, UnaryOperator, PostfixOperator, PrefixOperator, AssignOperator,
 };
 
 use crate::lexer::tokens::{Token, TokenType};
-use crate::error::{CompileError, CompileResult};
+use crate::error::{CompileError, CompileResult, Span};
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    /// Names of the modules imported so far, which can qualify idents
+    modules: HashSet<String>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self { tokens, current: 0, modules: HashSet::new() }
     }
 
     fn is_at_end(&self) -> bool {
@@ -77,6 +83,18 @@ impl Parser {
         }
     }
 
+    /// Return the token after the current one
+    fn peek_next(&self) -> &Token {
+        self.peek_ahead(1)
+    }
+
+    /// Return the token `offset` tokens after the current one, or the
+    /// end of file if there aren't that many
+    fn peek_ahead(&self, offset: usize) -> &Token {
+        let index = (self.current + offset).min(self.tokens.len() - 1);
+        &self.tokens[index]
+    }
+
     /// Return the previous token
     fn previous(&self) -> &Token {
         &self.tokens[self.current - 1]
@@ -84,76 +102,376 @@ impl Parser {
 
     /// Parse a program
     pub fn parse(&mut self) -> CompileResult<Program> {
+        let mut imports: Vec<Import> = Vec::new();
+        let mut globals: Vec<Global> = Vec::new();
+        let mut externs: Vec<ExternFunction> = Vec::new();
         let mut functions: Vec<Function> = Vec::new();
+        let mut structs: Vec<Struct> = Vec::new();
+        let mut enums: Vec<Enum> = Vec::new();
+
+        // imports come first, so a module is known before its names are used
+        while self.match_peek(TokenType::Import) {
+            imports.push(self.parse_import()?);
+        }
 
         while !self.is_at_end() {
-            let function = self.parse_function()?;
-            
-            println!("Function: {:#?}", function);
-            
-            functions.push(function);
-            
-            // print current token
-            println!("Current token: {:?} (pos {})", self.peek(), self.current);
+            let mut attributes = Vec::new();
+            while self.match_peek(TokenType::At) {
+                attributes.push(self.parse_attribute()?);
+            }
+
+            let public = self.match_advance(TokenType::Pub);
+
+            if !attributes.is_empty() && !self.match_peek(TokenType::Function) {
+                return Err(CompileError::SyntaxError(
+                    "Attributes can only be written before functions".to_string(),
+                    self.peek().span.clone(),
+                ));
+            }
+
+            if self.match_peek(TokenType::Let) || self.match_peek(TokenType::Const) {
+                globals.push(self.parse_global(public)?);
+                continue;
+            }
+
+            if self.match_peek(TokenType::Extern) {
+                externs.push(self.parse_extern(public)?);
+                continue;
+            }
+
+            if self.match_peek(TokenType::Struct) {
+                structs.push(self.parse_struct(public)?);
+                continue;
+            }
+
+            if self.match_peek(TokenType::Enum) {
+                enums.push(self.parse_enum(public)?);
+                continue;
+            }
+
+            functions.push(self.parse_function(attributes, public)?);
+        }
+
+        Ok(Program { imports, globals, externs, functions, structs, enums })
+    }
+
+    /// Parse the declaration of a C function
+    /// i.e. `extern func abs(x: i32): i32;`
+    fn parse_extern(&mut self, public: bool) -> CompileResult<ExternFunction> {
+        let start = self.peek().span.clone();
+
+        // extern func
+        self.consume(TokenType::Extern)?;
+        self.consume(TokenType::Function)?;
+
+        // ident
+        let ident = self.parse_ident()?;
+
+        // params
+        let params = self.parse_parameters()?;
+
+        // : type
+        self.consume(TokenType::Colon)?;
+        let return_type = self.parse_type()?;
+
+        // ;
+        self.consume(TokenType::Semicolon)?;
+
+        Ok(ExternFunction {
+            ident,
+            public,
+            params,
+            return_type,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse a global variable or constant
+    /// i.e. `let counter = 0;`, `const LIMIT: u8 = 200;`
+    fn parse_global(&mut self, public: bool) -> CompileResult<Global> {
+        let start = self.peek().span.clone();
+
+        // let or const
+        let constant = self.match_advance(TokenType::Const);
+        if !constant {
+            self.consume(TokenType::Let)?;
+        }
+
+        // ident
+        let ident_span = self.peek().span.clone();
+        let ident = self.parse_ident()?;
+
+        // (: type)?
+        let global_type = if self.match_advance(TokenType::Colon) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+
+        // = value
+        self.consume(TokenType::Equal)?;
+        let value = self.parse_expression()?;
+
+        // ;
+        self.consume(TokenType::Semicolon)?;
+
+        Ok(Global {
+            ident,
+            ident_span,
+            public,
+            constant,
+            global_type,
+            value,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse an import
+    /// A bare name is a file with that name in the importing file's directory
+    /// i.e. `import math;`, `import "lib/math.ken";`
+    fn parse_import(&mut self) -> CompileResult<Import> {
+        let start = self.peek().span.clone();
+
+        // import
+        self.consume(TokenType::Import)?;
+
+        let (module, path) = match self.peek().token_type.clone() {
+            TokenType::StringLiteral(path) => {
+                let token = self.consume(TokenType::StringLiteral(path.clone()))?;
+                let module = module_name(&path).ok_or_else(|| CompileError::SyntaxError(
+                    format!("`{}` can't be used as a module name", path),
+                    token.span,
+                ))?;
+                (module, path)
+            }
+            _ => {
+                let module = self.parse_ident()?;
+                let path = format!("{}.ken", module);
+                (module, path)
+            }
+        };
+
+        // ;
+        self.consume(TokenType::Semicolon)?;
+
+        let span = self.span_from(&start);
+
+        if !self.modules.insert(module.clone()) {
+            return Err(CompileError::SyntaxError(
+                format!("Module `{}` is imported more than once", module),
+                span,
+            ));
+        }
+
+        Ok(Import { module, path, span })
+    }
+
+    /// Parse a struct declaration
+    /// i.e. `struct Point { x: float, y: float }`
+    fn parse_struct(&mut self, public: bool) -> CompileResult<Struct> {
+        let start = self.peek().span.clone();
+
+        // struct
+        self.consume(TokenType::Struct)?;
+
+        // ident
+        let ident_span = self.peek().span.clone();
+        let ident = self.parse_ident()?;
+
+        // {
+        self.consume(TokenType::LeftBrace)?;
+
+        let mut fields: Vec<Field> = Vec::new();
+
+        while !self.match_peek(TokenType::RightBrace) {
+            let field_start = self.peek().span.clone();
+            let ident = self.parse_ident()?;
+            self.consume(TokenType::Colon)?;
+            let field_type = self.parse_type()?;
+            fields.push(Field { ident, field_type, span: self.span_from(&field_start) });
+
+            if !self.match_peek(TokenType::RightBrace) {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+
+        // }
+        self.consume(TokenType::RightBrace)?;
+
+        Ok(Struct { ident, ident_span, public, fields, span: self.span_from(&start) })
+    }
+
+    /// Parse an enum declaration
+    /// i.e. `enum Shape { Circle(float), Rect(float, float), Empty }`
+    fn parse_enum(&mut self, public: bool) -> CompileResult<Enum> {
+        let start = self.peek().span.clone();
+
+        // enum
+        self.consume(TokenType::Enum)?;
+
+        // ident
+        let ident_span = self.peek().span.clone();
+        let ident = self.parse_ident()?;
+
+        // {
+        self.consume(TokenType::LeftBrace)?;
+
+        let mut variants: Vec<Variant> = Vec::new();
+
+        while !self.match_peek(TokenType::RightBrace) {
+            let variant_start = self.peek().span.clone();
+            let ident = self.parse_ident()?;
+            let mut fields = Vec::new();
+
+            // (types)?
+            if self.match_advance(TokenType::LeftParen) {
+                while !self.match_peek(TokenType::RightParen) {
+                    fields.push(self.parse_type()?);
+
+                    if !self.match_peek(TokenType::RightParen) {
+                        self.consume(TokenType::Comma)?;
+                    }
+                }
+
+                self.consume(TokenType::RightParen)?;
+            }
+
+            variants.push(Variant { ident, fields, span: self.span_from(&variant_start) });
+
+            if !self.match_peek(TokenType::RightBrace) {
+                self.consume(TokenType::Comma)?;
+            }
         }
 
-        Ok(Program { functions })
+        // }
+        self.consume(TokenType::RightBrace)?;
+
+        Ok(Enum { ident, ident_span, public, variants, span: self.span_from(&start) })
     }
 
-    /// Parse a function
+    /// Parse an attribute of a function
+    /// i.e. `@inline` or `@allow(a, b)`
+    fn parse_attribute(&mut self) -> CompileResult<Attribute> {
+        let start = self.peek().span.clone();
+
+        // @
+        self.consume(TokenType::At)?;
+
+        // ident
+        let ident = self.parse_ident()?;
+
+        // (a, b)
+        let mut arguments = Vec::new();
+        if self.match_advance(TokenType::LeftParen) {
+            while !self.match_peek(TokenType::RightParen) {
+                arguments.push(self.parse_ident()?);
+
+                if !self.match_advance(TokenType::Comma) {
+                    break;
+                }
+            }
+
+            self.consume(TokenType::RightParen)?;
+        }
+
+        Ok(Attribute { ident, arguments, span: self.span_from(&start) })
+    }
+
+    /// Parse a function, after its attributes
     /// i.e. `func add(a: int, b: int): int { return a + b; }`
-    fn parse_function(&mut self) -> CompileResult<Function> {
-        println!("Parsing function, current token: {:?} (pos {})", self.peek(), self.current);
+    fn parse_function(&mut self, attributes: Vec<Attribute>, public: bool) -> CompileResult<Function> {
+        let start = self.peek().span.clone();
 
         // func
         self.consume(TokenType::Function)?;
 
         // ident
+        let ident_span = self.peek().span.clone();
         let ident = self.parse_ident()?;
 
-        println!("Ident: {:?}", ident);
+        // <T, U: numeric>
+        let type_params = if self.match_peek(TokenType::Less) {
+            self.parse_type_params()?
+        } else {
+            Vec::new()
+        };
 
         // params
         let params = self.parse_parameters()?;
 
-        println!("Params: {:?}", params);
-
         // :
         self.consume(TokenType::Colon)?;
 
         // type
         let return_type = self.parse_type()?;
 
-        println!("Return type: {:?}", return_type);
-
         // body
         let body = self.parse_block()?;
 
-        println!("Body: {:?}", body);
-
         Ok(Function {
+            attributes,
             ident,
+            ident_span,
+            public,
+            type_params,
             params,
             return_type,
             body,
+            span: self.span_from(&start),
         })
     }
 
+    /// Parse the type parameters of a generic function, each with an
+    /// optional bound
+    /// i.e. `<T, U: numeric>`
+    fn parse_type_params(&mut self) -> CompileResult<Vec<TypeParam>> {
+        // <
+        self.consume(TokenType::Less)?;
+
+        let mut type_params = Vec::new();
+        while !self.match_peek(TokenType::Greater) {
+            let start = self.peek().span.clone();
+            let ident = self.parse_ident()?;
+
+            let bound = if self.match_advance(TokenType::Colon) {
+                let span = self.peek().span.clone();
+                let bound = self.parse_ident()?;
+
+                Some(Bound::from_ident(&bound).ok_or_else(|| CompileError::SyntaxError(
+                    format!("Unknown bound `{}`, expected numeric, integer or equatable", bound),
+                    span,
+                ))?)
+            } else {
+                None
+            };
+
+            type_params.push(TypeParam { ident, bound, span: self.span_from(&start) });
+
+            if !self.match_peek(TokenType::Greater) {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+
+        // >
+        self.consume(TokenType::Greater)?;
+
+        Ok(type_params)
+    }
+
     /// Parse a list of parameters
     /// i.e. `a: int, b: int`
     fn parse_parameters(&mut self) -> CompileResult<Parameters> {
-        println!("Parsing parameters, current token: {:?} (pos {})", self.peek(), self.current);
-
         // (
         self.consume(TokenType::LeftParen)?;
 
         let mut parameters: Vec<Parameter> = Vec::new();
 
         while !self.match_peek(TokenType::RightParen) {
+            let start = self.peek().span.clone();
             let ident = self.parse_ident()?;
             self.consume(TokenType::Colon)?;
             let param_type = self.parse_type()?;
-            parameters.push(Parameter { ident, param_type });
+            parameters.push(Parameter { ident, param_type, span: self.span_from(&start) });
 
             if !self.match_peek(TokenType::RightParen) {
                 self.consume(TokenType::Comma)?;
@@ -169,7 +487,7 @@ impl Parser {
     /// Parse a block
     /// i.e. `{ a += 1; return a; }`
     fn parse_block(&mut self) -> CompileResult<Block> {
-        println!("Parsing block, current token: {:?} (pos {})", self.peek(), self.current);
+        let start = self.peek().span.clone();
 
         // {
         self.consume(TokenType::LeftBrace)?;
@@ -183,17 +501,29 @@ impl Parser {
         // }
         self.consume(TokenType::RightBrace)?;
 
-        Ok(Block { statements })
+        Ok(Block { statements, span: self.span_from(&start) })
+    }
+
+    /// Parse statements up to the end, outside of any function, as they're
+    /// typed into the REPL
+    pub fn parse_statements(&mut self) -> CompileResult<Vec<Statement>> {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() {
+            statements.push(self.parse_statement()?);
+        }
+
+        Ok(statements)
     }
 
     /// Parse a block statement
     /// i.e. `{ a += 1; return a; }`
     fn parse_block_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing block statement, current token: {:?} (pos {})", self.peek(), self.current);
-
         let block = self.parse_block()?;
-        Ok(Statement::Block{
+        let span = block.span.clone();
+        Ok(Statement::Block {
             block: Box::new(block),
+            span,
         })
     }
 
@@ -210,8 +540,6 @@ impl Parser {
     /// - Block
     /// - Expression
     fn parse_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing statement, current token: {:?} (pos {})", self.peek(), self.current);
-
         if self.match_peek(TokenType::Let) {
             self.parse_variable_declaration()
         // } else if self.match_peek(TokenType::Print) {
@@ -238,7 +566,7 @@ impl Parser {
     /// i.e. `let a = 1;` (type is inferred)
     /// Value must be assigned
     fn parse_variable_declaration(&mut self) -> CompileResult<Statement> {
-        println!("Parsing variable declaration, current token: {:?} (pos {})", self.peek(), self.current);
+        let start = self.peek().span.clone();
 
         // let
         self.consume(TokenType::Let)?;
@@ -265,138 +593,146 @@ impl Parser {
         // ;
         self.consume(TokenType::Semicolon)?;
 
-        Ok(Statement::VariableDeclaration { 
+        Ok(Statement::VariableDeclaration {
             ident,
             var_type,
             value,
+            span: self.span_from(&start),
         })
     }
 
     /// Parse an if statement
     /// May contain nested if/else statements
+    /// i.e. `if (a > 1) { ... } else if (a > 0) { ... } else { ... }`
     fn parse_if_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing if statement, current token: {:?} (pos {})", self.peek(), self.current);
-
-        unimplemented!()
+        let start = self.peek().span.clone();
 
         // if
-        // self.consume(TokenType::If)?;
+        self.consume(TokenType::If)?;
 
-        // // condition
-        // let condition = self.parse_expression()?;
-
-        // // body
-        // let body = self.parse_block()?;
+        // (condition)
+        self.consume(TokenType::LeftParen)?;
+        let condition = self.parse_expression()?;
+        self.consume(TokenType::RightParen)?;
 
-        // // else?
-        // let else_body = if self.match_peek(TokenType::Else) {
-        //     // else
-        //     self.consume(TokenType::Else)?;
+        // body
+        let then_branch = Box::new(self.parse_block_statement()?);
+
+        // else?
+        let else_branch = if self.match_advance(TokenType::Else) {
+            // else if?
+            if self.match_peek(TokenType::If) {
+                Some(Box::new(self.parse_if_statement()?))
+            } else {
+                Some(Box::new(self.parse_block_statement()?))
+            }
+        } else {
+            None
+        };
 
-        //     // if?
-        //     if self.match_peek(TokenType::If) {
-        //         Some(Box::new(self.parse_if_statement()?))
-        //     } else {
-        //         let block = Box::new(self.parse_block()?);
-        //         Some(Box::new(
-        //             Statement::Block { block: block }
-        //         ))
-        //     }
-        // } else {
-        //     None
-        // };
+        Ok(Statement::If {
+            condition,
+            then_branch,
+            else_branch,
+            span: self.span_from(&start),
+        })
     }
 
     /// Parse a for statement
     /// for (let i: int = 0; i < 10; i += 1) { ... }
     fn parse_for_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing for statement, current token: {:?} (pos {})", self.peek(), self.current);
-
-        unimplemented!()
-
-        // // for
-        // self.consume(TokenType::For)?;
-
-        // // (
-        // self.consume(TokenType::LeftParen)?;
-
-        // // init
-        // let init = if self.match_peek(TokenType::Let) {
-        //     Some(self.parse_variable_declaration()?)
-        // } else if self.match_peek(TokenType::Semicolon) {
-        //     None
-        // } else {
-        //     Some(self.parse_expression_statement()?)
-        // };
-
-        // // condition
-        // let condition = if self.match_peek(TokenType::Semicolon) {
-        //     None
-        // } else {
-        //     let expr = self.parse_expression()?;
-        //     self.consume(TokenType::Semicolon)?;
-        //     Some(expr)
-        // };
-
-        // // increment
-        // let increment = if self.match_peek(TokenType::RightParen) {
-        //     None
-        // } else {
-        //     let expr = self.parse_expression()?;
-        //     self.consume(TokenType::RightParen)?;
-        //     Some(expr)
-        // };
-
-        // // body
-        // let body = self.parse_block()?;
-
-        // Ok(Statement::For {
-        //     init,
-        //     condition,
-        //     increment,
-        //     body,
-        // })
+        let start = self.peek().span.clone();
+
+        // for
+        self.consume(TokenType::For)?;
+
+        // (
+        self.consume(TokenType::LeftParen)?;
+
+        // init (consumes its own ;)
+        let init = if self.match_peek(TokenType::Let) {
+            self.parse_variable_declaration()?
+        } else {
+            let statement = self.parse_simple_statement()?;
+            self.consume(TokenType::Semicolon)?;
+            statement
+        };
+
+        // condition
+        let condition = self.parse_expression()?;
+        self.consume(TokenType::Semicolon)?;
+
+        // increment
+        let increment = self.parse_simple_statement()?;
+
+        // )
+        self.consume(TokenType::RightParen)?;
+
+        // body
+        let body = self.parse_block()?;
+
+        Ok(Statement::For {
+            init: Box::new(init),
+            condition,
+            increment: Box::new(increment),
+            body,
+            span: self.span_from(&start),
+        })
     }
 
     /// Parse a while statement
     /// while (i < 10) { ... }
     fn parse_while_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing while statement, current token: {:?} (pos {})", self.peek(), self.current);
+        let start = self.peek().span.clone();
 
-        unimplemented!()
+        // while
+        self.consume(TokenType::While)?;
 
-        // // while
-        // self.consume(TokenType::While)?;
-
-        // // (
-        // self.consume(TokenType::LeftParen)?;
-
-        // // condition
-        // let condition = self.parse_expression()?;
-
-        // // )
-        // self.consume(TokenType::RightParen)?;
+        // (condition)
+        self.consume(TokenType::LeftParen)?;
+        let condition = self.parse_expression()?;
+        self.consume(TokenType::RightParen)?;
 
-        // // body
-        // let body = self.parse_block()?;
+        // body
+        let body = self.parse_block()?;
 
-        // Ok(Statement::While {
-        //     condition,
-        //     body,
-        // })
+        Ok(Statement::While {
+            condition,
+            body,
+            span: self.span_from(&start),
+        })
     }
 
     /// Parse a do until statement
     /// do { ... } until (i >= 10)
     fn parse_do_until_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing do until statement, current token: {:?} (pos {})", self.peek(), self.current);
+        let start = self.peek().span.clone();
+
+        // do
+        self.consume(TokenType::Do)?;
+
+        // body
+        let body = Box::new(self.parse_block_statement()?);
 
-        unimplemented!()
+        // until (condition)
+        self.consume(TokenType::Until)?;
+        self.consume(TokenType::LeftParen)?;
+        let condition = self.parse_expression()?;
+        self.consume(TokenType::RightParen)?;
+
+        Ok(Statement::DoUntil {
+            condition,
+            body,
+            span: self.span_from(&start),
+        })
     }
 
+
     /// Parse a return statement
     /// return 1;
     fn parse_return_statement(&mut self) -> CompileResult<Statement> {
+        let start = self.peek().span.clone();
+
         // return
         self.consume(TokenType::Return)?;
 
@@ -410,27 +746,59 @@ impl Parser {
         // ;
         self.consume(TokenType::Semicolon)?;
 
-        Ok(Statement::Return { value })
+        Ok(Statement::Return { value, span: self.span_from(&start) })
     }
     
+    /// Parse an assignment or expression without the trailing `;`
+    /// i.e. `a = 1` or `a += 1`
+    /// Used directly by the header of a for statement
+    fn parse_simple_statement(&mut self) -> CompileResult<Statement> {
+        let start = self.peek().span.clone();
+        let is_assignment = matches!(self.peek().token_type, TokenType::Ident(_))
+            && self.peek_next().token_type == TokenType::Equal;
+
+        if is_assignment {
+            // ident
+            let ident = self.parse_ident()?;
+
+            // =
+            self.consume(TokenType::Equal)?;
+
+            let value = self.parse_expression()?;
+
+            Ok(Statement::Assign { ident, value, span: self.span_from(&start) })
+        } else {
+            let expression = self.parse_expression()?;
+
+            Ok(Statement::Expression { expression, span: self.span_from(&start) })
+        }
+    }
+
     /// Parse an expression statement
-    /// i.e. `1 + 1;`
+    /// i.e. `1 + 1;` or `a = 1;`
     fn parse_expression_statement(&mut self) -> CompileResult<Statement> {
-        println!("Parsing expression statement, current token: {:?} (pos {})", self.peek(), self.current);
-
-        let expr = self.parse_expression()?;
+        let mut statement = self.parse_simple_statement()?;
+
+        // a match used as a statement ends in a brace, like a block, so the
+        // ; is optional
+        let is_match = matches!(statement, Statement::Expression { expression: Expression::Match { .. }, .. });
+        if !is_match || self.match_peek(TokenType::Semicolon) {
+            // ;
+            self.consume(TokenType::Semicolon)?;
+        }
 
-        // ;
-        self.consume(TokenType::Semicolon)?;
+        // the statement takes in its ;
+        match &mut statement {
+            Statement::Assign { span, .. } | Statement::Expression { span, .. } => span.end = self.previous().span.end,
+            _ => unreachable!(),
+        }
 
-        Ok(Statement::Expression { expression: expr })
+        Ok(statement)
     }
 
     /// Parse an ident
     /// i.e. `foo`
     fn parse_ident(&mut self) -> CompileResult<String> {
-        println!("Parsing ident, current token: {:?} (pos {})", self.peek(), self.current);
-
         match &self.peek().token_type {
             TokenType::Ident(x) => {
                 let token = self.consume(TokenType::Ident(x.clone()))?;
@@ -446,86 +814,245 @@ impl Parser {
         }
     }
 
+    /// Number of tokens the ident at the current token takes up: 3 if it's
+    /// qualified with an imported module (`math::add`), otherwise 1
+    fn ident_len(&self) -> usize {
+        match &self.peek().token_type {
+            TokenType::Ident(ident)
+                if self.modules.contains(ident) && self.peek_next().token_type == TokenType::ColonColon => 3,
+            _ => 1,
+        }
+    }
+
+    /// Parse an ident, which may be qualified with an imported module
+    /// i.e. `add`, `math::add`
+    fn parse_qualified_ident(&mut self) -> CompileResult<String> {
+        let ident = self.parse_ident()?;
+
+        if self.modules.contains(&ident) && self.match_advance(TokenType::ColonColon) {
+            return Ok(format!("{}::{}", ident, self.parse_ident()?));
+        }
+
+        Ok(ident)
+    }
+
     /// Parse a type
-    /// i.e. `int`
+    /// i.e. `int`, `string[]`, `[float; 4]`
     fn parse_type(&mut self) -> CompileResult<Type> {
-        println!("Parsing type, current token: {:?} (pos {})", self.peek(), self.current);
+        let mut parsed = if self.match_peek(TokenType::LeftBracket) {
+            self.parse_fixed_array_type()?
+        } else if self.match_peek(TokenType::Function) {
+            // a trailing `[]` belongs to the return type
+            return self.parse_function_type();
+        } else {
+            self.parse_scalar_type()?
+        };
 
-        match self.peek().token_type {
-            TokenType::Int => {
-                self.consume(TokenType::Int)?;
-                Ok(Type::Int)
-            }
-            TokenType::Float => {
-                self.consume(TokenType::Float)?;
-                Ok(Type::Float)
-            }
-            TokenType::String => {
-                self.consume(TokenType::String)?;
-                Ok(Type::String)
-            }
-            TokenType::Bool => {
-                self.consume(TokenType::Bool)?;
-                Ok(Type::Bool)
-            }
-            TokenType::Null => {
-                self.consume(TokenType::Null)?;
-                Ok(Type::Null)
+        // any number of trailing `[]`
+        while self.match_peek(TokenType::LeftBracket) && self.peek_next().token_type == TokenType::RightBracket {
+            self.consume(TokenType::LeftBracket)?;
+            self.consume(TokenType::RightBracket)?;
+            parsed = Type::Array(Box::new(parsed));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Parse a function type
+    /// i.e. `func(int, string): bool`
+    fn parse_function_type(&mut self) -> CompileResult<Type> {
+        // func
+        self.consume(TokenType::Function)?;
+
+        // (
+        self.consume(TokenType::LeftParen)?;
+
+        let mut params = Vec::new();
+        while !self.match_peek(TokenType::RightParen) {
+            params.push(self.parse_type()?);
+
+            if !self.match_peek(TokenType::RightParen) {
+                self.consume(TokenType::Comma)?;
             }
+        }
 
-            _ => Err(CompileError::SyntaxError(
+        // )
+        self.consume(TokenType::RightParen)?;
+
+        // :
+        self.consume(TokenType::Colon)?;
+
+        let return_type = self.parse_type()?;
+
+        Ok(Type::Function(params, Box::new(return_type)))
+    }
+
+    /// Parse a fixed size array type
+    /// i.e. `[int; 4]`
+    fn parse_fixed_array_type(&mut self) -> CompileResult<Type> {
+        // [
+        self.consume(TokenType::LeftBracket)?;
+
+        let element = self.parse_type()?;
+
+        // ;
+        self.consume(TokenType::Semicolon)?;
+
+        // length
+        let len = match self.peek().token_type {
+            TokenType::IntegerLiteral(len) if usize::try_from(len).is_ok() => len as usize,
+            _ => return Err(CompileError::SyntaxError(
+                format!("Expected array length, got {:?}", self.peek().token_type),
+                self.peek().span.clone(),
+            )),
+        };
+        self.consume(self.peek().token_type.clone())?;
+
+        // ]
+        self.consume(TokenType::RightBracket)?;
+
+        Ok(Type::FixedArray(Box::new(element), len))
+    }
+
+    /// Parse a type that isn't an array
+    /// i.e. `int`
+    fn parse_scalar_type(&mut self) -> CompileResult<Type> {
+        let parsed = match self.peek().token_type {
+            TokenType::Int => Type::Int,
+            TokenType::I8 => Type::I8,
+            TokenType::I16 => Type::I16,
+            TokenType::I32 => Type::I32,
+            TokenType::I64 => Type::I64,
+            TokenType::U8 => Type::U8,
+            TokenType::U16 => Type::U16,
+            TokenType::U32 => Type::U32,
+            TokenType::U64 => Type::U64,
+            TokenType::Float => Type::Float,
+            TokenType::F64 => Type::F64,
+            TokenType::String => Type::String,
+            TokenType::Bool => Type::Bool,
+            TokenType::Null => Type::Null,
+            TokenType::Ident(_) => return Ok(Type::Named(self.parse_qualified_ident()?)),
+
+            _ => return Err(CompileError::SyntaxError(
                 format!("Expected type, got {:?}", self.peek().token_type),
                 self.peek().span.clone(),
             )),
+        };
+
+        self.consume(self.peek().token_type.clone())?;
+
+        Ok(parsed)
+    }
+
+    /// Span from the start of `start` up to the end of the previous token
+    fn span_from(&self, start: &Span) -> Span {
+        Span {
+            start: start.start,
+            end: self.previous().span.end,
+        }
+    }
+
+    /// Build a binary expression spanning both operands
+    fn binary(lhs: Expression, operator: BinaryOperator, rhs: Expression) -> Expression {
+        let span = Span {
+            start: lhs.span().start,
+            end: rhs.span().end,
+        };
+
+        Expression::Binary {
+            left: Box::new(lhs),
+            operator,
+            right: Box::new(rhs),
+            span,
         }
     }
 
     /// Parse an expression
-    /// May be a binary expression, unary expression, or a literal
+    /// May be an assignment, binary expression, unary expression, or a literal
     /// i.e. `1 + 1`
     fn parse_expression(&mut self) -> CompileResult<Expression> {
-        println!("Parsing expression, current token: {:?} (pos {})", self.peek(), self.current);
+        self.parse_assign()
+    }
+
+    /// Parse an assignment
+    /// Right associative, so `a += b += 1` is `a += (b += 1)`
+    /// i.e. `a += 1`, `a[i] = 1`
+    fn parse_assign(&mut self) -> CompileResult<Expression> {
+        let lhs = self.parse_or()?;
+
+        let operator = match self.peek().token_type {
+            TokenType::Equal => AssignOperator::Equal,
+            TokenType::PlusEqual => AssignOperator::PlusEqual,
+            TokenType::MinusEqual => AssignOperator::MinusEqual,
+            TokenType::StarEqual => AssignOperator::StarEqual,
+            TokenType::SlashEqual => AssignOperator::SlashEqual,
+            _ => return Ok(lhs),
+        };
+        self.consume(self.peek().token_type.clone())?;
+
+        let rhs = self.parse_assign()?;
+        let span = Span {
+            start: lhs.span().start,
+            end: rhs.span().end,
+        };
 
+        Ok(Expression::Assign {
+            left: Box::new(lhs),
+            operator,
+            right: Box::new(rhs),
+            span,
+        })
+    }
+
+    /// Parse a logical or
+    /// i.e. `a or b`
+    fn parse_or(&mut self) -> CompileResult<Expression> {
+        let mut lhs = self.parse_and()?;
+
+        while self.match_advance(TokenType::Or) {
+            let rhs = self.parse_and()?;
+            lhs = Self::binary(lhs, BinaryOperator::Or, rhs);
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse a logical and
+    /// i.e. `a and b`
+    fn parse_and(&mut self) -> CompileResult<Expression> {
+        let mut lhs = self.parse_comparison()?;
+
+        while self.match_advance(TokenType::And) {
+            let rhs = self.parse_comparison()?;
+            lhs = Self::binary(lhs, BinaryOperator::And, rhs);
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse an equality or comparison
+    /// i.e. `a + 1 < b`
+    fn parse_comparison(&mut self) -> CompileResult<Expression> {
         // first we parse the left hand side
-        let lhs = self.parse_term()?;
-
-        // if the next token is an operator, we parse the right hand side
-        // and return a binary expression
-        if self.match_peek(TokenType::Plus)
-            || self.match_peek(TokenType::Minus)
-            || self.match_peek(TokenType::Star)
-            || self.match_peek(TokenType::Slash)
-            || self.match_peek(TokenType::EqualEqual)
-            || self.match_peek(TokenType::BangEqual)
-            || self.match_peek(TokenType::Greater)
-            || self.match_peek(TokenType::GreaterEqual)
-            || self.match_peek(TokenType::Less)
-            || self.match_peek(TokenType::LessEqual)
-        {
-            let op = self.consume(self.peek().token_type.clone())?;
-            let op_btoken = match op.token_type {
-                TokenType::Plus => BinaryOperator::Plus,
-                TokenType::Minus => BinaryOperator::Minus,
-                TokenType::Star => BinaryOperator::Star,
-                TokenType::Slash => BinaryOperator::Slash,
+        let mut lhs = self.parse_term()?;
+
+        // while the next token is an operator, we parse the right hand side
+        // and fold it into a binary expression
+        loop {
+            let operator = match self.peek().token_type {
                 TokenType::EqualEqual => BinaryOperator::EqualEqual,
                 TokenType::BangEqual => BinaryOperator::BangEqual,
                 TokenType::Greater => BinaryOperator::Greater,
                 TokenType::GreaterEqual => BinaryOperator::GreaterEqual,
                 TokenType::Less => BinaryOperator::Less,
                 TokenType::LessEqual => BinaryOperator::LessEqual,
-                _ => unreachable!(),
+                _ => return Ok(lhs),
             };
+            self.consume(self.peek().token_type.clone())?;
 
-            let rhs = self.parse_expression()?;
-
-            Ok(Expression::Binary {
-                left: Box::new(lhs),
-                operator: op_btoken,
-                right: Box::new(rhs),
-            })
-        } else {
-            Ok(lhs)
+            let rhs = self.parse_term()?;
+            lhs = Self::binary(lhs, operator, rhs);
         }
     }
 
@@ -533,30 +1060,20 @@ impl Parser {
     /// May have + or - in front of it
     /// i.e. `1 + 1`
     fn parse_term(&mut self) -> CompileResult<Expression> {
-        println!("Parsing term, current token: {:?} (pos {})", self.peek(), self.current);
-
         // first we parse the left hand side
-        let lhs = self.parse_factor()?;
+        let mut lhs = self.parse_factor()?;
 
-        // if the next token is an operator, we parse the right hand side
-        // and return a binary expression
-        if self.match_peek(TokenType::Plus) || self.match_peek(TokenType::Minus) {
-            let op = self.consume(self.peek().token_type.clone())?;
-            let op_btoken = match op.token_type {
+        // left associative, so `1 - 2 - 3` is `(1 - 2) - 3`
+        loop {
+            let operator = match self.peek().token_type {
                 TokenType::Plus => BinaryOperator::Plus,
                 TokenType::Minus => BinaryOperator::Minus,
-                _ => unreachable!(),
+                _ => return Ok(lhs),
             };
+            self.consume(self.peek().token_type.clone())?;
 
-            let rhs = self.parse_term()?;
-
-            Ok(Expression::Binary {
-                left: Box::new(lhs),
-                operator: op_btoken,
-                right: Box::new(rhs),
-            })
-        } else {
-            Ok(lhs)
+            let rhs = self.parse_factor()?;
+            lhs = Self::binary(lhs, operator, rhs);
         }
     }
 
@@ -564,45 +1081,34 @@ impl Parser {
     /// May have * or / in front of it
     /// i.e. `1 * 1`
     fn parse_factor(&mut self) -> CompileResult<Expression> {
-        println!("Parsing factor, current token: {:?} (pos {})", self.peek(), self.current);
-
         // first we parse the left hand side
-        let lhs = self.parse_unary()?;
+        let mut lhs = self.parse_unary()?;
 
-        // if the next token is an operator, we parse the right hand side
-        // and return a binary expression
-        if self.match_peek(TokenType::Star) || self.match_peek(TokenType::Slash) {
-            let op = self.consume(self.peek().token_type.clone())?;
-            let op_btoken = match op.token_type {
+        // left associative, so `8 / 4 / 2` is `(8 / 4) / 2`
+        loop {
+            let operator = match self.peek().token_type {
                 TokenType::Star => BinaryOperator::Star,
                 TokenType::Slash => BinaryOperator::Slash,
-                _ => unreachable!(),
+                _ => return Ok(lhs),
             };
+            self.consume(self.peek().token_type.clone())?;
 
-            let rhs = self.parse_factor()?;
-
-            Ok(Expression::Binary {
-                left: Box::new(lhs),
-                operator: op_btoken,
-                right: Box::new(rhs),
-            })
-        } else {
-            Ok(lhs)
+            let rhs = self.parse_unary()?;
+            lhs = Self::binary(lhs, operator, rhs);
         }
     }
 
     /// Parse a unary expression
-    /// May have +, -, or ! in front of it
+    /// May have -, !, ++ or -- in front of it
     /// i.e. `-1`
     fn parse_unary(&mut self) -> CompileResult<Expression> {
-        println!("Parsing unary, current token: {:?} (pos {})", self.peek(), self.current);
+        let start = self.peek().span.clone();
 
-        if self.match_peek(TokenType::Bang) || self.match_peek(TokenType::Minus)
-         {
+        if self.match_peek(TokenType::Bang) || self.match_peek(TokenType::Not) || self.match_peek(TokenType::Minus) {
             let op = self.consume(self.peek().token_type.clone())?;
             let op_utoken = match op.token_type {
                 TokenType::Minus => UnaryOperator::Minus,
-                TokenType::Bang => UnaryOperator::Bang,
+                TokenType::Bang | TokenType::Not => UnaryOperator::Bang,
                 _ => unreachable!(),
             };
 
@@ -611,9 +1117,382 @@ impl Parser {
             Ok(Expression::Unary {
                 operator: op_utoken,
                 right: Box::new(rhs),
+                span: self.span_from(&start),
             })
+        } else if self.match_peek(TokenType::PlusPlus) || self.match_peek(TokenType::MinusMinus) {
+            let op = self.consume(self.peek().token_type.clone())?;
+            let operator = match op.token_type {
+                TokenType::PlusPlus => PrefixOperator::PlusPlus,
+                TokenType::MinusMinus => PrefixOperator::MinusMinus,
+                _ => unreachable!(),
+            };
+
+            let rhs = self.parse_unary()?;
+
+            Ok(Expression::Prefix {
+                operator,
+                right: Box::new(rhs),
+                span: self.span_from(&start),
+            })
+        } else {
+            self.parse_postfix()
+        }
+    }
+
+    /// Parse a postfix expression
+    /// May be followed by a call, index, slice, field, `++`, `--` or a cast
+    /// i.e. `f(1)`, `s[0]`, `s[1:3]`, `p.x`, `i++`, `x as u8`
+    fn parse_postfix(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            if self.match_advance(TokenType::As) {
+                let target_type = self.parse_type()?;
+
+                expr = Expression::Cast {
+                    expression: Box::new(expr),
+                    target_type,
+                    span: self.span_from(&start),
+                };
+            } else if self.match_advance(TokenType::LeftParen) {
+                let arguments = self.parse_arguments()?;
+
+                expr = Expression::Call {
+                    callee: Box::new(expr),
+                    arguments,
+                    span: self.span_from(&start),
+                };
+            } else if self.match_advance(TokenType::LeftBracket) {
+                expr = self.parse_index(expr, &start)?;
+            } else if self.match_advance(TokenType::Dot) {
+                let field = self.parse_ident()?;
+
+                expr = Expression::Field {
+                    target: Box::new(expr),
+                    field,
+                    span: self.span_from(&start),
+                };
+            } else if self.match_peek(TokenType::PlusPlus) || self.match_peek(TokenType::MinusMinus) {
+                let op = self.consume(self.peek().token_type.clone())?;
+                let operator = match op.token_type {
+                    TokenType::PlusPlus => PostfixOperator::PlusPlus,
+                    TokenType::MinusMinus => PostfixOperator::MinusMinus,
+                    _ => unreachable!(),
+                };
+
+                expr = Expression::Postfix {
+                    left: Box::new(expr),
+                    operator,
+                    span: self.span_from(&start),
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parse the arguments of a call, after the `(`
+    /// i.e. `1, a + b)`
+    fn parse_arguments(&mut self) -> CompileResult<Vec<Expression>> {
+        let mut arguments = Vec::new();
+
+        while !self.match_peek(TokenType::RightParen) {
+            arguments.push(self.parse_expression()?);
+
+            if !self.match_peek(TokenType::RightParen) {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+
+        // )
+        self.consume(TokenType::RightParen)?;
+
+        Ok(arguments)
+    }
+
+    /// Parse an index or slice, after the `[`
+    /// Either bound of a slice may be left out
+    /// i.e. `0]`, `1:3]`, `:3]`
+    fn parse_index(&mut self, target: Expression, start: &Span) -> CompileResult<Expression> {
+        let from = if self.match_peek(TokenType::Colon) {
+            None
+        } else {
+            Some(Box::new(self.parse_expression()?))
+        };
+
+        if self.match_advance(TokenType::Colon) {
+            let to = if self.match_peek(TokenType::RightBracket) {
+                None
+            } else {
+                Some(Box::new(self.parse_expression()?))
+            };
+
+            // ]
+            self.consume(TokenType::RightBracket)?;
+
+            return Ok(Expression::Slice {
+                target: Box::new(target),
+                start: from,
+                end: to,
+                span: self.span_from(start),
+            });
+        }
+
+        // ]
+        self.consume(TokenType::RightBracket)?;
+
+        Ok(Expression::Index {
+            target: Box::new(target),
+            index: from.unwrap(),
+            span: self.span_from(start),
+        })
+    }
+
+    /// Parse an array literal
+    /// i.e. `[1, 2, 3]`
+    fn parse_array_literal(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+
+        // [
+        self.consume(TokenType::LeftBracket)?;
+
+        let mut elements = Vec::new();
+
+        while !self.match_peek(TokenType::RightBracket) {
+            elements.push(self.parse_expression()?);
+
+            if !self.match_peek(TokenType::RightBracket) {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+
+        // ]
+        self.consume(TokenType::RightBracket)?;
+
+        Ok(Expression::ArrayLiteral {
+            elements,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Whether the current ident starts a struct literal: it must be followed
+    /// by `{` and then `}` or `field:`, so it can't be confused with a block
+    fn is_struct_literal(&self) -> bool {
+        let len = self.ident_len();
+
+        self.peek_ahead(len).token_type == TokenType::LeftBrace
+            && match self.peek_ahead(len + 1).token_type {
+                TokenType::RightBrace => true,
+                TokenType::Ident(_) => self.peek_ahead(len + 2).token_type == TokenType::Colon,
+                _ => false,
+            }
+    }
+
+    /// Parse a struct literal
+    /// i.e. `Point { x: 1.0, y: 2.0 }`
+    fn parse_struct_literal(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+
+        // ident
+        let ident = self.parse_qualified_ident()?;
+
+        // {
+        self.consume(TokenType::LeftBrace)?;
+
+        let mut fields = Vec::new();
+
+        while !self.match_peek(TokenType::RightBrace) {
+            let field = self.parse_ident()?;
+            self.consume(TokenType::Colon)?;
+            fields.push((field, self.parse_expression()?));
+
+            if !self.match_peek(TokenType::RightBrace) {
+                self.consume(TokenType::Comma)?;
+            }
+        }
+
+        // }
+        self.consume(TokenType::RightBrace)?;
+
+        Ok(Expression::StructLiteral {
+            ident,
+            fields,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse a variant of an enum, with its values if it has any
+    /// i.e. `Shape::Circle(1.0)`, `Shape::Empty`
+    fn parse_variant_expression(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+
+        // enum ident
+        let enum_ident = self.parse_qualified_ident()?;
+
+        // ::
+        self.consume(TokenType::ColonColon)?;
+
+        // variant ident
+        let variant = self.parse_ident()?;
+
+        let arguments = if self.match_advance(TokenType::LeftParen) {
+            self.parse_arguments()?
         } else {
-            self.parse_primary()
+            Vec::new()
+        };
+
+        Ok(Expression::Variant {
+            enum_ident,
+            variant,
+            arguments,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse an anonymous function
+    /// i.e. `func(x: int): int { return x + n; }`
+    fn parse_lambda(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+
+        // func
+        self.consume(TokenType::Function)?;
+
+        let params = self.parse_parameters()?;
+
+        // :
+        self.consume(TokenType::Colon)?;
+
+        let return_type = self.parse_type()?;
+        let body = self.parse_block()?;
+
+        Ok(Expression::Function {
+            params,
+            return_type,
+            body,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse a match expression
+    /// Arms are separated by commas, which are optional after a block
+    /// i.e. `match (s) { Circle(r) => r * r, _ => { return 0.0; } }`
+    fn parse_match(&mut self) -> CompileResult<Expression> {
+        let start = self.peek().span.clone();
+
+        // match
+        self.consume(TokenType::Match)?;
+
+        // (scrutinee)
+        self.consume(TokenType::LeftParen)?;
+        let scrutinee = self.parse_expression()?;
+        self.consume(TokenType::RightParen)?;
+
+        // {
+        self.consume(TokenType::LeftBrace)?;
+
+        let mut arms = Vec::new();
+
+        while !self.match_peek(TokenType::RightBrace) {
+            let pattern = self.parse_pattern()?;
+
+            // =>
+            self.consume(TokenType::FatArrow)?;
+
+            let body = if self.match_peek(TokenType::LeftBrace) {
+                let block = self.parse_block()?;
+                self.match_advance(TokenType::Comma);
+                MatchBody::Block(block)
+            } else {
+                let expression = self.parse_expression()?;
+                if !self.match_peek(TokenType::RightBrace) {
+                    self.consume(TokenType::Comma)?;
+                }
+                MatchBody::Expression(expression)
+            };
+
+            arms.push(MatchArm { pattern, body });
+        }
+
+        // }
+        self.consume(TokenType::RightBrace)?;
+
+        Ok(Expression::Match {
+            scrutinee: Box::new(scrutinee),
+            arms,
+            span: self.span_from(&start),
+        })
+    }
+
+    /// Parse the pattern of a match arm
+    /// i.e. `Circle(r)`, `Shape::Rect(w, _)`, `-1`, `true`, `_`
+    fn parse_pattern(&mut self) -> CompileResult<Pattern> {
+        let start = self.peek().span.clone();
+
+        match self.peek().token_type.clone() {
+            TokenType::IntegerLiteral(_) | TokenType::Minus => {
+                let negative = self.match_advance(TokenType::Minus);
+
+                let value = match self.peek().token_type {
+                    TokenType::IntegerLiteral(value) => value,
+                    _ => return Err(CompileError::SyntaxError(
+                        format!("Expected integer, got {:?}", self.peek().token_type),
+                        self.peek().span.clone(),
+                    )),
+                };
+                self.consume(self.peek().token_type.clone())?;
+
+                Ok(Pattern::Integer {
+                    value: if negative { -value } else { value },
+                    span: self.span_from(&start),
+                })
+            }
+
+            TokenType::True | TokenType::False => {
+                let token = self.consume(self.peek().token_type.clone())?;
+                Ok(Pattern::Boolean { value: token.token_type == TokenType::True, span: token.span })
+            }
+
+            TokenType::Ident(ident) if ident == "_" => {
+                self.consume(TokenType::Ident(ident))?;
+                Ok(Pattern::Wildcard { span: start })
+            }
+
+            TokenType::Ident(_) => {
+                let mut variant = self.parse_qualified_ident()?;
+                let mut enum_ident = None;
+
+                // qualified with the enum?
+                if self.match_advance(TokenType::ColonColon) {
+                    enum_ident = Some(variant);
+                    variant = self.parse_ident()?;
+                }
+
+                let mut bindings = Vec::new();
+                if self.match_advance(TokenType::LeftParen) {
+                    while !self.match_peek(TokenType::RightParen) {
+                        bindings.push(self.parse_ident()?);
+
+                        if !self.match_peek(TokenType::RightParen) {
+                            self.consume(TokenType::Comma)?;
+                        }
+                    }
+
+                    self.consume(TokenType::RightParen)?;
+                }
+
+                Ok(Pattern::Variant {
+                    enum_ident,
+                    variant,
+                    bindings,
+                    span: self.span_from(&start),
+                })
+            }
+
+            _ => Err(CompileError::SyntaxError(
+                format!("Expected pattern, got {:?}", self.peek().token_type),
+                self.peek().span.clone(),
+            )),
         }
     }
 
@@ -621,49 +1500,52 @@ impl Parser {
     /// Can be a literal, a parenthesized expression, or a variable
     /// i.e. `1`, `(1 + 1)`, `foo`
     fn parse_primary(&mut self) -> CompileResult<Expression> {
-        println!("Parsing primary, current token: {:?} (pos {})", self.peek(), self.current);
+        let token = self.peek().clone();
 
-        match &self.peek().token_type {
-            TokenType::IntegerLiteral(x) => {
-                let token = self.consume(TokenType::IntegerLiteral(*x))?;
-                Ok(Expression::IntegerLiteral {
-                    value: match token.token_type {
-                        TokenType::IntegerLiteral(x) => x,
-                        _ => unreachable!(),
-                    },
-                })
+        match token.token_type {
+            TokenType::IntegerLiteral(value) => {
+                self.consume(token.token_type)?;
+                Ok(Expression::IntegerLiteral { value, span: token.span })
             }
 
-            TokenType::FloatLiteral(x) => {
-                let token = self.consume(TokenType::FloatLiteral(*x))?;
-                Ok(Expression::FloatLiteral {
-                    value: match token.token_type {
-                        TokenType::FloatLiteral(x) => x,
-                        _ => unreachable!(),
-                    },
-                })
+            TokenType::FloatLiteral(value) => {
+                self.consume(token.token_type)?;
+                Ok(Expression::FloatLiteral { value, span: token.span })
             }
 
-            TokenType::StringLiteral(x) => {
-                let token = self.consume(TokenType::StringLiteral(x.clone()))?;
-                Ok(Expression::StringLiteral {
-                    value: match token.token_type {
-                        TokenType::StringLiteral(x) => x,
-                        _ => unreachable!(),
-                    },
-                })
+            TokenType::StringLiteral(ref value) => {
+                self.consume(token.token_type.clone())?;
+                Ok(Expression::StringLiteral { value: value.clone(), span: token.span })
             }
 
-            TokenType::Ident(x) => {
-                let token = self.consume(TokenType::Ident(x.clone()))?;
-                Ok(Expression::Identifier {
-                    ident: match token.token_type {
-                        TokenType::Ident(x) => x,
-                        _ => unreachable!(),
-                    },
+            TokenType::True | TokenType::False => {
+                self.consume(token.token_type.clone())?;
+                Ok(Expression::BooleanLiteral {
+                    value: token.token_type == TokenType::True,
+                    span: token.span,
                 })
             }
 
+            TokenType::Null => {
+                self.consume(TokenType::Null)?;
+                Ok(Expression::NullLiteral { span: token.span })
+            }
+
+            TokenType::Ident(_) if self.is_struct_literal() => self.parse_struct_literal(),
+
+            TokenType::Ident(_) if self.peek_ahead(self.ident_len()).token_type == TokenType::ColonColon => {
+                self.parse_variant_expression()
+            }
+
+            TokenType::Match => self.parse_match(),
+
+            TokenType::Function => self.parse_lambda(),
+
+            TokenType::Ident(_) => {
+                let ident = self.parse_qualified_ident()?;
+                Ok(Expression::Identifier { ident, span: self.span_from(&token.span) })
+            }
+
             TokenType::LeftParen => {
                 self.consume(TokenType::LeftParen)?;
                 let expr = self.parse_expression()?;
@@ -671,6 +1553,8 @@ impl Parser {
                 Ok(expr)
             }
 
+            TokenType::LeftBracket => self.parse_array_literal(),
+
             _ => Err(CompileError::SyntaxError(
                 format!("Expected primary expression, got {:?}", self.peek().token_type),
                 self.peek().span.clone(),
@@ -679,6 +1563,18 @@ impl Parser {
     }
 }
 
+/// Name of the module a file is imported as: the file name without its
+/// extension, if that's an ident
+fn module_name(path: &str) -> Option<String> {
+    let stem = Path::new(path).file_stem()?.to_str()?;
+    let mut chars = stem.chars();
+
+    let valid = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+
+    valid.then(|| stem.to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::lexer;
@@ -84,6 +84,7 @@ pub fn precedence_of_prefix_operator(operator: PrefixOperator) -> Precedence {
 /// Precedence of an assignment operator
 pub fn precedence_of_assign_operator(operator: AssignOperator) -> Precedence {
     match operator {
+        AssignOperator::Equal => Precedence::Assign,
         AssignOperator::PlusEqual => Precedence::Assign,
         AssignOperator::MinusEqual => Precedence::Assign,
         AssignOperator::StarEqual => Precedence::Assign,
@@ -0,0 +1,1026 @@
+//! Kennedy source from its syntax tree
+//!
+//! Every node displays as canonical Kennedy: four space indents, one
+//! statement per line, blocks always broken over lines, and parentheses only
+//! where the parser needs them. Printing what the parser made of a program
+//! and parsing it again gives the same tree.
+//!
+//! The formatter prints through the same `Printer`, handing it the source and
+//! its comments so they can be put back where they were.
+
+use std::fmt;
+
+use crate::ast::{
+    AssignOperator, Attribute, BinaryOperator, Block, Enum, Expression, ExternFunction, Field, Function, Global, Import,
+    MatchArm, MatchBody, Parameter, Parameters, Pattern, PostfixOperator, PrefixOperator, Program, Span,
+    Statement, Struct, TypeParam, UnaryOperator, Variant,
+};
+use crate::lexer::Comment;
+
+const INDENT: &str = "    ";
+
+/// Writes nodes out as source
+pub struct Printer<'a> {
+    out: String,
+    indent: usize,
+    trivia: Option<Trivia<'a>>,
+}
+
+/// The source being formatted, and its comments
+struct Trivia<'a> {
+    source: Vec<char>,
+    comments: &'a [Comment],
+    /// First comment not printed yet
+    next: usize,
+    /// Where what was printed last ends, to look for blank lines after it
+    printed: usize,
+}
+
+impl Trivia<'_> {
+    /// Number of line breaks in the source between two positions
+    fn newlines(&self, from: usize, to: usize) -> usize {
+        let to = to.min(self.source.len());
+        let from = from.min(to);
+        self.source[from..to].iter().filter(|c| **c == '\n').count()
+    }
+
+    /// The next comment, if it starts before `limit`
+    fn pending(&self, limit: usize) -> Option<&Comment> {
+        self.comments.get(self.next).filter(|comment| comment.span.start < limit)
+    }
+}
+
+/// Whether a blank line goes before a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Gap {
+    Never,
+    Always,
+    /// If there's one in the source
+    Source,
+}
+
+/// A top level declaration, which the program keeps by kind
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Global(&'a Global),
+    Extern(&'a ExternFunction),
+    Struct(&'a Struct),
+    Enum(&'a Enum),
+    Function(&'a Function),
+}
+
+impl Item<'_> {
+    fn start(&self) -> usize {
+        match self {
+            Item::Global(global) => global.span.start,
+            Item::Extern(function) => function.span.start,
+            Item::Struct(declaration) => declaration.span.start,
+            Item::Enum(declaration) => declaration.span.start,
+            Item::Function(function) => function.attributes.first().map_or(function.span.start, |a| a.span.start),
+        }
+    }
+
+    /// Consecutive one line declarations of the same kind are kept together
+    fn groups_with(&self, other: &Item) -> bool {
+        matches!((self, other), (Item::Global(_), Item::Global(_)) | (Item::Extern(_), Item::Extern(_)))
+    }
+}
+
+impl<'a> Printer<'a> {
+    pub fn new() -> Self {
+        Self { out: String::new(), indent: 0, trivia: None }
+    }
+
+    /// A printer putting back the comments of `source`, the text the nodes
+    /// it prints were parsed from
+    pub fn with_comments(source: &str, comments: &'a [Comment]) -> Self {
+        Self {
+            trivia: Some(Trivia { source: source.chars().collect(), comments, next: 0, printed: 0 }),
+            ..Self::new()
+        }
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn start_line(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn end_line(&mut self) {
+        self.out.push('\n');
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    /// Print each of `items` with `print`, separated by commas
+    fn list<T>(&mut self, items: &[T], mut print: impl FnMut(&mut Self, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            print(self, item);
+        }
+    }
+
+    fn gap(&mut self, at: usize, gap: Gap) {
+        let blank = match (gap, &self.trivia) {
+            (Gap::Always, _) => true,
+            (Gap::Source, Some(trivia)) => trivia.newlines(trivia.printed, at) > 1,
+            _ => false,
+        };
+
+        if blank {
+            self.end_line();
+        }
+    }
+
+    /// Note that the source up to `end` has been printed
+    fn printed(&mut self, end: usize) {
+        if let Some(trivia) = &mut self.trivia {
+            trivia.printed = trivia.printed.max(end);
+        }
+    }
+
+    /// Print the comments before `start` on lines of their own, then the
+    /// blank line going before whatever starts there
+    /// `gap` is for the first line printed; blank lines after that are
+    /// kept from the source
+    fn leading(&mut self, start: usize, mut gap: Gap) {
+        while let Some(comment) = self.trivia.as_ref().and_then(|trivia| trivia.pending(start)).cloned() {
+            self.gap(comment.span.start, gap);
+            gap = Gap::Source;
+
+            self.start_line();
+            self.write(&comment.text);
+            self.end_line();
+
+            let trivia = self.trivia.as_mut().unwrap();
+            trivia.next += 1;
+            trivia.printed = comment.span.end;
+        }
+
+        self.gap(start, gap);
+    }
+
+    /// Print, at the end of the current line, the comments inside what was
+    /// printed up to `end` and those following it on the same line, unless
+    /// they start at `limit` or after
+    fn trailing(&mut self, end: usize, limit: usize) {
+        while let Some(trivia) = &self.trivia {
+            let Some(comment) = trivia.pending(limit).cloned() else {
+                break;
+            };
+
+            if comment.span.start >= end && trivia.newlines(end, comment.span.start) > 0 {
+                break;
+            }
+
+            self.write(" ");
+            self.write(&comment.text);
+
+            let trivia = self.trivia.as_mut().unwrap();
+            trivia.next += 1;
+            trivia.printed = trivia.printed.max(comment.span.end);
+
+            // nothing more fits on the line after a line comment
+            if comment.is_line() {
+                break;
+            }
+        }
+
+        self.printed(end);
+    }
+
+    pub fn program(&mut self, program: &Program) {
+        let mut gap = Gap::Never;
+
+        for import in &program.imports {
+            self.leading(import.span.start, gap);
+            gap = Gap::Source;
+
+            self.start_line();
+            self.import(import);
+            self.trailing(import.span.end, usize::MAX);
+            self.end_line();
+        }
+
+        // declarations are kept in the order they were written in, or
+        // failing that with the ones functions use first
+        let mut items: Vec<Item> = program.globals.iter().map(Item::Global)
+            .chain(program.externs.iter().map(Item::Extern))
+            .chain(program.structs.iter().map(Item::Struct))
+            .chain(program.enums.iter().map(Item::Enum))
+            .chain(program.functions.iter().map(Item::Function))
+            .collect();
+        items.sort_by_key(Item::start);
+
+        let mut previous: Option<Item> = None;
+        for item in items {
+            let gap = match previous {
+                None if program.imports.is_empty() => Gap::Never,
+                Some(previous) if previous.groups_with(&item) => Gap::Source,
+                _ => Gap::Always,
+            };
+            self.leading(item.start(), gap);
+
+            match item {
+                Item::Global(global) => self.global(global),
+                Item::Extern(function) => self.extern_function(function),
+                Item::Struct(declaration) => self.struct_declaration(declaration),
+                Item::Enum(declaration) => self.enum_declaration(declaration),
+                Item::Function(function) => self.function(function),
+            }
+
+            previous = Some(item);
+        }
+
+        // comments at the end of the file
+        let gap = if previous.is_some() || !program.imports.is_empty() { Gap::Source } else { Gap::Never };
+        self.leading(usize::MAX, gap);
+    }
+
+    fn import(&mut self, import: &Import) {
+        if import.path == format!("{}.ken", import.module) {
+            self.write(&format!("import {};", import.module));
+        } else {
+            self.write(&format!("import \"{}\";", import.path));
+        }
+    }
+
+    fn public(&mut self, public: bool) {
+        if public {
+            self.write("pub ");
+        }
+    }
+
+    fn global(&mut self, global: &Global) {
+        self.start_line();
+        self.public(global.public);
+        self.write(if global.constant { "const " } else { "let " });
+        self.write(&global.ident);
+        if let Some(global_type) = &global.global_type {
+            self.write(&format!(": {}", global_type));
+        }
+        self.write(" = ");
+        self.expression(&global.value);
+        self.write(";");
+        self.trailing(global.span.end, usize::MAX);
+        self.end_line();
+    }
+
+    fn extern_function(&mut self, function: &ExternFunction) {
+        self.start_line();
+        self.public(function.public);
+        self.write(&format!("extern func {}", function.ident));
+        self.parameters(&function.params);
+        self.write(&format!(": {};", function.return_type));
+        self.trailing(function.span.end, usize::MAX);
+        self.end_line();
+    }
+
+    fn struct_declaration(&mut self, declaration: &Struct) {
+        self.start_line();
+        self.public(declaration.public);
+        self.write(&format!("struct {} ", declaration.ident));
+        self.members(&declaration.fields, &declaration.span, |field| &field.span, Self::field);
+        self.trailing(declaration.span.end, usize::MAX);
+        self.end_line();
+    }
+
+    fn field(&mut self, field: &Field) {
+        self.write(&format!("{}: {}", field.ident, field.field_type));
+    }
+
+    fn enum_declaration(&mut self, declaration: &Enum) {
+        self.start_line();
+        self.public(declaration.public);
+        self.write(&format!("enum {} ", declaration.ident));
+        self.members(&declaration.variants, &declaration.span, |variant| &variant.span, Self::variant);
+        self.trailing(declaration.span.end, usize::MAX);
+        self.end_line();
+    }
+
+    fn variant(&mut self, variant: &Variant) {
+        self.write(&variant.ident);
+        if !variant.fields.is_empty() {
+            self.write("(");
+            self.list(&variant.fields, |printer, field| printer.write(&field.to_string()));
+            self.write(")");
+        }
+    }
+
+    /// The braced fields of a struct or variants of an enum, one per line
+    fn members<T>(
+        &mut self,
+        members: &[T],
+        span: &Span,
+        member_span: impl Fn(&T) -> &Span,
+        print: impl Fn(&mut Self, &T),
+    ) {
+        let starts: Vec<usize> = members.iter().map(|member| member_span(member).start).collect();
+        self.braced(span.start, span.end, &starts, |printer, i| {
+            printer.start_line();
+            print(printer, &members[i]);
+            printer.write(",");
+            member_span(&members[i]).end
+        });
+    }
+
+    /// `{`, the lines `print` prints, each starting at one of `starts`,
+    /// and `}`, or `{}` if there's nothing in between
+    /// `print` prints a line up to its trailing comments, and returns the
+    /// end of what it printed
+    fn braced(&mut self, open: usize, close: usize, starts: &[usize], mut print: impl FnMut(&mut Self, usize) -> usize) {
+        self.write("{");
+
+        let empty = starts.is_empty()
+            && self.trivia.as_ref().and_then(|trivia| trivia.pending(close)).is_none();
+        if empty {
+            self.write("}");
+            self.printed(close);
+            return;
+        }
+
+        // comments on the line of the `{`
+        self.trailing(open + 1, starts.first().copied().unwrap_or(close));
+        self.end_line();
+        self.indent += 1;
+
+        for (i, start) in starts.iter().enumerate() {
+            self.leading(*start, if i == 0 { Gap::Never } else { Gap::Source });
+            let end = print(self, i);
+            self.trailing(end, starts.get(i + 1).copied().unwrap_or(close));
+            self.end_line();
+        }
+
+        // comments after the last line
+        let gap = if starts.is_empty() { Gap::Never } else { Gap::Source };
+        self.leading(close.saturating_sub(1), gap);
+
+        self.indent -= 1;
+        self.start_line();
+        self.write("}");
+        self.printed(close);
+    }
+
+    fn function(&mut self, function: &Function) {
+        // attributes go on lines of their own
+        for attribute in &function.attributes {
+            self.start_line();
+            self.attribute(attribute);
+            self.trailing(attribute.span.end, function.span.start);
+            self.end_line();
+        }
+        if !function.attributes.is_empty() {
+            self.leading(function.span.start, Gap::Never);
+        }
+
+        self.start_line();
+        self.public(function.public);
+        self.write(&format!("func {}", function.ident));
+        if function.is_generic() {
+            self.write("<");
+            self.list(&function.type_params, |printer, type_param| printer.type_param(type_param));
+            self.write(">");
+        }
+        self.parameters(&function.params);
+        self.write(&format!(": {} ", function.return_type));
+        self.block(&function.body);
+        self.trailing(function.span.end, usize::MAX);
+        self.end_line();
+    }
+
+    fn attribute(&mut self, attribute: &Attribute) {
+        self.write(&format!("@{}", attribute.ident));
+        if !attribute.arguments.is_empty() {
+            self.write(&format!("({})", attribute.arguments.join(", ")));
+        }
+    }
+
+    fn type_param(&mut self, type_param: &TypeParam) {
+        self.write(&type_param.ident);
+        if let Some(bound) = type_param.bound {
+            self.write(&format!(": {}", bound));
+        }
+    }
+
+    fn parameters(&mut self, params: &Parameters) {
+        self.write("(");
+        self.list(&params.params, |printer, param| printer.parameter(param));
+        self.write(")");
+    }
+
+    fn parameter(&mut self, param: &Parameter) {
+        self.write(&format!("{}: {}", param.ident, param.param_type));
+    }
+
+    pub fn block(&mut self, block: &Block) {
+        let starts: Vec<usize> = block.statements.iter().map(|statement| statement.span().start).collect();
+        self.braced(block.span.start, block.span.end, &starts, |printer, i| {
+            let statement = &block.statements[i];
+            printer.start_line();
+            printer.statement(statement);
+            statement.span().end
+        });
+    }
+
+    /// A statement, from the start of its line up to its trailing comments
+    pub fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::If { .. } => self.if_statement(statement),
+
+            Statement::Block { block, .. } => self.block(block),
+
+            Statement::While { condition, body, .. } => {
+                self.write("while (");
+                self.expression(condition);
+                self.write(") ");
+                self.block(body);
+            }
+
+            Statement::DoUntil { condition, body, .. } => {
+                self.write("do ");
+                self.branch(body);
+                self.write(" until (");
+                self.expression(condition);
+                self.write(")");
+            }
+
+            Statement::For { init, condition, increment, body, .. } => {
+                self.write("for (");
+                self.simple_statement(init);
+                self.write("; ");
+                self.expression(condition);
+                self.write("; ");
+                self.simple_statement(increment);
+                self.write(") ");
+                self.block(body);
+            }
+
+            Statement::Return { value: None, .. } => self.write("return;"),
+
+            Statement::Return { value: Some(value), .. } => {
+                self.write("return ");
+                self.expression(value);
+                self.write(";");
+            }
+
+            _ => {
+                self.simple_statement(statement);
+                self.write(";");
+            }
+        }
+    }
+
+    /// `if (...) {...} else if (...) {...} else {...}`
+    fn if_statement(&mut self, statement: &Statement) {
+        let Statement::If { condition, then_branch, else_branch, .. } = statement else {
+            return self.statement(statement);
+        };
+
+        self.write("if (");
+        self.expression(condition);
+        self.write(") ");
+        self.branch(then_branch);
+
+        if let Some(else_branch) = else_branch {
+            self.write(" else ");
+            if matches!(**else_branch, Statement::If { .. }) {
+                self.if_statement(else_branch);
+            } else {
+                self.branch(else_branch);
+            }
+        }
+    }
+
+    /// The body of an `if` or `do`, which is a block
+    fn branch(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Block { block, .. } => self.block(block),
+            statement => {
+                let block = Block { statements: vec![statement.clone()], span: statement.span().clone() };
+                self.block(&block)
+            }
+        }
+    }
+
+    /// A statement that can go in the header of a `for`, without its `;`
+    fn simple_statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::VariableDeclaration { ident, var_type, value, .. } => {
+                self.write(&format!("let {}", ident));
+                if let Some(var_type) = var_type {
+                    self.write(&format!(": {}", var_type));
+                }
+                self.write(" = ");
+                self.expression(value);
+            }
+
+            Statement::Assign { ident, value, .. } => {
+                self.write(&format!("{} = ", ident));
+                self.expression(value);
+            }
+
+            Statement::Expression { expression, .. } => self.expression(expression),
+
+            // not allowed in a for header, but printed as written
+            statement => self.statement(statement),
+        }
+    }
+
+    pub fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::IntegerLiteral { value, .. } => self.write(&value.to_string()),
+
+            Expression::FloatLiteral { value, .. } => self.write(&float_literal(*value)),
+
+            Expression::StringLiteral { value, .. } => self.write(&format!("\"{}\"", value)),
+
+            Expression::BooleanLiteral { value, .. } => self.write(&value.to_string()),
+
+            Expression::NullLiteral { .. } => self.write("null"),
+
+            Expression::Identifier { ident, .. } => self.write(ident),
+
+            Expression::Binary { left, operator, right, .. } => {
+                let binding = binding(expression);
+                self.operand(left, binding);
+                self.write(&format!(" {} ", operator));
+                self.operand(right, binding + 1);
+            }
+
+            Expression::Unary { operator, right, .. } => {
+                self.write(&operator.to_string());
+                self.prefixed(right, *operator == UnaryOperator::Minus);
+            }
+
+            Expression::Prefix { operator, right, .. } => {
+                self.write(&operator.to_string());
+                self.prefixed(right, *operator == PrefixOperator::MinusMinus);
+            }
+
+            Expression::Grouping { expression, .. } => {
+                self.write("(");
+                self.expression(expression);
+                self.write(")");
+            }
+
+            Expression::Function { params, return_type, body, .. } => {
+                self.write("func");
+                self.parameters(params);
+                self.write(&format!(": {} ", return_type));
+                self.block(body);
+            }
+
+            Expression::Call { callee, arguments, .. } => {
+                self.operand(callee, POSTFIX);
+                self.arguments(arguments);
+            }
+
+            Expression::Postfix { left, operator, .. } => {
+                self.operand(left, POSTFIX);
+                self.write(&operator.to_string());
+            }
+
+            Expression::Assign { left, operator, right, .. } => {
+                // right associative
+                self.operand(left, ASSIGN + 1);
+                self.write(&format!(" {} ", operator));
+                self.operand(right, ASSIGN);
+            }
+
+            Expression::Cast { expression, target_type, .. } => {
+                self.operand(expression, POSTFIX);
+                self.write(&format!(" as {}", target_type));
+            }
+
+            Expression::Index { target, index, .. } => {
+                self.operand(target, POSTFIX);
+                self.write("[");
+                self.expression(index);
+                self.write("]");
+            }
+
+            Expression::Slice { target, start, end, .. } => {
+                self.operand(target, POSTFIX);
+                self.write("[");
+                if let Some(start) = start {
+                    self.expression(start);
+                }
+                self.write(":");
+                if let Some(end) = end {
+                    self.expression(end);
+                }
+                self.write("]");
+            }
+
+            Expression::ArrayLiteral { elements, .. } => {
+                self.write("[");
+                self.list(elements, Self::expression);
+                self.write("]");
+            }
+
+            Expression::StructLiteral { ident, fields, .. } => {
+                self.write(ident);
+                if fields.is_empty() {
+                    self.write(" {}");
+                } else {
+                    self.write(" { ");
+                    self.list(fields, |printer, (field, value)| {
+                        printer.write(&format!("{}: ", field));
+                        printer.expression(value);
+                    });
+                    self.write(" }");
+                }
+            }
+
+            Expression::Field { target, field, .. } => {
+                self.operand(target, POSTFIX);
+                self.write(&format!(".{}", field));
+            }
+
+            Expression::Variant { enum_ident, variant, arguments, .. } => {
+                self.write(&format!("{}::{}", enum_ident, variant));
+                if !arguments.is_empty() {
+                    self.arguments(arguments);
+                }
+            }
+
+            Expression::Match { scrutinee, arms, span } => {
+                self.write("match (");
+                self.expression(scrutinee);
+                self.write(") ");
+
+                let starts: Vec<usize> = arms.iter().map(|arm| arm.pattern.span().start).collect();
+                self.braced(scrutinee.span().end, span.end, &starts, |printer, i| {
+                    printer.start_line();
+                    printer.match_arm(&arms[i]);
+                    match &arms[i].body {
+                        MatchBody::Expression(expression) => expression.span().end,
+                        MatchBody::Block(block) => block.span.end,
+                    }
+                });
+            }
+        }
+    }
+
+    /// An operand that must bind at least as tightly as `binding`, or be
+    /// put in parentheses
+    fn operand(&mut self, expression: &Expression, binding_at_least: u8) {
+        if binding(expression) < binding_at_least {
+            self.write("(");
+            self.expression(expression);
+            self.write(")");
+        } else {
+            self.expression(expression);
+        }
+    }
+
+    /// The operand of a prefix operator
+    /// After a `-`, one starting with `-` is parenthesised, so the two
+    /// don't lex as `--`
+    fn prefixed(&mut self, expression: &Expression, after_minus: bool) {
+        let mut operand = Printer { out: String::new(), indent: self.indent, trivia: self.trivia.take() };
+        operand.operand(expression, UNARY);
+        self.trivia = operand.trivia.take();
+
+        if after_minus && operand.out.starts_with('-') {
+            self.write(&format!("({})", operand.out));
+        } else {
+            self.write(&operand.out);
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Expression]) {
+        self.write("(");
+        self.list(arguments, Self::expression);
+        self.write(")");
+    }
+
+    /// `pattern => value,` or `pattern => {...}`
+    fn match_arm(&mut self, arm: &MatchArm) {
+        self.pattern(&arm.pattern);
+        self.write(" => ");
+        match &arm.body {
+            MatchBody::Expression(expression) => {
+                self.expression(expression);
+                self.write(",");
+            }
+            MatchBody::Block(block) => self.block(block),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Variant { enum_ident, variant, bindings, .. } => {
+                if let Some(enum_ident) = enum_ident {
+                    self.write(&format!("{}::", enum_ident));
+                }
+                self.write(variant);
+                if !bindings.is_empty() {
+                    self.write(&format!("({})", bindings.join(", ")));
+                }
+            }
+            Pattern::Integer { value, .. } => self.write(&value.to_string()),
+            Pattern::Boolean { value, .. } => self.write(&value.to_string()),
+            Pattern::Wildcard { .. } => self.write("_"),
+        }
+    }
+}
+
+impl Default for Printer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// How tightly expressions bind, as the parser sees them: `==` and `<` are
+// at the same level, and casts are postfix
+const ASSIGN: u8 = 1;
+const UNARY: u8 = 7;
+const POSTFIX: u8 = 8;
+const PRIMARY: u8 = 9;
+
+fn binding(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Assign { .. } => ASSIGN,
+        Expression::Binary { operator, .. } => match operator {
+            BinaryOperator::Or => 2,
+            BinaryOperator::And => 3,
+            BinaryOperator::EqualEqual
+            | BinaryOperator::BangEqual
+            | BinaryOperator::Greater
+            | BinaryOperator::GreaterEqual
+            | BinaryOperator::Less
+            | BinaryOperator::LessEqual => 4,
+            BinaryOperator::Plus | BinaryOperator::Minus => 5,
+            BinaryOperator::Star | BinaryOperator::Slash | BinaryOperator::StarStar | BinaryOperator::SlashSlash => 6,
+        },
+        Expression::Unary { .. } | Expression::Prefix { .. } => UNARY,
+        // a negative literal is written with a `-`
+        Expression::IntegerLiteral { value, .. } if *value < 0 => UNARY,
+        Expression::FloatLiteral { value, .. } if value.is_sign_negative() => UNARY,
+        Expression::Call { .. }
+        | Expression::Postfix { .. }
+        | Expression::Cast { .. }
+        | Expression::Index { .. }
+        | Expression::Slice { .. }
+        | Expression::Field { .. } => POSTFIX,
+        _ => PRIMARY,
+    }
+}
+
+/// A float as the lexer reads it back, which always has a `.`
+fn float_literal(value: f64) -> String {
+    let text = value.to_string();
+    if text.contains('.') || !value.is_finite() {
+        text
+    } else {
+        format!("{}.0", text)
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Star => "*",
+            BinaryOperator::Slash => "/",
+            BinaryOperator::StarStar => "**",
+            BinaryOperator::SlashSlash => "//",
+            BinaryOperator::EqualEqual => "==",
+            BinaryOperator::BangEqual => "!=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Or => "or",
+            BinaryOperator::And => "and",
+        })
+    }
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Minus => "-",
+            UnaryOperator::Bang => "!",
+        })
+    }
+}
+
+impl fmt::Display for PostfixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PostfixOperator::PlusPlus => "++",
+            PostfixOperator::MinusMinus => "--",
+        })
+    }
+}
+
+impl fmt::Display for PrefixOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PrefixOperator::PlusPlus => "++",
+            PrefixOperator::MinusMinus => "--",
+        })
+    }
+}
+
+impl fmt::Display for AssignOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            AssignOperator::Equal => "=",
+            AssignOperator::PlusEqual => "+=",
+            AssignOperator::MinusEqual => "-=",
+            AssignOperator::StarEqual => "*=",
+            AssignOperator::SlashEqual => "/=",
+        })
+    }
+}
+
+/// Display nodes by printing them, without a newline at the end
+macro_rules! display {
+    ($($node:ty => $print:ident),* $(,)?) => {
+        $(
+            impl fmt::Display for $node {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    let mut printer = Printer::new();
+                    printer.$print(self);
+                    f.write_str(printer.finish().trim_end_matches('\n'))
+                }
+            }
+        )*
+    };
+}
+
+display! {
+    Import => import,
+    Global => global,
+    ExternFunction => extern_function,
+    Struct => struct_declaration,
+    Field => field,
+    Enum => enum_declaration,
+    Variant => variant,
+    Function => function,
+    Attribute => attribute,
+    TypeParam => type_param,
+    Parameters => parameters,
+    Parameter => parameter,
+    Block => block,
+    Statement => statement,
+    Expression => expression,
+    MatchArm => match_arm,
+    Pattern => pattern,
+}
+
+/// A whole program, ending with a newline like a source file
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer::new();
+        printer.program(self);
+        f.write_str(&printer.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Program;
+    use crate::lexer;
+    use crate::parser::Parser;
+
+    fn parse(source: &str) -> Program {
+        Parser::new(lexer::lex(source.to_string()).unwrap()).parse().unwrap()
+    }
+
+    /// The body of `main` in `source`, printed
+    fn body(source: &str) -> String {
+        let program = parse(&format!("func main(): int {{ {} }}", source));
+        program.functions[0].body.statements.iter().map(|statement| statement.to_string()).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn test_expressions() {
+        for (source, printed) in [
+            ("1 - (2 - 3);", "1 - (2 - 3);"),
+            ("(1 - 2) - 3;", "1 - 2 - 3;"),
+            ("(1 + 2) * 3;", "(1 + 2) * 3;"),
+            ("1 + (2 * 3);", "1 + 2 * 3;"),
+            ("a == (b < c);", "a == (b < c);"),
+            ("(a == b) < c;", "a == b < c;"),
+            ("a or (b and c);", "a or b and c;"),
+            ("(a or b) and not c;", "(a or b) and !c;"),
+            ("-(-x);", "-(-x);"),
+            ("-(--x);", "-(--x);"),
+            ("(-x) as u8;", "(-x) as u8;"),
+            ("-(x as u8);", "-x as u8;"),
+            ("(a + b).len;", "(a + b).len;"),
+            ("x = (y = 1);", "x = y = 1;"),
+            ("(x = y) == 1;", "(x = y) == 1;"),
+            ("f(1, 2.0)[0][1:](\"s\");", "f(1, 2.0)[0][1:](\"s\");"),
+            ("s[:n]; s[n:];", "s[:n];\ns[n:];"),
+            ("x = 3.0;", "x = 3.0;"),
+            ("Shape::Circle(1.5); Shape::Empty;", "Shape::Circle(1.5);\nShape::Empty;"),
+            ("Point {}; Point { x: 1.0, y: 2.0 };", "Point {};\nPoint { x: 1.0, y: 2.0 };"),
+            ("i++; --i;", "i++;\n--i;"),
+            ("return;", "return;"),
+        ] {
+            assert_eq!(body(source), printed, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_statements() {
+        let printed = body(r#"
+let x: int = 0;
+if (x) { x = 1; } else if (y) {} else { return x; }
+for (i = 0; i < 3; i += 1) { do { x--; } until (x < 0) }
+let s = match (x) { 1 => func(a: int): int { return a; }, Shape::Rect(w, _) => { x++; } true => 0 };
+"#);
+        assert_eq!(printed, "\
+let x: int = 0;
+if (x) {
+    x = 1;
+} else if (y) {} else {
+    return x;
+}
+for (i = 0; i < 3; i += 1) {
+    do {
+        x--;
+    } until (x < 0)
+}
+let s = match (x) {
+    1 => func(a: int): int {
+        return a;
+    },
+    Shape::Rect(w, _) => {
+        x++;
+    }
+    true => 0,
+};");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+import "lib/math.ken";
+enum Shape { Circle(float), Empty }
+const LIMIT: u8 = 200;
+pub let names: string[] = ["a"];
+struct Pair { first: [int; 2], next: func(int): bool }
+pub extern func abs(x: i32): i32;
+func max<T: numeric, U>(a: T, b: T): T {
+    if (a > b) { return a; }
+    return b;
+}
+@inline pub func sq(x: int): int { return x * x; }
+@noinline() @other(a, b)
+func cube(x: int): int { return x * sq(x); }
+"#;
+        let printed = parse(source).to_string();
+        assert_eq!(printed, "\
+import \"lib/math.ken\";
+
+enum Shape {
+    Circle(float),
+    Empty,
+}
+
+const LIMIT: u8 = 200;
+pub let names: string[] = [\"a\"];
+
+struct Pair {
+    first: [int; 2],
+    next: func(int): bool,
+}
+
+pub extern func abs(x: i32): i32;
+
+func max<T: numeric, U>(a: T, b: T): T {
+    if (a > b) {
+        return a;
+    }
+    return b;
+}
+
+@inline
+pub func sq(x: int): int {
+    return x * x;
+}
+
+@noinline
+@other(a, b)
+func cube(x: int): int {
+    return x * sq(x);
+}
+");
+        assert_eq!(parse(&printed).to_string(), printed);
+    }
+}
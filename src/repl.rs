@@ -0,0 +1,545 @@
+//! An interactive session, compiling what's typed as it's typed
+//!
+//! `kennedy repl` takes definitions and statements one input at a time.
+//! Each is compiled into the same JIT module as the ones before by
+//! [`Compiler::update`], so what one input defines can be used by the next,
+//! and defining a function again replaces it, callers and all.
+//!
+//! Statements are compiled into a function of their own, which is called
+//! straight away. The value of an expression ending an input is shown with
+//! its type, as are variables declared outside any block. Those of numbers,
+//! `bool` and `string` become globals, which later inputs can use; the rest
+//! only live as long as their input, which a warning says.
+//!
+//! A trap stops the input with an error, leaving the session as the input
+//! left it, and leaking what the input's compiled code held. Imports aren't
+//! supported.
+
+use std::mem;
+
+use crate::ast::{self, Block, Expression, Parameters, Statement, Type};
+use crate::compiler::runtime::{self, KennedyArray, KennedyString, Trap, STRING_HEADER_SIZE, STRUCT_FIELDS_OFFSET};
+use crate::compiler::{Compiler, CompilerOptions};
+use crate::error::{CompileError, CompileResult, Span};
+use crate::lexer::{lex, Token, TokenType};
+use crate::modules::SourceMap;
+use crate::parser::Parser;
+
+/// Name of the function an input's statements are compiled into, which no
+/// function typed in can have
+const INPUT: &str = "<input>";
+
+pub struct Repl {
+    compiler: Compiler,
+    /// Every input so far, laid end to end
+    sources: SourceMap,
+    /// Everything defined so far, the latest definition of each name
+    /// having replaced the others
+    definitions: ast::Program,
+    /// Number of inputs so far
+    inputs: usize,
+}
+
+/// What evaluating an input has to show
+#[derive(Debug, Default)]
+pub struct Evaluated {
+    /// The variables the input declared, i.e. `x: int = 1`, then the value
+    /// of the expression ending it, i.e. `2: int`
+    pub values: Vec<String>,
+    /// What the checks and lints found in it
+    pub warnings: Vec<CompileError>,
+    /// The trap that stopped it, if one did, in which case there are no
+    /// values. Its location is within the input the trapping code is from
+    pub trap: Option<Trap>,
+}
+
+impl Repl {
+    pub fn new(options: CompilerOptions) -> CompileResult<Self> {
+        // functions typed in again are swapped in where that's supported,
+        // rather than compiling their callers again
+        let options = options.hotswap(cfg!(target_arch = "x86_64"));
+
+        Ok(Self {
+            compiler: Compiler::new(options)?,
+            sources: SourceMap::default(),
+            definitions: ast::Program::default(),
+            inputs: 0,
+        })
+    }
+
+    /// Whether an input has `{` or `(` left open, so the next line should
+    /// be read as more of it
+    /// Input that doesn't lex is complete, so it's reported as it is
+    pub fn is_incomplete(input: &str) -> bool {
+        let Ok(tokens) = lex(input.to_string()) else {
+            return false;
+        };
+
+        let (mut braces, mut parens) = (0, 0);
+        for token in &tokens {
+            match token.token_type {
+                TokenType::LeftBrace => braces += 1,
+                TokenType::RightBrace => braces -= 1,
+                TokenType::LeftParen => parens += 1,
+                TokenType::RightParen => parens -= 1,
+                _ => {}
+            }
+        }
+
+        braces > 0 || parens > 0
+    }
+
+    /// Compile and run an input, returning what to show for it
+    /// Errors (and warnings) point into the input
+    pub fn eval(&mut self, input: &str) -> CompileResult<Evaluated> {
+        self.inputs += 1;
+        let name = format!("<input {}>", self.inputs);
+        let warnings = self.compiler.warnings().len();
+
+        // errors are attributed to the input they're in, and only those in
+        // others need saying which
+        let local = |error: CompileError| match error {
+            CompileError::InFile { path, error, .. } if path == name => *error,
+            error => error,
+        };
+
+        let mut evaluated = self.eval_input(&name, input)
+            .map_err(|error| local(self.sources.attribute(error)))?;
+
+        // a variable that only lasts for the input gets a warning saying so,
+        // rather than one that it's never used
+        let notes: Vec<CompileError> = evaluated.warnings.drain(..).map(|note| local(self.sources.attribute(note))).collect();
+        let noted = |warning: &CompileError| notes.iter().any(|note| match (note, warning) {
+            (CompileError::Warning(_, noted), CompileError::Warning(_, span)) => noted == span,
+            _ => false,
+        });
+
+        evaluated.warnings = self.compiler.warnings()[warnings..].iter()
+            .map(|warning| local(clone(warning)))
+            .filter(|warning| !noted(warning))
+            .collect();
+        evaluated.warnings.extend(notes);
+
+        Ok(evaluated)
+    }
+
+    /// What to show for an input, its only warnings being about the
+    /// variables it declares that only last for it
+    fn eval_input(&mut self, name: &str, input: &str) -> CompileResult<Evaluated> {
+        let tokens = self.sources.lex(Some(name.to_string()), input.to_string())?;
+
+        if is_definition(&tokens) {
+            let program = Parser::new(tokens).parse()?;
+            if let Some(import) = program.imports.first() {
+                return Err(CompileError::SemanticError("Imports aren't supported in the REPL".to_string(), import.span.clone()));
+            }
+
+            let mut definitions = self.definitions.clone();
+            define(&mut definitions, program);
+            self.compiler.update(&definitions, &self.sources)?;
+            self.definitions = definitions;
+            return Ok(Evaluated::default());
+        }
+
+        if tokens.len() == 1 {
+            return Ok(Evaluated::default());
+        }
+
+        let statements = parse_statements(tokens)?;
+        let span = Span {
+            start: statements.first().map_or(0, |statement| statement.span().start),
+            end: statements.last().map_or(0, |statement| statement.span().end),
+        };
+        let mut function = ast::Function {
+            attributes: Vec::new(),
+            ident: INPUT.to_string(),
+            ident_span: Span::default(),
+            public: true,
+            type_params: Vec::new(),
+            params: Parameters { params: Vec::new() },
+            return_type: Type::Null,
+            body: Block { statements, span: span.clone() },
+            span,
+        };
+
+        // checked as typed first, for the types of what it declares and ends
+        // with
+        let mut program = self.definitions.clone();
+        program.functions.push(function.clone());
+        let checker = self.compiler.check(&program)?;
+        program.functions.pop();
+
+        let mut variables = Vec::new();
+        let mut notes = Vec::new();
+        for statement in &mut function.body.statements {
+            let Statement::VariableDeclaration { ident, var_type, value, span } = statement else {
+                continue;
+            };
+            let Some(variable_type) = var_type.clone().or_else(|| checker.expression_type(INPUT, value.span()).cloned()) else {
+                continue;
+            };
+            let Some(zero) = zero(&variable_type, span) else {
+                notes.push(CompileError::Warning(
+                    format!("`{}` only lasts for this input, as only numbers, bools and strings are kept", ident),
+                    span.clone(),
+                ));
+                continue;
+            };
+
+            // the variable becomes a global, set by the input
+            program.globals.retain(|global| global.ident != *ident);
+            program.globals.push(ast::Global {
+                ident: ident.clone(),
+                ident_span: span.clone(),
+                public: false,
+                constant: false,
+                global_type: Some(variable_type.clone()),
+                value: zero,
+                span: span.clone(),
+            });
+            variables.push((ident.clone(), variable_type));
+
+            *statement = Statement::Assign { ident: ident.clone(), value: value.clone(), span: span.clone() };
+        }
+
+        if let Some(Statement::Expression { expression, span }) = function.body.statements.last() {
+            if let Some(value_type) = checker.expression_type(INPUT, expression.span()).filter(|ty| **ty != Type::Null) {
+                function.return_type = value_type.clone();
+                *function.body.statements.last_mut().unwrap() = Statement::Return {
+                    value: Some(expression.clone()),
+                    span: span.clone(),
+                };
+            }
+        }
+
+        let return_type = function.return_type.clone();
+        program.functions.push(function);
+        self.compiler.update(&program, &self.sources)?;
+        program.functions.pop();
+        self.definitions = program;
+
+        let value = match self.run(&return_type)? {
+            Ok(value) => value,
+            Err(trap) => return Ok(Evaluated { values: Vec::new(), warnings: notes, trap: Some(trap) }),
+        };
+
+        let mut values = Vec::new();
+        for (ident, variable_type) in variables {
+            let address = self.compiler.get_global(&ident)?;
+            values.push(format!("{}: {} = {}", ident, variable_type, unsafe { self.show(&variable_type, address, false) }));
+        }
+        values.extend(value);
+
+        Ok(Evaluated { values, warnings: notes, trap: None })
+    }
+
+    /// Call the function an input compiled into, returning its value as
+    /// it's shown, if it has one, or the trap that stopped it
+    fn run(&self, return_type: &Type) -> CompileResult<Result<Option<String>, Trap>> {
+        let address = self.compiler.get_function(INPUT)?;
+
+        // the value is kept in memory, as fields and elements are
+        let mut value = [0u8; 8];
+        let caught = runtime::catch_traps(|| unsafe {
+            match return_type {
+                Type::Null => call::<()>(address),
+                Type::Float => store(&mut value, call::<f32>(address)),
+                Type::F64 => store(&mut value, call::<f64>(address)),
+                Type::Bool | Type::I8 | Type::U8 => store(&mut value, call::<u8>(address)),
+                Type::I16 | Type::U16 => store(&mut value, call::<u16>(address)),
+                Type::I32 | Type::U32 => store(&mut value, call::<u32>(address)),
+                _ => store(&mut value, call::<u64>(address)),
+            }
+        });
+
+        if let Err(trap) = caught {
+            return Ok(Err(trap));
+        }
+        if *return_type == Type::Null {
+            return Ok(Ok(None));
+        }
+
+        unsafe {
+            let shown = format!("{}: {}", self.show(return_type, value.as_ptr(), false), return_type);
+            release(return_type, value.as_ptr());
+            Ok(Ok(Some(shown)))
+        }
+    }
+
+    /// Show the value of type `value_type` at `at` the way the interpreter
+    /// displays its values, quoting strings inside other values
+    unsafe fn show(&self, value_type: &Type, at: *const u8, nested: bool) -> String {
+        match value_type {
+            Type::Int | Type::I64 => read::<i64>(at).to_string(),
+            Type::I8 => read::<i8>(at).to_string(),
+            Type::I16 => read::<i16>(at).to_string(),
+            Type::I32 => read::<i32>(at).to_string(),
+            Type::U8 => read::<u8>(at).to_string(),
+            Type::U16 => read::<u16>(at).to_string(),
+            Type::U32 => read::<u32>(at).to_string(),
+            Type::U64 => read::<u64>(at).to_string(),
+            Type::Float => read::<f32>(at).to_string(),
+            Type::F64 => read::<f64>(at).to_string(),
+            Type::Bool => (read::<u8>(at) != 0).to_string(),
+            Type::Null => "null".to_string(),
+            Type::String => {
+                let string = read::<*const KennedyString>(at);
+                let bytes = std::slice::from_raw_parts((string as *const u8).add(STRING_HEADER_SIZE), (*string).len);
+                let text = String::from_utf8_lossy(bytes);
+
+                if nested { format!("{:?}", text) } else { text.into_owned() }
+            }
+            Type::Array(element_type) | Type::FixedArray(element_type, _) => {
+                let array = &*read::<*const KennedyArray>(at);
+                let elements: Vec<String> = (0..array.len)
+                    .map(|i| self.show(element_type, array.data.add(i * array.element_size), true))
+                    .collect();
+
+                format!("[{}]", elements.join(", "))
+            }
+            Type::Named(ident) => {
+                let fields = read::<*const u8>(at).offset(STRUCT_FIELDS_OFFSET as isize);
+
+                if let Some(layout) = self.compiler.struct_layout(ident) {
+                    let fields: Vec<String> = layout.fields.iter()
+                        .map(|field| format!("{}: {}", field.ident, self.show(&field.field_type, fields.add(field.offset as usize), true)))
+                        .collect();
+
+                    return format!("{} {{ {} }}", ident, fields.join(", "));
+                }
+
+                let Some((variant, layout)) = self.compiler.variant_layout(ident, read::<u32>(fields)) else {
+                    return format!("<{}>", ident);
+                };
+
+                // the tag comes first
+                let values: Vec<String> = layout.fields[1..].iter()
+                    .map(|field| self.show(&field.field_type, fields.add(field.offset as usize), true))
+                    .collect();
+
+                if values.is_empty() {
+                    format!("{}::{}", ident, variant)
+                } else {
+                    format!("{}::{}({})", ident, variant, values.join(", "))
+                }
+            }
+            Type::Function(..) => "<func>".to_string(),
+        }
+    }
+}
+
+/// Whether an input is definitions, rather than statements
+fn is_definition(tokens: &[Token]) -> bool {
+    match tokens.first().map(|token| &token.token_type) {
+        Some(TokenType::Struct | TokenType::Enum | TokenType::Extern | TokenType::Const | TokenType::Pub | TokenType::At | TokenType::Import) => true,
+        // rather than an anonymous function
+        Some(TokenType::Function) => matches!(tokens.get(1).map(|token| &token.token_type), Some(TokenType::Ident(_))),
+        _ => false,
+    }
+}
+
+/// Parse the statements of an input, which may leave out the `;` ending
+/// the last
+fn parse_statements(mut tokens: Vec<Token>) -> CompileResult<Vec<Statement>> {
+    let eof = tokens.pop().unwrap();
+    let last = tokens.last().map(|token| token.token_type.clone());
+    let semicolon = Token::new(TokenType::Semicolon, Span { start: eof.span.start, end: eof.span.start });
+
+    let mut typed = tokens.clone();
+    if !matches!(last, Some(TokenType::Semicolon | TokenType::RightBrace)) {
+        typed.push(semicolon.clone());
+    }
+    typed.push(eof.clone());
+
+    match Parser::new(typed).parse_statements() {
+        // a `}` may end a statement, or the anonymous function in one
+        Err(error) if last == Some(TokenType::RightBrace) => {
+            tokens.extend([semicolon, eof]);
+            Parser::new(tokens).parse_statements().map_err(|_| error)
+        }
+        result => result,
+    }
+}
+
+/// Replace the definitions of the names `program` defines with its own
+fn define(definitions: &mut ast::Program, program: ast::Program) {
+    for function in program.functions {
+        definitions.functions.retain(|defined| defined.ident != function.ident);
+        definitions.functions.push(function);
+    }
+
+    for global in program.globals {
+        definitions.globals.retain(|defined| defined.ident != global.ident);
+        definitions.globals.push(global);
+    }
+
+    for function in program.externs {
+        definitions.externs.retain(|defined| defined.ident != function.ident);
+        definitions.externs.push(function);
+    }
+
+    for declaration in program.structs {
+        definitions.structs.retain(|defined| defined.ident != declaration.ident);
+        definitions.structs.push(declaration);
+    }
+
+    for declaration in program.enums {
+        definitions.enums.retain(|defined| defined.ident != declaration.ident);
+        definitions.enums.push(declaration);
+    }
+}
+
+/// The value a variable of `variable_type` starts with as a global, if it
+/// can be one
+fn zero(variable_type: &Type, span: &Span) -> Option<Expression> {
+    let span = span.clone();
+    Some(match variable_type {
+        Type::Float | Type::F64 => Expression::FloatLiteral { value: 0.0, span },
+        Type::Bool => Expression::BooleanLiteral { value: false, span },
+        Type::String => Expression::StringLiteral { value: String::new(), span },
+        integer if integer.is_integer() => Expression::IntegerLiteral { value: 0, span },
+        _ => return None,
+    })
+}
+
+/// A copy of a warning, which is only ever a `Warning`, possibly in a file
+fn clone(warning: &CompileError) -> CompileError {
+    match warning {
+        CompileError::Warning(message, span) => CompileError::Warning(message.clone(), span.clone()),
+        CompileError::InFile { path, source, error } => CompileError::InFile {
+            path: path.clone(),
+            source: source.clone(),
+            error: Box::new(clone(error)),
+        },
+        error => CompileError::CompileError(error.to_string()),
+    }
+}
+
+/// Call a compiled function taking nothing and returning a `T`
+/// Its traps unwind out of it, to be caught
+unsafe fn call<T>(address: *const u8) -> T {
+    mem::transmute::<*const u8, extern "C-unwind" fn() -> T>(address)()
+}
+
+unsafe fn read<T: Copy>(at: *const u8) -> T {
+    (at as *const T).read_unaligned()
+}
+
+fn store<T>(slot: &mut [u8; 8], value: T) {
+    unsafe { (slot.as_mut_ptr() as *mut T).write_unaligned(value) }
+}
+
+/// Release a value returned by an input, now that it's been shown
+unsafe fn release(value_type: &Type, at: *const u8) {
+    match value_type {
+        Type::String => runtime::kennedy_string_release(read(at)),
+        Type::Array(_) | Type::FixedArray(..) => runtime::kennedy_array_release(read(at)),
+        Type::Named(_) | Type::Function(..) => runtime::kennedy_struct_release(read(at)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::runtime::TrapKind;
+
+    fn eval(repl: &mut Repl, input: &str) -> Vec<String> {
+        repl.eval(input).unwrap_or_else(|e| panic!("{}", e.to_string_with_source(input))).values
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut repl = Repl::new(CompilerOptions::default()).unwrap();
+        let live_strings = runtime::live_strings();
+        let live_structs = runtime::live_structs();
+
+        assert_eq!(eval(&mut repl, "1 + 2"), ["3: int"]);
+        assert_eq!(eval(&mut repl, "let x = 40;"), ["x: int = 40"]);
+        assert_eq!(eval(&mut repl, "x += 2; x"), ["42: int"]);
+        assert_eq!(eval(&mut repl, "func double(n: int): int { return n * 2; }"), Vec::<String>::new());
+        assert_eq!(eval(&mut repl, "double(x)"), ["84: int"]);
+
+        // callers are compiled again to call the new version
+        eval(&mut repl, "func twice(n: int): int { return double(n); }");
+        eval(&mut repl, "func double(n: int): int { return n + n + 1; }");
+        assert_eq!(eval(&mut repl, "twice(1)"), ["3: int"]);
+
+        eval(&mut repl, "struct Point { x: float, y: float }");
+        eval(&mut repl, "enum Shape { Dot(Point), Empty }");
+        assert_eq!(
+            eval(&mut repl, "let name = \"p\"; Shape::Dot(Point { x: 1.5, y: 2.0 })"),
+            ["name: string = p", "Shape::Dot(Point { x: 1.5, y: 2 }): Shape"],
+        );
+        assert_eq!(eval(&mut repl, "[name, \"q\"]"), ["[\"p\", \"q\"]: string[]"]);
+        assert_eq!(eval(&mut repl, "let x = true;"), ["x: bool = true"]);
+        assert_eq!(eval(&mut repl, "if (x) { double(2); }"), Vec::<String>::new());
+
+        assert_eq!(runtime::live_strings(), live_strings);
+        assert_eq!(runtime::live_structs(), live_structs);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut repl = Repl::new(CompilerOptions::default()).unwrap();
+        eval(&mut repl, "func f(): int { return 1; }");
+
+        let error = repl.eval("f() + true").unwrap_err();
+        assert!(matches!(error, CompileError::SemanticError(_, Span { start: 0, .. })), "{:?}", error);
+        assert!(repl.eval("func f(): int { return false; }").is_err());
+        assert!(repl.eval("import math;").is_err());
+
+        // nothing failed changes what's defined
+        assert_eq!(eval(&mut repl, "f()"), ["1: int"]);
+    }
+
+    #[test]
+    fn test_temporaries() {
+        let mut repl = Repl::new(CompilerOptions::default()).unwrap();
+        let warnings = |evaluated: Evaluated| -> Vec<(String, usize)> {
+            evaluated.warnings.into_iter().map(|warning| match warning {
+                CompileError::Warning(message, span) => (message, span.start),
+                error => panic!("{:?}", error),
+            }).collect()
+        };
+        let lasts = |ident: &str, start: usize| {
+            (format!("`{}` only lasts for this input, as only numbers, bools and strings are kept", ident), start)
+        };
+
+        let evaluated = repl.eval("let a: int[] = [1, 2];").unwrap();
+        assert_eq!(evaluated.values, Vec::<String>::new());
+        assert_eq!(warnings(evaluated), [lasts("a", 0)]);
+
+        let evaluated = repl.eval("let b = [3, 4];\nlet c = 5;\nb[0] + b[1]").unwrap();
+        assert_eq!(evaluated.values, ["c: int = 5", "7: int"]);
+        assert_eq!(warnings(evaluated), [lasts("b", 0)]);
+
+        assert!(repl.eval("a").unwrap_err().to_string().contains("`a`"));
+    }
+
+    #[test]
+    fn test_traps() {
+        let mut repl = Repl::new(CompilerOptions::default()).unwrap();
+        eval(&mut repl, "func divide(a: int, b: int): int { return a / b; }");
+        eval(&mut repl, "let n = 0;");
+
+        // in the input `divide` is from
+        let mut trap = |input: &str| repl.eval(input).unwrap().trap.map(|trap| (trap.kind, trap.line, trap.column));
+        assert_eq!(trap("n = 1;\ndivide(n, 0)"), Some((TrapKind::DivisionByZero, 1, 43)));
+        assert_eq!(trap("let s = \"abc\";\n\ns[5]"), Some((TrapKind::IndexOutOfBounds, 3, 1)));
+
+        // the session goes on, as the inputs left it
+        assert_eq!(eval(&mut repl, "n + len(s)"), ["4: int"]);
+        assert_eq!(eval(&mut repl, "divide(6, n + 1)"), ["3: int"]);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        assert!(Repl::is_incomplete("func f(): int {"));
+        assert!(Repl::is_incomplete("f(1,"));
+        assert!(Repl::is_incomplete("if x { while y {}"));
+        assert!(!Repl::is_incomplete("func f(): int { return 1; }"));
+        assert!(!Repl::is_incomplete("}"));
+        assert!(!Repl::is_incomplete("\"{"));
+    }
+}
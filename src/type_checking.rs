@@ -0,0 +1,1790 @@
+//! Type checking of a parsed program
+//!
+//! Runs before code generation so the compiler can assume every expression
+//! is well typed. Integer literals have no width of their own: they take the
+//! integer type their context expects (`let x: u8 = 1;`), defaulting to `int`.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    Program, Global, ExternFunction, Function, Parameters, Struct, Enum, Block, Statement, Expression, Type,
+    BinaryOperator, UnaryOperator, AssignOperator, MatchArm, MatchBody, Pattern,
+};
+use crate::builtins::Builtin;
+use crate::generics;
+use crate::compiler::symbol_table::SymbolTable;
+use crate::error::{CompileError, CompileResult, Span};
+use crate::lint;
+
+/// Parameter and return types of a function
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSignature {
+    /// Empty unless the function is generic
+    pub type_params: Vec<String>,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl FunctionSignature {
+    /// Signature of a function that isn't generic
+    pub fn new(params: Vec<Type>, return_type: Type) -> Self {
+        Self { type_params: Vec::new(), params, return_type }
+    }
+
+    pub fn of(function: &Function) -> Self {
+        Self {
+            type_params: function.type_params.iter().map(|p| p.ident.clone()).collect(),
+            params: function.params.params.iter().map(|p| p.param_type.clone()).collect(),
+            return_type: function.return_type.clone(),
+        }
+    }
+
+    pub fn of_extern(function: &ExternFunction) -> Self {
+        Self {
+            type_params: Vec::new(),
+            params: function.params.params.iter().map(|p| p.param_type.clone()).collect(),
+            return_type: function.return_type.clone(),
+        }
+    }
+}
+
+/// Most instances of generic functions a program may need, so a function
+/// instantiating itself with ever larger types fails instead of looping
+const MAX_INSTANCES: usize = 1000;
+
+/// An anonymous function found while checking, with the variables it
+/// captures from the functions around it
+#[derive(Debug, Clone)]
+pub struct Lambda {
+    /// Function (or instance of a generic function) it is written in
+    /// Together with the span this identifies the lambda, as the body of a
+    /// generic function is checked once per instance
+    pub owner: String,
+    pub span: Span,
+    pub params: Parameters,
+    pub return_type: Type,
+    pub body: Block,
+    /// Copied into the closure when it is created, in order of first use
+    pub captures: Vec<(String, Type)>,
+}
+
+/// A lambda whose body is being checked
+struct Capturing {
+    /// Depth of the scope the lambda is written in; variables declared at
+    /// this depth or shallower live outside it
+    depth: usize,
+    captures: Vec<(String, Type)>,
+}
+
+pub struct TypeChecker {
+    /// Every function in the program, so calls can be checked in any order
+    functions: HashMap<String, FunctionSignature>,
+    /// Every struct in the program, by name
+    structs: HashMap<String, Struct>,
+    /// Every enum in the program, by name
+    enums: HashMap<String, Enum>,
+    /// Globals checked so far, with their types and whether they're constant
+    globals: HashMap<String, (Type, bool)>,
+    /// Variables in scope in the function being checked
+    variables: SymbolTable<String, Type>,
+    /// Return type of the function being checked
+    return_type: Type,
+    /// Name of the function being checked
+    function_ident: String,
+    /// Every generic function in the program, by name
+    generics: HashMap<String, Function>,
+    /// Instances of generic functions called so far but not yet checked
+    pending_instances: Vec<Function>,
+    /// Instances of generic functions that have been checked
+    instances: Vec<Function>,
+    /// Name of the top level function (or instance) being checked
+    owner: String,
+    /// Lambdas being checked, innermost last
+    capturing: Vec<Capturing>,
+    /// Every lambda checked so far
+    lambdas: Vec<Lambda>,
+    /// Type of every match expression checked so far, by the function it's
+    /// written in and where
+    match_types: HashMap<(String, Span), Type>,
+    /// Type of every expression checked so far, by the function it's
+    /// written in and where
+    expression_types: HashMap<(String, Span), Type>,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            globals: HashMap::new(),
+            variables: SymbolTable::new(),
+            return_type: Type::Null,
+            function_ident: String::new(),
+            generics: HashMap::new(),
+            pending_instances: Vec::new(),
+            instances: Vec::new(),
+            owner: String::new(),
+            capturing: Vec::new(),
+            lambdas: Vec::new(),
+            match_types: HashMap::new(),
+            expression_types: HashMap::new(),
+        }
+    }
+
+    /// Every instance of a generic function the checked program calls,
+    /// with its type parameters replaced
+    pub fn instances(&self) -> &[Function] {
+        &self.instances
+    }
+
+    /// Every lambda in the checked program
+    pub fn lambdas(&self) -> &[Lambda] {
+        &self.lambdas
+    }
+
+    /// Type of every match expression in the checked program, by the
+    /// function (or instance) it's written in and where
+    /// This depends on the match's arms, not just on its context
+    pub fn match_types(&self) -> &HashMap<(String, Span), Type> {
+        &self.match_types
+    }
+
+    /// Type of every expression in the checked program, by the function
+    /// (or instance) it's written in and where
+    pub fn expression_types(&self) -> &HashMap<(String, Span), Type> {
+        &self.expression_types
+    }
+
+    /// Type of the expression at `span` in a function (or instance) of the
+    /// checked program
+    /// Expressions in generic functions only have types in their instances
+    pub fn expression_type(&self, owner: &str, span: &Span) -> Option<&Type> {
+        self.expression_types.get(&(owner.to_string(), span.clone()))
+    }
+
+    /// Declare a function defined outside the program, which it may call
+    pub fn declare_function(&mut self, ident: &str, signature: FunctionSignature) {
+        self.functions.insert(ident.to_string(), signature);
+    }
+
+    /// Type of a global in the checked program
+    pub fn global_type(&self, ident: &str) -> Option<&Type> {
+        self.globals.get(ident).map(|(global_type, _)| global_type)
+    }
+
+    /// Check every global, struct, enum and function in a program
+    pub fn check_program(&mut self, program: &Program) -> CompileResult<()> {
+        for declaration in &program.structs {
+            if self.structs.contains_key(&declaration.ident) {
+                return Err(CompileError::CompileError(
+                    format!("Struct `{}` is defined more than once", declaration.ident),
+                ));
+            }
+
+            self.structs.insert(declaration.ident.clone(), declaration.clone());
+        }
+
+        // structs and enums share one namespace of type names
+        for declaration in &program.enums {
+            if self.structs.contains_key(&declaration.ident) || self.enums.contains_key(&declaration.ident) {
+                return Err(CompileError::CompileError(
+                    format!("Type `{}` is defined more than once", declaration.ident),
+                ));
+            }
+
+            self.enums.insert(declaration.ident.clone(), declaration.clone());
+        }
+
+        // fields may refer to types declared later on
+        for declaration in &program.structs {
+            self.check_struct(declaration)?;
+        }
+
+        for declaration in &program.enums {
+            self.check_enum(declaration)?;
+        }
+
+        // extern functions are called like any other
+        for function in &program.externs {
+            self.check_extern(function)?;
+        }
+
+        // collect signatures first so functions can call each other
+        for function in &program.functions {
+            if Builtin::from_ident(&function.ident).is_some() {
+                return Err(CompileError::CompileError(
+                    format!("`{}` is a built-in function and cannot be redefined", function.ident),
+                ));
+            }
+
+            if self.functions.contains_key(&function.ident) {
+                return Err(CompileError::CompileError(
+                    format!("Function `{}` is defined more than once", function.ident),
+                ));
+            }
+
+            check_attributes(function)?;
+
+            if function.is_generic() {
+                self.check_type_params(function)?;
+                self.generics.insert(function.ident.clone(), function.clone());
+            }
+
+            self.functions.insert(function.ident.clone(), FunctionSignature::of(function));
+        }
+
+        // initializers may only use the globals before them
+        for global in &program.globals {
+            self.check_global(global)?;
+        }
+
+        // generic functions are checked once per instance instead
+        for function in program.functions.iter().filter(|function| !function.is_generic()) {
+            self.check_function(function)?;
+        }
+
+        while let Some(instance) = self.pending_instances.pop() {
+            self.check_function(&instance).map_err(|error| in_instance(error, &instance.ident))?;
+            self.instances.push(instance);
+        }
+
+        Ok(())
+    }
+
+    fn check_type_params(&self, function: &Function) -> CompileResult<()> {
+        for (i, type_param) in function.type_params.iter().enumerate() {
+            if function.type_params[..i].iter().any(|other| other.ident == type_param.ident) {
+                return Err(CompileError::CompileError(format!(
+                    "Type parameter `{}` of `{}` is declared more than once",
+                    type_param.ident, function.ident,
+                )));
+            }
+
+            if self.structs.contains_key(&type_param.ident) || self.enums.contains_key(&type_param.ident) {
+                return Err(CompileError::CompileError(format!(
+                    "Type parameter `{}` of `{}` has the same name as a type",
+                    type_param.ident, function.ident,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_struct(&self, declaration: &Struct) -> CompileResult<()> {
+        for (i, field) in declaration.fields.iter().enumerate() {
+            if declaration.fields[..i].iter().any(|other| other.ident == field.ident) {
+                return Err(CompileError::CompileError(format!(
+                    "Field `{}` of `{}` is declared more than once",
+                    field.ident, declaration.ident,
+                )));
+            }
+
+            if field.field_type == Type::Null {
+                return Err(CompileError::CompileError(format!(
+                    "Field `{}` of `{}` cannot be null",
+                    field.ident, declaration.ident,
+                )));
+            }
+
+            self.check_type_exists(&field.field_type)
+                .map_err(CompileError::CompileError)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_enum(&self, declaration: &Enum) -> CompileResult<()> {
+        for (i, variant) in declaration.variants.iter().enumerate() {
+            if declaration.variants[..i].iter().any(|other| other.ident == variant.ident) {
+                return Err(CompileError::CompileError(format!(
+                    "Variant `{}` of `{}` is declared more than once",
+                    variant.ident, declaration.ident,
+                )));
+            }
+
+            for field_type in &variant.fields {
+                if *field_type == Type::Null {
+                    return Err(CompileError::CompileError(format!(
+                        "Variant `{}` of `{}` cannot hold null",
+                        variant.ident, declaration.ident,
+                    )));
+                }
+
+                self.check_type_exists(field_type)
+                    .map_err(CompileError::CompileError)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Error if a type names a struct or enum that doesn't exist
+    fn check_type_exists(&self, ty: &Type) -> Result<(), String> {
+        match ty {
+            Type::Named(ident) if !self.structs.contains_key(ident) && !self.enums.contains_key(ident) => {
+                Err(format!("Unknown type `{}`", ident))
+            }
+            Type::Array(element) | Type::FixedArray(element, _) => self.check_type_exists(element),
+            Type::Function(params, return_type) => {
+                params.iter().try_for_each(|param| self.check_type_exists(param))?;
+                self.check_type_exists(return_type)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that C can be called with the types of an extern function
+    /// Only numbers and bools are passed the same way in Kennedy and C:
+    /// `float` is C's `float`, `f64` its `double`, `bool` its `bool`, and a
+    /// `null` return `void`
+    fn check_extern(&mut self, function: &ExternFunction) -> CompileResult<()> {
+        let error = |message: String| CompileError::SemanticError(message, function.span.clone());
+        let signature = FunctionSignature::of_extern(function);
+
+        if Builtin::from_ident(&function.ident).is_some() {
+            return Err(error(format!("`{}` is a built-in function and cannot be redefined", function.ident)));
+        }
+
+        match self.functions.get(&function.ident) {
+            // several modules may declare the same C function
+            Some(declared) if *declared == signature => return Ok(()),
+            Some(_) => {
+                return Err(error(format!("Extern function `{}` is declared with different types", function.ident)));
+            }
+            None => {}
+        }
+
+        let is_c_type = |ty: &Type| ty.is_numeric() || *ty == Type::Bool;
+
+        for param in &function.params.params {
+            if !is_c_type(&param.param_type) {
+                return Err(error(format!(
+                    "Parameter `{}` of extern function `{}` is of type {}, which cannot be passed to C",
+                    param.ident, function.ident, param.param_type,
+                )));
+            }
+        }
+
+        if function.return_type != Type::Null && !is_c_type(&function.return_type) {
+            return Err(error(format!(
+                "Extern function `{}` returns {}, which cannot be returned from C",
+                function.ident, function.return_type,
+            )));
+        }
+
+        self.functions.insert(function.ident.clone(), signature);
+        Ok(())
+    }
+
+    fn check_global(&mut self, global: &Global) -> CompileResult<()> {
+        if self.globals.contains_key(&global.ident) {
+            return Err(CompileError::SemanticError(
+                format!("Global `{}` is defined more than once", global.ident),
+                global.span.clone(),
+            ));
+        }
+
+        if self.functions.contains_key(&global.ident) || Builtin::from_ident(&global.ident).is_some() {
+            return Err(CompileError::SemanticError(
+                format!("Global `{}` has the same name as a function", global.ident),
+                global.span.clone(),
+            ));
+        }
+
+        self.variables = SymbolTable::new();
+        self.function_ident = global.ident.clone();
+        self.owner = global.ident.clone();
+
+        let value_type = self.check_expression(&global.value, global.global_type.as_ref())?;
+        let global_type = match &global.global_type {
+            Some(global_type) => {
+                expect_type(global_type, &value_type, global.value.span())?;
+                global_type.clone()
+            }
+            None => value_type,
+        };
+
+        // only these can be stored in a data object as they are
+        if !global_type.is_numeric() && global_type != Type::Bool && global_type != Type::String {
+            return Err(CompileError::SemanticError(
+                format!("Globals cannot be of type {}", global_type),
+                global.span.clone(),
+            ));
+        }
+
+        self.globals.insert(global.ident.clone(), (global_type, global.constant));
+        Ok(())
+    }
+
+    fn check_function(&mut self, function: &Function) -> CompileResult<()> {
+        self.variables = SymbolTable::new();
+        self.return_type = function.return_type.clone();
+        self.function_ident = function.ident.clone();
+        self.owner = function.ident.clone();
+
+        self.check_type_exists(&function.return_type)
+            .map_err(CompileError::CompileError)?;
+
+        for param in &function.params.params {
+            self.check_type_exists(&param.param_type)
+                .map_err(CompileError::CompileError)?;
+
+            if self.variables.contains_local(&param.ident) {
+                return Err(CompileError::CompileError(format!(
+                    "Parameter `{}` of `{}` is declared more than once",
+                    param.ident, function.ident,
+                )));
+            }
+
+            self.variables.insert(param.ident.clone(), param.param_type.clone());
+        }
+
+        self.check_block(&function.body)?;
+
+        if !returns(&function.return_type, &function.body) {
+            return Err(CompileError::SemanticError(
+                format!("Function `{}` can reach its end without returning {}", function.ident, function.return_type),
+                function.ident_span.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check a block in its own scope
+    fn check_block(&mut self, block: &Block) -> CompileResult<()> {
+        self.variables.push_scope();
+
+        let result = block.statements.iter()
+            .try_for_each(|statement| self.check_statement(statement));
+
+        self.variables.pop_scope();
+        result
+    }
+
+    fn check_statement(&mut self, statement: &Statement) -> CompileResult<()> {
+        match statement {
+            Statement::VariableDeclaration { ident, var_type, value, .. } => {
+                if let Some(var_type) = var_type {
+                    self.check_type_exists(var_type)
+                        .map_err(|message| CompileError::SemanticError(message, value.span().clone()))?;
+                }
+
+                let value_type = self.check_expression(value, var_type.as_ref())?;
+
+                if let Some(var_type) = var_type {
+                    expect_type(var_type, &value_type, value.span())?;
+                }
+
+                if self.variables.contains_local(ident) {
+                    return Err(CompileError::SemanticError(
+                        format!("Variable `{}` is already declared in this scope", ident),
+                        value.span().clone(),
+                    ));
+                }
+
+                self.variables.insert(ident.clone(), var_type.clone().unwrap_or(value_type));
+                Ok(())
+            }
+
+            Statement::Assign { ident, value, .. } => {
+                let var_type = self.assignable_variable(ident, value.span())?;
+
+                let value_type = self.check_expression(value, Some(&var_type))?;
+                expect_type(&var_type, &value_type, value.span())
+            }
+
+            Statement::Return { value, .. } => {
+                let return_type = self.return_type.clone();
+
+                match value {
+                    Some(value) => {
+                        let value_type = self.check_expression(value, Some(&return_type))?;
+                        expect_type(&return_type, &value_type, value.span())
+                    }
+                    None if return_type == Type::Null => Ok(()),
+                    None => Err(CompileError::CompileError(format!(
+                        "Function `{}` must return a value of type {}",
+                        self.function_ident, return_type,
+                    ))),
+                }
+            }
+
+            Statement::Block { block, .. } => self.check_block(block),
+
+            Statement::If { condition, then_branch, else_branch, .. } => {
+                self.check_condition(condition)?;
+                self.check_statement(then_branch)?;
+
+                if let Some(else_branch) = else_branch {
+                    self.check_statement(else_branch)?;
+                }
+
+                Ok(())
+            }
+
+            Statement::While { condition, body, .. } => {
+                self.check_condition(condition)?;
+                self.check_block(body)
+            }
+
+            Statement::DoUntil { condition, body, .. } => {
+                self.check_statement(body)?;
+                self.check_condition(condition)
+            }
+
+            Statement::For { init, condition, increment, body, .. } => {
+                // the loop variable is only visible inside the loop
+                self.variables.push_scope();
+
+                let result = self.check_statement(init)
+                    .and_then(|_| self.check_condition(condition))
+                    .and_then(|_| self.check_statement(increment))
+                    .and_then(|_| self.check_block(body));
+
+                self.variables.pop_scope();
+                result
+            }
+
+            Statement::Expression { expression, .. } => {
+                self.check_expression(expression, None)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Conditions of if/while/for/do-until must be booleans
+    fn check_condition(&mut self, condition: &Expression) -> CompileResult<()> {
+        let condition_type = self.check_expression(condition, Some(&Type::Bool))?;
+        expect_type(&Type::Bool, &condition_type, condition.span())
+    }
+
+    /// Type of an expression, which is remembered
+    /// `expected` is the type the surrounding context wants, used to give
+    /// integer literals a width
+    fn check_expression(&mut self, expr: &Expression, expected: Option<&Type>) -> CompileResult<Type> {
+        let expr_type = self.expression_type_of(expr, expected)?;
+
+        // an operand checked again with another type keeps the last one
+        self.expression_types.insert((self.owner.clone(), expr.span().clone()), expr_type.clone());
+        Ok(expr_type)
+    }
+
+    fn expression_type_of(&mut self, expr: &Expression, expected: Option<&Type>) -> CompileResult<Type> {
+        if let Some(value) = expr.integer_literal_value() {
+            let literal_type = match expected {
+                Some(expected) if expected.is_integer() => expected.clone(),
+                _ => Type::Int,
+            };
+
+            let (min, max) = literal_type.integer_range().unwrap();
+            if value < min || value > max {
+                return Err(CompileError::SemanticError(
+                    format!("Integer literal {} does not fit in {}", value, literal_type),
+                    expr.span().clone(),
+                ));
+            }
+
+            return Ok(literal_type);
+        }
+
+        match expr {
+            Expression::IntegerLiteral { .. } => unreachable!(),
+            Expression::FloatLiteral { .. } => Ok(match expected {
+                Some(Type::F64) => Type::F64,
+                _ => Type::Float,
+            }),
+            Expression::StringLiteral { .. } => Ok(Type::String),
+            Expression::BooleanLiteral { .. } => Ok(Type::Bool),
+            Expression::NullLiteral { .. } => Ok(Type::Null),
+
+            Expression::Identifier { ident, span } => {
+                // named functions can be used as values too
+                match self.functions.get(ident) {
+                    Some(signature) if self.variables.get(ident).is_none() && !signature.type_params.is_empty() => {
+                        Err(CompileError::SemanticError(
+                            format!("Generic function `{}` can only be called, not used as a value", ident),
+                            span.clone(),
+                        ))
+                    }
+                    Some(signature) if self.variables.get(ident).is_none() => Ok(Type::Function(
+                        signature.params.clone(),
+                        Box::new(signature.return_type.clone()),
+                    )),
+                    _ => self.variable_type(ident, span),
+                }
+            }
+
+            Expression::Binary { left, operator, right, span } => {
+                let operand_expected = match operator {
+                    BinaryOperator::Plus | BinaryOperator::Minus
+                    | BinaryOperator::Star | BinaryOperator::Slash => expected,
+                    _ => None,
+                };
+
+                let (left_type, right_type) = self.check_operands(left, right, operand_expected)?;
+
+                if !left_type.same_as(&right_type) {
+                    return Err(CompileError::SemanticError(
+                        format!(
+                            "Mismatched operand types for {:?}: {} and {}",
+                            operator, left_type, right_type,
+                        ),
+                        span.clone(),
+                    ));
+                }
+
+                let valid = match operator {
+                    // + also concatenates strings
+                    BinaryOperator::Plus => left_type.is_numeric() || left_type == Type::String,
+                    BinaryOperator::Minus
+                    | BinaryOperator::Star | BinaryOperator::Slash
+                    | BinaryOperator::Greater | BinaryOperator::GreaterEqual
+                    | BinaryOperator::Less | BinaryOperator::LessEqual => left_type.is_numeric(),
+                    BinaryOperator::EqualEqual | BinaryOperator::BangEqual => {
+                        left_type.is_numeric() || left_type == Type::Bool || left_type == Type::String
+                    }
+                    BinaryOperator::And | BinaryOperator::Or => left_type == Type::Bool,
+                    BinaryOperator::StarStar | BinaryOperator::SlashSlash => false,
+                };
+
+                if !valid {
+                    return Err(CompileError::SemanticError(
+                        format!("Operator {:?} cannot be applied to {}", operator, left_type),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(match operator {
+                    BinaryOperator::Plus | BinaryOperator::Minus
+                    | BinaryOperator::Star | BinaryOperator::Slash => left_type,
+                    _ => Type::Bool,
+                })
+            }
+
+            Expression::Unary { operator, right, span } => {
+                let right_type = self.check_expression(right, expected)?;
+
+                let valid = match operator {
+                    UnaryOperator::Minus => right_type.is_signed() || right_type.is_float(),
+                    UnaryOperator::Bang => right_type == Type::Bool,
+                };
+
+                if !valid {
+                    return Err(CompileError::SemanticError(
+                        format!("Operator {:?} cannot be applied to {}", operator, right_type),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(right_type)
+            }
+
+            Expression::Grouping { expression, .. } => self.check_expression(expression, expected),
+
+            Expression::Function { params, return_type, body, span } => {
+                self.check_lambda(params, return_type, body, span)
+            }
+
+            Expression::Call { callee, arguments, span } => {
+                // variables shadow functions of the same name
+                let named = match callee.as_ref() {
+                    Expression::Identifier { ident, .. } if self.variables.get(ident).is_none() => Some(ident),
+                    _ => None,
+                };
+
+                let Some(ident) = named else {
+                    let callee_type = self.check_expression(callee, None)?;
+
+                    let Type::Function(params, return_type) = &callee_type else {
+                        return Err(CompileError::SemanticError(
+                            format!("Cannot call {}", callee_type),
+                            callee.span().clone(),
+                        ));
+                    };
+
+                    self.check_arguments(&format!("`{}`", callee_type), params, arguments, span)?;
+                    return Ok(*return_type.clone());
+                };
+
+                if let Some(builtin) = Builtin::from_ident(ident) {
+                    return self.check_builtin(builtin, arguments, span);
+                }
+
+                let signature = self.functions.get(ident).cloned().ok_or_else(|| {
+                    CompileError::SemanticError(
+                        format!("Call to undefined function `{}`", ident),
+                        callee.span().clone(),
+                    )
+                })?;
+
+                if !signature.type_params.is_empty() {
+                    return self.check_generic_call(ident, &signature, arguments, span);
+                }
+
+                self.check_arguments(&format!("Function `{}`", ident), &signature.params, arguments, span)?;
+                Ok(signature.return_type)
+            }
+
+            Expression::Postfix { left: operand, span, .. }
+            | Expression::Prefix { right: operand, span, .. } => {
+                let operand_type = self.assignable_type(operand)?;
+
+                if !operand_type.is_integer() {
+                    return Err(CompileError::SemanticError(
+                        format!("Cannot increment or decrement {}", operand_type),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(operand_type)
+            }
+
+            Expression::Assign { left, operator, right, span } => {
+                let left_type = self.assignable_type(left)?;
+                let right_type = self.check_expression(right, Some(&left_type))?;
+                expect_type(&left_type, &right_type, right.span())?;
+
+                let concatenates = *operator == AssignOperator::PlusEqual && left_type == Type::String;
+                if *operator != AssignOperator::Equal && !left_type.is_numeric() && !concatenates {
+                    return Err(CompileError::SemanticError(
+                        format!("Compound assignment cannot be applied to {}", left_type),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(left_type)
+            }
+
+            Expression::Cast { expression, target_type, span } => {
+                let source_type = self.check_expression(expression, None)?;
+
+                let valid = (source_type.is_numeric() && target_type.is_numeric())
+                    || (source_type == Type::Bool && target_type.is_integer())
+                    || ((source_type.is_numeric() || source_type == Type::Bool) && *target_type == Type::String)
+                    || source_type.same_as(target_type);
+
+                if !valid {
+                    return Err(CompileError::SemanticError(
+                        format!("Cannot cast {} to {}", source_type, target_type),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(target_type.clone())
+            }
+
+            Expression::Index { target, index, span } => {
+                let target_type = self.check_expression(target, None)?;
+                self.check_index(index)?;
+
+                match target_type {
+                    // strings index to their bytes
+                    Type::String => Ok(Type::U8),
+                    Type::Array(element) | Type::FixedArray(element, _) => Ok(*element),
+                    _ => Err(CompileError::SemanticError(
+                        format!("Cannot index into {}", target_type),
+                        span.clone(),
+                    )),
+                }
+            }
+
+            Expression::Slice { target, start, end, span } => {
+                let target_type = self.check_expression(target, None)?;
+
+                for bound in [start, end].into_iter().flatten() {
+                    self.check_index(bound)?;
+                }
+
+                match target_type {
+                    Type::String => Ok(Type::String),
+                    // slices are copies, so they can always grow
+                    Type::Array(element) | Type::FixedArray(element, _) => Ok(Type::Array(element)),
+                    _ => Err(CompileError::SemanticError(
+                        format!("Cannot slice {}", target_type),
+                        span.clone(),
+                    )),
+                }
+            }
+
+            Expression::ArrayLiteral { elements, span } => self.check_array_literal(elements, expected, span),
+
+            Expression::StructLiteral { ident, fields, span } => {
+                let declaration = self.structs.get(ident).cloned().ok_or_else(|| {
+                    CompileError::SemanticError(format!("Unknown struct `{}`", ident), span.clone())
+                })?;
+
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let field_type = &self.field(&declaration, field, span)?.field_type;
+
+                    if fields[..i].iter().any(|(other, _)| other == field) {
+                        return Err(CompileError::SemanticError(
+                            format!("Field `{}` is given more than once", field),
+                            value.span().clone(),
+                        ));
+                    }
+
+                    let value_type = self.check_expression(value, Some(field_type))?;
+                    expect_type(field_type, &value_type, value.span())?;
+                }
+
+                if let Some(missing) = declaration.fields.iter().find(|field| !fields.iter().any(|(given, _)| *given == field.ident)) {
+                    return Err(CompileError::SemanticError(
+                        format!("Missing field `{}` of `{}`", missing.ident, ident),
+                        span.clone(),
+                    ));
+                }
+
+                Ok(Type::Named(ident.clone()))
+            }
+
+            Expression::Field { target, field, span } => self.check_field(target, field, span),
+
+            Expression::Variant { enum_ident, variant, arguments, span } => {
+                let declaration = self.enums.get(enum_ident).cloned().ok_or_else(|| {
+                    CompileError::SemanticError(format!("Unknown enum `{}`", enum_ident), span.clone())
+                })?;
+
+                let (_, variant_declaration) = self.variant(&declaration, variant, span)?;
+
+                if variant_declaration.fields.len() != arguments.len() {
+                    return Err(CompileError::SemanticError(
+                        format!(
+                            "Variant `{}::{}` holds {} values but {} were given",
+                            enum_ident, variant, variant_declaration.fields.len(), arguments.len(),
+                        ),
+                        span.clone(),
+                    ));
+                }
+
+                for (argument, field_type) in arguments.iter().zip(&variant_declaration.fields) {
+                    let argument_type = self.check_expression(argument, Some(field_type))?;
+                    expect_type(field_type, &argument_type, argument.span())?;
+                }
+
+                Ok(Type::Named(enum_ident.clone()))
+            }
+
+            Expression::Match { scrutinee, arms, span } => self.check_match(scrutinee, arms, expected, span),
+        }
+    }
+
+    /// Check the arguments of a call against the parameter types of what
+    /// is being called, which `callee` describes in errors
+    fn check_arguments(
+        &mut self,
+        callee: &str,
+        params: &[Type],
+        arguments: &[Expression],
+        span: &Span,
+    ) -> CompileResult<()> {
+        if params.len() != arguments.len() {
+            return Err(CompileError::SemanticError(
+                format!("{} takes {} arguments but {} were given", callee, params.len(), arguments.len()),
+                span.clone(),
+            ));
+        }
+
+        for (argument, param_type) in arguments.iter().zip(params) {
+            let argument_type = self.check_expression(argument, Some(param_type))?;
+            expect_type(param_type, &argument_type, argument.span())?;
+        }
+
+        Ok(())
+    }
+
+    /// Return type of a call to a generic function, inferring its type
+    /// arguments and queueing the instance for them to be checked
+    fn check_generic_call(
+        &mut self,
+        ident: &str,
+        signature: &FunctionSignature,
+        arguments: &[Expression],
+        span: &Span,
+    ) -> CompileResult<Type> {
+        if signature.params.len() != arguments.len() {
+            return Err(CompileError::SemanticError(
+                format!(
+                    "Function `{}` takes {} arguments but {} were given",
+                    ident, signature.params.len(), arguments.len(),
+                ),
+                span.clone(),
+            ));
+        }
+
+        let (_, type_args) = generics::infer_call(ident, signature, arguments, |argument, expected| {
+            self.check_expression(argument, expected).map(|ty| ((), ty))
+        }).map_err(|error| match error {
+            CompileError::CompileError(message) => CompileError::SemanticError(message, span.clone()),
+            error => error,
+        })?;
+
+        let function = &self.generics[ident];
+
+        for (type_param, type_arg) in function.type_params.iter().zip(&type_args) {
+            if let Some(bound) = type_param.bound {
+                if !bound.allows(type_arg) {
+                    return Err(CompileError::SemanticError(
+                        format!(
+                            "`{}` does not satisfy the `{}` bound on `{}` of `{}`",
+                            type_arg, bound, type_param.ident, ident,
+                        ),
+                        span.clone(),
+                    ));
+                }
+            }
+        }
+
+        let instance_ident = generics::instance_ident(ident, &type_args);
+        if !self.functions.contains_key(&instance_ident) {
+            if self.instances.len() + self.pending_instances.len() >= MAX_INSTANCES {
+                return Err(CompileError::SemanticError(
+                    format!("Too many instances of generic functions, the last being `{}`", instance_ident),
+                    span.clone(),
+                ));
+            }
+
+            let instance = generics::instantiate(function, &type_args);
+            self.functions.insert(instance_ident, FunctionSignature::of(&instance));
+            self.pending_instances.push(instance);
+        }
+
+        let substitution = signature.type_params.iter().cloned().zip(type_args).collect();
+        Ok(generics::substitute(&signature.return_type, &substitution))
+    }
+
+    /// Type of an anonymous function, recording what it captures
+    fn check_lambda(
+        &mut self,
+        params: &Parameters,
+        return_type: &Type,
+        body: &Block,
+        span: &Span,
+    ) -> CompileResult<Type> {
+        self.check_type_exists(return_type)
+            .map_err(|message| CompileError::SemanticError(message, span.clone()))?;
+
+        let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+        let outer_ident = std::mem::replace(&mut self.function_ident, "<anonymous>".to_string());
+
+        self.capturing.push(Capturing { depth: self.variables.depth(), captures: Vec::new() });
+        self.variables.push_scope();
+
+        let result = self.check_lambda_body(params, return_type, body, span);
+
+        self.variables.pop_scope();
+        let capturing = self.capturing.pop().unwrap();
+        self.return_type = outer_return_type;
+        self.function_ident = outer_ident;
+
+        result?;
+
+        self.lambdas.push(Lambda {
+            owner: self.owner.clone(),
+            span: span.clone(),
+            params: params.clone(),
+            return_type: return_type.clone(),
+            body: body.clone(),
+            captures: capturing.captures,
+        });
+
+        Ok(Type::Function(
+            params.params.iter().map(|param| param.param_type.clone()).collect(),
+            Box::new(return_type.clone()),
+        ))
+    }
+
+    fn check_lambda_body(&mut self, params: &Parameters, return_type: &Type, body: &Block, span: &Span) -> CompileResult<()> {
+        for param in &params.params {
+            self.check_type_exists(&param.param_type)
+                .map_err(|message| CompileError::SemanticError(message, span.clone()))?;
+
+            if self.variables.contains_local(&param.ident) {
+                return Err(CompileError::SemanticError(
+                    format!("Parameter `{}` is declared more than once", param.ident),
+                    span.clone(),
+                ));
+            }
+
+            self.variables.insert(param.ident.clone(), param.param_type.clone());
+        }
+
+        self.check_block(body)?;
+
+        if !returns(return_type, body) {
+            return Err(CompileError::SemanticError(
+                format!("Anonymous function can reach its end without returning {}", return_type),
+                span.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Type of a match expression
+    /// Arms that aren't integer literals are checked first, and the first of
+    /// them decides the type of the match; literal arms then take that type,
+    /// the same way the operands of a binary expression do
+    fn check_match(
+        &mut self,
+        scrutinee: &Expression,
+        arms: &[MatchArm],
+        expected: Option<&Type>,
+        span: &Span,
+    ) -> CompileResult<Type> {
+        let scrutinee_type = self.check_expression(scrutinee, None)?;
+        self.check_patterns(&scrutinee_type, arms, span)?;
+
+        let mut result_type: Option<Type> = None;
+
+        for arm in arms.iter().filter(|arm| !arm.body.is_integer_literal()) {
+            let arm_type = self.check_arm(&scrutinee_type, arm, result_type.as_ref().or(expected))?;
+
+            match &result_type {
+                Some(result_type) => expect_type(result_type, &arm_type, arm.span())?,
+                None => result_type = Some(arm_type),
+            }
+        }
+
+        let result_type = result_type.unwrap_or_else(|| match expected {
+            Some(expected) if expected.is_integer() => expected.clone(),
+            _ => Type::Int,
+        });
+
+        for arm in arms.iter().filter(|arm| arm.body.is_integer_literal()) {
+            let arm_type = self.check_arm(&scrutinee_type, arm, Some(&result_type))?;
+            expect_type(&result_type, &arm_type, arm.span())?;
+        }
+
+        self.match_types.insert((self.owner.clone(), span.clone()), result_type.clone());
+        Ok(result_type)
+    }
+
+    /// Type of the body of a match arm, with the pattern's bindings in scope
+    /// Block bodies have no value
+    fn check_arm(&mut self, scrutinee_type: &Type, arm: &MatchArm, expected: Option<&Type>) -> CompileResult<Type> {
+        self.variables.push_scope();
+
+        for (ident, binding_type) in self.pattern_bindings(scrutinee_type, &arm.pattern) {
+            self.variables.insert(ident, binding_type);
+        }
+
+        let result = match &arm.body {
+            MatchBody::Expression(body) => self.check_expression(body, expected),
+            MatchBody::Block(block) => self.check_block(block).map(|_| Type::Null),
+        };
+
+        self.variables.pop_scope();
+        result
+    }
+
+    /// Variables bound by a pattern that has already been checked
+    fn pattern_bindings(&self, scrutinee_type: &Type, pattern: &Pattern) -> Vec<(String, Type)> {
+        match (scrutinee_type, pattern) {
+            (Type::Named(ident), Pattern::Variant { variant, bindings, .. }) => {
+                let (_, variant) = self.enums[ident].variant(variant).unwrap();
+
+                bindings.iter()
+                    .zip(&variant.fields)
+                    .filter(|(binding, _)| *binding != "_")
+                    .map(|(binding, field_type)| (binding.clone(), field_type.clone()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check that every pattern fits the scrutinee, that no arm is covered
+    /// by the arms before it and that the arms cover every possible value
+    fn check_patterns(&self, scrutinee_type: &Type, arms: &[MatchArm], span: &Span) -> CompileResult<()> {
+        let declaration = match scrutinee_type {
+            Type::Named(ident) => self.enums.get(ident),
+            _ => None,
+        };
+
+        let range = match scrutinee_type {
+            Type::Bool => Some((0, 1)),
+            _ => scrutinee_type.integer_range(),
+        };
+
+        if declaration.is_none() && range.is_none() {
+            return Err(CompileError::SemanticError(
+                format!("Cannot match on {}", scrutinee_type),
+                span.clone(),
+            ));
+        }
+
+        // values already handled by an earlier arm: variant discriminants for
+        // enums, otherwise the integers themselves (bools as 0 and 1)
+        let mut covered: Vec<i128> = Vec::new();
+        let mut wildcard = false;
+
+        // every pattern is a distinct value, of the enum or within the range
+        let exhaustive = |covered: &[i128]| match (declaration, range) {
+            (Some(declaration), _) => covered.len() >= declaration.variants.len(),
+            (None, Some((min, max))) => covered.len() as i128 > max - min,
+            (None, None) => false,
+        };
+
+        for arm in arms {
+            let pattern = &arm.pattern;
+
+            if wildcard {
+                return Err(CompileError::SemanticError(
+                    "Unreachable pattern: every value is already matched".to_string(),
+                    pattern.span().clone(),
+                ));
+            }
+
+            let value = match (declaration, pattern) {
+                (_, Pattern::Wildcard { span }) => {
+                    if exhaustive(&covered) {
+                        return Err(CompileError::SemanticError(
+                            "Unreachable pattern: every value is already matched".to_string(),
+                            span.clone(),
+                        ));
+                    }
+
+                    wildcard = true;
+                    continue;
+                }
+                (Some(declaration), Pattern::Variant { enum_ident, variant, bindings, span }) => {
+                    if let Some(enum_ident) = enum_ident {
+                        if *enum_ident != declaration.ident {
+                            return Err(CompileError::SemanticError(
+                                format!("Expected a variant of `{}`, got `{}::{}`", declaration.ident, enum_ident, variant),
+                                span.clone(),
+                            ));
+                        }
+                    }
+
+                    let (discriminant, variant_declaration) = self.variant(declaration, variant, span)?;
+
+                    if variant_declaration.fields.len() != bindings.len() {
+                        return Err(CompileError::SemanticError(
+                            format!(
+                                "Variant `{}::{}` holds {} values but the pattern binds {}",
+                                declaration.ident, variant, variant_declaration.fields.len(), bindings.len(),
+                            ),
+                            span.clone(),
+                        ));
+                    }
+
+                    for (i, binding) in bindings.iter().enumerate() {
+                        if binding != "_" && bindings[..i].contains(binding) {
+                            return Err(CompileError::SemanticError(
+                                format!("`{}` is bound more than once in the pattern", binding),
+                                span.clone(),
+                            ));
+                        }
+                    }
+
+                    discriminant as i128
+                }
+                (None, Pattern::Integer { value, span }) if scrutinee_type.is_integer() => {
+                    let (min, max) = range.unwrap();
+                    if *value < min || *value > max {
+                        return Err(CompileError::SemanticError(
+                            format!("Integer literal {} does not fit in {}", value, scrutinee_type),
+                            span.clone(),
+                        ));
+                    }
+
+                    *value
+                }
+                (None, Pattern::Boolean { value, .. }) if *scrutinee_type == Type::Bool => *value as i128,
+                _ => return Err(CompileError::SemanticError(
+                    format!("Pattern does not match values of type {}", scrutinee_type),
+                    pattern.span().clone(),
+                )),
+            };
+
+            if covered.contains(&value) {
+                return Err(CompileError::SemanticError(
+                    "Unreachable pattern: already matched by an earlier arm".to_string(),
+                    pattern.span().clone(),
+                ));
+            }
+
+            covered.push(value);
+        }
+
+        if wildcard {
+            return Ok(());
+        }
+
+        match declaration {
+            Some(declaration) => {
+                if let Some(missing) = declaration.variants.iter().enumerate()
+                    .find(|(discriminant, _)| !covered.contains(&(*discriminant as i128)))
+                {
+                    return Err(CompileError::SemanticError(
+                        format!("Non-exhaustive match: `{}::{}` is not covered", declaration.ident, missing.1.ident),
+                        span.clone(),
+                    ));
+                }
+            }
+            None => {
+                if !exhaustive(&covered) {
+                    return Err(CompileError::SemanticError(
+                        format!("Non-exhaustive match: not every {} is covered, add a `_` arm", scrutinee_type),
+                        span.clone(),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A variant of an enum, or an error if there is no such variant
+    fn variant<'e>(&self, declaration: &'e Enum, variant: &str, span: &Span) -> CompileResult<(usize, &'e crate::ast::Variant)> {
+        declaration.variant(variant).ok_or_else(|| {
+            CompileError::SemanticError(
+                format!("Enum `{}` has no variant `{}`", declaration.ident, variant),
+                span.clone(),
+            )
+        })
+    }
+
+    /// Type of `target.field`
+    fn check_field(&mut self, target: &Expression, field: &str, span: &Span) -> CompileResult<Type> {
+        let target_type = self.check_expression(target, None)?;
+
+        match target_type {
+            Type::Named(ident) if self.structs.contains_key(&ident) => {
+                let declaration = &self.structs[&ident];
+                Ok(self.field(declaration, field, span)?.field_type.clone())
+            }
+            _ => Err(CompileError::SemanticError(
+                format!("{} has no field `{}`", target_type, field),
+                span.clone(),
+            )),
+        }
+    }
+
+    /// A field of a struct, or an error if there is no such field
+    fn field<'s>(&self, declaration: &'s Struct, field: &str, span: &Span) -> CompileResult<&'s crate::ast::Field> {
+        declaration.field(field).ok_or_else(|| {
+            CompileError::SemanticError(
+                format!("Struct `{}` has no field `{}`", declaration.ident, field),
+                span.clone(),
+            )
+        })
+    }
+
+    /// Type of an array literal
+    /// The elements take their type from the expected array type if there is
+    /// one, otherwise from the first element, like integer literals do
+    fn check_array_literal(
+        &mut self,
+        elements: &[Expression],
+        expected: Option<&Type>,
+        span: &Span,
+    ) -> CompileResult<Type> {
+        let mut element_type = expected.and_then(|expected| expected.element_type()).cloned();
+
+        for element in elements {
+            let actual = self.check_expression(element, element_type.as_ref())?;
+
+            match &element_type {
+                Some(element_type) => expect_type(element_type, &actual, element.span())?,
+                None => element_type = Some(actual),
+            }
+        }
+
+        let element_type = element_type.ok_or_else(|| {
+            CompileError::SemanticError(
+                "Cannot infer the element type of an empty array".to_string(),
+                span.clone(),
+            )
+        })?;
+
+        match expected {
+            Some(Type::FixedArray(_, len)) if *len != elements.len() => Err(CompileError::SemanticError(
+                format!("Expected {} elements, got {}", len, elements.len()),
+                span.clone(),
+            )),
+            Some(Type::FixedArray(_, len)) => Ok(Type::FixedArray(Box::new(element_type), *len)),
+            _ => Ok(Type::Array(Box::new(element_type))),
+        }
+    }
+
+    /// Indices can be any integer type
+    fn check_index(&mut self, index: &Expression) -> CompileResult<()> {
+        let index_type = self.check_expression(index, Some(&Type::Int))?;
+
+        if !index_type.is_integer() {
+            return Err(CompileError::SemanticError(
+                format!("Index must be an integer, got {}", index_type),
+                index.span().clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Return type of a call to a built-in
+    fn check_builtin(&mut self, builtin: Builtin, arguments: &[Expression], span: &Span) -> CompileResult<Type> {
+        // each argument is expected to be the element type of the one before,
+        // so `push(a, 1)` gives the literal the array's element type
+        let mut argument_types: Vec<Type> = Vec::new();
+        for argument in arguments {
+            let expected = argument_types.last().and_then(|ty| ty.element_type()).cloned();
+            argument_types.push(self.check_expression(argument, expected.as_ref())?);
+        }
+
+        match (builtin, argument_types.as_slice()) {
+            (Builtin::Len, [Type::String | Type::Array(_) | Type::FixedArray(..)]) => Ok(Type::Int),
+            (Builtin::Push, [Type::Array(element), value]) if element.same_as(value) => Ok(Type::Null),
+            _ => Err(CompileError::SemanticError(
+                format!(
+                    "No built-in {:?} taking ({})",
+                    builtin,
+                    argument_types.iter().map(|t| t.to_string()).collect::<Vec<_>>().join(", "),
+                ),
+                span.clone(),
+            )),
+        }
+    }
+
+    /// Type the operands of a binary expression
+    /// If only the left operand is a literal, it takes the type of the right
+    fn check_operands(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        expected: Option<&Type>,
+    ) -> CompileResult<(Type, Type)> {
+        let mut left_type = self.check_expression(left, expected)?;
+        let right_type = self.check_expression(right, Some(&left_type))?;
+
+        if !left_type.same_as(&right_type) && left.is_number_literal() {
+            left_type = self.check_expression(left, Some(&right_type))?;
+        }
+
+        Ok((left_type, right_type))
+    }
+
+    /// Type of something that can be assigned to: a variable, array element
+    /// or field
+    fn assignable_type(&mut self, expr: &Expression) -> CompileResult<Type> {
+        match expr {
+            Expression::Identifier { ident, span } => self.assignable_variable(ident, span),
+            Expression::Field { target, field, span } => self.check_field(target, field, span),
+            Expression::Index { target, index, span } => {
+                let target_type = self.check_expression(target, None)?;
+                self.check_index(index)?;
+
+                match target_type {
+                    Type::Array(element) | Type::FixedArray(element, _) => Ok(*element),
+                    Type::String => Err(CompileError::SemanticError(
+                        "Strings are immutable".to_string(),
+                        span.clone(),
+                    )),
+                    _ => Err(CompileError::SemanticError(
+                        format!("Cannot index into {}", target_type),
+                        span.clone(),
+                    )),
+                }
+            }
+            _ => Err(CompileError::SemanticError(
+                "Invalid assignment target".to_string(),
+                expr.span().clone(),
+            )),
+        }
+    }
+
+    /// Type of a variable, capturing it into the lambdas being checked if
+    /// it is declared outside them
+    /// Globals are used where they are, so they're never captured
+    fn variable_type(&mut self, ident: &String, span: &Span) -> CompileResult<Type> {
+        let Some((var_type, depth)) = self.variables.get_with_depth(ident)
+            .map(|(var_type, depth)| (var_type.clone(), depth)) else {
+            return match self.globals.get(ident) {
+                Some((global_type, _)) => Ok(global_type.clone()),
+                None => Err(CompileError::SemanticError(
+                    format!("Use of undeclared variable `{}`", ident),
+                    span.clone(),
+                )),
+            };
+        };
+
+        for lambda in self.capturing.iter_mut().filter(|lambda| depth <= lambda.depth) {
+            if !lambda.captures.iter().any(|(captured, _)| captured == ident) {
+                lambda.captures.push((ident.clone(), var_type.clone()));
+            }
+        }
+
+        Ok(var_type)
+    }
+
+    /// Type of a variable being assigned to
+    /// Captured variables are copies, so assigning to them is an error
+    /// rather than silently leaving the original unchanged
+    fn assignable_variable(&mut self, ident: &String, span: &Span) -> CompileResult<Type> {
+        let depth = self.variables.get_with_depth(ident).map(|(_, depth)| depth);
+
+        if let (None, Some((_, true))) = (depth, self.globals.get(ident)) {
+            return Err(CompileError::SemanticError(
+                format!("Cannot assign to constant `{}`", ident),
+                span.clone(),
+            ));
+        }
+
+        if let (Some(depth), Some(lambda)) = (depth, self.capturing.last()) {
+            if depth <= lambda.depth {
+                return Err(CompileError::SemanticError(
+                    format!("Cannot assign to `{}`, which the anonymous function captures by value", ident),
+                    span.clone(),
+                ));
+            }
+        }
+
+        self.variable_type(ident, span).map_err(|_| {
+            CompileError::SemanticError(
+                format!("Assignment to undeclared variable `{}`", ident),
+                span.clone(),
+            )
+        })
+    }
+}
+
+/// Point out which instance of a generic function an error was found in
+/// Functions may only have the attributes the compiler knows about
+fn check_attributes(function: &Function) -> CompileResult<()> {
+    for attribute in &function.attributes {
+        match attribute.ident.as_str() {
+            "inline" | "noinline" if !attribute.arguments.is_empty() => {
+                return Err(CompileError::SemanticError(
+                    format!("`@{}` takes no arguments", attribute.ident),
+                    attribute.span.clone(),
+                ));
+            }
+            "inline" | "noinline" => {}
+            "allow" | "warn" | "deny" if attribute.arguments.is_empty() => {
+                return Err(CompileError::SemanticError(
+                    format!("`@{}` needs the lints it applies to", attribute.ident),
+                    attribute.span.clone(),
+                ));
+            }
+            "allow" | "warn" | "deny" => {
+                if let Some(id) = attribute.arguments.iter().find(|id| !lint::is_known(id)) {
+                    return Err(CompileError::SemanticError(format!("Unknown lint `{}`", id), attribute.span.clone()));
+                }
+            }
+            _ => {
+                return Err(CompileError::SemanticError(
+                    format!("Unknown attribute `@{}`", attribute.ident),
+                    attribute.span.clone(),
+                ));
+            }
+        }
+
+        if function.attributes.iter().filter(|other| other.ident == attribute.ident).count() > 1 {
+            return Err(CompileError::SemanticError(
+                format!("`@{}` is written more than once", attribute.ident),
+                attribute.span.clone(),
+            ));
+        }
+    }
+
+    if let (Some(_), Some(attribute)) = (function.attribute("inline"), function.attribute("noinline")) {
+        return Err(CompileError::SemanticError(
+            format!("Function `{}` cannot be both `@inline` and `@noinline`", function.ident),
+            attribute.span.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn in_instance(error: CompileError, instance: &str) -> CompileError {
+    match error {
+        CompileError::SemanticError(message, span) => {
+            CompileError::SemanticError(format!("{}, in `{}`", message, instance), span)
+        }
+        CompileError::CompileError(message) => CompileError::CompileError(format!("{}, in `{}`", message, instance)),
+        error => error,
+    }
+}
+
+/// Whether a body can't reach its end, which only functions returning
+/// nothing may
+fn returns(return_type: &Type, body: &Block) -> bool {
+    *return_type == Type::Null || body.statements.iter().any(Statement::diverges)
+}
+
+/// Error unless `actual` is the `expected` type
+fn expect_type(expected: &Type, actual: &Type, span: &Span) -> CompileResult<()> {
+    if expected.same_as(actual) {
+        Ok(())
+    } else {
+        Err(CompileError::SemanticError(
+            format!("Expected {}, got {}", expected, actual),
+            span.clone(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex;
+    use crate::parser::Parser;
+
+    fn check(source: &str) -> CompileResult<()> {
+        let tokens = lex(source.to_string())?;
+        let program = Parser::new(tokens).parse()?;
+        TypeChecker::new().check_program(&program)
+    }
+
+    #[test]
+    fn test_fixed_width_integers() {
+        assert!(check("func f(a: u8, b: u8): u8 { return a + b * 2; }").is_ok());
+        assert!(check("func f(a: i32): i64 { return a as i64 + 1; }").is_ok());
+        assert!(check("func f(): i8 { return -128; }").is_ok());
+
+        // literals must fit the type they're given
+        assert!(check("func f(): u8 { return 256; }").is_err());
+        assert!(check("func f(): i8 { return -129; }").is_err());
+        assert!(check("func f(): u64 { return 18446744073709551615; }").is_ok());
+        assert!(check("func f(): int { return 9223372036854775808; }").is_err());
+        assert!(check("func f(): int { return -9223372036854775808; }").is_ok());
+
+        // no implicit conversions between widths or signedness
+        assert!(check("func f(a: u8, b: u16): u16 { return a + b; }").is_err());
+        assert!(check("func f(a: i32): u32 { return a; }").is_err());
+
+        // unsigned values cannot be negated
+        assert!(check("func f(a: u32): u32 { return -a; }").is_err());
+    }
+
+    #[test]
+    fn test_strings() {
+        assert!(check(r#"func f(a: string): string { return a + "!" + (len(a) as string); }"#).is_ok());
+        assert!(check(r#"func f(a: string): bool { return a[0] == 104 and a[1:] == "i"; }"#).is_ok());
+
+        assert!(check(r#"func f(a: string): string { return a - "b"; }"#).is_err());
+        assert!(check(r#"func f(a: string): int { return a as int; }"#).is_err());
+        assert!(check(r#"func f(a: int): int { return len(a); }"#).is_err());
+        assert!(check(r#"func len(a: string): int { return 0; }"#).is_err());
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert!(check("func f(): int { let a = [1, 2, 3]; a[0] = a[1] + a[2]; push(a, 4); return len(a); }").is_ok());
+        assert!(check("func f(a: [u8; 2]): u8 { a[1] += 1; return a[0] + a[1]; }").is_ok());
+        assert!(check("func f(): [u16; 3] { return [1, 2, 300]; }").is_ok());
+        assert!(check(r#"func f(a: string[][]): string[] { push(a[0], "x"); return a[0][1:]; }"#).is_ok());
+
+        // elements must all have the same type
+        assert!(check(r#"func f(): int[] { return [1, "two"]; }"#).is_err());
+        assert!(check("func f(): u8[] { return [1, 256]; }").is_err());
+        // fixed arrays have exactly their length and can't grow
+        assert!(check("func f(): [int; 3] { return [1, 2]; }").is_err());
+        assert!(check("func f(a: [int; 3]): int[] { return a; }").is_err());
+        assert!(check("func f(a: [int; 3]) : null { push(a, 1); }").is_err());
+        // and empty literals need a type from their context
+        assert!(check("func f(): int { let a = []; return 0; }").is_err());
+        assert!(check("func f(): int[] { return []; }").is_ok());
+
+        assert!(check(r#"func f(s: string): null { s[0] = 1; }"#).is_err());
+        assert!(check("func f(a: float[]): int { return a[0.5]; }").is_err());
+    }
+
+    #[test]
+    fn test_structs() {
+        let point = "struct Point { x: float, y: float }";
+        let with = |body: &str| check(&format!("{} {}", point, body));
+
+        assert!(with("func f(): float { let p = Point { y: 2.0, x: 1.0 }; p.x += p.y; return p.x; }").is_ok());
+        assert!(with("struct Line { from: Point, to: Point } func f(l: Line): float { return l.to.y - l.from.y; }").is_ok());
+        assert!(with("func f(ps: Point[]): null { ps[0].x = 0.0; }").is_ok());
+
+        assert!(with("func f(): Point { return Point { x: 1.0 }; }").is_err());
+        assert!(with("func f(): Point { return Point { x: 1.0, y: 2.0, z: 3.0 }; }").is_err());
+        assert!(with("func f(): Point { return Point { x: 1.0, x: 1.0, y: 2.0 }; }").is_err());
+        assert!(with("func f(): Point { return Point { x: 1, y: 2.0 }; }").is_err());
+        assert!(with("func f(p: Point): float { return p.z; }").is_err());
+        assert!(with("func f(p: int): int { return p.x; }").is_err());
+        assert!(with("func f(p: Vector): null { }").is_err());
+        assert!(with("struct Point { z: int }").is_err());
+        assert!(check("struct Pair { a: int, a: int }").is_err());
+    }
+
+    #[test]
+    fn test_enums_and_match() {
+        let shape = "enum Shape { Circle(float), Rect(float, float), Empty }";
+        let with = |body: &str| check(&format!("{} {}", shape, body));
+
+        assert!(with("func area(s: Shape): float { return match (s) { Circle(r) => 3.0 * r * r, Shape::Rect(w, h) => w * h, Empty => 0.0 }; }").is_ok());
+        assert!(with("func f(): Shape { return Shape::Rect(1.0, 2.0); }").is_ok());
+        assert!(with("func f(s: Shape): u8 { return match (s) { Rect(_, h) => 2, _ => 1 }; }").is_ok());
+        assert!(with("func f(s: Shape): null { let x = 0.0; match (s) { Circle(r) => { x = r; } _ => { } } }").is_ok());
+        assert!(check("func f(b: bool): int { return match (b) { true => 1, false => 0 }; }").is_ok());
+        assert!(check("func f(x: u8): u8 { return match (x) { 0 => 1, 255 => x, _ => 0 }; }").is_ok());
+
+        // not every variant or value is covered
+        assert!(with("func f(s: Shape): int { return match (s) { Circle(r) => 1, Empty => 0 }; }").is_err());
+        assert!(check("func f(x: int): int { return match (x) { 0 => 1, 1 => 2 }; }").is_err());
+        assert!(check("func f(b: bool): int { return match (b) { true => 1 }; }").is_err());
+
+        // unreachable arms
+        assert!(with("func f(s: Shape): int { return match (s) { _ => 1, Empty => 0 }; }").is_err());
+        assert!(check("func f(x: int): int { return match (x) { 1 => 1, 1 => 2, _ => 0 }; }").is_err());
+        // nothing is left for `_` once every variant or value is matched
+        let error = check("enum E { A } func f(e: E): int { return match (e) { E::A => 1, _ => 2 }; }").unwrap_err();
+        assert!(error.to_string().contains("Unreachable pattern"), "{}", error);
+        let error = check("func f(): int { return match (true) { true => 1, false => 3, _ => 2 }; }").unwrap_err();
+        assert!(error.to_string().contains("Unreachable pattern"), "{}", error);
+
+        assert!(with("func f(s: Shape): int { return match (s) { Circle(a, b) => 1, _ => 0 }; }").is_err());
+        assert!(with("func f(s: Shape): int { return match (s) { Square(a) => 1, _ => 0 }; }").is_err());
+        assert!(with("func f(s: Shape): float { return match (s) { Circle(r) => r, _ => 0 }; }").is_err());
+        assert!(check("func f(x: u8): int { return match (x) { 256 => 1, _ => 0 }; }").is_err());
+        assert!(with("func f(): Shape { return Shape::Circle(1.0, 2.0); }").is_err());
+        assert!(with("func f(): Shape { return Shape::Circle(1); }").is_err());
+        assert!(with("func f(s: Shape): float { return s.x; }").is_err());
+        assert!(with("struct Shape { x: int }").is_err());
+        assert!(check("enum E { A, A }").is_err());
+    }
+
+    #[test]
+    fn test_globals() {
+        assert!(check("const LIMIT: u8 = 200; let count = 0; func f(x: u8): bool { count += 1; return x < LIMIT; }").is_ok());
+        assert!(check("let greeting = \"hi\"; func f(greeting: int): int { greeting = 1; return greeting; }").is_ok());
+        assert!(check("const A = 2.0; const B = A * 3.0; func f(): float { return B; }").is_ok());
+
+        let error = |source: &str| check(source).unwrap_err().to_string();
+
+        assert!(error("const A = 1; func f(): null { A = 2; }").contains("Cannot assign to constant `A`"));
+        assert!(error("let a = 1; let a = 2;").contains("Global `a` is defined more than once"));
+        assert!(error("let f = 1; func f(): int { return 0; }").contains("Global `f` has the same name as a function"));
+        assert!(error("let a = [1, 2];").contains("Globals cannot be of type int[]"));
+        assert!(error("let a: u8 = 1.0;").contains("Expected u8, got float"));
+        // initializers can only see the globals before them
+        assert!(check("const A = B; const B = 1;").is_err());
+    }
+
+    #[test]
+    fn test_extern_functions() {
+        assert!(check("extern func abs(x: i32): i32; func f(): i32 { return abs(-1); }").is_ok());
+        assert!(check("extern func srand(seed: u32): null; extern func srand(seed: u32): null;").is_ok());
+
+        let error = |source: &str| check(source).unwrap_err().to_string();
+
+        assert!(error("extern func getenv(name: string): int;")
+            .contains("Parameter `name` of extern function `getenv` is of type string, which cannot be passed to C"));
+        assert!(error("extern func f(): int[];").contains("returns int[], which cannot be returned from C"));
+        assert!(error("extern func abs(x: i32): i32; extern func abs(x: int): int;")
+            .contains("Extern function `abs` is declared with different types"));
+        assert!(error("extern func abs(x: i32): i32; func f(): i32 { return abs(1.0); }").contains("Expected i32, got float"));
+    }
+
+    #[test]
+    fn test_lambdas() {
+        assert!(check("func f(n: int): func(int): int { return func(x: int): int { return x + n; }; }").is_ok());
+        assert!(check("func twice(g: func(int): int, x: int): int { return g(g(x)); } func inc(x: int): int { return x + 1; } func f(): int { return twice(inc, 1); }").is_ok());
+        assert!(check("func f(): int { let g = func(): func(): int { let a = 1; return func(): int { return a; }; }; return g()(); }").is_ok());
+        assert!(check("func inc(x: int): int { return x + 1; } func f(inc: func(int): int): int { return inc(1); }").is_ok());
+
+        assert!(check("func f(): int { let g = func(x: int): int { return x; }; return g(true); }").is_err());
+        assert!(check("func f(): int { let g = func(x: int): int { return x; }; return g(); }").is_err());
+        assert!(check("func f(): int { let g = func(x: int): bool { return x; }; return 0; }").is_err());
+        assert!(check("func f(g: func(int): int): func(int): bool { return g; }").is_err());
+        assert!(check("func f(x: int): int { return x(1); }").is_err());
+
+        // captured variables are copies, so they can't be assigned to
+        assert!(check("func f(): int { let n = 0; let g = func(): null { n = 1; }; return n; }").is_err());
+        assert!(check("func f(): int { let n = 0; let g = func(): null { n += 1; }; return n; }").is_err());
+        assert!(check("func f(): int { let g = func(): int { let n = 0; n = 1; return n; }; return g(); }").is_ok());
+    }
+
+    #[test]
+    fn test_lambda_captures() {
+        let mut checker = TypeChecker::new();
+        let source = "func f(a: int, s: string): int { let b = 2; let g = func(x: int): int { let h = func(): int { return a + x; }; return h() + b; }; return g(1); }";
+        let program = crate::parser::Parser::new(crate::lexer::lex(source.to_string()).unwrap()).parse().unwrap();
+        checker.check_program(&program).unwrap();
+
+        // the inner lambda is finished first; the outer one captures `a` for it
+        let captures: Vec<Vec<&str>> = checker.lambdas().iter()
+            .map(|lambda| lambda.captures.iter().map(|(ident, _)| ident.as_str()).collect())
+            .collect();
+
+        assert_eq!(captures, vec![vec!["a", "x"], vec!["a", "b"]]);
+    }
+
+    #[test]
+    fn test_generics() {
+        let max = "func max<T: numeric>(a: T, b: T): T { if (a > b) { return a; } return b; }";
+        let with = |body: &str| check(&format!("{} {}", max, body));
+
+        assert!(with("func f(): float { return max(1.0, 2.5); }").is_ok());
+        assert!(with("func f(x: u8): u8 { return max(x, 200); }").is_ok());
+        assert!(with("func f(): int { return max(1, 2); }").is_ok());
+        assert!(check("func first<T>(a: T[]): T { return a[0]; } func f(): string { return first([\"a\"]); }").is_ok());
+        assert!(check("func apply<T, U>(x: T, f: func(T): U): U { return f(x); } func f(): bool { return apply(1, func(x: int): bool { return x > 0; }); }").is_ok());
+
+        let error = |source: &str| check(source).unwrap_err().to_string();
+
+        assert!(error(&format!("{} func f(): string {{ return max(\"a\", \"b\"); }}", max))
+            .contains("`string` does not satisfy the `numeric` bound on `T` of `max`"));
+        assert!(error(&format!("{} func f(): float {{ return max(1.0, 2); }}", max)).contains("Expected float, got int"));
+        assert!(error("func make<T>(): T[] { return []; } func f(): int[] { return make(); }")
+            .contains("Cannot infer type parameter `T` of `make`"));
+        // without a bound, the body is what rejects the type argument
+        assert!(error("func neg<T>(x: T): T { return -x; } func f(): bool { return neg(true); }")
+            .contains("in `neg<bool>`"));
+
+        assert!(with("func f(): int { let g = max; return 0; }").is_err());
+        assert!(with("func f(): int { return max(1); }").is_err());
+        assert!(check("func f<T, T>(a: T): T { return a; }").is_err());
+        assert!(check("func grow<T>(x: T): int { return grow([x]); } func f(): int { return grow(1); }").is_err());
+    }
+
+    #[test]
+    fn test_attributes() {
+        assert!(check("@inline func sq(x: int): int { return x * x; } @noinline func f(): int { return sq(2); }").is_ok());
+        assert!(check("@inline func id<T>(x: T): T { return x; }").is_ok());
+
+        let error = |source: &str| check(source).unwrap_err().to_string();
+
+        assert!(error("@inlined func f(): null {}").contains("Unknown attribute `@inlined`"));
+        assert!(error("@inline(always) func f(): null {}").contains("`@inline` takes no arguments"));
+        assert!(error("@inline @inline func f(): null {}").contains("`@inline` is written more than once"));
+        assert!(error("@inline @noinline func f(): null {}")
+            .contains("Function `f` cannot be both `@inline` and `@noinline`"));
+        assert!(error("@inline let x = 1;").contains("Attributes can only be written before functions"));
+
+        assert!(check("@allow(naming, unused_parameters) @deny(float_equality) func F(x: int): null {}").is_ok());
+        assert!(error("@allow(nameing) func f(): null {}").contains("Unknown lint `nameing`"));
+        assert!(error("@warn func f(): null {}").contains("`@warn` needs the lints it applies to"));
+    }
+
+    #[test]
+    fn test_returns() {
+        assert!(check("func f(n: int): int { if (n > 0) { return 1; } else { return 2; } }").is_ok());
+        assert!(check("func f(n: int): int { while (true) { if (n > 0) { return n; } n++; } }").is_ok());
+        assert!(check("func f(n: int): int { do { return n; } until (n > 0) }").is_ok());
+        assert!(check("func f(n: int): null { if (n > 0) { return; } }").is_ok());
+
+        let error = |source: &str| check(source).unwrap_err().to_string();
+
+        assert!(error("func f(n: int): int { if (n > 0) { return 1; } }")
+            .contains("Function `f` can reach its end without returning int"));
+        assert!(error("func f(n: int): int { while (n > 0) { return n; } }")
+            .contains("Function `f` can reach its end without returning int"));
+        assert!(error("func f(): int { let g = func(n: int): bool { if (n > 0) { return true; } }; return 0; }")
+            .contains("Anonymous function can reach its end without returning bool"));
+    }
+}